@@ -64,6 +64,51 @@ char* offline_intelligence_generate_title(
 // Free a C string allocated by the library
 void offline_intelligence_free_string(char* s);
 
+// Generation options for streaming chat
+typedef struct {
+    uint32_t max_tokens;
+    float temperature;
+} StreamOptions;
+
+// Opaque handle used to cancel an in-progress stream
+typedef struct StreamAbortHandle StreamAbortHandle;
+
+// Outcome reported with the final callback invocation
+typedef enum {
+    OI_STREAM_OK = 0,       // the backend finished the reply
+    OI_STREAM_ERROR = 1,    // the backend could not be reached or failed mid-stream
+    OI_STREAM_ABORTED = 2,  // offline_intelligence_stream_abort cancelled the stream
+} StreamStatus;
+
+// Receives each UTF-8 delta as a null-terminated string with status
+// OI_STREAM_OK, then a final call with delta == NULL whose status says whether
+// the stream finished, failed or was aborted.
+// The callback runs on an internal tokio worker thread, not the calling
+// thread: it must be thread-safe, must not block for long, and must not call
+// offline_intelligence_free. The delta pointer is only valid during the call.
+typedef void (*StreamCallback)(const char* delta, int32_t status, void* user_data);
+
+// Start streaming a chat completion. Returns immediately; deltas are
+// delivered through the callback. options may be NULL for defaults
+// (max_tokens = 2000, temperature = 0.7). Returns NULL on invalid arguments,
+// in which case the callback is never invoked.
+StreamAbortHandle* offline_intelligence_stream_chat(
+    OfflineIntelligenceHandle* handle,
+    const char* session_id,
+    const Message* messages,
+    int32_t message_count,
+    const StreamOptions* options,
+    StreamCallback callback,
+    void* user_data
+);
+
+// Cancel a stream. The callback still receives its final NULL call, with
+// OI_STREAM_ABORTED unless the stream had already ended.
+void offline_intelligence_stream_abort(StreamAbortHandle* stream);
+
+// Release a stream handle. Does not cancel the stream.
+void offline_intelligence_stream_free(StreamAbortHandle* stream);
+
 #ifdef __cplusplus
 }
 #endif
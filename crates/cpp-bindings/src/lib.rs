@@ -2,7 +2,10 @@
 use std::os::raw::{c_char, c_int, c_void};
 use std::ptr;
 use std::sync::Arc;
+use futures_util::StreamExt;
+use offline_intelligence::worker_threads::{LLMWorker, StreamEvent};
 use tokio::runtime::Runtime;
+use tokio::task::AbortHandle;
 /
 #[repr(C)]
 pub struct OfflineIntelligenceHandle {
@@ -29,15 +32,47 @@ pub struct SearchResult {
     pub search_type: *const c_char,
 }
 /
+#[repr(C)]
+pub struct StreamOptions {
+    pub max_tokens: u32,
+    pub temperature: f32,
+}
+/
+/
+/
+pub type StreamCallback = extern "C" fn(delta: *const c_char, status: c_int, user_data: *mut c_void);
+/
+pub const OI_STREAM_OK: c_int = 0;
+/
+pub const OI_STREAM_ERROR: c_int = 1;
+/
+pub const OI_STREAM_ABORTED: c_int = 2;
+/
+pub struct StreamAbortHandle {
+    abort: AbortHandle,
+}
+/
+struct Instance {
+    runtime: Runtime,
+    llm_worker: Arc<LLMWorker>,
+}
+fn instance<'a>(handle: *mut OfflineIntelligenceHandle) -> &'a Instance {
+    unsafe { &*(handle as *const Instance) }
+}
+/
 #[no_mangle]
 pub extern "C" fn offline_intelligence_new() -> *mut OfflineIntelligenceHandle {
     let rt = match Runtime::new() {
         Ok(runtime) => runtime,
         Err(_) => return ptr::null_mut(),
     };
+    let backend_url = offline_intelligence::Config::from_env()
+        .map(|cfg| cfg.backend_url)
+        .unwrap_or_else(|_| format!("http://{}:{}", "127.0.0.1", 8001));
 
-    let handle = Box::new(OfflineIntelligenceHandle {
-        _private: [],
+    let handle = Box::new(Instance {
+        runtime: rt,
+        llm_worker: Arc::new(LLMWorker::new_with_backend(backend_url)),
     });
 
     Box::into_raw(handle) as *mut OfflineIntelligenceHandle
@@ -46,9 +81,8 @@ pub extern "C" fn offline_intelligence_new() -> *mut OfflineIntelligenceHandle {
 #[no_mangle]
 pub extern "C" fn offline_intelligence_free(handle: *mut OfflineIntelligenceHandle) {
     if !handle.is_null() {
-        unsafe {
-            let _ = Box::from_raw(handle);
-        }
+        let instance = unsafe { Box::from_raw(handle as *mut Instance) };
+        instance.runtime.shutdown_background();
     }
 }
 /
@@ -161,4 +195,137 @@ pub extern "C" fn offline_intelligence_free_string(s: *mut c_char) {
         }
     }
 }
+/
+struct CallbackTarget {
+    callback: StreamCallback,
+    user_data: *mut c_void,
+    // Reported on the final call; stays ABORTED unless the task reaches an outcome first.
+    status: c_int,
+}
+unsafe impl Send for CallbackTarget {}
+impl CallbackTarget {
+    fn emit(&self, delta: &str) {
+        if let Ok(text) = CString::new(delta.replace('\0', "")) {
+            (self.callback)(text.as_ptr(), OI_STREAM_OK, self.user_data);
+        }
+    }
+}
+impl Drop for CallbackTarget {
+    fn drop(&mut self) {
+        (self.callback)(ptr::null(), self.status, self.user_data);
+    }
+}
+fn delta_content(sse_line: &str) -> Option<String> {
+    let data = sse_line.trim_start_matches("data: ").trim();
+    if data == "[DONE]" {
+        return None;
+    }
+    let chunk: serde_json::Value = serde_json::from_str(data).ok()?;
+    chunk.get("choices")?.get(0)?.get("delta")?.get("content")?.as_str().map(str::to_string)
+}
+/
+/
+/
+/
+/
+/
+/
+/
+/
+/
+#[no_mangle]
+pub extern "C" fn offline_intelligence_stream_chat(
+    handle: *mut OfflineIntelligenceHandle,
+    session_id: *const c_char,
+    messages: *const Message,
+    message_count: c_int,
+    options: *const StreamOptions,
+    callback: Option<StreamCallback>,
+    user_data: *mut c_void,
+) -> *mut StreamAbortHandle {
+    let callback = match callback {
+        Some(callback) => callback,
+        None => return ptr::null_mut(),
+    };
+    if handle.is_null() || session_id.is_null() || messages.is_null() || message_count <= 0 {
+        return ptr::null_mut();
+    }
+
+    let session_id = match unsafe { CStr::from_ptr(session_id) }.to_str() {
+        Ok(s) => s.to_string(),
+        Err(_) => return ptr::null_mut(),
+    };
+    let message_slice = unsafe { std::slice::from_raw_parts(messages, message_count as usize) };
+    let mut chat_messages = Vec::with_capacity(message_slice.len());
+    for message in message_slice {
+        if message.role.is_null() || message.content.is_null() {
+            return ptr::null_mut();
+        }
+        let (role, content) = unsafe {
+            (CStr::from_ptr(message.role).to_str(), CStr::from_ptr(message.content).to_str())
+        };
+        match (role, content) {
+            (Ok(role), Ok(content)) => chat_messages.push(offline_intelligence::Message {
+                role: role.into(),
+                content: content.to_string(),
+                parts: None,
+            }),
+            _ => return ptr::null_mut(),
+        }
+    }
+    let (max_tokens, temperature) = if options.is_null() {
+        (2000, 0.7)
+    } else {
+        let options = unsafe { &*options };
+        (options.max_tokens, options.temperature)
+    };
 
+    let instance = instance(handle);
+    let llm_worker = instance.llm_worker.clone();
+    let target = CallbackTarget { callback, user_data, status: OI_STREAM_ABORTED };
+    let task = instance.runtime.spawn(async move {
+        let mut target = target;
+        let stream = match llm_worker.stream_response(&session_id, chat_messages, max_tokens, temperature, 1).await {
+            Ok(stream) => stream,
+            Err(_) => {
+                target.status = OI_STREAM_ERROR;
+                return;
+            }
+        };
+        futures_util::pin_mut!(stream);
+        while let Some(event) = stream.next().await {
+            match event {
+                Ok(StreamEvent::Frame(sse_line)) => {
+                    if let Some(delta) = delta_content(&sse_line) {
+                        target.emit(&delta);
+                    }
+                }
+                Ok(StreamEvent::Finished(_)) => {}
+                Err(_) => {
+                    target.status = OI_STREAM_ERROR;
+                    return;
+                }
+            }
+        }
+        target.status = OI_STREAM_OK;
+    });
+
+    Box::into_raw(Box::new(StreamAbortHandle { abort: task.abort_handle() }))
+}
+/
+/
+#[no_mangle]
+pub extern "C" fn offline_intelligence_stream_abort(stream: *mut StreamAbortHandle) {
+    if !stream.is_null() {
+        unsafe { &*stream }.abort.abort();
+    }
+}
+/
+#[no_mangle]
+pub extern "C" fn offline_intelligence_stream_free(stream: *mut StreamAbortHandle) {
+    if !stream.is_null() {
+        unsafe {
+            let _ = Box::from_raw(stream);
+        }
+    }
+}
@@ -1,6 +1,40 @@
-﻿
-
-export declare function helloWorld(): string
+﻿export declare function helloWorld(): string
 
 export declare function getVersion(): string
 
+export interface Message {
+  role: string
+  content: string
+}
+
+export interface OptimizationResult {
+  optimizedMessages: Array<Message>
+  originalCount: number
+  optimizedCount: number
+  compressionRatio: number
+}
+
+export interface SearchHit {
+  sessionId: string
+  messageId: number
+  role: string
+  content: string
+}
+
+export interface SearchResult {
+  results: Array<SearchHit>
+  total: number
+  searchType: string
+}
+
+export interface StreamChunk {
+  content: string
+}
+
+export declare class OfflineIntelligence {
+  constructor(dbPath?: string | undefined | null)
+  optimizeContext(sessionId: string, messages: Array<Message>, userQuery?: string | undefined | null): Promise<OptimizationResult>
+  search(query: string, sessionId?: string | undefined | null, limit?: number | undefined | null, userId?: string | undefined | null): Promise<SearchResult>
+  generateTitle(messages: Array<Message>): Promise<string>
+  streamResponse(sessionId: string, messages: Array<Message>, onChunk: (chunk: StreamChunk) => void, maxTokens?: number | undefined | null, temperature?: number | undefined | null): Promise<string>
+}
@@ -306,8 +306,9 @@ if (!nativeBinding) {
   throw new Error(`Failed to load native binding`)
 }
 
-const { helloWorld, getVersion } = nativeBinding
+const { helloWorld, getVersion, OfflineIntelligence } = nativeBinding
 
 module.exports.helloWorld = helloWorld
 module.exports.getVersion = getVersion
+module.exports.OfflineIntelligence = OfflineIntelligence
 
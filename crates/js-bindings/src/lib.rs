@@ -1,5 +1,12 @@
-﻿use napi::{bindgen_prelude::*, JsObject, Env};
+﻿use std::sync::Arc;
+use futures_util::StreamExt;
+use napi::bindgen_prelude::*;
+use napi::threadsafe_function::{ErrorStrategy, ThreadsafeFunction, ThreadsafeFunctionCallMode};
 use napi_derive::napi;
+use offline_intelligence::context_engine::{ContextOrchestrator, OrchestratorConfig};
+use offline_intelligence::memory_db::{MemoryDatabase, MessageSearchFilter};
+use offline_intelligence::worker_threads::{LLMWorker, StreamEvent};
+use offline_intelligence::Role;
 /
 #[napi]
 pub fn hello_world() -> String {
@@ -10,5 +17,189 @@ pub fn hello_world() -> String {
 pub fn get_version() -> String {
     env!("CARGO_PKG_VERSION").to_string()
 }
-// Simple module - functions are automatically exported by napi-derive
+/
+#[napi(object)]
+#[derive(Clone)]
+pub struct Message {
+    pub role: String,
+    pub content: String,
+}
+/
+#[napi(object)]
+pub struct OptimizationResult {
+    pub optimized_messages: Vec<Message>,
+    pub original_count: u32,
+    pub optimized_count: u32,
+    pub compression_ratio: f64,
+}
+/
+#[napi(object)]
+pub struct SearchHit {
+    pub session_id: String,
+    pub message_id: i64,
+    pub role: String,
+    pub content: String,
+}
+/
+#[napi(object)]
+pub struct SearchResult {
+    pub results: Vec<SearchHit>,
+    pub total: u32,
+    pub search_type: String,
+}
+/
+#[napi(object)]
+pub struct StreamChunk {
+    pub content: String,
+}
+fn to_core(messages: Vec<Message>) -> Vec<offline_intelligence::Message> {
+    messages
+        .into_iter()
+        .map(|m| offline_intelligence::Message { role: m.role.as_str().into(), content: m.content, parts: None })
+        .collect()
+}
+fn generic_error(context: &str, e: impl std::fmt::Display) -> Error {
+    Error::new(Status::GenericFailure, format!("{}: {}", context, e))
+}
+fn delta_content(sse_line: &str) -> Option<String> {
+    let data = sse_line.trim_start_matches("data: ").trim();
+    if data == "[DONE]" {
+        return None;
+    }
+    let chunk: serde_json::Value = serde_json::from_str(data).ok()?;
+    chunk.get("choices")?.get(0)?.get("delta")?.get("content")?.as_str().map(str::to_string)
+}
+/
+#[napi]
+pub struct OfflineIntelligence {
+    orchestrator: ContextOrchestrator,
+    llm_worker: Arc<LLMWorker>,
+}
+#[napi]
+impl OfflineIntelligence {
+    #[napi(constructor)]
+    pub fn new(db_path: Option<String>) -> Result<Self> {
+        let db_path = db_path.unwrap_or_else(|| "./data/conversations.db".to_string());
+        let database = MemoryDatabase::new(std::path::Path::new(&db_path))
+            .map_err(|e| generic_error("Failed to open memory database", e))?;
+        let backend_url = offline_intelligence::Config::from_env()
+            .map(|cfg| cfg.backend_url)
+            .map_err(|e| generic_error("Failed to load config", e))?;
+        let llm_worker = Arc::new(LLMWorker::new_with_backend(backend_url));
+        let mut orchestrator = block_on(ContextOrchestrator::new(Arc::new(database), OrchestratorConfig::default()))
+            .map_err(|e| generic_error("Failed to create context orchestrator", e))?;
+        block_on(orchestrator.set_llm_worker(llm_worker.clone()));
+
+        Ok(OfflineIntelligence { orchestrator, llm_worker })
+    }
 
+    /
+    #[napi]
+    pub async fn optimize_context(
+        &self,
+        session_id: String,
+        messages: Vec<Message>,
+        user_query: Option<String>,
+    ) -> Result<OptimizationResult> {
+        let original = to_core(messages);
+        let optimized = self.orchestrator
+            .process_conversation(&session_id, &original, user_query.as_deref())
+            .await
+            .map_err(|e| generic_error("Context optimization failed", e))?;
+
+        Ok(OptimizationResult {
+            original_count: original.len() as u32,
+            optimized_count: optimized.len() as u32,
+            // Same definition as the HTTP /memory/optimize response: the share of messages removed.
+            compression_ratio: if original.is_empty() {
+                0.0
+            } else {
+                (original.len() as f64 - optimized.len() as f64) / original.len() as f64
+            },
+            optimized_messages: optimized
+                .into_iter()
+                .map(|m| Message { role: m.role.to_string(), content: m.content })
+                .collect(),
+        })
+    }
+
+    /
+    #[napi]
+    pub async fn search(&self, query: String, session_id: Option<String>, limit: Option<u32>, user_id: Option<String>) -> Result<SearchResult> {
+        let keywords: Vec<String> = query
+            .split_whitespace()
+            .filter(|word| word.len() > 2)
+            .map(|s| s.to_lowercase())
+            .collect();
+        let limit = limit.unwrap_or(10).clamp(1, 100) as usize;
+        let filter = MessageSearchFilter { user_id, ..Default::default() };
+        let found = self.orchestrator
+            .search_messages(session_id.as_deref(), &keywords, &filter, limit)
+            .await
+            .map_err(|e| generic_error("Search failed", e))?;
+
+        Ok(SearchResult {
+            total: found.len() as u32,
+            search_type: "keyword".to_string(),
+            results: found
+                .into_iter()
+                .map(|m| SearchHit { session_id: m.session_id, message_id: m.id, role: m.role, content: m.content })
+                .collect(),
+        })
+    }
+
+    /
+    #[napi]
+    pub async fn generate_title(&self, messages: Vec<Message>) -> Result<String> {
+        let prompt = messages.iter()
+            .filter(|m| Role::from(m.role.as_str()) == Role::User)
+            .map(|m| m.content.as_str())
+            .collect::<Vec<_>>()
+            .join("\n");
+        let title_instruction = format!(
+            "User prompt: {}\n\n\
+             Create a short, meaningful chat title using 1-5 words maximum that captures the essence of this prompt.",
+            prompt
+        );
+        self.llm_worker
+            .generate_title(&title_instruction, 20)
+            .await
+            .map_err(|e| generic_error("Title generation failed", e))
+    }
+
+    /
+    /
+    #[napi]
+    pub async fn stream_response(
+        &self,
+        session_id: String,
+        messages: Vec<Message>,
+        #[napi(ts_arg_type = "(chunk: StreamChunk) => void")]
+        on_chunk: ThreadsafeFunction<StreamChunk, ErrorStrategy::Fatal>,
+        max_tokens: Option<u32>,
+        temperature: Option<f64>,
+    ) -> Result<String> {
+        let stream = self.llm_worker
+            .stream_response(
+                &session_id,
+                to_core(messages),
+                max_tokens.unwrap_or(2000),
+                temperature.unwrap_or(0.7) as f32,
+                1,
+            )
+            .await
+            .map_err(|e| generic_error("LLM backend error", e))?;
+        futures_util::pin_mut!(stream);
+        let mut full_text = String::new();
+        while let Some(item) = stream.next().await {
+            let StreamEvent::Frame(sse_line) = item.map_err(|e| generic_error("Stream error", e))? else {
+                continue;
+            };
+            if let Some(delta) = delta_content(&sse_line) {
+                full_text.push_str(&delta);
+                on_chunk.call(StreamChunk { content: delta }, ThreadsafeFunctionCallMode::NonBlocking);
+            }
+        }
+        Ok(full_text)
+    }
+}
@@ -0,0 +1,80 @@
+//! Before/after timings for the database hot paths
+//!
+//! Run with `cargo bench --bench db_hot_paths`. Each pair measures the same read of a
+//! session's history: once the way it was done before (a fresh `prepare`, no statement
+//! timeout) and once the way the store does it now (`prepare_cached`, timeout armed).
+use offline_intelligence::memory_db::{MemoryDatabase, PoolSettings};
+use rusqlite::params;
+use std::time::{Duration, Instant};
+
+const HISTORY_SQL: &str =
+    "SELECT id, session_id, message_index, role, content, tokens, timestamp, importance_score, embedding_generated
+     FROM messages WHERE session_id = ?1 ORDER BY message_index LIMIT ?2 OFFSET ?3";
+const MESSAGES: i32 = 200;
+const ITERATIONS: u32 = 2_000;
+
+fn open(dir: &tempfile::TempDir, name: &str, statement_timeout: Duration) -> (MemoryDatabase, String) {
+    let settings = PoolSettings { statement_timeout, ..PoolSettings::default() };
+    let db = MemoryDatabase::new_with_pool_settings(&dir.path().join(name), Duration::from_secs(5), settings)
+        .expect("open database");
+    let session = db.conversations.create_session(None).expect("create session");
+    let messages: Vec<_> = (0..MESSAGES)
+        .map(|i| {
+            let role = if i % 2 == 0 { "user" } else { "assistant" };
+            (role.to_string(), format!("message {} {}", i, "lorem ipsum ".repeat(20)), i, 40, 0.5)
+        })
+        .collect();
+    db.conversations.store_messages_batch(&session.id, &messages).expect("seed messages");
+    (db, session.id)
+}
+
+fn read_history(db: &MemoryDatabase, session_id: &str, cached: bool) -> usize {
+    let conn = db.conversations.get_conn_public().expect("connection");
+    if cached {
+        count_rows(&mut conn.prepare_cached(HISTORY_SQL).expect("prepare"), session_id)
+    } else {
+        count_rows(&mut conn.prepare(HISTORY_SQL).expect("prepare"), session_id)
+    }
+}
+
+fn count_rows(stmt: &mut rusqlite::Statement<'_>, session_id: &str) -> usize {
+    stmt.query_map(params![session_id, 1000, 0], |row| row.get::<_, String>(4))
+        .expect("query")
+        .count()
+}
+
+fn time(label: &str, mut run: impl FnMut() -> usize) -> Duration {
+    // Warm the pool and page cache before measuring.
+    for _ in 0..50 {
+        run();
+    }
+    let start = Instant::now();
+    let mut rows = 0;
+    for _ in 0..ITERATIONS {
+        rows += run();
+    }
+    let elapsed = start.elapsed();
+    println!(
+        "{:<44} {:>9.1} us/iter  ({} rows)",
+        label,
+        elapsed.as_secs_f64() * 1e6 / ITERATIONS as f64,
+        rows / ITERATIONS as usize,
+    );
+    elapsed
+}
+
+fn main() {
+    let dir = tempfile::tempdir().expect("temp dir");
+    let (before_db, before_session) = open(&dir, "before.db", Duration::ZERO);
+    let (after_db, after_session) = open(&dir, "after.db", PoolSettings::default().statement_timeout);
+
+    println!("session history read, {} messages, {} iterations", MESSAGES, ITERATIONS);
+    let before = time("before: prepare, no statement timeout", || read_history(&before_db, &before_session, false));
+    let after = time("after: prepare_cached, statement timeout", || read_history(&after_db, &after_session, true));
+    println!("change: {:+.1}%", (after.as_secs_f64() / before.as_secs_f64() - 1.0) * 100.0);
+
+    println!();
+    println!("isolated costs");
+    time("prepare_cached, no statement timeout", || read_history(&before_db, &before_session, true));
+    time("prepare, statement timeout", || read_history(&after_db, &after_session, false));
+}
@@ -2,14 +2,19 @@
 //! This module provides administrative functionality for system management.
 //! Currently a placeholder for future implementation.
 use axum::{
-    extract::State,
+    extract::{Path, Query, State},
     http::StatusCode,
     response::IntoResponse,
-    Json,
+    Extension, Json,
 };
 use std::sync::Arc;
 use serde::{Deserialize, Serialize};
 use crate::shared_state::SharedState;
+use crate::api::error::ApiError;
+use crate::api::auth::{authorize_session, AuthenticatedTenant};
+use crate::metrics;
+use crate::model_runtime::HealthStatus;
+use crate::context_engine::BackfillStats;
 /
 #[derive(Debug, Serialize)]
 pub struct HealthResponse {
@@ -60,6 +65,219 @@ pub async fn db_stats(
     ))
 }
 /
+pub async fn counters(
+    State(shared_state): State<Arc<SharedState>>,
+) -> impl IntoResponse {
+    Json(shared_state.counters.snapshot())
+}
+/
+/
+pub async fn readiness(
+    State(shared_state): State<Arc<SharedState>>,
+) -> Result<(StatusCode, Json<HealthStatus>), ApiError> {
+    let runtime_manager = shared_state.runtime_manager.read().ok().and_then(|guard| guard.clone())
+        .ok_or_else(|| ApiError::new(StatusCode::SERVICE_UNAVAILABLE, "Model runtime not started"))?;
+    let status = runtime_manager.health_check().await
+        .map_err(|e| ApiError::new(StatusCode::SERVICE_UNAVAILABLE, e.to_string()))?;
+    let code = if status.healthy { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+    Ok((code, Json(status)))
+}
+/
+#[derive(Debug, Deserialize)]
+pub struct BackfillRequest {
+    #[serde(default = "default_backfill_batch_size")]
+    pub batch_size: usize,
+    #[serde(default = "default_backfill_max_batches")]
+    pub max_batches: usize,
+    #[serde(default = "default_backfill_pause_ms")]
+    pub pause_ms: u64,
+}
+fn default_backfill_batch_size() -> usize { 32 }
+fn default_backfill_max_batches() -> usize { 10 }
+fn default_backfill_pause_ms() -> u64 { 250 }
+/
+/
+pub async fn backfill_embeddings(
+    State(shared_state): State<Arc<SharedState>>,
+    Json(req): Json<BackfillRequest>,
+) -> Result<Json<BackfillStats>, ApiError> {
+    // Clone out of the slot so a long backfill does not block config reloads.
+    let orchestrator = shared_state.context_orchestrator.read().await.clone()
+        .ok_or_else(|| ApiError::new(StatusCode::SERVICE_UNAVAILABLE, "Memory system not available"))?;
+    let stats = orchestrator.backfill_embeddings(
+        req.batch_size.min(256),
+        req.max_batches,
+        std::time::Duration::from_millis(req.pause_ms),
+    ).await.map_err(|e| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(Json(stats))
+}
+/
+#[derive(Debug, Deserialize)]
+pub struct CacheHistoryQuery {
+    #[serde(default)]
+    pub session_id: Option<String>,
+    #[serde(default = "default_history_limit")]
+    pub limit: usize,
+}
+fn default_history_limit() -> usize { 100 }
+/
+pub async fn cache_history(
+    State(shared_state): State<Arc<SharedState>>,
+    Query(query): Query<CacheHistoryQuery>,
+) -> Result<impl IntoResponse, ApiError> {
+    let cache_manager = shared_state.cache_manager.read()
+        .map_err(|_| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "Failed to acquire cache manager lock"))?
+        .clone()
+        .ok_or_else(|| ApiError::new(StatusCode::SERVICE_UNAVAILABLE, "Cache manager not initialized"))?;
+    let operations = cache_manager.lock().await
+        .get_statistics()
+        .recent_operations(query.session_id.as_deref().filter(|id| !id.is_empty()), query.limit);
+    Ok(Json(serde_json::json!({
+        "session_id": query.session_id,
+        "count": operations.len(),
+        "operations": operations,
+    })))
+}
+/
+pub async fn db_pool_stats(
+    State(shared_state): State<Arc<SharedState>>,
+) -> impl IntoResponse {
+    Json(shared_state.database_pool_stats())
+}
+/
+pub async fn prometheus_metrics(
+    State(shared_state): State<Arc<SharedState>>,
+) -> impl IntoResponse {
+    metrics::record_counters(&shared_state.counters.snapshot());
+    metrics::get_metrics().await
+}
+/
+pub async fn context_stats(
+    State(shared_state): State<Arc<SharedState>>,
+) -> Result<impl IntoResponse, ApiError> {
+    let orchestrator = shared_state.context_orchestrator.read().await;
+    match *orchestrator {
+        Some(ref orchestrator) => Ok(Json(orchestrator.optimization_stats())),
+        None => Err(ApiError::new(StatusCode::SERVICE_UNAVAILABLE, "Context orchestrator not initialized")),
+    }
+}
+/
+#[derive(Debug, Deserialize)]
+pub struct ContextConfigUpdate {
+    pub enabled: Option<bool>,
+    pub max_context_tokens: Option<usize>,
+    pub auto_optimize: Option<bool>,
+    pub enable_metrics: Option<bool>,
+    pub relevance_weight: Option<f32>,
+    pub recency_weight: Option<f32>,
+}
+/
+pub async fn update_context_config(
+    State(shared_state): State<Arc<SharedState>>,
+    Json(update): Json<ContextConfigUpdate>,
+) -> Result<impl IntoResponse, ApiError> {
+    if update.max_context_tokens == Some(0) {
+        return Err(ApiError::new(StatusCode::BAD_REQUEST, "max_context_tokens must be positive"));
+    }
+    for weight in [update.relevance_weight, update.recency_weight].into_iter().flatten() {
+        if !weight.is_finite() || weight < 0.0 {
+            return Err(ApiError::new(StatusCode::BAD_REQUEST, "retrieval weights must be non-negative numbers"));
+        }
+    }
+    shared_state.update_orchestrator_config(|config| {
+        if let Some(enabled) = update.enabled {
+            config.enabled = enabled;
+        }
+        if let Some(max_context_tokens) = update.max_context_tokens {
+            config.max_context_tokens = max_context_tokens;
+        }
+        if let Some(auto_optimize) = update.auto_optimize {
+            config.auto_optimize = auto_optimize;
+        }
+        if let Some(enable_metrics) = update.enable_metrics {
+            config.enable_metrics = enable_metrics;
+        }
+        if let Some(relevance_weight) = update.relevance_weight {
+            config.relevance_weight = relevance_weight;
+        }
+        if let Some(recency_weight) = update.recency_weight {
+            config.recency_weight = recency_weight;
+        }
+    }).await
+        .map(Json)
+        .ok_or_else(|| ApiError::new(StatusCode::SERVICE_UNAVAILABLE, "Context orchestrator not initialized"))
+}
+/
+pub async fn create_session_snapshot(
+    State(shared_state): State<Arc<SharedState>>,
+    Path(session_id): Path<String>,
+    tenant: Option<Extension<AuthenticatedTenant>>,
+) -> Result<impl IntoResponse, ApiError> {
+    authorize_session(&shared_state.database_pool, &session_id, tenant.as_deref())?;
+    let cache_manager = shared_state.cache_manager.read()
+        .map_err(|_| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "Failed to acquire cache manager lock"))?
+        .clone()
+        .ok_or_else(|| ApiError::new(StatusCode::SERVICE_UNAVAILABLE, "Cache manager not initialized"))?;
+    let (snapshot_id, entries) = cache_manager.lock().await
+        .snapshot_live_cache(&session_id)
+        .await
+        .map_err(|e| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to create snapshot: {}", e)))?
+        .ok_or_else(|| ApiError::new(StatusCode::CONFLICT, "Session has no cached entries to snapshot"))?;
+    Ok((StatusCode::CREATED, Json(serde_json::json!({
+        "session_id": session_id,
+        "snapshot_id": snapshot_id,
+        "entry_count": entries.len(),
+        "entries": entries,
+    }))))
+}
+/
+#[derive(Debug, Deserialize)]
+pub struct ListSnapshotsQuery {
+    #[serde(default = "default_snapshot_limit")]
+    pub limit: usize,
+}
+fn default_snapshot_limit() -> usize { 100 }
+/
+#[derive(Debug, Deserialize)]
+pub struct PruneSnapshotsQuery {
+    pub keep: usize,
+}
+/
+pub async fn session_snapshots(
+    State(shared_state): State<Arc<SharedState>>,
+    Path(session_id): Path<String>,
+    tenant: Option<Extension<AuthenticatedTenant>>,
+    Query(query): Query<ListSnapshotsQuery>,
+) -> Result<impl IntoResponse, ApiError> {
+    authorize_session(&shared_state.database_pool, &session_id, tenant.as_deref())?;
+    let snapshots = shared_state.database_pool
+        .get_recent_kv_snapshots(&session_id, query.limit)
+        .await
+        .map_err(|e| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to list snapshots: {}", e)))?;
+    Ok(Json(serde_json::json!({
+        "session_id": session_id,
+        "snapshots": snapshots,
+    })))
+}
+/
+pub async fn prune_session_snapshots(
+    State(shared_state): State<Arc<SharedState>>,
+    Path(session_id): Path<String>,
+    tenant: Option<Extension<AuthenticatedTenant>>,
+    Query(query): Query<PruneSnapshotsQuery>,
+) -> Result<impl IntoResponse, ApiError> {
+    authorize_session(&shared_state.database_pool, &session_id, tenant.as_deref())?;
+    let deleted = shared_state.database_pool
+        .prune_session_kv_snapshots(&session_id, query.keep)
+        .await
+        .map_err(|e| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to prune snapshots: {}", e)))?;
+    Ok(Json(serde_json::json!({
+        "session_id": session_id,
+        "kept": query.keep,
+        "deleted": deleted,
+    })))
+}
+/
 pub async fn maintenance(
     State(_shared_state): State<Arc<SharedState>>,
     Json(_payload): Json<MaintenanceRequest>,
@@ -0,0 +1,239 @@
+﻿//! Optional API-key authentication for every endpoint except health probes
+//!
+//! Clients send `Authorization: Bearer <key>`. Each configured key may name a tenant,
+//! which is attached to the request and recorded as the owner of sessions it creates.
+//! A key without a tenant is an operator (superuser) key: it sees every session and is
+//! the only kind of key accepted on the server-wide `/admin` endpoints.
+use axum::{
+    extract::{Request, State},
+    http::{header, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tracing::debug;
+use crate::api::error::ApiError;
+use crate::config::Config;
+use crate::memory_db::{MemoryDatabase, Session};
+use crate::metrics;
+/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuthenticatedTenant(pub String);
+pub struct ApiKeyAuth {
+    enabled: bool,
+    keys: HashMap<String, Option<String>>,
+}
+impl ApiKeyAuth {
+    /
+    pub fn new(enabled: bool, entries: &[String]) -> anyhow::Result<Self> {
+        let mut keys = HashMap::new();
+        for entry in entries {
+            let (key, tenant) = match entry.split_once(':') {
+                Some((key, tenant)) => (key.trim(), Some(tenant.trim()).filter(|t| !t.is_empty())),
+                None => (entry.trim(), None),
+            };
+            if key.is_empty() {
+                return Err(anyhow::anyhow!("API_KEYS contains an entry with an empty key"));
+            }
+            if keys.insert(key.to_string(), tenant.map(str::to_string)).is_some() {
+                return Err(anyhow::anyhow!("API_KEYS lists the same key more than once"));
+            }
+        }
+        if enabled && keys.is_empty() {
+            return Err(anyhow::anyhow!("API_AUTH_ENABLED=true but API_KEYS is empty"));
+        }
+        Ok(Self { enabled, keys })
+    }
+    pub fn from_config(cfg: &Config) -> anyhow::Result<Self> {
+        Self::new(cfg.api_auth_enabled, &cfg.api_keys)
+    }
+    fn lookup(&self, presented: &str) -> Option<Option<&str>> {
+        // Compare against every key so the response time does not reveal a matching prefix.
+        let mut found = None;
+        for (key, tenant) in &self.keys {
+            if constant_time_eq(key.as_bytes(), presented.as_bytes()) {
+                found = Some(tenant.as_deref());
+            }
+        }
+        found
+    }
+}
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+/
+fn requires_key(method: &Method, path: &str) -> bool {
+    *method != Method::OPTIONS && !matches!(path, "/healthz" | "/readyz" | "/metrics")
+}
+/
+fn requires_operator(path: &str) -> bool {
+    path.starts_with("/admin/") && !path.starts_with("/admin/sessions/")
+}
+/
+pub async fn require_api_key(
+    State(auth): State<Arc<ApiKeyAuth>>,
+    mut req: Request,
+    next: Next,
+) -> Response {
+    if !auth.enabled || !requires_key(req.method(), req.uri().path()) {
+        return next.run(req).await;
+    }
+    let presented = req.headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(str::trim);
+    let Some(tenant) = presented.and_then(|key| auth.lookup(key)) else {
+        debug!("Rejected unauthenticated {} {}", req.method(), req.uri().path());
+        return unauthorized();
+    };
+    if let Some(tenant) = tenant {
+        if requires_operator(req.uri().path()) {
+            debug!("Rejected tenant key on operator endpoint {} {}", req.method(), req.uri().path());
+            metrics::inc_request("auth", "forbidden");
+            return ApiError::new(StatusCode::FORBIDDEN, "This endpoint requires an operator API key").into_response();
+        }
+        req.extensions_mut().insert(AuthenticatedTenant(tenant.to_string()));
+    }
+    next.run(req).await
+}
+fn unauthorized() -> Response {
+    metrics::inc_request("auth", "rejected");
+    (
+        StatusCode::UNAUTHORIZED,
+        [(header::WWW_AUTHENTICATE, "Bearer")],
+        axum::Json(serde_json::json!({
+            "error": "Missing or invalid API key",
+            "code": StatusCode::UNAUTHORIZED.as_u16(),
+        })),
+    )
+        .into_response()
+}
+/
+/
+pub fn visible_to(session: &Session, tenant: Option<&AuthenticatedTenant>) -> bool {
+    match tenant {
+        Some(AuthenticatedTenant(tenant)) => session.metadata.user_id.as_deref() == Some(tenant.as_str()),
+        None => true,
+    }
+}
+/
+/
+pub fn authorize_session(
+    database: &MemoryDatabase,
+    session_id: &str,
+    tenant: Option<&AuthenticatedTenant>,
+) -> Result<(), ApiError> {
+    if tenant.is_none() {
+        return Ok(());
+    }
+    let session = database.conversations.get_session(session_id)
+        .map_err(|e| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, format!("Database error: {}", e)))?;
+    match session {
+        // Report sessions the tenant does not own as missing rather than confirming they exist.
+        Some(session) if !visible_to(&session, tenant) => Err(ApiError::new(StatusCode::NOT_FOUND, "Conversation not found")),
+        _ => Ok(()),
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, routing::get, Extension, Router};
+    use tower::ServiceExt;
+    fn app(auth: ApiKeyAuth) -> Router {
+        let whoami = |tenant: Option<Extension<AuthenticatedTenant>>| async move {
+            tenant.map(|Extension(AuthenticatedTenant(t))| t).unwrap_or_default()
+        };
+        Router::new()
+            .route("/conversations", get(whoami).post(whoami))
+            .route("/generate/ws", get(whoami))
+            .route("/healthz", get(|| async { "ok" }))
+            .route("/admin/context-config", axum::routing::put(|| async { "updated" }))
+            .route("/admin/sessions/:id/snapshots", get(whoami))
+            .layer(axum::middleware::from_fn_with_state(Arc::new(auth), require_api_key))
+    }
+    fn request(method: Method, path: &str, key: Option<&str>) -> Request {
+        let mut builder = Request::builder().method(method).uri(path);
+        if let Some(key) = key {
+            builder = builder.header(header::AUTHORIZATION, format!("Bearer {}", key));
+        }
+        builder.body(Body::empty()).unwrap()
+    }
+    async fn body_text(response: Response) -> String {
+        let bytes = axum::body::to_bytes(response.into_body(), 1024).await.unwrap();
+        String::from_utf8(bytes.to_vec()).unwrap()
+    }
+    #[tokio::test]
+    async fn test_keys_gate_every_route_but_health_and_map_to_tenants() {
+        let auth = ApiKeyAuth::new(true, &["alpha-key:team-a".to_string(), "beta-key:team-b".to_string()]).unwrap();
+        let app = app(auth);
+
+        let health = app.clone().oneshot(request(Method::GET, "/healthz", None)).await.unwrap();
+        assert_eq!(health.status(), StatusCode::OK);
+        let preflight = app.clone().oneshot(request(Method::OPTIONS, "/conversations", None)).await.unwrap();
+        assert_ne!(preflight.status(), StatusCode::UNAUTHORIZED);
+
+        let read = app.clone().oneshot(request(Method::GET, "/conversations", None)).await.unwrap();
+        assert_eq!(read.status(), StatusCode::UNAUTHORIZED);
+        let missing = app.clone().oneshot(request(Method::POST, "/conversations", None)).await.unwrap();
+        assert_eq!(missing.status(), StatusCode::UNAUTHORIZED);
+        let wrong = app.clone().oneshot(request(Method::GET, "/generate/ws", Some("alpha-kez"))).await.unwrap();
+        assert_eq!(wrong.status(), StatusCode::UNAUTHORIZED);
+
+        let alpha = app.clone().oneshot(request(Method::POST, "/conversations", Some("alpha-key"))).await.unwrap();
+        assert_eq!(body_text(alpha).await, "team-a");
+        let beta = app.oneshot(request(Method::GET, "/generate/ws", Some("beta-key"))).await.unwrap();
+        assert_eq!(body_text(beta).await, "team-b");
+    }
+    #[tokio::test]
+    async fn test_admin_endpoints_require_an_operator_key() {
+        let auth = ApiKeyAuth::new(true, &["ops-key".to_string(), "alpha-key:team-a".to_string()]).unwrap();
+        let app = app(auth);
+
+        let tenant = app.clone().oneshot(request(Method::PUT, "/admin/context-config", Some("alpha-key"))).await.unwrap();
+        assert_eq!(tenant.status(), StatusCode::FORBIDDEN);
+        let operator = app.clone().oneshot(request(Method::PUT, "/admin/context-config", Some("ops-key"))).await.unwrap();
+        assert_eq!(operator.status(), StatusCode::OK);
+
+        // Session-scoped admin routes stay open to tenants; the handlers check ownership.
+        let scoped = app.clone().oneshot(request(Method::GET, "/admin/sessions/s1/snapshots", Some("alpha-key"))).await.unwrap();
+        assert_eq!(body_text(scoped).await, "team-a");
+        // Operator keys carry no tenant, so handlers treat them as unrestricted.
+        let unrestricted = app.oneshot(request(Method::GET, "/conversations", Some("ops-key"))).await.unwrap();
+        assert_eq!(body_text(unrestricted).await, "");
+    }
+    #[tokio::test]
+    async fn test_disabled_auth_lets_everything_through() {
+        let app = app(ApiKeyAuth::new(false, &[]).unwrap());
+        let response = app.oneshot(request(Method::POST, "/conversations", None)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+    #[test]
+    fn test_key_configuration_is_validated() {
+        assert!(ApiKeyAuth::new(true, &[]).is_err());
+        assert!(ApiKeyAuth::new(true, &["dup:a".to_string(), "dup:b".to_string()]).is_err());
+        assert!(ApiKeyAuth::new(true, &[":tenant".to_string()]).is_err());
+        let auth = ApiKeyAuth::new(true, &["solo".to_string()]).unwrap();
+        assert_eq!(auth.lookup("solo"), Some(None));
+        assert_eq!(auth.lookup("other"), None);
+    }
+    #[test]
+    fn test_tenants_only_see_their_own_sessions_and_operators_see_all() {
+        let database = MemoryDatabase::new_in_memory().unwrap();
+        let metadata = crate::memory_db::SessionMetadata { user_id: Some("team-a".to_string()), ..Default::default() };
+        let owned = database.conversations.create_session(Some(metadata)).unwrap();
+        let shared = database.conversations.create_session(None).unwrap();
+        let team = |t: &str| AuthenticatedTenant(t.to_string());
+
+        assert!(authorize_session(&database, &owned.id, Some(&team("team-a"))).is_ok());
+        assert!(authorize_session(&database, &owned.id, None).is_ok());
+        let denied = authorize_session(&database, &owned.id, Some(&team("team-b"))).unwrap_err();
+        assert_eq!(denied.status, StatusCode::NOT_FOUND);
+        let unowned = authorize_session(&database, &shared.id, Some(&team("team-b"))).unwrap_err();
+        assert_eq!(unowned.status, StatusCode::NOT_FOUND);
+        assert!(authorize_session(&database, &shared.id, None).is_ok());
+        // A session that does not exist yet may be created, and will be owned, by the caller.
+        assert!(authorize_session(&database, "not-created-yet", Some(&team("team-b"))).is_ok());
+    }
+}
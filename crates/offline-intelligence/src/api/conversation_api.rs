@@ -1,13 +1,16 @@
 ﻿use axum::{
-    extract::{State, Path},
-    response::{IntoResponse, Response},
-    Json,
+    extract::{State, Path, Query},
+    Extension, Json,
 };
 use axum::http::StatusCode;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use tracing::{info, error};
+use tracing::{info, error, debug, warn};
+use crate::memory_db::schema::{MessageAlternate, MessageRevision, Session, SessionExport, SessionMetadata};
 use crate::shared_state::UnifiedAppState;
+use crate::api::error::ApiError;
+use crate::api::stream_api::embed_messages;
+use crate::api::auth::{authorize_session, visible_to, AuthenticatedTenant};
 /
 #[derive(Debug, Serialize)]
 pub struct ConversationsResponse {
@@ -22,6 +25,10 @@ pub struct ConversationSummary {
     pub last_accessed: String,
     pub message_count: usize,
     pub pinned: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub folder: Option<String>,
+    pub tags: Vec<String>,
+    pub metadata: std::collections::HashMap<String, String>,
 }
 /
 #[derive(Debug, Serialize)]
@@ -35,11 +42,26 @@ pub struct ConversationDetailResponse {
 pub struct MessageResponse {
     pub role: String,
     pub content: String,
+    /
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub finish_reason: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub completion_tokens: Option<u32>,
+    /
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub alternates: Vec<MessageAlternate>,
+}
+/
+#[derive(Debug, Deserialize)]
+pub struct ConversationListQuery {
+    pub folder: Option<String>,
 }
 /
 pub async fn get_conversations(
     State(state): State<UnifiedAppState>,
-) -> Result<Json<ConversationsResponse>, Response> {
+    tenant: Option<Extension<AuthenticatedTenant>>,
+    Query(query): Query<ConversationListQuery>,
+) -> Result<Json<ConversationsResponse>, ApiError> {
     info!("Fetching all conversations");
 
     let orchestrator_lock = state.context_orchestrator.read().await;
@@ -50,7 +72,12 @@ pub async fn get_conversations(
                 let mut conversations = Vec::new();
 
                 for session in sessions {
-
+                    if !visible_to(&session, tenant.as_deref()) {
+                        continue;
+                    }
+                    if query.folder.is_some() && session.metadata.folder != query.folder {
+                        continue;
+                    }
 
                     if let Some(ref title) = session.metadata.title {
 
@@ -65,6 +92,9 @@ pub async fn get_conversations(
                             last_accessed: session.last_accessed.to_rfc3339(),
                             message_count,
                             pinned: session.metadata.pinned,
+                            folder: session.metadata.folder.clone(),
+                            tags: session.metadata.tags.clone(),
+                            metadata: session.metadata.user_defined.clone(),
                         });
                     }
                 }
@@ -74,20 +104,63 @@ pub async fn get_conversations(
             }
             Err(e) => {
                 error!("Failed to fetch conversations: {}", e);
-                Err((StatusCode::INTERNAL_SERVER_ERROR, format!("Database error: {}", e)).into_response())
+                Err(ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, format!("Database error: {}", e)))
             }
         }
     } else {
         error!("Context orchestrator not initialized");
-        Err((StatusCode::SERVICE_UNAVAILABLE, "Memory system not available").into_response())
+        Err(ApiError::new(StatusCode::SERVICE_UNAVAILABLE, "Memory system not available"))
+    }
+}
+/
+#[derive(Debug, Deserialize)]
+pub struct CreateConversationRequest {
+    pub session_id: Option<String>,
+    pub title: Option<String>,
+}
+/
+/
+/
+pub async fn create_conversation(
+    State(state): State<UnifiedAppState>,
+    tenant: Option<Extension<AuthenticatedTenant>>,
+    Json(req): Json<CreateConversationRequest>,
+) -> Result<(StatusCode, Json<Session>), ApiError> {
+    let metadata = SessionMetadata {
+        title: req.title.filter(|t| !t.trim().is_empty()),
+        user_id: tenant.as_deref().map(|AuthenticatedTenant(tenant)| tenant.clone()),
+        ..Default::default()
+    };
+    match req.session_id {
+        Some(ref session_id) if session_id.trim().is_empty() => {
+            return Err(ApiError::new(StatusCode::BAD_REQUEST, "session_id must not be empty"));
+        }
+        Some(ref session_id) => authorize_session(&state.shared_state.database_pool, session_id, tenant.as_deref())?,
+        None => {}
+    }
+    match state.database_worker.create_session(req.session_id.as_deref(), metadata).await {
+        Ok((session, true)) => {
+            info!("Created conversation {}", session.id);
+            Ok((StatusCode::CREATED, Json(session)))
+        }
+        Ok((session, false)) => {
+            debug!("Conversation {} already exists", session.id);
+            Ok((StatusCode::OK, Json(session)))
+        }
+        Err(e) => {
+            error!("Failed to create conversation: {}", e);
+            Err(ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, format!("Database error: {}", e)))
+        }
     }
 }
 /
 pub async fn get_conversation(
     State(state): State<UnifiedAppState>,
     Path(session_id): Path<String>,
-) -> Result<Json<ConversationDetailResponse>, Response> {
+    tenant: Option<Extension<AuthenticatedTenant>>,
+) -> Result<Json<ConversationDetailResponse>, ApiError> {
     info!("Fetching conversation: {}", session_id);
+    authorize_session(&state.shared_state.database_pool, &session_id, tenant.as_deref())?;
 
     let orchestrator_lock = state.context_orchestrator.read().await;
 
@@ -96,25 +169,41 @@ pub async fn get_conversation(
         let session = match orchestrator.database().conversations.get_session(&session_id) {
             Ok(Some(s)) => s,
             Ok(None) => {
-                return Err((StatusCode::NOT_FOUND, "Conversation not found").into_response());
+                return Err(ApiError::new(StatusCode::NOT_FOUND, "Conversation not found"));
             }
             Err(e) => {
                 error!("Failed to fetch session: {}", e);
-                return Err((StatusCode::INTERNAL_SERVER_ERROR, format!("Database error: {}", e)).into_response());
+                return Err(ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, format!("Database error: {}", e)));
             }
         };
 
 
+        let mut outcomes = orchestrator.database().conversations.get_generation_outcomes(&session_id)
+            .unwrap_or_else(|e| {
+                warn!("Failed to fetch generation outcomes: {}", e);
+                Default::default()
+            });
+        let mut alternates = orchestrator.database().conversations.get_session_alternates(&session_id)
+            .unwrap_or_else(|e| {
+                warn!("Failed to fetch alternate choices: {}", e);
+                Default::default()
+            });
         let messages = match orchestrator.database().conversations.get_session_messages(&session_id, None, None) {
             Ok(msgs) => msgs.into_iter()
-                .map(|msg| MessageResponse {
-                    role: msg.role,
-                    content: msg.content,
+                .map(|msg| {
+                    let outcome = outcomes.remove(&msg.id).unwrap_or_default();
+                    MessageResponse {
+                        role: msg.role,
+                        content: msg.content,
+                        finish_reason: outcome.finish_reason,
+                        completion_tokens: outcome.completion_tokens,
+                        alternates: alternates.remove(&msg.id).unwrap_or_default(),
+                    }
                 })
                 .collect(),
             Err(e) => {
                 error!("Failed to fetch messages: {}", e);
-                return Err((StatusCode::INTERNAL_SERVER_ERROR, format!("Database error: {}", e)).into_response());
+                return Err(ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, format!("Database error: {}", e)));
             }
         };
 
@@ -125,7 +214,7 @@ pub async fn get_conversation(
         }))
     } else {
         error!("Context orchestrator not initialized");
-        Err((StatusCode::SERVICE_UNAVAILABLE, "Memory system not available").into_response())
+        Err(ApiError::new(StatusCode::SERVICE_UNAVAILABLE, "Memory system not available"))
     }
 }
 /
@@ -137,72 +226,194 @@ pub struct UpdateTitleRequest {
 pub async fn update_conversation_title(
     State(state): State<UnifiedAppState>,
     Path(session_id): Path<String>,
+    tenant: Option<Extension<AuthenticatedTenant>>,
     Json(req): Json<UpdateTitleRequest>,
-) -> Result<Json<Value>, Response> {
+) -> Result<Json<Value>, ApiError> {
+    authorize_session(&state.shared_state.database_pool, &session_id, tenant.as_deref())?;
     info!("Updating title for conversation: {}", session_id);
 
     if req.title.is_empty() {
-        return Err((StatusCode::BAD_REQUEST, "Title cannot be empty").into_response());
+        return Err(ApiError::new(StatusCode::BAD_REQUEST, "Title cannot be empty"));
     }
 
+    match state.database_worker.update_conversation_title(&session_id, &req.title).await {
+        Ok(_) => {
+            info!("Successfully updated title for conversation: {}", session_id);
+            Ok(Json(serde_json::json!({
+                "success": true,
+                "id": session_id,
+                "title": req.title
+            })))
+        }
+        Err(e) => {
+
+            error!("Failed to update conversation title for session {}: {}", session_id, e);
+            Err(ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, format!("Database error: {}", e)))
+        }
+    }
+}
+/
+/
+/
+pub async fn delete_conversation(
+    State(state): State<UnifiedAppState>,
+    Path(session_id): Path<String>,
+    tenant: Option<Extension<AuthenticatedTenant>>,
+) -> Result<Json<Value>, ApiError> {
+    authorize_session(&state.shared_state.database_pool, &session_id, tenant.as_deref())?;
+    info!("Deleting conversation: {}", session_id);
+
+    match state.database_worker.delete_conversation(&session_id).await {
+        Ok(0) => {
+            info!("Conversation not found for deletion: {}", session_id);
+            Err(ApiError::new(StatusCode::NOT_FOUND, format!("Conversation not found: {}", session_id)))
+        }
+        Ok(_) => {
+            info!("Successfully deleted conversation: {}", session_id);
+            Ok(Json(serde_json::json!({
+                "success": true,
+                "id": session_id
+            })))
+        }
+        Err(e) => {
+            error!("Failed to delete conversation: {}", e);
+
+            Err(ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, format!("Database error: {}", e)))
+        }
+    }
+}
+/
+/
+pub async fn clear_conversation(
+    State(state): State<UnifiedAppState>,
+    Path(session_id): Path<String>,
+    tenant: Option<Extension<AuthenticatedTenant>>,
+) -> Result<Json<Value>, ApiError> {
+    authorize_session(&state.shared_state.database_pool, &session_id, tenant.as_deref())?;
+    info!("Clearing conversation: {}", session_id);
+
     let orchestrator_lock = state.context_orchestrator.read().await;
 
     if let Some(ref orchestrator) = *orchestrator_lock {
-        match orchestrator.database().conversations.update_session_title(&session_id, &req.title) {
-            Ok(_) => {
-                info!("Successfully updated title for conversation: {}", session_id);
+        let database = orchestrator.database();
+        match database.conversations.get_session(&session_id) {
+            Ok(Some(_)) => {}
+            Ok(None) => {
+                info!("Conversation not found for clearing: {}", session_id);
+                return Err(ApiError::new(StatusCode::NOT_FOUND, format!("Conversation not found: {}", session_id)));
+            }
+            Err(e) => {
+                error!("Failed to fetch conversation: {}", e);
+                return Err(ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, format!("Database error: {}", e)));
+            }
+        }
+        match state.database_worker.clear_conversation(&session_id).await {
+            Ok(messages_removed) => {
+                orchestrator.tier_manager().read().await.evict_session(&session_id);
+                state.shared_state.clear_session_state(&session_id);
+                info!("Cleared {} messages from conversation {}", messages_removed, session_id);
                 Ok(Json(serde_json::json!({
                     "success": true,
                     "id": session_id,
-                    "title": req.title
+                    "messages_removed": messages_removed
                 })))
             }
             Err(e) => {
-
-                error!("Failed to update conversation title for session {}: {}", session_id, e);
-                Err((StatusCode::INTERNAL_SERVER_ERROR, format!("Database error: {}", e)).into_response())
+                error!("Failed to clear conversation: {}", e);
+                Err(ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, format!("Database error: {}", e)))
             }
         }
     } else {
         error!("Context orchestrator not initialized");
-        Err((StatusCode::SERVICE_UNAVAILABLE, "Memory system not available").into_response())
+        Err(ApiError::new(StatusCode::SERVICE_UNAVAILABLE, "Memory system not available"))
     }
 }
 /
+#[derive(Debug, Serialize)]
+pub struct MessageHistoryResponse {
+    pub message_id: i64,
+    pub current_content: String,
+    pub revisions: Vec<MessageRevision>,
+}
 /
-/
-pub async fn delete_conversation(
+pub async fn get_message_history(
     State(state): State<UnifiedAppState>,
-    Path(session_id): Path<String>,
-) -> Result<Json<Value>, Response> {
-    info!("Deleting conversation: {}", session_id);
+    Path((session_id, message_id)): Path<(String, i64)>,
+    tenant: Option<Extension<AuthenticatedTenant>>,
+) -> Result<Json<MessageHistoryResponse>, ApiError> {
+    debug!("Fetching history for message {} in conversation {}", message_id, session_id);
+    authorize_session(&state.shared_state.database_pool, &session_id, tenant.as_deref())?;
 
     let orchestrator_lock = state.context_orchestrator.read().await;
 
     if let Some(ref orchestrator) = *orchestrator_lock {
-        match orchestrator.database().conversations.delete_session(&session_id) {
-            Ok(deleted_count) => {
-                if deleted_count == 0 {
-                    info!("Conversation not found for deletion: {}", session_id);
-                    Err((StatusCode::NOT_FOUND, format!("Conversation not found: {}", session_id)).into_response())
-                } else {
-                    info!("Successfully deleted conversation: {}", session_id);
-                    Ok(Json(serde_json::json!({
-                        "success": true,
-                        "id": session_id
-                    })))
-                }
+        let conversations = &orchestrator.database().conversations;
+        let message = match conversations.get_message(message_id) {
+            Ok(Some(message)) if message.session_id == session_id => message,
+            Ok(_) => {
+                return Err(ApiError::new(StatusCode::NOT_FOUND, format!("Message {} not found in conversation {}", message_id, session_id)));
             }
             Err(e) => {
-                error!("Failed to delete conversation: {}", e);
-
-                Err((StatusCode::INTERNAL_SERVER_ERROR, format!("Database error: {}", e)).into_response())
+                error!("Failed to fetch message: {}", e);
+                return Err(ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, format!("Database error: {}", e)));
+            }
+        };
+        match conversations.get_message_history(message_id) {
+            Ok(revisions) => Ok(Json(MessageHistoryResponse {
+                message_id,
+                current_content: message.content,
+                revisions,
+            })),
+            Err(e) => {
+                error!("Failed to fetch message history: {}", e);
+                Err(ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, format!("Database error: {}", e)))
             }
         }
     } else {
         error!("Context orchestrator not initialized");
-        Err((StatusCode::SERVICE_UNAVAILABLE, "Memory system not available").into_response())
+        Err(ApiError::new(StatusCode::SERVICE_UNAVAILABLE, "Memory system not available"))
+    }
+}
+/
+#[derive(Debug, Deserialize)]
+pub struct EditMessageRequest {
+    pub content: String,
+}
+/
+/
+pub async fn edit_message(
+    State(state): State<UnifiedAppState>,
+    Path((session_id, message_id)): Path<(String, i64)>,
+    tenant: Option<Extension<AuthenticatedTenant>>,
+    Json(req): Json<EditMessageRequest>,
+) -> Result<Json<MessageHistoryResponse>, ApiError> {
+    authorize_session(&state.shared_state.database_pool, &session_id, tenant.as_deref())?;
+    if req.content.trim().is_empty() {
+        return Err(ApiError::new(StatusCode::BAD_REQUEST, "Message content cannot be empty"));
+    }
+    let conversations = &state.shared_state.database_pool.conversations;
+    let db_error = |e: anyhow::Error| {
+        error!("Failed to edit message {}: {}", message_id, e);
+        ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, format!("Database error: {}", e))
+    };
+    match conversations.get_message(message_id).map_err(db_error)? {
+        Some(message) if message.session_id == session_id => {}
+        _ => return Err(ApiError::new(StatusCode::NOT_FOUND, format!("Message {} not found in conversation {}", message_id, session_id))),
+    }
+    let tokens = crate::utils::TextUtils::estimate_tokens(&req.content) as i32;
+    state.database_worker.edit_message(message_id, &req.content, tokens).await.map_err(db_error)?;
+    // Cached context still holds the old text, so the next turn rebuilds it from the database.
+    if let Some(ref orchestrator) = *state.context_orchestrator.read().await {
+        orchestrator.tier_manager().read().await.evict_session(&session_id);
     }
+    state.shared_state.clear_session_state(&session_id);
+    info!("Edited message {} in conversation {}", message_id, session_id);
+
+    Ok(Json(MessageHistoryResponse {
+        message_id,
+        current_content: req.content,
+        revisions: conversations.get_message_history(message_id).map_err(db_error)?,
+    }))
 }
 /
 #[derive(Debug, Deserialize)]
@@ -213,14 +424,16 @@ pub struct UpdatePinnedRequest {
 pub async fn update_conversation_pinned(
     State(state): State<UnifiedAppState>,
     Path(session_id): Path<String>,
+    tenant: Option<Extension<AuthenticatedTenant>>,
     Json(req): Json<UpdatePinnedRequest>,
-) -> Result<Json<Value>, Response> {
+) -> Result<Json<Value>, ApiError> {
+    authorize_session(&state.shared_state.database_pool, &session_id, tenant.as_deref())?;
     info!("Updating pinned status for conversation: {} to {}", session_id, req.pinned);
 
     let orchestrator_lock = state.context_orchestrator.read().await;
 
-    if let Some(ref orchestrator) = *orchestrator_lock {
-        match orchestrator.database().conversations.update_session_pinned(&session_id, req.pinned) {
+    if orchestrator_lock.is_some() {
+        match state.database_worker.update_conversation_pinned(&session_id, req.pinned).await {
             Ok(_) => {
                 info!("Successfully updated pinned status for conversation: {}", session_id);
                 Ok(Json(serde_json::json!({
@@ -234,17 +447,298 @@ pub async fn update_conversation_pinned(
 
                 if error_msg.contains("not found") {
                     error!("Conversation not found: {}", session_id);
-                    Err((StatusCode::NOT_FOUND, format!("Conversation not found: {}", session_id)).into_response())
+                    Err(ApiError::new(StatusCode::NOT_FOUND, format!("Conversation not found: {}", session_id)))
                 } else {
                     error!("Failed to update conversation pinned status: {}", e);
-                    Err((StatusCode::INTERNAL_SERVER_ERROR, format!("Database error: {}", e)).into_response())
+                    Err(ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, format!("Database error: {}", e)))
                 }
             }
         }
     } else {
         error!("Context orchestrator not initialized");
-        Err((StatusCode::SERVICE_UNAVAILABLE, "Memory system not available").into_response())
+        Err(ApiError::new(StatusCode::SERVICE_UNAVAILABLE, "Memory system not available"))
     }
 }
+/
+#[derive(Debug, Deserialize)]
+pub struct UpdateFolderRequest {
+    pub folder: Option<String>,
+}
+/
+pub async fn update_conversation_folder(
+    State(state): State<UnifiedAppState>,
+    Path(session_id): Path<String>,
+    tenant: Option<Extension<AuthenticatedTenant>>,
+    Json(req): Json<UpdateFolderRequest>,
+) -> Result<Json<Value>, ApiError> {
+    authorize_session(&state.shared_state.database_pool, &session_id, tenant.as_deref())?;
+    let folder = req.folder.map(|f| f.trim().to_string()).filter(|f| !f.is_empty());
+    if folder.as_ref().is_some_and(|f| f.len() > 128) {
+        return Err(ApiError::new(StatusCode::BAD_REQUEST, "Folder name too long (max 128 chars)"));
+    }
+    info!("Moving conversation {} to folder {:?}", session_id, folder);
+
+    let orchestrator_lock = state.context_orchestrator.read().await;
+
+    if orchestrator_lock.is_some() {
+        match state.database_worker.update_conversation_folder(&session_id, folder.as_deref()).await {
+            Ok(_) => Ok(Json(serde_json::json!({
+                "success": true,
+                "id": session_id,
+                "folder": folder
+            }))),
+            Err(e) => {
+                let error_msg = e.to_string();
+
+                if error_msg.contains("not found") {
+                    error!("Conversation not found: {}", session_id);
+                    Err(ApiError::new(StatusCode::NOT_FOUND, format!("Conversation not found: {}", session_id)))
+                } else {
+                    error!("Failed to update conversation folder: {}", e);
+                    Err(ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, format!("Database error: {}", e)))
+                }
+            }
+        }
+    } else {
+        error!("Context orchestrator not initialized");
+        Err(ApiError::new(StatusCode::SERVICE_UNAVAILABLE, "Memory system not available"))
+    }
+}
+/
+#[derive(Debug, Serialize)]
+pub struct FolderSummary {
+    pub name: String,
+    pub conversation_count: usize,
+}
+/
+pub async fn list_folders(
+    State(state): State<UnifiedAppState>,
+    tenant: Option<Extension<AuthenticatedTenant>>,
+) -> Result<Json<Value>, ApiError> {
+    let owner = tenant.as_deref().map(|AuthenticatedTenant(t)| t.as_str());
+    match state.shared_state.database_pool.conversations.list_folders(owner) {
+        Ok(folders) => {
+            let folders: Vec<FolderSummary> = folders
+                .into_iter()
+                .map(|(name, conversation_count)| FolderSummary { name, conversation_count })
+                .collect();
+            Ok(Json(serde_json::json!({ "folders": folders })))
+        }
+        Err(e) => {
+            error!("Failed to list folders: {}", e);
+            Err(ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, format!("Database error: {}", e)))
+        }
+    }
+}
+/
+#[derive(Debug, Deserialize)]
+pub struct SetMetadataRequest {
+    pub key: String,
+    pub value: String,
+}
+/
+pub async fn set_conversation_metadata(
+    State(state): State<UnifiedAppState>,
+    Path(session_id): Path<String>,
+    tenant: Option<Extension<AuthenticatedTenant>>,
+    Json(req): Json<SetMetadataRequest>,
+) -> Result<Json<Value>, ApiError> {
+    authorize_session(&state.shared_state.database_pool, &session_id, tenant.as_deref())?;
+    if req.key.trim().is_empty() || req.key.len() > 128 {
+        return Err(ApiError::new(StatusCode::BAD_REQUEST, "Metadata key must be 1-128 characters"));
+    }
+    if req.value.len() > 4_096 {
+        return Err(ApiError::new(StatusCode::BAD_REQUEST, "Metadata value too long (max 4KB)"));
+    }
+
+    let orchestrator_lock = state.context_orchestrator.read().await;
+
+    if orchestrator_lock.is_some() {
+        match state.database_worker.set_conversation_metadata(&session_id, &req.key, &req.value).await {
+            Ok(metadata) => Ok(Json(serde_json::json!({
+                "success": true,
+                "id": session_id,
+                "metadata": metadata.user_defined
+            }))),
+            Err(e) => {
+                let error_msg = e.to_string();
+
+                if error_msg.contains("not found") {
+                    error!("Conversation not found: {}", session_id);
+                    Err(ApiError::new(StatusCode::NOT_FOUND, format!("Conversation not found: {}", session_id)))
+                } else {
+                    error!("Failed to update conversation metadata: {}", e);
+                    Err(ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, format!("Database error: {}", e)))
+                }
+            }
+        }
+    } else {
+        error!("Context orchestrator not initialized");
+        Err(ApiError::new(StatusCode::SERVICE_UNAVAILABLE, "Memory system not available"))
+    }
+}
+/
+#[derive(Debug, Deserialize)]
+pub struct ImportConversationRequest {
+    #[serde(flatten)]
+    pub export: SessionExport,
+    #[serde(default)]
+    pub remap_id: bool,
+    #[serde(default)]
+    pub regenerate_embeddings: bool,
+}
+/
+pub async fn import_conversation(
+    State(state): State<UnifiedAppState>,
+    tenant: Option<Extension<AuthenticatedTenant>>,
+    Json(mut req): Json<ImportConversationRequest>,
+) -> Result<Json<Value>, ApiError> {
+    info!("Importing conversation: {} ({} messages)", req.export.session.id, req.export.messages.len());
+    // The exported owner is not trusted: the import belongs to the caller, or to no one for operator keys.
+    req.export.session.metadata.user_id = tenant.map(|Extension(AuthenticatedTenant(tenant))| tenant);
 
+    let message_count = req.export.messages.len();
+    let session_id = match state.database_worker.import_conversation(req.export, req.remap_id).await {
+        Ok(id) => id,
+        Err(e) => {
+            let error_msg = e.to_string();
+            if error_msg.contains("already exists") {
+                return Err(ApiError::new(StatusCode::CONFLICT, error_msg));
+            }
+            error!("Failed to import conversation: {}", e);
+            return Err(ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, format!("Database error: {}", e)));
+        }
+    };
 
+    if req.regenerate_embeddings {
+        let database = state.shared_state.database_pool.clone();
+        let llm_worker = state.llm_worker.clone();
+        let session_id = session_id.clone();
+        tokio::spawn(async move {
+            let reader = database.clone();
+            let owned_session_id = session_id.clone();
+            let messages = tokio::task::spawn_blocking(move || {
+                reader.conversations.get_session_messages(&owned_session_id, None, None)
+            })
+            .await
+            .map_err(|e| anyhow::anyhow!("Database task failed: {}", e))
+            .and_then(|result| result);
+            let messages = match messages {
+                Ok(messages) if !messages.is_empty() => messages,
+                Ok(_) => return,
+                Err(e) => {
+                    debug!("Skipping embedding regeneration for {}: {}", session_id, e);
+                    return;
+                }
+            };
+            let messages = messages.into_iter().map(|m| (m.id, m.content)).collect();
+            match embed_messages(&llm_worker, &database, messages).await {
+                Ok(count) => debug!("Regenerated {} embeddings for imported session {}", count, session_id),
+                Err(e) => {
+                    debug!("Embedding regeneration skipped for imported session {}: {}", session_id, e);
+                }
+            }
+        });
+    }
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "id": session_id,
+        "messages": message_count,
+    })))
+}
+/
+#[derive(Debug, Deserialize)]
+pub struct ForkConversationRequest {
+    pub up_to_message_id: i64,
+}
+/
+/
+pub async fn fork_conversation(
+    State(state): State<UnifiedAppState>,
+    Path(session_id): Path<String>,
+    tenant: Option<Extension<AuthenticatedTenant>>,
+    Json(req): Json<ForkConversationRequest>,
+) -> Result<Json<Value>, ApiError> {
+    authorize_session(&state.shared_state.database_pool, &session_id, tenant.as_deref())?;
+    info!("Forking conversation {} at message {}", session_id, req.up_to_message_id);
+
+    match state.database_worker.fork_conversation(&session_id, req.up_to_message_id).await {
+        Ok(Some(fork_id)) => Ok(Json(serde_json::json!({
+            "success": true,
+            "id": fork_id,
+            "forked_from": session_id,
+        }))),
+        Ok(None) => Err(ApiError::new(
+            StatusCode::NOT_FOUND,
+            format!("Message {} not found in conversation {}", req.up_to_message_id, session_id),
+        )),
+        Err(e) => {
+            error!("Failed to fork conversation {}: {}", session_id, e);
+            Err(ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, format!("Database error: {}", e)))
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory_db::MemoryDatabase;
+    use crate::shared_state::SharedState;
+    use crate::worker_threads::DatabaseWorker;
+    use std::sync::Arc;
+    #[tokio::test]
+    async fn test_edit_message_records_a_revision_for_the_owner_only() {
+        let database = Arc::new(MemoryDatabase::new_in_memory().unwrap());
+        let shared_state = Arc::new(SharedState::new(crate::config::tests::create_test_config(), database.clone()).unwrap());
+        let state = UnifiedAppState::new(shared_state.clone(), Arc::new(DatabaseWorker::new(shared_state)));
+        let metadata = SessionMetadata { user_id: Some("team-a".to_string()), ..Default::default() };
+        let session = database.conversations.create_session(Some(metadata)).unwrap();
+        let other = database.conversations.create_session(None).unwrap();
+        let stored = database.conversations.append_messages(&session.id, &[
+            ("user".to_string(), "first draft".to_string(), 2, 0.5),
+        ]).unwrap();
+        let id = stored[0].id;
+        let tenant = |t: &str| Some(Extension(AuthenticatedTenant(t.to_string())));
+        let body = |text: &str| Json(EditMessageRequest { content: text.to_string() });
+
+        let Json(edited) = edit_message(State(state.clone()), Path((session.id.clone(), id)), tenant("team-a"), body("final text"))
+            .await
+            .unwrap();
+        assert_eq!(edited.current_content, "final text");
+        assert_eq!(edited.revisions.iter().map(|r| r.content.as_str()).collect::<Vec<_>>(), vec!["first draft"]);
+        assert_eq!(database.conversations.get_message(id).unwrap().unwrap().content, "final text");
+
+        let foreign = edit_message(State(state.clone()), Path((session.id.clone(), id)), tenant("team-b"), body("hijacked"))
+            .await
+            .unwrap_err();
+        assert_eq!(foreign.status, StatusCode::NOT_FOUND);
+        let wrong_session = edit_message(State(state.clone()), Path((other.id, id)), None, body("moved"))
+            .await
+            .unwrap_err();
+        assert_eq!(wrong_session.status, StatusCode::NOT_FOUND);
+        let empty = edit_message(State(state), Path((session.id, id)), None, body("  ")).await.unwrap_err();
+        assert_eq!(empty.status, StatusCode::BAD_REQUEST);
+        assert_eq!(database.conversations.get_message(id).unwrap().unwrap().content, "final text");
+    }
+    #[tokio::test]
+    async fn test_imported_sessions_belong_to_the_caller_not_the_export() {
+        let database = Arc::new(MemoryDatabase::new_in_memory().unwrap());
+        let shared_state = Arc::new(SharedState::new(crate::config::tests::create_test_config(), database.clone()).unwrap());
+        let state = UnifiedAppState::new(shared_state.clone(), Arc::new(DatabaseWorker::new(shared_state)));
+        let metadata = SessionMetadata { user_id: Some("team-a".to_string()), ..Default::default() };
+        let source = database.conversations.create_session(Some(metadata)).unwrap();
+        database.conversations.append_messages(&source.id, &[("user".to_string(), "hello".to_string(), 1, 0.5)]).unwrap();
+        let export = database.conversations.export_session(&source.id).unwrap().unwrap();
+        let import = || Json(ImportConversationRequest { export: export.clone(), remap_id: true, regenerate_embeddings: false });
+
+        let Json(by_tenant) = import_conversation(State(state.clone()), Some(Extension(AuthenticatedTenant("team-b".to_string()))), import())
+            .await
+            .unwrap();
+        let imported = database.conversations.get_session(by_tenant["id"].as_str().unwrap()).unwrap().unwrap();
+        assert_eq!(imported.metadata.user_id.as_deref(), Some("team-b"));
+
+        let Json(by_operator) = import_conversation(State(state), None, import()).await.unwrap();
+        let imported = database.conversations.get_session(by_operator["id"].as_str().unwrap()).unwrap().unwrap();
+        assert_eq!(imported.metadata.user_id, None);
+    }
+}
@@ -0,0 +1,117 @@
+﻿use axum::{
+    extract::{State, Json},
+    http::StatusCode,
+};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+use crate::shared_state::UnifiedAppState;
+use crate::api::error::ApiError;
+use crate::utils::TextUtils;
+const MAX_EMBEDDING_INPUTS: usize = 256;
+const MAX_EMBEDDING_INPUT_CHARS: usize = 32_768;
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum EmbeddingInput {
+    Single(String),
+    Batch(Vec<String>),
+}
+impl EmbeddingInput {
+    fn into_texts(self) -> Vec<String> {
+        match self {
+            EmbeddingInput::Single(text) => vec![text],
+            EmbeddingInput::Batch(texts) => texts,
+        }
+    }
+}
+#[derive(Debug, Deserialize)]
+pub struct EmbeddingsRequest {
+    pub input: EmbeddingInput,
+    #[serde(default)]
+    pub model: Option<String>,
+}
+#[derive(Debug, Serialize)]
+pub struct EmbeddingData {
+    pub object: &'static str,
+    pub embedding: Vec<f32>,
+    pub index: usize,
+}
+#[derive(Debug, Serialize)]
+pub struct EmbeddingsUsage {
+    pub prompt_tokens: usize,
+    pub total_tokens: usize,
+}
+#[derive(Debug, Serialize)]
+pub struct EmbeddingsResponse {
+    pub object: &'static str,
+    pub data: Vec<EmbeddingData>,
+    pub model: String,
+    pub usage: EmbeddingsUsage,
+}
+fn bad_request(message: String) -> ApiError {
+    ApiError::new(StatusCode::BAD_REQUEST, message)
+}
+/
+/
+pub async fn create_embeddings(
+    State(state): State<UnifiedAppState>,
+    Json(req): Json<EmbeddingsRequest>,
+) -> Result<Json<EmbeddingsResponse>, ApiError> {
+    let model = req.model.unwrap_or_else(|| "local-llm".to_string());
+    let texts = req.input.into_texts();
+    if texts.is_empty() {
+        return Err(bad_request("input must contain at least one string".to_string()));
+    }
+    if texts.len() > MAX_EMBEDDING_INPUTS {
+        return Err(bad_request(format!(
+            "Too many inputs ({}, max {})",
+            texts.len(),
+            MAX_EMBEDDING_INPUTS
+        )));
+    }
+    for (idx, text) in texts.iter().enumerate() {
+        if text.trim().is_empty() {
+            return Err(bad_request(format!("input[{}] is empty", idx)));
+        }
+        if text.len() > MAX_EMBEDDING_INPUT_CHARS {
+            return Err(bad_request(format!(
+                "input[{}] too long ({} chars, max {})",
+                idx,
+                text.len(),
+                MAX_EMBEDDING_INPUT_CHARS
+            )));
+        }
+    }
+    let prompt_tokens: usize = texts.iter().map(|t| TextUtils::estimate_tokens(t)).sum();
+    let input_count = texts.len();
+    debug!("Embedding request: {} input(s), ~{} tokens", input_count, prompt_tokens);
+
+    let embeddings = state.llm_worker.generate_embeddings(texts).await.map_err(|e| {
+        warn!("Embedding generation failed: {}", e);
+        ApiError::new(e.status_code(), format!("Embedding generation failed: {}", e))
+    })?;
+    if embeddings.len() != input_count {
+        return Err(ApiError::new(
+            StatusCode::BAD_GATEWAY,
+            format!(
+                "Backend returned {} embeddings for {} inputs",
+                embeddings.len(),
+                input_count
+            ),
+        ));
+    }
+
+    let data = embeddings
+        .into_iter()
+        .enumerate()
+        .map(|(index, embedding)| EmbeddingData { object: "embedding", embedding, index })
+        .collect();
+    Ok(Json(EmbeddingsResponse {
+        object: "list",
+        data,
+        model,
+        usage: EmbeddingsUsage {
+            prompt_tokens,
+            total_tokens: prompt_tokens,
+        },
+    }))
+}
@@ -0,0 +1,42 @@
+﻿use axum::{
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use serde_json::json;
+/
+/
+#[derive(Debug)]
+pub struct ApiError {
+    pub status: StatusCode,
+    pub message: String,
+}
+impl ApiError {
+    pub fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        Self { status, message: message.into() }
+    }
+}
+impl IntoResponse for ApiError {
+    fn into_response(self) -> axum::response::Response {
+        (
+            self.status,
+            Json(json!({
+                "error": self.message,
+                "code": self.status.as_u16(),
+            })),
+        )
+            .into_response()
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[tokio::test]
+    async fn test_api_error_body_carries_message_and_code() {
+        let response = ApiError::new(StatusCode::NOT_FOUND, "Conversation not found").into_response();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(body, json!({ "error": "Conversation not found", "code": 404 }));
+    }
+}
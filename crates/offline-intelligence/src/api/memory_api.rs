@@ -2,7 +2,7 @@
     extract::{Path, State},
     http::StatusCode,
     response::IntoResponse,
-    Json,
+    Extension, Json,
 };
 use serde_json::json;
 use std::sync::Arc;
@@ -10,24 +10,8 @@ use tracing::{info, warn, error};
 use serde::{Deserialize, Serialize};
 use crate::shared_state::SharedState;
 use crate::metrics;
-/
-#[derive(Debug)]
-pub struct ApiError {
-    pub status: StatusCode,
-    pub message: String,
-}
-impl IntoResponse for ApiError {
-    fn into_response(self) -> axum::response::Response {
-        (
-            self.status,
-            Json(json!({
-                "error": self.message,
-                "code": self.status.as_u16(),
-            })),
-        )
-            .into_response()
-    }
-}
+use crate::api::error::ApiError;
+use crate::api::auth::{authorize_session, AuthenticatedTenant};
 /
 fn validate_session_id(session_id: &str) -> Result<(), ApiError> {
     if session_id.is_empty() {
@@ -68,7 +52,7 @@ fn validate_messages(messages: &[crate::memory::Message]) -> Result<(), ApiError
         });
     }
     for (idx, msg) in messages.iter().enumerate() {
-        if msg.role.is_empty() || msg.content.is_empty() {
+        if msg.role.as_str().trim().is_empty() || (msg.content.is_empty() && msg.parts.is_none()) {
             return Err(ApiError {
                 status: StatusCode::BAD_REQUEST,
                 message: format!("Message {} has empty role or content", idx + 1),
@@ -110,13 +94,168 @@ pub struct CleanupStats {
     pub memory_freed_bytes: Option<usize>,
 }
 /
+const MAX_OPTIMIZE_BATCH: usize = 100;
+/
+const OPTIMIZE_BATCH_CONCURRENCY: usize = 4;
+/
+fn validate_optimize_request(payload: &MemoryOptimizeRequest) -> Result<(), ApiError> {
+    validate_session_id(&payload.session_id)?;
+    validate_messages(&payload.messages)?;
+    if let Some(ref query) = payload.user_query {
+        if query.len() > 8_192 {
+            return Err(ApiError {
+                status: StatusCode::BAD_REQUEST,
+                message: "User query too long (max 8KB)".to_string(),
+            });
+        }
+    }
+
+    if payload.max_context_tokens == Some(0) {
+        return Err(ApiError {
+            status: StatusCode::BAD_REQUEST,
+            message: "max_context_tokens must be positive".to_string(),
+        });
+    }
+    Ok(())
+}
+/
+async fn optimize_one(
+    shared_state: &SharedState,
+    orchestrator: &crate::context_engine::ContextOrchestrator,
+    payload: &MemoryOptimizeRequest,
+) -> Result<serde_json::Value, ApiError> {
+    let budget = payload.max_context_tokens.map(|tokens| shared_state.clamp_context_tokens(tokens));
+    match orchestrator
+        .process_conversation_with_budget(
+            &payload.session_id,
+            &payload.messages,
+            payload.user_query.as_deref(),
+            budget,
+        )
+        .await
+    {
+        Ok(optimized) => {
+            metrics::inc_request("memory_optimize", "ok");
+            let original_len: usize = payload.messages.len();
+            let optimized_len: usize = optimized.len();
+            Ok(json!({
+                "optimized_messages": optimized,
+                "original_count": original_len,
+                "optimized_count": optimized_len,
+                "compression_ratio": if original_len > 0 {
+                    (original_len as f32 - optimized_len as f32) / original_len as f32
+                } else {
+                    0.0
+                }
+            }))
+        }
+        Err(e) => {
+            metrics::inc_request("memory_optimize", "error");
+            warn!(
+                "Optimization failed for session {}: {}",
+                payload.session_id,
+                e
+            );
+            Err(ApiError {
+                status: StatusCode::INTERNAL_SERVER_ERROR,
+                message: format!("Optimization failed: {}", e),
+            })
+        }
+    }
+}
+/
 pub async fn memory_optimize(
     State(shared_state): State<Arc<SharedState>>,
+    tenant: Option<Extension<AuthenticatedTenant>>,
     Json(payload): Json<MemoryOptimizeRequest>,
 ) -> Result<impl IntoResponse, ApiError> {
+    validate_optimize_request(&payload)?;
+    authorize_session(&shared_state.database_pool, &payload.session_id, tenant.as_deref())?;
 
+    let orchestrator_guard = shared_state.context_orchestrator.read().await;
+    if let Some(orchestrator) = &*orchestrator_guard {
+        let response = optimize_one(&shared_state, orchestrator, &payload).await?;
+        Ok((StatusCode::OK, Json(response)))
+    } else {
+        metrics::inc_request("memory_optimize", "disabled");
+        Err(ApiError {
+            status: StatusCode::SERVICE_UNAVAILABLE,
+            message: "Memory system not available".to_string(),
+        })
+    }
+}
+/
+/
+/
+pub async fn memory_optimize_batch(
+    State(shared_state): State<Arc<SharedState>>,
+    tenant: Option<Extension<AuthenticatedTenant>>,
+    Json(items): Json<Vec<MemoryOptimizeRequest>>,
+) -> Result<impl IntoResponse, ApiError> {
+    use futures_util::StreamExt;
+    if items.is_empty() || items.len() > MAX_OPTIMIZE_BATCH {
+        return Err(ApiError {
+            status: StatusCode::BAD_REQUEST,
+            message: format!("Batch must contain between 1 and {} sessions", MAX_OPTIMIZE_BATCH),
+        });
+    }
+
+    // Take a cheap handle and release the lock so config reloads are not blocked for the whole batch.
+    let orchestrator = shared_state.context_orchestrator.read().await.clone();
+    let Some(orchestrator) = orchestrator else {
+        metrics::inc_request("memory_optimize", "disabled");
+        return Err(ApiError {
+            status: StatusCode::SERVICE_UNAVAILABLE,
+            message: "Memory system not available".to_string(),
+        });
+    };
+
+    let results: Vec<serde_json::Value> = futures_util::stream::iter(&items)
+        .map(|item| {
+            let shared_state = &shared_state;
+            let orchestrator = &orchestrator;
+            let tenant = tenant.as_deref();
+            async move {
+                let outcome = match validate_optimize_request(item)
+                    .and_then(|()| authorize_session(&shared_state.database_pool, &item.session_id, tenant))
+                {
+                    Ok(()) => optimize_one(shared_state, orchestrator, item).await,
+                    Err(e) => Err(e),
+                };
+                match outcome {
+                    Ok(mut result) => {
+                        result["session_id"] = json!(item.session_id);
+                        result
+                    }
+                    Err(e) => json!({
+                        "session_id": item.session_id,
+                        "error": e.message,
+                        "status": e.status.as_u16(),
+                    }),
+                }
+            }
+        })
+        .buffered(OPTIMIZE_BATCH_CONCURRENCY)
+        .collect()
+        .await;
+
+    let failed = results.iter().filter(|r| r.get("error").is_some()).count();
+    info!("Batch optimization processed {} session(s), {} failed", results.len(), failed);
+    Ok((StatusCode::OK, Json(json!({
+        "results": results,
+        "succeeded": results.len() - failed,
+        "failed": failed,
+    }))))
+}
+/
+pub async fn memory_plan(
+    State(shared_state): State<Arc<SharedState>>,
+    tenant: Option<Extension<AuthenticatedTenant>>,
+    Json(payload): Json<MemoryPlanRequest>,
+) -> Result<impl IntoResponse, ApiError> {
     validate_session_id(&payload.session_id)?;
     validate_messages(&payload.messages)?;
+    authorize_session(&shared_state.database_pool, &payload.session_id, tenant.as_deref())?;
     if let Some(ref query) = payload.user_query {
         if query.len() > 8_192 {
             return Err(ApiError {
@@ -126,47 +265,27 @@ pub async fn memory_optimize(
         }
     }
 
-    let mut orchestrator_guard = shared_state.context_orchestrator.write().await;
-    if let Some(orchestrator) = &mut *orchestrator_guard {
+    let orchestrator_guard = shared_state.context_orchestrator.read().await;
+    if let Some(orchestrator) = &*orchestrator_guard {
         match orchestrator
-            .process_conversation(
-                &payload.session_id,
-                &payload.messages,
-                payload.user_query.as_deref(),
-            )
+            .plan_only(&payload.session_id, &payload.messages, payload.user_query.as_deref())
             .await
         {
-            Ok(optimized) => {
-                metrics::inc_request("memory_optimize", "ok");
-                let original_len: usize = payload.messages.len();
-                let optimized_len: usize = optimized.len();
-                let response = json!({
-                    "optimized_messages": optimized,
-                    "original_count": original_len,
-                    "optimized_count": optimized_len,
-                    "compression_ratio": if original_len > 0 {
-                        (original_len as f32 - optimized_len as f32) / original_len as f32
-                    } else {
-                        0.0
-                    }
-                });
-                Ok((StatusCode::OK, Json(response)))
+            Ok(plan) => {
+                metrics::inc_request("memory_plan", "ok");
+                Ok((StatusCode::OK, Json(plan)))
             }
             Err(e) => {
-                metrics::inc_request("memory_optimize", "error");
-                warn!(
-                    "Optimization failed for session {}: {}",
-                    payload.session_id,
-                    e
-                );
+                metrics::inc_request("memory_plan", "error");
+                warn!("Planning failed for session {}: {}", payload.session_id, e);
                 Err(ApiError {
                     status: StatusCode::INTERNAL_SERVER_ERROR,
-                    message: format!("Optimization failed: {}", e),
+                    message: format!("Planning failed: {}", e),
                 })
             }
         }
     } else {
-        metrics::inc_request("memory_optimize", "disabled");
+        metrics::inc_request("memory_plan", "disabled");
         Err(ApiError {
             status: StatusCode::SERVICE_UNAVAILABLE,
             message: "Memory system not available".to_string(),
@@ -268,9 +387,56 @@ pub struct MemoryOptimizeRequest {
     pub session_id: String,
     pub messages: Vec<crate::memory::Message>,
     pub user_query: Option<String>,
+    #[serde(default)]
+    pub max_context_tokens: Option<usize>,
+}
+#[derive(Debug, Deserialize)]
+pub struct MemoryPlanRequest {
+    pub session_id: String,
+    pub messages: Vec<crate::memory::Message>,
+    pub user_query: Option<String>,
 }
 #[derive(Debug, Deserialize)]
 pub struct MemoryCleanupRequest {
     pub older_than_seconds: u64,
 }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::tests::create_test_config;
+    use crate::context_engine::{ContextOrchestrator, OrchestratorConfig};
+    use crate::memory::{Message, Role};
+    use crate::memory_db::MemoryDatabase;
 
+    #[tokio::test]
+    async fn test_batch_optimize_reports_per_item_failures() {
+        let database = Arc::new(MemoryDatabase::new_in_memory().unwrap());
+        let shared_state = Arc::new(SharedState::new(create_test_config(), database.clone()).unwrap());
+        let orchestrator = ContextOrchestrator::new(database, OrchestratorConfig::default()).await.unwrap();
+        *shared_state.context_orchestrator.write().await = Some(orchestrator);
+
+        let item = |session_id: &str, content: &str| MemoryOptimizeRequest {
+            session_id: session_id.to_string(),
+            messages: vec![Message { role: Role::User, content: content.to_string(), parts: None }],
+            user_query: None,
+            max_context_tokens: None,
+        };
+        let batch = vec![item("batch-a", "hello"), item("bad id!", "hello"), item("batch-b", "")];
+        let response = memory_optimize_batch(State(shared_state), None, Json(batch))
+            .await
+            .unwrap()
+            .into_response();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!((body["succeeded"].as_u64(), body["failed"].as_u64()), (Some(1), Some(2)));
+        let results = body["results"].as_array().unwrap();
+        assert_eq!(results[0]["session_id"], "batch-a");
+        assert!(results[0].get("error").is_none());
+        assert!(results[0]["optimized_messages"].is_array());
+        assert_eq!(results[1]["status"], 400);
+        assert_eq!(results[2]["session_id"], "batch-b");
+        assert!(results[2]["error"].as_str().unwrap().contains("empty role or content"));
+    }
+}
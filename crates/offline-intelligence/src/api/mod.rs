@@ -1,12 +1,26 @@
 ﻿
 //! API module - External interfaces for the memory system
+pub mod error;
 pub mod memory_api;
 pub mod search_api;
 pub mod admin_api;
 pub mod title_api;
 pub mod conversation_api;
 pub mod stream_api;
-pub use memory_api::{memory_optimize, memory_stats, memory_cleanup};
+pub mod embeddings_api;
+pub mod models_api;
+#[cfg(feature = "websocket")]
+pub mod ws_api;
+pub mod rate_limit;
+pub mod auth;
+pub use error::ApiError;
+pub use memory_api::{memory_optimize, memory_plan, memory_stats, memory_cleanup};
 pub use title_api::{generate_title, GenerateTitleRequest, GenerateTitleResponse};
-pub use conversation_api::{get_conversations, get_conversation, update_conversation_title, delete_conversation, update_conversation_pinned};
-pub use stream_api::generate_stream;
+pub use conversation_api::{get_conversations, get_conversation, update_conversation_title, delete_conversation, update_conversation_pinned, import_conversation, get_message_history, edit_message};
+pub use stream_api::{generate_stream, stop_generation};
+pub use embeddings_api::create_embeddings;
+pub use models_api::list_models;
+#[cfg(feature = "websocket")]
+pub use ws_api::generate_ws;
+pub use rate_limit::ClientRateLimiter;
+pub use auth::{ApiKeyAuth, AuthenticatedTenant};
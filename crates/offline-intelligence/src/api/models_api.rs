@@ -0,0 +1,51 @@
+﻿use axum::{extract::State, Json};
+use serde::Serialize;
+use std::sync::Arc;
+use crate::shared_state::SharedState;
+use crate::model_runtime::{ModelFormat, RegisteredModel};
+/
+#[derive(Debug, Serialize)]
+pub struct ModelObject {
+    pub id: String,
+    pub object: &'static str,
+    pub created: i64,
+    pub owned_by: &'static str,
+    pub format: ModelFormat,
+    pub runtime: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub context_length: Option<u32>,
+    pub ready: bool,
+}
+impl From<RegisteredModel> for ModelObject {
+    fn from(model: RegisteredModel) -> Self {
+        Self {
+            id: model.id,
+            object: "model",
+            // Local models have no publish date; OpenAI clients only need the field present.
+            created: 0,
+            owned_by: "local",
+            format: model.format,
+            runtime: model.runtime_name,
+            context_length: model.context_length,
+            ready: model.ready,
+        }
+    }
+}
+/
+#[derive(Debug, Serialize)]
+pub struct ModelList {
+    pub object: &'static str,
+    pub data: Vec<ModelObject>,
+}
+/
+/
+pub async fn list_models(
+    State(shared_state): State<Arc<SharedState>>,
+) -> Json<ModelList> {
+    let runtime_manager = shared_state.runtime_manager.read().ok().and_then(|guard| guard.clone());
+    let data = match runtime_manager {
+        Some(manager) => manager.registered_models().await.into_iter().map(ModelObject::from).collect(),
+        None => Vec::new(),
+    };
+    Json(ModelList { object: "list", data })
+}
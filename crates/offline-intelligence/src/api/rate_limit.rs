@@ -0,0 +1,202 @@
+﻿//! Per-client rate limiting for the inference-heavy endpoints
+//!
+//! Clients are keyed on the tenant of their API key, falling back to the peer IP address;
+//! nothing the client puts in the request body picks the key. Each key gets its own
+//! requests-per-second quota and its own cap on concurrently open responses, so one
+//! client cannot starve the single model backend. Idle keys are pruned periodically.
+use arc_swap::ArcSwap;
+use axum::{
+    body::Body,
+    extract::{ConnectInfo, Request, State},
+    http::{header, request::Parts, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use dashmap::DashMap;
+use futures_util::StreamExt;
+use governor::{
+    clock::{Clock, DefaultClock},
+    state::keyed::DefaultKeyedStateStore,
+    Quota, RateLimiter,
+};
+use std::net::SocketAddr;
+use std::num::NonZeroU32;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tracing::debug;
+use crate::api::auth::AuthenticatedTenant;
+use crate::config::Config;
+use crate::metrics;
+type KeyedLimiter = RateLimiter<String, DefaultKeyedStateStore<String>, DefaultClock>;
+/
+/
+#[derive(Clone)]
+pub struct StreamPermit {
+    _permit: Arc<OwnedSemaphorePermit>,
+}
+pub struct ClientRateLimiter {
+    limiter: ArcSwap<KeyedLimiter>,
+    clock: DefaultClock,
+    streams: DashMap<String, Arc<Semaphore>>,
+    max_concurrent_streams: usize,
+}
+impl ClientRateLimiter {
+    pub fn new(requests_per_second: u32, max_concurrent_streams: u32) -> Self {
+        Self {
+            limiter: ArcSwap::from_pointee(Self::keyed_limiter(requests_per_second)),
+            clock: DefaultClock::default(),
+            streams: DashMap::new(),
+            max_concurrent_streams: max_concurrent_streams.max(1) as usize,
+        }
+    }
+    pub fn from_config(cfg: &Config) -> Self {
+        Self::new(cfg.requests_per_second, cfg.max_concurrent_streams)
+    }
+    fn keyed_limiter(requests_per_second: u32) -> KeyedLimiter {
+        let rate = NonZeroU32::new(requests_per_second).unwrap_or(NonZeroU32::MIN);
+        RateLimiter::keyed(Quota::per_second(rate))
+    }
+    /
+    pub fn set_requests_per_second(&self, requests_per_second: u32) {
+        self.limiter.store(Arc::new(Self::keyed_limiter(requests_per_second)));
+    }
+    fn stream_slots(&self, key: &str) -> Arc<Semaphore> {
+        self.streams
+            .entry(key.to_string())
+            .or_insert_with(|| Arc::new(Semaphore::new(self.max_concurrent_streams)))
+            .clone()
+    }
+    /
+    pub fn prune(&self) {
+        let limiter = self.limiter.load();
+        limiter.retain_recent();
+        limiter.shrink_to_fit();
+        // The map holds one reference; every open response holds another through its permit.
+        self.streams.retain(|_, slots| Arc::strong_count(slots) > 1);
+        self.streams.shrink_to_fit();
+    }
+    /
+    pub fn spawn_pruning(self: Arc<Self>, every: Duration) {
+        tokio::spawn(async move {
+            let mut ticks = tokio::time::interval(every);
+            ticks.tick().await;
+            loop {
+                ticks.tick().await;
+                self.prune();
+            }
+        });
+    }
+}
+/
+pub async fn rate_limit(
+    State(limiter): State<Arc<ClientRateLimiter>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let (parts, body) = req.into_parts();
+    let key = client_key(&parts);
+
+    if let Err(not_until) = limiter.limiter.load().check_key(&key) {
+        let wait = not_until.wait_time_from(limiter.clock.now());
+        debug!("Rate limit exceeded for {}", key);
+        return too_many_requests(wait.as_secs_f64().ceil() as u64, "Rate limit exceeded");
+    }
+
+    let permit = match limiter.stream_slots(&key).try_acquire_owned() {
+        Ok(permit) => permit,
+        Err(_) => {
+            debug!("Concurrent stream limit reached for {}", key);
+            return too_many_requests(1, "Too many concurrent streams");
+        }
+    };
+
+    let permit = Arc::new(permit);
+    let mut req = Request::from_parts(parts, body);
+    req.extensions_mut().insert(StreamPermit { _permit: permit.clone() });
+    let response = next.run(req).await;
+
+
+    let (parts, body) = response.into_parts();
+    let body = Body::from_stream(body.into_data_stream().map(move |chunk| {
+        let _ = &permit;
+        chunk
+    }));
+    Response::from_parts(parts, body)
+}
+fn client_key(parts: &Parts) -> String {
+    if let Some(AuthenticatedTenant(tenant)) = parts.extensions.get::<AuthenticatedTenant>() {
+        return format!("tenant:{}", tenant);
+    }
+    match parts.extensions.get::<ConnectInfo<SocketAddr>>() {
+        Some(ConnectInfo(addr)) => format!("ip:{}", addr.ip()),
+        None => "anonymous".to_string(),
+    }
+}
+fn too_many_requests(retry_after_secs: u64, message: &str) -> Response {
+    metrics::inc_request("rate_limit", "rejected");
+    (
+        StatusCode::TOO_MANY_REQUESTS,
+        [(header::RETRY_AFTER, retry_after_secs.max(1).to_string())],
+        axum::Json(serde_json::json!({
+            "error": message,
+            "code": StatusCode::TOO_MANY_REQUESTS.as_u16(),
+        })),
+    )
+        .into_response()
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{routing::post, Router};
+    use tower::ServiceExt;
+    fn app(limiter: Arc<ClientRateLimiter>) -> Router {
+        Router::new()
+            .route("/", post(|| async { "ok" }))
+            .route_layer(axum::middleware::from_fn_with_state(limiter, rate_limit))
+    }
+    fn request(ip: [u8; 4], session_id: &str) -> Request {
+        let mut req = Request::post("/")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(format!("{{\"session_id\":\"{}\"}}", session_id)))
+            .unwrap();
+        req.extensions_mut().insert(ConnectInfo(SocketAddr::from((ip, 40000))));
+        req
+    }
+    #[tokio::test]
+    async fn test_rate_limit_is_per_client_not_per_session() {
+        let app = app(Arc::new(ClientRateLimiter::new(1, 4)));
+
+        let first = app.clone().oneshot(request([10, 0, 0, 1], "a")).await.unwrap();
+        assert_eq!(first.status(), StatusCode::OK);
+
+        // A fresh session_id does not buy a fresh quota.
+        let second = app.clone().oneshot(request([10, 0, 0, 1], "b")).await.unwrap();
+        assert_eq!(second.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert!(second.headers().contains_key(header::RETRY_AFTER));
+
+        let other = app.clone().oneshot(request([10, 0, 0, 2], "a")).await.unwrap();
+        assert_eq!(other.status(), StatusCode::OK);
+
+        let mut tenant = request([10, 0, 0, 1], "a");
+        tenant.extensions_mut().insert(AuthenticatedTenant("team-a".to_string()));
+        assert_eq!(app.oneshot(tenant).await.unwrap().status(), StatusCode::OK);
+    }
+    #[tokio::test]
+    async fn test_prune_drops_idle_stream_slots() {
+        let limiter = Arc::new(ClientRateLimiter::new(100, 4));
+        let app = app(limiter.clone());
+        for ip in 1..=3 {
+            let response = app.clone().oneshot(request([10, 0, 0, ip], "s")).await.unwrap();
+            let _ = axum::body::to_bytes(response.into_body(), 1024).await.unwrap();
+        }
+        let held = limiter.stream_slots("ip:10.0.0.9").try_acquire_owned().unwrap();
+        assert_eq!(limiter.streams.len(), 4);
+
+        limiter.prune();
+        assert_eq!(limiter.streams.len(), 1);
+        drop(held);
+        limiter.prune();
+        assert!(limiter.streams.is_empty());
+    }
+}
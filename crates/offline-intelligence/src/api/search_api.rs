@@ -5,24 +5,65 @@
 //! 3. Fall back to keyword search if embeddings are unavailable
 //! 4. Merge and rank results by combined relevance score
 use axum::{
-    extract::State,
-    http::StatusCode,
-    response::IntoResponse,
-    Json,
+    body::Body,
+    extract::{Query, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Extension, Json,
 };
+use std::convert::Infallible;
 use std::sync::Arc;
 use serde::{Deserialize, Serialize};
 use tracing::{info, warn, debug};
+use crate::memory_db::{MessageSearchFilter, SavedSearch};
 use crate::shared_state::SharedState;
+use crate::api::error::ApiError;
+use crate::api::auth::{authorize_session, visible_to, AuthenticatedTenant};
+use crate::utils::TextUtils;
 use crate::worker_threads::LLMWorker;
 /
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SearchRequest {
     pub query: String,
     pub session_id: Option<String>,
     pub limit: Option<i32>,
     /
     pub similarity_threshold: Option<f32>,
+    /
+    pub from: Option<chrono::DateTime<chrono::Utc>>,
+    pub to: Option<chrono::DateTime<chrono::Utc>>,
+    /
+    pub role: Option<String>,
+    /
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub within: Option<Box<SearchRequest>>,
+    /
+    #[serde(default)]
+    pub include_summaries: bool,
+}
+/
+const MAX_SEARCH_REFINEMENTS: usize = 4;
+impl SearchRequest {
+    fn validate_refinements(&self) -> Result<(), ApiError> {
+        let mut depth = 0;
+        let mut current = self;
+        loop {
+            if current.query.trim().is_empty() {
+                return Err(ApiError::new(StatusCode::BAD_REQUEST, "Query cannot be empty"));
+            }
+            match current.within {
+                Some(ref prior) => current = prior,
+                None => return Ok(()),
+            }
+            depth += 1;
+            if depth > MAX_SEARCH_REFINEMENTS {
+                return Err(ApiError::new(
+                    StatusCode::BAD_REQUEST,
+                    format!("At most {} nested 'within' refinements are supported", MAX_SEARCH_REFINEMENTS),
+                ));
+            }
+        }
+    }
 }
 /
 #[derive(Debug, Serialize)]
@@ -35,30 +76,122 @@ pub struct SearchResponse {
 #[derive(Debug, Serialize, Clone)]
 pub struct SearchResult {
     pub session_id: String,
-    pub message_id: i64,
+    /
+    pub message_id: Option<i64>,
+    /
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub summary_id: Option<i64>,
     pub content: String,
     pub role: String,
+    pub timestamp: chrono::DateTime<chrono::Utc>,
     pub relevance_score: f32,
     pub search_source: String,
+    /
+    /
+    pub source: String,
+    /
+    pub snippet: String,
 }
 /
 pub async fn search(
     State(shared_state): State<Arc<SharedState>>,
+    tenant: Option<Extension<AuthenticatedTenant>>,
     Json(payload): Json<SearchRequest>,
-) -> Result<impl IntoResponse, (StatusCode, String)> {
+) -> Result<impl IntoResponse, ApiError> {
     info!("Search request: query='{}', session={:?}, limit={:?}",
           payload.query, payload.session_id, payload.limit);
-
+    payload.validate_refinements()?;
+    let limit = payload.limit.unwrap_or(10).clamp(1, 100) as usize;
+    let (results, search_type) = run_search(&shared_state, &payload, tenant.as_deref(), limit).await?;
+    let total = results.len();
+    info!("Search completed: {} results ({})", total, search_type);
+    Ok(Json(SearchResponse {
+        results,
+        total,
+        search_type,
+    }))
+}
+/
+async fn check_search_scope(
+    shared_state: &SharedState,
+    payload: &SearchRequest,
+    tenant: Option<&AuthenticatedTenant>,
+) -> Result<(), ApiError> {
     if payload.query.trim().is_empty() {
-        return Err((StatusCode::BAD_REQUEST, "Query cannot be empty".to_string()));
+        return Err(ApiError::new(StatusCode::BAD_REQUEST, "Query cannot be empty"));
     }
-    let limit = payload.limit.unwrap_or(10).clamp(1, 100) as usize;
+    let db = &shared_state.database_pool;
+    match payload.session_id {
+        Some(ref session_id) => authorize_session(db, session_id, tenant)?,
+        None if tenant.is_none() => {
+            let isolated = shared_state.context_orchestrator.read().await
+                .as_ref()
+                .is_some_and(|orchestrator| orchestrator.config().tenant_isolation);
+            if isolated {
+                return Err(ApiError::new(
+                    StatusCode::BAD_REQUEST,
+                    "Cross-session search needs a tenant API key while tenant isolation is enabled; pass session_id instead",
+                ));
+            }
+        }
+        None => {}
+    }
+    Ok(())
+}
+/
+/
+async fn run_search(
+    shared_state: &SharedState,
+    payload: &SearchRequest,
+    tenant: Option<&AuthenticatedTenant>,
+    limit: usize,
+) -> Result<(Vec<SearchResult>, String), ApiError> {
+    check_search_scope(shared_state, payload, tenant).await?;
+    let db = &shared_state.database_pool;
+    // Semantic and summary hits are filtered after the fact; remember each session's verdict.
+    let mut visible_sessions: std::collections::HashMap<String, bool> = std::collections::HashMap::new();
+    let mut visible = |session_id: &str| {
+        tenant.is_none() || *visible_sessions.entry(session_id.to_string()).or_insert_with(|| {
+            db.conversations.get_session(session_id).ok().flatten()
+                .is_some_and(|session| visible_to(&session, tenant))
+        })
+    };
+    let role = match payload.role.as_deref() {
+        None | Some("any") => None,
+        Some(role @ ("user" | "assistant" | "system")) => Some(role.to_string()),
+        Some(other) => {
+            return Err(ApiError::new(StatusCode::BAD_REQUEST, format!("Invalid role filter: {}", other)));
+        }
+    };
+    if let (Some(from), Some(to)) = (payload.from, payload.to) {
+        if from > to {
+            return Err(ApiError::new(StatusCode::BAD_REQUEST, "'from' must not be after 'to'"));
+        }
+    }
+    // A refinement is an AND over message ids: the new query only matches
+    // messages the prior search returned.
+    let message_ids = match payload.within {
+        Some(ref prior) => {
+            let prior_limit = prior.limit.unwrap_or(10).clamp(1, 100) as usize;
+            let (prior_results, _) = Box::pin(run_search(shared_state, prior, tenant, prior_limit)).await?;
+            Some(prior_results.into_iter()
+                .filter_map(|r| r.message_id)
+                .collect::<Vec<_>>())
+        }
+        None => None,
+    };
+    let filter = MessageSearchFilter {
+        from: payload.from,
+        to: payload.to,
+        role,
+        message_ids,
+        user_id: tenant.map(|AuthenticatedTenant(t)| t.clone()),
+    };
     let similarity_threshold = payload.similarity_threshold.unwrap_or(0.3);
     let mut all_results: Vec<SearchResult> = Vec::new();
     let mut search_type = String::from("keyword");
 
     let llm_worker = &shared_state.llm_worker;
-    let db = &shared_state.database_pool;
 
     match llm_worker.generate_embeddings(vec![payload.query.clone()]).await {
         Ok(query_embeddings) if !query_embeddings.is_empty() => {
@@ -66,7 +199,7 @@ pub async fn search(
 
             match db.embeddings.find_similar_embeddings(
                 query_vec,
-                "llama-server",
+                llm_worker.embedding_model(),
                 (limit * 2) as i32,
                 similarity_threshold,
             ) {
@@ -76,6 +209,9 @@ pub async fn search(
                     debug!("Semantic search found {} candidates", similar_ids.len());
 
                     for (message_id, similarity) in &similar_ids {
+                        if !filter.allows_message(*message_id) {
+                            continue;
+                        }
 
                         if let Ok(Some(session_id_filter)) = get_message_session_id(db, *message_id) {
 
@@ -84,14 +220,24 @@ pub async fn search(
                                     continue;
                                 }
                             }
+                            if !visible(&session_id_filter) {
+                                continue;
+                            }
                             if let Ok(msg) = get_message_by_id(db, *message_id) {
+                                if !filter.matches(&msg.role, msg.timestamp) {
+                                    continue;
+                                }
                                 all_results.push(SearchResult {
                                     session_id: session_id_filter,
-                                    message_id: *message_id,
+                                    message_id: Some(*message_id),
+                                    summary_id: None,
                                     content: msg.content,
                                     role: msg.role,
+                                    timestamp: msg.timestamp,
                                     relevance_score: *similarity,
                                     search_source: "semantic".to_string(),
+                                    source: "message".to_string(),
+                                    snippet: String::new(),
                                 });
                             }
                         }
@@ -113,22 +259,19 @@ pub async fn search(
         }
     }
 
-    let keywords: Vec<String> = payload.query
-        .split_whitespace()
-        .filter(|word| word.len() > 2)
-        .map(|s| s.to_lowercase())
-        .collect();
+    let keywords = query_keywords(&payload.query);
     if !keywords.is_empty() {
         let orchestrator_guard = shared_state.context_orchestrator.read().await;
         if let Some(orchestrator) = &*orchestrator_guard {
             if let Ok(stored_messages) = orchestrator.search_messages(
                 payload.session_id.as_deref(),
                 &keywords,
+                &filter,
                 limit,
             ).await {
                 let stored_messages: Vec<crate::memory_db::StoredMessage> = stored_messages;
                 let semantic_ids: std::collections::HashSet<i64> = all_results.iter()
-                    .map(|r| r.message_id)
+                    .filter_map(|r| r.message_id)
                     .collect();
                 for msg in stored_messages {
 
@@ -138,11 +281,15 @@ pub async fn search(
                     let keyword_score = calculate_relevance(&msg.content, &keywords);
                     all_results.push(SearchResult {
                         session_id: msg.session_id,
-                        message_id: msg.id,
+                        message_id: Some(msg.id),
+                        summary_id: None,
                         content: msg.content,
                         role: msg.role,
+                        timestamp: msg.timestamp,
                         relevance_score: keyword_score,
                         search_source: "keyword".to_string(),
+                        source: "message".to_string(),
+                        snippet: String::new(),
                     });
                 }
                 if search_type == "semantic" && all_results.iter().any(|r| r.search_source == "keyword") {
@@ -152,15 +299,212 @@ pub async fn search(
         }
     }
 
+    // Summaries have no role and are not addressable by a refinement's message ids.
+    if payload.include_summaries && filter.role.is_none() && filter.message_ids.is_none() && !keywords.is_empty() {
+        match db.summaries.search_summaries(payload.session_id.as_deref(), &keywords, limit) {
+            Ok(summaries) => {
+                for summary in summaries {
+                    if !visible(&summary.session_id) {
+                        continue;
+                    }
+                    if payload.from.is_some_and(|from| summary.generated_at < from)
+                        || payload.to.is_some_and(|to| summary.generated_at > to)
+                    {
+                        continue;
+                    }
+                    let searchable = format!("{} {}", summary.summary_text, summary.key_topics.join(" "));
+                    all_results.push(SearchResult {
+                        session_id: summary.session_id,
+                        message_id: None,
+                        summary_id: Some(summary.id),
+                        relevance_score: calculate_relevance(&searchable, &keywords),
+                        content: summary.summary_text,
+                        role: "summary".to_string(),
+                        timestamp: summary.generated_at,
+                        search_source: "keyword".to_string(),
+                        source: "summary".to_string(),
+                        snippet: String::new(),
+                    });
+                }
+            }
+            Err(e) => warn!("Summary search failed: {}", e),
+        }
+    }
+
     all_results.sort_by(|a, b| b.relevance_score.partial_cmp(&a.relevance_score).unwrap_or(std::cmp::Ordering::Equal));
     all_results.truncate(limit);
-    let total = all_results.len();
-    info!("Search completed: {} results ({})", total, search_type);
-    Ok(Json(SearchResponse {
-        results: all_results,
-        total,
-        search_type,
-    }))
+    for result in &mut all_results {
+        result.snippet = TextUtils::highlight_snippet(&result.content, &keywords, SNIPPET_RADIUS);
+    }
+    Ok((all_results, search_type))
+}
+/
+#[derive(Debug, Deserialize)]
+pub struct SearchExportQuery {
+    pub q: String,
+    #[serde(default)]
+    pub format: Option<String>,
+    pub session_id: Option<String>,
+    pub limit: Option<i32>,
+}
+/
+pub async fn export_search(
+    State(shared_state): State<Arc<SharedState>>,
+    tenant: Option<Extension<AuthenticatedTenant>>,
+    Query(params): Query<SearchExportQuery>,
+) -> Result<Response, ApiError> {
+    let format = params.format.as_deref().unwrap_or("md");
+    if format != "md" && format != "csv" {
+        return Err(ApiError::new(StatusCode::BAD_REQUEST, format!("Unsupported export format: {}", format)));
+    }
+    let request = SearchRequest {
+        query: params.q.clone(),
+        session_id: params.session_id.clone(),
+        limit: None,
+        similarity_threshold: None,
+        from: None,
+        to: None,
+        role: None,
+        within: None,
+        include_summaries: false,
+    };
+    let limit = params.limit.unwrap_or(500).clamp(1, 5000) as usize;
+    let csv = format == "csv";
+    let tenant = tenant.map(|Extension(tenant)| tenant);
+    // Check access up front so a rejected export still answers with a plain status code.
+    check_search_scope(&shared_state, &request, tenant.as_ref()).await?;
+
+    // The header goes out before the search runs; rows follow as soon as it returns.
+    let rows = async_stream::stream! {
+        if csv {
+            yield Ok::<_, Infallible>("session_id,message_id,timestamp,role,relevance,snippet\n".to_string());
+        } else {
+            yield Ok(format!("# Search report: {}\n\n", escape_markdown_cell(&request.query)));
+            yield Ok(format!("Generated {}\n\n", chrono::Utc::now().to_rfc3339()));
+            yield Ok("| Session | Message | Timestamp | Role | Snippet |\n".to_string());
+            yield Ok("|---|---|---|---|---|\n".to_string());
+        }
+        let (results, search_type) = match run_search(&shared_state, &request, tenant.as_ref(), limit).await {
+            Ok(found) => found,
+            Err(err) => {
+                warn!("Search export failed: {}", err.message);
+                yield Ok(if csv {
+                    format!("# export failed: {}\n", err.message)
+                } else {
+                    format!("\nExport failed: {}\n", err.message)
+                });
+                return;
+            }
+        };
+        info!("Search export: query='{}', {} rows ({}, {})", request.query, results.len(), search_type, if csv { "csv" } else { "md" });
+        let total = results.len();
+        for result in results {
+            let message_id = result.message_id.map(|id| id.to_string()).unwrap_or_default();
+            yield Ok(if csv {
+                format!(
+                    "{},{},{},{},{:.3},{}\n",
+                    escape_csv_field(&result.session_id),
+                    message_id,
+                    result.timestamp.to_rfc3339(),
+                    escape_csv_field(&result.role),
+                    result.relevance_score,
+                    escape_csv_field(&result.snippet)
+                )
+            } else {
+                format!(
+                    "| {} | {} | {} | {} | {} |\n",
+                    escape_markdown_cell(&result.session_id),
+                    message_id,
+                    result.timestamp.to_rfc3339(),
+                    result.role,
+                    escape_markdown_cell(&result.snippet)
+                )
+            });
+        }
+        if !csv {
+            yield Ok(format!("\n{} result(s) · {} search\n", total, search_type));
+        }
+    };
+    let body = Body::from_stream(rows);
+
+    let (content_type, extension) = if csv {
+        ("text/csv; charset=utf-8", "csv")
+    } else {
+        ("text/markdown; charset=utf-8", "md")
+    };
+    Ok((
+        [
+            (header::CONTENT_TYPE, content_type.to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"search-report.{}\"", extension),
+            ),
+        ],
+        body,
+    ).into_response())
+}
+/
+#[derive(Debug, Deserialize)]
+pub struct SaveSearchRequest {
+    pub name: String,
+    pub search: SearchRequest,
+}
+/
+/
+pub async fn save_search(
+    State(shared_state): State<Arc<SharedState>>,
+    tenant: Option<Extension<AuthenticatedTenant>>,
+    Json(payload): Json<SaveSearchRequest>,
+) -> Result<(StatusCode, Json<SavedSearch>), ApiError> {
+    let name = payload.name.trim();
+    if name.is_empty() {
+        return Err(ApiError::new(StatusCode::BAD_REQUEST, "Saved search name cannot be empty"));
+    }
+    payload.search.validate_refinements()?;
+    let query = serde_json::to_value(&payload.search)
+        .map_err(|e| ApiError::new(StatusCode::BAD_REQUEST, format!("Invalid search: {}", e)))?;
+    let owner = tenant.as_deref().map(|AuthenticatedTenant(t)| t.as_str());
+    let saved = shared_state.database_pool
+        .save_search(owner, name, &query)
+        .await
+        .map_err(|e| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, format!("Database error: {}", e)))?;
+    info!("Saved search {} ('{}') for user {:?}", saved.id, saved.name, saved.user_id);
+    Ok((StatusCode::CREATED, Json(saved)))
+}
+/
+#[derive(Debug, Serialize)]
+pub struct SavedSearchesResponse {
+    pub saved_searches: Vec<SavedSearch>,
+}
+/
+pub async fn list_saved_searches(
+    State(shared_state): State<Arc<SharedState>>,
+    tenant: Option<Extension<AuthenticatedTenant>>,
+) -> Result<Json<SavedSearchesResponse>, ApiError> {
+    let owner = tenant.as_deref().map(|AuthenticatedTenant(t)| t.as_str());
+    let saved_searches = shared_state.database_pool
+        .list_saved_searches(owner)
+        .await
+        .map_err(|e| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, format!("Database error: {}", e)))?;
+    Ok(Json(SavedSearchesResponse { saved_searches }))
+}
+const SNIPPET_RADIUS: usize = 80;
+fn query_keywords(query: &str) -> Vec<String> {
+    query
+        .split_whitespace()
+        .filter(|word| word.len() > 2)
+        .map(|s| s.to_lowercase())
+        .collect()
+}
+fn escape_csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+fn escape_markdown_cell(value: &str) -> String {
+    value.replace('|', "\\|").replace(['\n', '\r'], " ")
 }
 /
 fn calculate_relevance(content: &str, keywords: &[String]) -> f32 {
@@ -195,6 +539,7 @@ fn get_message_session_id(
 struct MinimalMessage {
     content: String,
     role: String,
+    timestamp: chrono::DateTime<chrono::Utc>,
 }
 fn get_message_by_id(
     db: &crate::memory_db::MemoryDatabase,
@@ -202,17 +547,103 @@ fn get_message_by_id(
 ) -> anyhow::Result<MinimalMessage> {
     let conn = db.conversations.get_conn_public()?;
     let mut stmt = conn.prepare(
-        "SELECT content, role FROM messages WHERE id = ?1"
+        "SELECT content, role, timestamp FROM messages WHERE id = ?1"
     )?;
     let mut rows = stmt.query([message_id])?;
     if let Some(row) = rows.next()? {
         Ok(MinimalMessage {
             content: row.get::<usize, String>(0)?,
             role: row.get::<usize, String>(1)?,
+            timestamp: chrono::DateTime::parse_from_rfc3339(&row.get::<usize, String>(2)?)
+                .map_err(|e| anyhow::anyhow!("Failed to parse timestamp: {}", e))?
+                .with_timezone(&chrono::Utc),
         })
     } else {
         Err(anyhow::anyhow!("Message {} not found", message_id))
     }
 }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[tokio::test]
+    async fn test_saved_searches_belong_to_the_calling_tenant() {
+        let database = Arc::new(crate::memory_db::MemoryDatabase::new_in_memory().unwrap());
+        let shared_state = Arc::new(SharedState::new(crate::config::tests::create_test_config(), database).unwrap());
+        let tenant = |t: &str| Some(Extension(AuthenticatedTenant(t.to_string())));
+        // A user_id in the body is not part of the request any more and cannot pick the owner.
+        let payload: SaveSearchRequest = serde_json::from_value(serde_json::json!({
+            "name": "invoices",
+            "user_id": "team-b",
+            "search": { "query": "invoice" },
+        })).unwrap();
+        let (status, Json(saved)) = save_search(State(shared_state.clone()), tenant("team-a"), Json(payload)).await.unwrap();
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(saved.user_id.as_deref(), Some("team-a"));
+
+        let Json(own) = list_saved_searches(State(shared_state.clone()), tenant("team-a")).await.unwrap();
+        assert_eq!(own.saved_searches.len(), 1);
+        let Json(other) = list_saved_searches(State(shared_state.clone()), tenant("team-b")).await.unwrap();
+        assert!(other.saved_searches.is_empty());
+        let Json(anonymous) = list_saved_searches(State(shared_state), None).await.unwrap();
+        assert!(anonymous.saved_searches.is_empty());
+    }
+    #[tokio::test]
+    async fn test_summary_hits_carry_a_summary_id_instead_of_a_message_id() {
+        let database = Arc::new(crate::memory_db::MemoryDatabase::new_in_memory().unwrap());
+        let session = database.conversations.create_session(None).unwrap();
+        database.summaries.store_summary(&crate::memory_db::Summary {
+            id: 0,
+            session_id: session.id.clone(),
+            message_range_start: 0,
+            message_range_end: 9,
+            summary_text: "Agreed to move invoicing to the new ledger".to_string(),
+            compression_ratio: 0.2,
+            key_topics: vec!["invoicing".to_string()],
+            generated_at: chrono::Utc::now(),
+        }).unwrap();
+        let stored = database.summaries.get_session_summaries(&session.id).unwrap();
+        let shared_state = Arc::new(SharedState::new(crate::config::tests::create_test_config(), database).unwrap());
+        let request: SearchRequest = serde_json::from_value(serde_json::json!({
+            "query": "invoicing ledger",
+            "include_summaries": true,
+        })).unwrap();
+
+        let (results, _) = run_search(&shared_state, &request, None, 10).await.unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!((results[0].message_id, results[0].summary_id), (None, Some(stored[0].id)));
+        let wire = serde_json::to_value(&results[0]).unwrap();
+        assert!(wire["message_id"].is_null());
+        assert_eq!(wire["source"], "summary");
+        assert_eq!(wire["snippet"], "Agreed to move **invoicing** to the new **ledger**");
+    }
+    #[tokio::test]
+    async fn test_export_rejects_a_bad_request_before_streaming() {
+        let database = Arc::new(crate::memory_db::MemoryDatabase::new_in_memory().unwrap());
+        let metadata = crate::memory_db::SessionMetadata { user_id: Some("team-a".to_string()), ..Default::default() };
+        let owned = database.conversations.create_session(Some(metadata)).unwrap();
+        let shared_state = Arc::new(SharedState::new(crate::config::tests::create_test_config(), database).unwrap());
+        let params = |q: &str, session_id: Option<&str>| Query(SearchExportQuery {
+            q: q.to_string(),
+            format: Some("csv".to_string()),
+            session_id: session_id.map(str::to_string),
+            limit: None,
+        });
+
+        let err = export_search(State(shared_state.clone()), None, params("  ", None)).await.unwrap_err();
+        assert_eq!(err.status, StatusCode::BAD_REQUEST);
+        let team_b = Some(Extension(AuthenticatedTenant("team-b".to_string())));
+        let err = export_search(State(shared_state.clone()), team_b, params("invoice", Some(&owned.id))).await.unwrap_err();
+        assert_eq!(err.status, StatusCode::NOT_FOUND);
 
+        let response = export_search(State(shared_state), None, params("invoice", None)).await.unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(&body[..], b"session_id,message_id,timestamp,role,relevance,snippet\n");
+    }
+    #[test]
+    fn test_report_escaping() {
+        assert_eq!(escape_csv_field("plain"), "plain");
+        assert_eq!(escape_csv_field("a,\"b\""), "\"a,\"\"b\"\"\"");
+        assert_eq!(escape_markdown_cell("a|b\nc"), "a\\|b c");
+    }
+}
 
@@ -2,7 +2,7 @@
 //! Flow: Client POST â†’ SharedState (session + cache lookup) â†’ LLM Worker (HTTP to llama-server) â†’ SSE stream back
 //! All state access is in-process via Arc/shared memory. The only network hop is to localhost llama-server.
 use axum::{
-    extract::State,
+    extract::{Extension, State},
     response::{
         sse::{Event, Sse},
         IntoResponse, Response,
@@ -10,13 +10,20 @@ use axum::{
     http::StatusCode,
     Json,
 };
-use futures_util::StreamExt;
+use futures_util::{stream, StreamExt};
 use serde::Deserialize;
 use std::convert::Infallible;
-use tracing::{info, error, debug};
-use crate::memory::Message;
-use crate::memory_db::schema::Embedding;
-use crate::shared_state::UnifiedAppState;
+use std::sync::Arc;
+use tracing::{info, error, debug, Instrument};
+use tokio_util::sync::CancellationToken;
+use crate::memory::{Message, Role};
+use crate::memory_db::schema::{Embedding, MessageAlternate, SessionMetadata};
+use crate::memory_db::{score_message_importance, MemoryDatabase, StoredMessage};
+use crate::shared_state::{SharedState, UnifiedAppState};
+use crate::context_engine::{ContextDecision, ContextOrchestrator};
+use crate::worker_threads::{LLMWorker, LlmError, StreamEvent, StreamSummary};
+use crate::api::error::ApiError;
+use crate::api::auth::{authorize_session, AuthenticatedTenant};
 /
 #[derive(Debug, Deserialize)]
 pub struct StreamChatRequest {
@@ -29,10 +36,83 @@ pub struct StreamChatRequest {
     pub temperature: f32,
     #[serde(default = "default_stream")]
     pub stream: bool,
+    #[serde(default = "default_n")]
+    pub n: u32,
+    #[serde(default)]
+    pub persist_all_choices: bool,
+    /
+    /
+    #[serde(default)]
+    pub max_context_tokens: Option<usize>,
+    /
+    #[serde(skip)]
+    pub tenant: Option<AuthenticatedTenant>,
 }
 fn default_max_tokens() -> u32 { 2000 }
 fn default_temperature() -> f32 { 0.7 }
 fn default_stream() -> bool { true }
+fn default_n() -> u32 { 1 }
+/
+const MAX_CHOICES: u32 = 8;
+/
+#[derive(Debug, Deserialize)]
+pub struct StopGenerationRequest {
+    pub session_id: String,
+}
+/
+struct ActiveGeneration {
+    shared_state: Arc<SharedState>,
+    session_id: String,
+    generation_id: usize,
+}
+impl Drop for ActiveGeneration {
+    fn drop(&mut self) {
+        self.shared_state.finish_generation(&self.session_id, self.generation_id);
+    }
+}
+/
+/
+pub(crate) struct StreamSlot {
+    _permit: tokio::sync::OwnedSemaphorePermit,
+    slots: u32,
+}
+impl StreamSlot {
+    pub(crate) fn acquire(shared_state: &SharedState) -> Result<Self, ApiError> {
+        Self::acquire_many(shared_state, 1)
+    }
+    /
+    /
+    pub(crate) fn acquire_many(shared_state: &SharedState, slots: u32) -> Result<Self, ApiError> {
+        let capacity = shared_state.config.load().max_concurrent_streams.max(1);
+        if slots > capacity {
+            return Err(ApiError::new(
+                StatusCode::BAD_REQUEST,
+                format!("n={} needs more than the {} concurrent generation slots", slots, capacity),
+            ));
+        }
+        match shared_state.stream_slots.clone().try_acquire_many_owned(slots) {
+            Ok(permit) => {
+                crate::metrics::inc_active_streams(slots);
+                Ok(Self { _permit: permit, slots })
+            }
+            Err(_) => {
+                crate::metrics::inc_stream_rejections();
+                debug!("All {} stream slots busy, rejecting generation", shared_state.config.load().max_concurrent_streams);
+                Err(ApiError::new(StatusCode::SERVICE_UNAVAILABLE, "Too many concurrent generations; retry shortly"))
+            }
+        }
+    }
+}
+impl Drop for StreamSlot {
+    fn drop(&mut self) {
+        crate::metrics::dec_active_streams(self.slots);
+    }
+}
+/
+/
+/
+/
+/
 /
 /
 /
@@ -41,14 +121,116 @@ fn default_stream() -> bool { true }
 /
 pub async fn generate_stream(
     State(state): State<UnifiedAppState>,
-    Json(req): Json<StreamChatRequest>,
+    tenant: Option<Extension<AuthenticatedTenant>>,
+    Json(mut req): Json<StreamChatRequest>,
 ) -> Response {
+    req.tenant = tenant.map(|Extension(tenant)| tenant);
+    // Each candidate is its own backend request, so n>1 takes n slots.
+    let slot = match StreamSlot::acquire_many(&state.shared_state, req.n.max(1)) {
+        Ok(slot) => slot,
+        Err(rejection) => return rejection.into_response(),
+    };
+    if !req.stream {
+        return match complete_generation(&state, req).await {
+            Ok((decision, completion)) => (context_headers(&decision), Json(completion)).into_response(),
+            Err(rejection) => rejection.into_response(),
+        };
+    }
+    let keep_alive_secs = state.shared_state.config.load().sse_keep_alive_seconds.max(1);
+    let ready = ready_event(&req.session_id);
+    match start_generation(&state, req).await {
+        Ok((decision, frames)) => {
+            // The slot is released when the client disconnects or the stream ends.
+            let events = stream::once(async move { ready })
+                .chain(frames.map(move |data| {
+                    let _ = &slot;
+                    Event::default().data(data)
+                }))
+                .map(Ok::<_, Infallible>);
+            // Pings are SSE comments (":\n\n") so clients never mistake them for content.
+            let sse = Sse::new(events)
+                .keep_alive(
+                    axum::response::sse::KeepAlive::new()
+                        .interval(std::time::Duration::from_secs(keep_alive_secs))
+                        .text("")
+                );
+            (context_headers(&decision), sse).into_response()
+        }
+        Err(rejection) => rejection.into_response(),
+    }
+}
+/
+/
+/
+fn context_headers(decision: &ContextDecision) -> [(&'static str, String); 3] {
+    let tiers = decision.tiers.iter().map(u8::to_string).collect::<Vec<_>>().join(",");
+    [
+        ("x-context-retrieved", decision.retrieved.to_string()),
+        ("x-context-tiers", tiers),
+        ("x-context-semantic", decision.semantic.to_string()),
+    ]
+}
+/
+fn ready_event(session_id: &str) -> Event {
+    Event::default()
+        .event("ready")
+        .data(serde_json::json!({ "session_id": session_id }).to_string())
+}
+/
+struct PreparedGeneration {
+    request_num: usize,
+    session_id: String,
+    context_messages: Vec<Message>,
+    context_decision: ContextDecision,
+    persister: ResponsePersister,
+    cancel_token: CancellationToken,
+    active_generation: ActiveGeneration,
+}
+/
+/
+/
+async fn prepare_generation(
+    state: &UnifiedAppState,
+    mut req: StreamChatRequest,
+) -> Result<PreparedGeneration, ApiError> {
     let request_num = state.shared_state.counters.inc_total_requests();
-    info!("Stream request #{} for session: {}", request_num, req.session_id);
+    info!("Generation request #{} for session: {}", request_num, req.session_id);
     if req.messages.is_empty() {
-        return (StatusCode::BAD_REQUEST, "Messages array cannot be empty").into_response();
+        return Err(ApiError::new(StatusCode::BAD_REQUEST, "Messages array cannot be empty"));
+    }
+    if req.n == 0 || req.n > MAX_CHOICES {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            format!("n must be between 1 and {}", MAX_CHOICES),
+        ));
+    }
+    if req.max_context_tokens == Some(0) {
+        return Err(ApiError::new(StatusCode::BAD_REQUEST, "max_context_tokens must be positive"));
     }
     let session_id = req.session_id.clone();
+    authorize_session(&state.shared_state.database_pool, &session_id, req.tenant.as_ref())?;
+    // Claim the session before anything is persisted so a rejected request leaves no trace.
+    let Some(cancel_token) = state.shared_state.register_generation(&session_id, request_num) else {
+        return Err(ApiError::new(
+            StatusCode::CONFLICT,
+            "A generation is already running for this session; stop it or wait for it to finish",
+        ));
+    };
+    let active_generation = ActiveGeneration {
+        shared_state: state.shared_state.clone(),
+        session_id: session_id.clone(),
+        generation_id: request_num,
+    };
+
+    if let Some(ref system_prompt) = state.shared_state.config.load().default_system_prompt {
+        if !req.messages.iter().any(|m| m.role == Role::System) {
+            req.messages.insert(0, Message {
+                role: Role::System,
+                content: system_prompt.clone(),
+                parts: None,
+            });
+        }
+    }
 
     let session = state.shared_state.get_or_create_session(&session_id).await;
 
@@ -60,189 +242,528 @@ pub async fn generate_stream(
     }
 
 
-    let user_msg_content = req.messages.iter().rev().find(|m| m.role == "user").map(|m| m.content.clone());
-    if let Some(ref content) = user_msg_content {
-        let db = state.shared_state.database_pool.clone();
-        let sid = session_id.clone();
-        let content = content.clone();
-        let msg_count = req.messages.len() as i32;
-        tokio::spawn(async move {
-
-            let _ = db.conversations.create_session_with_id(&sid, None);
+    if let Ok(None) = state.shared_state.database_pool.conversations.get_session(&session_id) {
+        let metadata = SessionMetadata {
+            // Ownership comes from the API key only; without one the session stays unowned.
+            user_id: req.tenant.as_ref().map(|AuthenticatedTenant(tenant)| tenant.clone()),
+            ..Default::default()
+        };
+        if let Err(e) = state.shared_state.database_pool.conversations.create_session_with_id(&session_id, Some(metadata)) {
+            debug!("Session {} not created: {}", session_id, e);
+        }
+    }
 
-            if let Err(e) = db.conversations.store_messages_batch(
-                &sid,
-                &[("user".to_string(), content, msg_count - 1, 0, 0.5)],
-            ) {
-                error!("Failed to persist user message: {}", e);
-            }
-        });
+    let user_msg_content = req.messages.iter().rev().find(|m| m.role == Role::User).map(|m| m.content.clone());
+    // The orchestrator persists the user turn through the tier manager in
+    // process_conversation; only write directly when it is unavailable.
+    let orchestrator = state.context_orchestrator.read().await.clone();
+    if orchestrator.is_none() {
+        if let Some(ref content) = user_msg_content {
+            let db = state.shared_state.database_pool.clone();
+            let sid = session_id.clone();
+            let content = content.clone();
+            let msg_count = req.messages.len() as i32;
+            tokio::spawn(async move {
+                if let Err(e) = db.conversations.store_messages_batch(
+                    &sid,
+                    &[("user".to_string(), content.clone(), msg_count - 1, 0, score_message_importance(&Role::User, &content))],
+                ) {
+                    error!("Failed to persist user message: {}", e);
+                }
+            });
+        }
     }
 
 
 
 
 
-    let context_messages = {
-        let orchestrator_guard = state.context_orchestrator.read().await;
-        if let Some(ref orchestrator) = *orchestrator_guard {
+    let (context_messages, context_decision) = {
+        if let Some(ref orchestrator) = orchestrator {
             let user_query = user_msg_content.as_deref();
-            match orchestrator.process_conversation(&session_id, &req.messages, user_query).await {
-                Ok(optimized) => {
+            let budget = req.max_context_tokens.map(|tokens| state.shared_state.clamp_context_tokens(tokens));
+            match orchestrator.process_conversation_with_decision(&session_id, &req.messages, user_query, budget).await {
+                Ok((optimized, decision)) => {
                     if optimized.len() != req.messages.len() {
                         info!("Context engine optimized: {} â†’ {} messages (retrieved past context)",
                             req.messages.len(), optimized.len());
                     }
-                    optimized
+                    (optimized, decision)
                 }
                 Err(e) => {
                     error!("Context engine error (falling back to raw messages): {}", e);
-                    req.messages.clone()
+                    (req.messages.clone(), ContextDecision::default())
                 }
             }
         } else {
             debug!("Context orchestrator not initialized, using raw messages");
-            req.messages.clone()
+            (req.messages.clone(), ContextDecision::default())
         }
     };
 
-    let llm_worker = state.llm_worker.clone();
-    let max_tokens = req.max_tokens;
-    let temperature = req.temperature;
-    let db_for_persist = state.shared_state.database_pool.clone();
-    let session_id_for_persist = session_id.clone();
-    let msg_index = req.messages.len() as i32;
-
-    let llm_worker_for_embed = state.llm_worker.clone();
-    let db_for_embed_persist = state.shared_state.database_pool.clone();
-    let session_id_for_embed = session_id.clone();
-    let user_msg_for_embed = user_msg_content.clone();
-    match llm_worker.stream_response(context_messages, max_tokens, temperature).await {
+    let context_size = state.shared_state.config.load().ctx_size as usize;
+    let prompt_tokens = state.llm_worker.count_tokens(&context_messages).await;
+    if prompt_tokens >= context_size {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            format!(
+                "Prompt is {} tokens but the model context window is {} tokens; shorten the conversation",
+                prompt_tokens, context_size
+            ),
+        ));
+    }
+    let persister = ResponsePersister {
+        orchestrator,
+        database: state.shared_state.database_pool.clone(),
+        llm_worker: state.llm_worker.clone(),
+        session_id: session_id.clone(),
+        user_message: user_msg_content,
+        msg_index: req.messages.len() as i32,
+    };
+    Ok(PreparedGeneration {
+        request_num,
+        session_id,
+        context_messages,
+        context_decision,
+        persister,
+        cancel_token,
+        active_generation,
+    })
+}
+/
+/
+/
+/
+#[tracing::instrument(name = "generation", skip_all, fields(session_id = %req.session_id))]
+pub(crate) async fn start_generation(
+    state: &UnifiedAppState,
+    req: StreamChatRequest,
+) -> Result<(ContextDecision, impl futures_util::Stream<Item = String> + Send), ApiError> {
+    let (n, persist_all_choices) = (req.n, req.persist_all_choices);
+    let (max_tokens, temperature) = (req.max_tokens, req.temperature);
+    let stream_timeout = std::time::Duration::from_secs(state.shared_state.config.load().stream_timeout_seconds);
+    let PreparedGeneration { session_id, context_messages, context_decision, persister, cancel_token, active_generation, .. } =
+        prepare_generation(state, req).await?;
+    // The returned stream is polled outside this function's span, so its
+    // events re-enter the span explicitly to keep their session_id.
+    let span = tracing::Span::current();
+    match state.llm_worker.stream_response(&session_id, context_messages, max_tokens, temperature, n).await {
         Ok(llm_stream) => {
 
             let output_stream = async_stream::stream! {
-                let mut full_response = String::new();
-                futures_util::pin_mut!(llm_stream);
-                while let Some(item) = llm_stream.next().await {
+                let _active_generation = active_generation;
+                let mut choices = vec![String::new(); n as usize];
+                let mut summaries: Vec<StreamSummary> = Vec::new();
+                let deadline = tokio::time::sleep(stream_timeout);
+                futures_util::pin_mut!(llm_stream, deadline);
+                loop {
+                    let item = tokio::select! {
+                        _ = cancel_token.cancelled() => {
+                            span.in_scope(|| info!("Generation stopped for session {}", session_id));
+                            break;
+                        }
+                        _ = &mut deadline => {
+                            span.in_scope(|| error!("Stream exceeded {}s for session {}", stream_timeout.as_secs(), session_id));
+                            yield format!("{{\"error\": \"Generation exceeded {}s\"}}", stream_timeout.as_secs());
+                            break;
+                        }
+                        item = llm_stream.next() => match item {
+                            Some(item) => item,
+                            None => break,
+                        },
+                    };
                     match item {
-                        Ok(sse_line) => {
+                        Ok(StreamEvent::Finished(summary)) => summaries.push(summary),
+                        Ok(StreamEvent::Frame(sse_line)) => {
 
                             if sse_line.starts_with("data: ") && !sse_line.contains("[DONE]") {
                                 if let Ok(chunk) = serde_json::from_str::<serde_json::Value>(&sse_line[6..].trim()) {
-                                    if let Some(content) = chunk
-                                        .get("choices")
-                                        .and_then(|c| c.get(0))
-                                        .and_then(|c| c.get("delta"))
-                                        .and_then(|d| d.get("content"))
-                                        .and_then(|c| c.as_str())
-                                    {
-                                        full_response.push_str(content);
-                                    }
+                                    append_choice_deltas(&chunk, &mut choices);
                                 }
                             }
 
-                            let data = sse_line.trim_start_matches("data: ").trim_end().to_string();
-                            yield Ok::<_, Infallible>(Event::default().data(data));
+                            yield sse_line.trim_start_matches("data: ").trim_end().to_string();
                         }
                         Err(e) => {
-                            error!("Stream error: {}", e);
-                            yield Ok(Event::default().data(
-                                format!("{{\"error\": \"{}\"}}", e)
-                            ));
+                            span.in_scope(|| error!("Stream error: {}", e));
+                            yield format!("{{\"error\": \"{}\"}}", e);
                             break;
                         }
                     }
                 }
+                persister.persist(&choices, &summaries, persist_all_choices).instrument(span.clone()).await;
+            };
+            Ok((context_decision, output_stream))
+        }
+        Err(e) => {
+            error!("Failed to start LLM stream: {}", e);
+            Err(ApiError::new(e.status_code(), format!("LLM backend error: {}", e)))
+        }
+    }
+}
+/
+/
+#[tracing::instrument(name = "generation", skip_all, fields(session_id = %req.session_id))]
+async fn complete_generation(
+    state: &UnifiedAppState,
+    req: StreamChatRequest,
+) -> Result<(ContextDecision, serde_json::Value), ApiError> {
+    let (n, persist_all_choices) = (req.n, req.persist_all_choices);
+    let (max_tokens, temperature) = (req.max_tokens, req.temperature);
+    let model = req.model.clone().unwrap_or_else(|| "local-llm".to_string());
+    let generate_timeout = std::time::Duration::from_secs(state.shared_state.config.load().generate_timeout_seconds);
+    let PreparedGeneration { request_num, session_id, context_messages, context_decision, persister, cancel_token, active_generation: _active_generation } =
+        prepare_generation(state, req).await?;
+    let generation = state.llm_worker.generate_choices(&session_id, context_messages, max_tokens, temperature, n);
+    let choices = tokio::select! {
+        _ = cancel_token.cancelled() => {
+            info!("Generation stopped for session {}", session_id);
+            return Err(ApiError::new(StatusCode::CONFLICT, "Generation was stopped"));
+        }
+        result = tokio::time::timeout(generate_timeout, generation) => match result {
+            Ok(result) => result.map_err(|e| {
+                error!("LLM completion failed: {}", e);
+                ApiError::new(e.status_code(), format!("LLM backend error: {}", e))
+            })?,
+            Err(_) => {
+                error!("LLM completion exceeded {}s for session {}", generate_timeout.as_secs(), session_id);
+                return Err(ApiError::new(StatusCode::GATEWAY_TIMEOUT, format!("Generation exceeded {}s", generate_timeout.as_secs())));
+            }
+        },
+    };
+    persister.persist(&choices, &[], persist_all_choices).await;
+    Ok((context_decision, serde_json::json!({
+        "id": format!("chatcmpl-{}", request_num),
+        "object": "chat.completion",
+        "model": model,
+        "session_id": session_id,
+        "choices": choices.iter().enumerate().map(|(index, content)| serde_json::json!({
+            "index": index,
+            "message": { "role": "assistant", "content": content },
+        })).collect::<Vec<_>>(),
+    })))
+}
+/
+fn append_choice_deltas(chunk: &serde_json::Value, choices: &mut [String]) {
+    let Some(deltas) = chunk.get("choices").and_then(|c| c.as_array()) else {
+        return;
+    };
+    for choice in deltas {
+        let index = choice.get("index").and_then(|i| i.as_u64()).unwrap_or(0) as usize;
+        let content = choice
+            .get("delta")
+            .and_then(|d| d.get("content"))
+            .and_then(|c| c.as_str());
+        if let (Some(content), Some(text)) = (content, choices.get_mut(index)) {
+            text.push_str(content);
+        }
+    }
+}
+/
+struct ResponsePersister {
+    orchestrator: Option<ContextOrchestrator>,
+    database: Arc<MemoryDatabase>,
+    llm_worker: Arc<LLMWorker>,
+    session_id: String,
+    user_message: Option<String>,
+    msg_index: i32,
+}
+impl ResponsePersister {
+    /
+    /
+    async fn persist(self, choices: &[String], summaries: &[StreamSummary], persist_all_choices: bool) {
+        let Some(primary) = choices.first().filter(|c| !c.is_empty()) else {
+            return;
+        };
+        let stored_msgs = match self.store(primary, self.msg_index).await {
+            Ok(stored_msgs) => stored_msgs,
+            Err(e) => {
+                error!("Failed to persist assistant message: {}", e);
+                return;
+            }
+        };
+        debug!("Persisted assistant response ({} chars) for session {}",
+            primary.len(), self.session_id);
+        self.record_outcome(stored_msgs.first(), summaries, 0);
+        if persist_all_choices {
+            if let Some(stored) = stored_msgs.first() {
+                self.store_alternates(stored.id, choices, summaries);
+            }
+        }
+        self.spawn_embeddings(primary.clone(), stored_msgs);
+        self.spawn_summarization();
+    }
+    /
+    fn store_alternates(&self, message_id: i64, choices: &[String], summaries: &[StreamSummary]) {
+        let alternates: Vec<MessageAlternate> = choices.iter()
+            .enumerate()
+            .skip(1)
+            .filter(|(_, content)| !content.is_empty())
+            .map(|(index, content)| {
+                let summary = summaries.iter().find(|s| s.index == index as u32);
+                MessageAlternate {
+                    choice_index: index as u32,
+                    content: content.clone(),
+                    finish_reason: summary.and_then(|s| s.finish_reason.clone()),
+                    completion_tokens: summary.and_then(|s| s.completion_tokens),
+                }
+            })
+            .collect();
+        if alternates.is_empty() {
+            return;
+        }
+        if let Err(e) = self.database.conversations.store_alternates(message_id, &alternates) {
+            error!("Failed to persist {} alternate choice(s) for message {}: {}", alternates.len(), message_id, e);
+        }
+    }
+    /
+    fn record_outcome(&self, stored: Option<&StoredMessage>, summaries: &[StreamSummary], index: u32) {
+        let (Some(stored), Some(summary)) = (stored, summaries.iter().find(|s| s.index == index)) else {
+            return;
+        };
+        if let Err(e) = self.database.conversations.set_generation_outcome(
+            stored.id,
+            summary.finish_reason.as_deref(),
+            summary.completion_tokens,
+        ) {
+            error!("Failed to record finish reason for message {}: {}", stored.id, e);
+        }
+    }
+    async fn store(&self, content: &str, msg_index: i32) -> anyhow::Result<Vec<StoredMessage>> {
+        match self.orchestrator {
+            Some(ref orchestrator) => orchestrator
+                .save_assistant_response(&self.session_id, content)
+                .await,
+            None => self.database.conversations.store_messages_batch(
+                &self.session_id,
+                &[(
+                    "assistant".to_string(),
+                    content.to_string(),
+                    msg_index,
+                    0,
+                    score_message_importance(&Role::Assistant, content),
+                )],
+            ),
+        }
+    }
+    /
+    fn spawn_summarization(&self) {
+        let Some(orchestrator) = self.orchestrator.clone() else {
+            return;
+        };
+        let session_id = self.session_id.clone();
+        tokio::spawn(async move {
+            match orchestrator.summarize_backlog(&session_id).await {
+                Ok(Some(summary)) => debug!(
+                    "Summarized messages {}-{} of session {}",
+                    summary.message_range_start, summary.message_range_end, session_id
+                ),
+                Ok(None) => {}
+                Err(e) => debug!("Summarization skipped for session {}: {}", session_id, e),
+            }
+        });
+    }
+    fn spawn_embeddings(&self, assistant_content: String, stored: Vec<StoredMessage>) {
+        let llm_for_embed = self.llm_worker.clone();
+        let db_for_embed = self.database.clone();
+        let session_id_for_embed = self.session_id.clone();
+        let user_content_for_embed = self.user_message.clone();
+        tokio::spawn(async move {
+
+            let mut texts = Vec::new();
+            let mut message_ids = Vec::new();
 
-                if !full_response.is_empty() {
-                    match db_for_persist.conversations.store_messages_batch(
-                        &session_id_for_persist,
-                        &[("assistant".to_string(), full_response.clone(), msg_index, 0, 0.5)],
-                    ) {
-                        Ok(stored_msgs) => {
-                            debug!("Persisted assistant response ({} chars) for session {}",
-                                full_response.len(), session_id_for_persist);
+            if let Some(ref user_text) = user_content_for_embed {
 
 
+                if let Ok(msgs) = db_for_embed.search_messages_by_keywords(
+                    &session_id_for_embed,
+                    &[user_text.clone()],
+                    1,
+                ).await {
+                    if let Some(user_stored) = msgs.first() {
+                        texts.push(user_text.clone());
+                        message_ids.push(user_stored.id);
+                    }
+                }
+            }
+
+            if let Some(assistant_stored) = stored.first() {
+                texts.push(assistant_content);
+                message_ids.push(assistant_stored.id);
+            }
+
+            match embed_messages(&llm_for_embed, &db_for_embed, message_ids.into_iter().zip(texts).collect()).await {
+                Ok(0) => {}
+                Ok(count) => debug!("Stored {} embeddings for session {}", count, session_id_for_embed),
+                Err(e) => {
+                    debug!("Embedding generation skipped (llama-server may not support /v1/embeddings): {}", e);
+                }
+            }
+        }.instrument(tracing::Span::current()));
+    }
+}
+/
+/
+pub(crate) async fn embed_messages(
+    llm_worker: &LLMWorker,
+    database: &Arc<MemoryDatabase>,
+    messages: Vec<(i64, String)>,
+) -> Result<usize, LlmError> {
+    if messages.is_empty() {
+        return Ok(0);
+    }
+    let (message_ids, texts): (Vec<i64>, Vec<String>) = messages.into_iter().unzip();
+    let embeddings = llm_worker.generate_embeddings(texts).await?;
+    let now = chrono::Utc::now();
+    let database = Arc::clone(database);
+    let embedding_model = llm_worker.embedding_model().to_string();
+    // An imported session can carry thousands of messages; store them off the runtime.
+    let stored = tokio::task::spawn_blocking(move || {
+        let mut stored = 0;
+        for (message_id, embedding) in message_ids.into_iter().zip(embeddings) {
+            let result = database.embeddings.store_embedding(&Embedding {
+                id: 0,
+                message_id,
+                embedding,
+                embedding_model: embedding_model.clone(),
+                generated_at: now,
+            }).and_then(|_| database.conversations.mark_embedding_generated(message_id));
+            match result {
+                Ok(()) => stored += 1,
+                Err(e) => debug!("Failed to store embedding for msg {}: {}", message_id, e),
+            }
+        }
+        stored
+    })
+    .await
+    .unwrap_or_else(|e| {
+        debug!("Embedding store task failed: {}", e);
+        0
+    });
+    Ok(stored)
+}
+/
+pub async fn stop_generation(
+    State(state): State<UnifiedAppState>,
+    tenant: Option<Extension<AuthenticatedTenant>>,
+    Json(req): Json<StopGenerationRequest>,
+) -> Response {
+    if let Err(rejection) = authorize_session(&state.shared_state.database_pool, &req.session_id, tenant.as_deref()) {
+        return rejection.into_response();
+    }
+    let stopped = state.shared_state.cancel_generation(&req.session_id);
+    debug!("Stop requested for session {} (active: {})", req.session_id, stopped);
+    Json(serde_json::json!({
+        "session_id": req.session_id,
+        "stopped": stopped,
+    })).into_response()
+}
 
-                            let llm_for_embed = llm_worker_for_embed.clone();
-                            let db_for_embed = db_for_embed_persist.clone();
-                            let assistant_content = full_response.clone();
-                            let user_content_for_embed = user_msg_for_embed.clone();
-                            let stored = stored_msgs;
-                            tokio::spawn(async move {
 
-                                let mut texts = Vec::new();
-                                let mut message_ids = Vec::new();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::tests::create_test_config;
 
-                                if let Some(ref user_text) = user_content_for_embed {
+    #[test]
+    fn test_stream_slots_bound_concurrent_generations() {
+        let database = Arc::new(MemoryDatabase::new_in_memory().unwrap());
+        let shared_state = SharedState::new(create_test_config(), database).unwrap();
+        let first = StreamSlot::acquire(&shared_state).unwrap();
+        let _second = StreamSlot::acquire(&shared_state).unwrap();
+        let rejected = StreamSlot::acquire(&shared_state).err().unwrap();
+        assert_eq!(rejected.status, StatusCode::SERVICE_UNAVAILABLE);
 
+        drop(first);
+        assert!(StreamSlot::acquire(&shared_state).is_ok());
+    }
+    #[tokio::test]
+    async fn test_second_generation_for_a_busy_session_is_rejected_without_stopping_the_first() {
+        use axum::routing::post;
+        let app = axum::Router::new().route("/v1/chat/completions", post(|| async {
+            Json(serde_json::json!({
+                "choices": [{ "index": 0, "message": { "role": "assistant", "content": "Done." }, "finish_reason": "stop" }],
+            }))
+        }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let backend = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
 
-                                    if let Ok(msgs) = db_for_embed.search_messages_by_keywords(
-                                        &session_id_for_embed,
-                                        &[user_text.clone()],
-                                        1,
-                                    ).await {
-                                        if let Some(user_stored) = msgs.first() {
-                                            texts.push(user_text.clone());
-                                            message_ids.push(user_stored.id);
-                                        }
-                                    }
-                                }
+        let mut config = create_test_config();
+        config.backend_url = backend;
+        let database = Arc::new(MemoryDatabase::new_in_memory().unwrap());
+        let shared_state = Arc::new(SharedState::new(config, database.clone()).unwrap());
+        let state = UnifiedAppState::new(shared_state.clone(), Arc::new(crate::worker_threads::DatabaseWorker::new(shared_state.clone())));
+        let request = || Json(serde_json::from_value::<StreamChatRequest>(serde_json::json!({
+            "session_id": "busy",
+            "messages": [{ "role": "user", "content": "Second question." }],
+            "stream": false,
+        })).unwrap());
 
-                                if let Some(assistant_stored) = stored.first() {
-                                    texts.push(assistant_content);
-                                    message_ids.push(assistant_stored.id);
-                                }
-                                if texts.is_empty() {
-                                    return;
-                                }
+        let running = shared_state.register_generation("busy", usize::MAX).unwrap();
+        let rejected = generate_stream(State(state.clone()), None, request()).await;
+        assert_eq!(rejected.status(), StatusCode::CONFLICT);
+        assert!(!running.is_cancelled());
+        assert!(shared_state.register_generation("busy", 0).is_none());
+        assert!(database.conversations.get_session_messages("busy", None, None).unwrap().is_empty());
 
-                                match llm_for_embed.generate_embeddings(texts).await {
-                                    Ok(embeddings) => {
-                                        let now = chrono::Utc::now();
-                                        for (embedding_vec, msg_id) in embeddings.into_iter().zip(message_ids.iter()) {
-                                            let emb = Embedding {
-                                                id: 0,
-                                                message_id: *msg_id,
-                                                embedding: embedding_vec,
-                                                embedding_model: "llama-server".to_string(),
-                                                generated_at: now,
-                                            };
-                                            if let Err(e) = db_for_embed.embeddings.store_embedding(&emb) {
-                                                debug!("Failed to store embedding for msg {}: {}", msg_id, e);
-                                            }
-                                        }
-
-                                        for msg_id in &message_ids {
-                                            let _ = db_for_embed.conversations.mark_embedding_generated(*msg_id);
-                                        }
-                                        debug!("Stored {} embeddings for session {}", message_ids.len(), session_id_for_embed);
-                                    }
-                                    Err(e) => {
-                                        debug!("Embedding generation skipped (llama-server may not support /v1/embeddings): {}", e);
-                                    }
-                                }
-                            });
-                        }
-                        Err(e) => {
-                            error!("Failed to persist assistant message: {}", e);
-                        }
-                    }
-                }
-            };
-            Sse::new(output_stream)
-                .keep_alive(
-                    axum::response::sse::KeepAlive::new()
-                        .interval(std::time::Duration::from_secs(15))
-                )
-                .into_response()
-        }
-        Err(e) => {
-            error!("Failed to start LLM stream: {}", e);
-            (StatusCode::BAD_GATEWAY, format!("LLM backend error: {}", e)).into_response()
-        }
+        shared_state.finish_generation("busy", usize::MAX);
+        let response = generate_stream(State(state), None, request()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(shared_state.active_generations.get("busy").is_none());
     }
-}
+    #[tokio::test]
+    async fn test_multiple_choices_take_a_slot_each_and_store_alternates_beside_the_reply() {
+        use axum::routing::post;
+        let app = axum::Router::new().route("/v1/chat/completions", post(|| async {
+            Json(serde_json::json!({
+                "choices": [
+                    { "index": 1, "message": { "role": "assistant", "content": "Second take." }, "finish_reason": "stop" },
+                    { "index": 0, "message": { "role": "assistant", "content": "First take." }, "finish_reason": "stop" },
+                ],
+            }))
+        }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let backend = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
 
+        let mut config = create_test_config();
+        config.backend_url = backend;
+        config.max_concurrent_streams = 3;
+        let database = Arc::new(MemoryDatabase::new_in_memory().unwrap());
+        let shared_state = Arc::new(SharedState::new(config, database.clone()).unwrap());
+        let state = UnifiedAppState::new(shared_state.clone(), Arc::new(crate::worker_threads::DatabaseWorker::new(shared_state.clone())));
+        let request = |n: u32| Json(serde_json::from_value::<StreamChatRequest>(serde_json::json!({
+            "session_id": "best-of",
+            "messages": [{ "role": "user", "content": "Name the release." }],
+            "stream": false,
+            "n": n,
+            "persist_all_choices": true,
+        })).unwrap());
 
+        // Two of three slots are busy, so a second candidate has nowhere to run.
+        let busy = StreamSlot::acquire_many(&shared_state, 2).unwrap();
+        let rejected = generate_stream(State(state.clone()), None, request(2)).await;
+        assert_eq!(rejected.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let oversized = generate_stream(State(state.clone()), None, request(4)).await;
+        assert_eq!(oversized.status(), StatusCode::BAD_REQUEST);
+        drop(busy);
+
+        let response = generate_stream(State(state), None, request(2)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(shared_state.stream_slots.available_permits(), 3);
+
+        let messages = database.conversations.get_session_messages("best-of", None, None).unwrap();
+        let replies: Vec<_> = messages.iter().filter(|m| m.role == "assistant").collect();
+        assert_eq!(replies.len(), 1);
+        assert_eq!(replies[0].content, "First take.");
+        let alternates = database.conversations.get_session_alternates("best-of").unwrap();
+        assert_eq!(alternates[&replies[0].id], vec![MessageAlternate {
+            choice_index: 1,
+            content: "Second take.".to_string(),
+            finish_reason: None,
+            completion_tokens: None,
+        }]);
+    }
+}
@@ -6,6 +6,7 @@ use axum::{
 use serde::{Deserialize, Serialize};
 use tracing::info;
 use crate::shared_state::UnifiedAppState;
+use crate::api::error::ApiError;
 #[derive(Debug, Deserialize)]
 pub struct GenerateTitleRequest {
     pub prompt: String,
@@ -19,24 +20,15 @@ fn default_max_tokens() -> u32 {
 pub struct GenerateTitleResponse {
     pub title: String,
 }
-#[derive(Debug, Serialize)]
-pub struct ErrorResponse {
-    pub error: String,
-}
 /
 /
 pub async fn generate_title(
     State(state): State<UnifiedAppState>,
     Json(req): Json<GenerateTitleRequest>,
-) -> Result<Json<GenerateTitleResponse>, (StatusCode, Json<ErrorResponse>)> {
+) -> Result<Json<GenerateTitleResponse>, ApiError> {
     info!("Generating title for prompt (length: {} chars)", req.prompt.len());
     if req.prompt.is_empty() {
-        return Err((
-            StatusCode::BAD_REQUEST,
-            Json(ErrorResponse {
-                error: "Prompt cannot be empty".to_string(),
-            }),
-        ));
+        return Err(ApiError::new(StatusCode::BAD_REQUEST, "Prompt cannot be empty"));
     }
     let llm_worker = state.llm_worker.clone();
 
@@ -54,12 +46,7 @@ pub async fn generate_title(
         }
         Err(e) => {
             info!("Title generation failed: {}", e);
-            Err((
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ErrorResponse {
-                    error: format!("Title generation failed: {}", e),
-                }),
-            ))
+            Err(ApiError::new(e.status_code(), format!("Title generation failed: {}", e)))
         }
     }
 }
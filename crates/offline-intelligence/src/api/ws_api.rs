@@ -0,0 +1,239 @@
+﻿//!
+//! WebSocket transport for streaming generation. The first client message carries a
+//! `StreamChatRequest`; every SSE data payload from `generate_stream` is then sent as a text frame.
+use axum::{
+    extract::{
+        ws::{Message as WsMessage, WebSocket, WebSocketUpgrade},
+        Extension, State,
+    },
+    response::{IntoResponse, Response},
+};
+use futures_util::{SinkExt, StreamExt};
+use serde::Deserialize;
+use tracing::{debug, info, warn};
+use crate::api::auth::AuthenticatedTenant;
+use crate::api::rate_limit::StreamPermit;
+use crate::api::stream_api::{start_generation, StreamChatRequest, StreamSlot};
+use crate::shared_state::UnifiedAppState;
+// The socket holds a generation slot and a rate-limit permit from the upgrade on, so a
+// client that never sends its request must not keep them.
+const FIRST_MESSAGE_TIMEOUT: std::time::Duration = if cfg!(test) {
+    std::time::Duration::from_millis(500)
+} else {
+    std::time::Duration::from_secs(10)
+};
+/
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ControlMessage {
+    Cancel,
+}
+/
+pub async fn generate_ws(
+    State(state): State<UnifiedAppState>,
+    tenant: Option<Extension<AuthenticatedTenant>>,
+    permit: Option<Extension<StreamPermit>>,
+    ws: WebSocketUpgrade,
+) -> Response {
+    let tenant = tenant.map(|Extension(tenant)| tenant);
+    let permit = permit.map(|Extension(permit)| permit);
+    // Reserve a generation slot before upgrading so a busy server answers 503 over plain HTTP.
+    let slot = match StreamSlot::acquire(&state.shared_state) {
+        Ok(slot) => slot,
+        Err(rejection) => return rejection.into_response(),
+    };
+    ws.on_upgrade(move |socket| async move {
+        // The 101 response ends at once; the rate limiter's permit and the generation
+        // slot must instead last as long as the socket.
+        let _held = (permit, slot);
+        handle_socket(socket, state, tenant).await
+    })
+}
+async fn handle_socket(socket: WebSocket, state: UnifiedAppState, tenant: Option<AuthenticatedTenant>) {
+    let (mut sender, mut receiver) = socket.split();
+
+    let first = match tokio::time::timeout(FIRST_MESSAGE_TIMEOUT, receiver.next()).await {
+        Ok(first) => first,
+        Err(_) => {
+            debug!("WebSocket sent no request within {:?}", FIRST_MESSAGE_TIMEOUT);
+            let _ = sender.send(error_frame("Timed out waiting for the request")).await;
+            let _ = sender.close().await;
+            return;
+        }
+    };
+    let req = match first {
+        Some(Ok(WsMessage::Text(text))) => match serde_json::from_str::<StreamChatRequest>(&text) {
+            Ok(req) => StreamChatRequest { tenant, ..req },
+            Err(e) => {
+                let _ = sender.send(error_frame(&format!("Invalid request: {}", e))).await;
+                let _ = sender.close().await;
+                return;
+            }
+        },
+        Some(Ok(_)) => {
+            let _ = sender.send(error_frame("First message must be a JSON text frame")).await;
+            let _ = sender.close().await;
+            return;
+        }
+        Some(Err(e)) => {
+            debug!("WebSocket closed before request: {}", e);
+            return;
+        }
+        None => return,
+    };
+    let session_id = req.session_id.clone();
+    info!("WebSocket generation for session: {}", session_id);
+
+    // The upgrade reserved one slot; every further candidate is another backend request.
+    let _extra_slots = match req.n {
+        0 | 1 => None,
+        n => match StreamSlot::acquire_many(&state.shared_state, n - 1) {
+            Ok(slots) => Some(slots),
+            Err(err) => {
+                let _ = sender.send(WsMessage::Text(
+                    serde_json::json!({ "error": err.message, "code": err.status.as_u16() }).to_string(),
+                )).await;
+                let _ = sender.close().await;
+                return;
+            }
+        },
+    };
+    let frames = match start_generation(&state, req).await {
+        Ok((_, frames)) => frames,
+        Err(err) => {
+            let _ = sender.send(WsMessage::Text(
+                serde_json::json!({ "error": err.message, "code": err.status.as_u16() }).to_string(),
+            )).await;
+            let _ = sender.close().await;
+            return;
+        }
+    };
+    futures_util::pin_mut!(frames);
+
+    loop {
+        tokio::select! {
+            frame = frames.next() => match frame {
+                Some(data) => {
+                    if sender.send(WsMessage::Text(data)).await.is_err() {
+                        debug!("WebSocket client for session {} went away", session_id);
+                        state.shared_state.cancel_generation(&session_id);
+                        break;
+                    }
+                }
+                None => break,
+            },
+            incoming = receiver.next() => match incoming {
+                Some(Ok(WsMessage::Text(text))) => match serde_json::from_str::<ControlMessage>(&text) {
+                    Ok(ControlMessage::Cancel) => {
+                        info!("WebSocket cancel requested for session {}", session_id);
+                        state.shared_state.cancel_generation(&session_id);
+                    }
+                    Err(e) => warn!("Ignoring unknown WebSocket control message: {}", e),
+                },
+                Some(Ok(WsMessage::Close(_))) | None | Some(Err(_)) => {
+                    state.shared_state.cancel_generation(&session_id);
+                    break;
+                }
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+    let _ = sender.close().await;
+}
+fn error_frame(message: &str) -> WsMessage {
+    WsMessage::Text(serde_json::json!({ "error": message }).to_string())
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::rate_limit::{rate_limit, ClientRateLimiter};
+    use crate::config::tests::create_test_config;
+    use crate::memory_db::MemoryDatabase;
+    use crate::shared_state::SharedState;
+    use crate::worker_threads::DatabaseWorker;
+    use axum::{routing::get, Router};
+    use std::sync::Arc;
+    async fn serve(max_concurrent_streams: u32, per_client_streams: u32) -> (String, Arc<SharedState>) {
+        let mut config = create_test_config();
+        config.max_concurrent_streams = max_concurrent_streams;
+        let database = Arc::new(MemoryDatabase::new_in_memory().unwrap());
+        let shared_state = Arc::new(SharedState::new(config, database).unwrap());
+        let database_worker = Arc::new(DatabaseWorker::new(shared_state.clone()));
+        let state = UnifiedAppState::new(shared_state.clone(), database_worker);
+        let limiter = Arc::new(ClientRateLimiter::new(100, per_client_streams));
+        let app = Router::new()
+            .route(
+                "/generate/ws",
+                get(generate_ws).route_layer(axum::middleware::from_fn_with_state(limiter, rate_limit)),
+            )
+            .with_state(state);
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}/generate/ws", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            axum::serve(listener, app.into_make_service_with_connect_info::<std::net::SocketAddr>()).await.unwrap()
+        });
+        (url, shared_state)
+    }
+    #[tokio::test]
+    async fn test_open_socket_holds_the_concurrency_permit() {
+        let (url, _) = serve(4, 1).await;
+
+        // The first socket stays open without sending its request.
+        let (first, _) = tokio_tungstenite::connect_async(&url).await.unwrap();
+        match tokio_tungstenite::connect_async(&url).await {
+            Err(tokio_tungstenite::tungstenite::Error::Http(response)) => {
+                assert_eq!(response.status(), axum::http::StatusCode::TOO_MANY_REQUESTS);
+            }
+            other => panic!("second socket should be rejected, got {:?}", other.map(|_| ())),
+        }
+
+        drop(first);
+        let mut reconnected = None;
+        for _ in 0..50 {
+            if let Ok(socket) = tokio_tungstenite::connect_async(&url).await {
+                reconnected = Some(socket);
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+        assert!(reconnected.is_some(), "permit was not released when the socket closed");
+    }
+    #[tokio::test]
+    async fn test_open_socket_holds_a_generation_slot() {
+        let (url, shared_state) = serve(1, 4).await;
+
+        let (first, _) = tokio_tungstenite::connect_async(&url).await.unwrap();
+        assert_eq!(shared_state.stream_slots.available_permits(), 0);
+        match tokio_tungstenite::connect_async(&url).await {
+            Err(tokio_tungstenite::tungstenite::Error::Http(response)) => {
+                assert_eq!(response.status(), axum::http::StatusCode::SERVICE_UNAVAILABLE);
+            }
+            other => panic!("second socket should be rejected, got {:?}", other.map(|_| ())),
+        }
+
+        drop(first);
+        for _ in 0..50 {
+            if shared_state.stream_slots.available_permits() == 1 {
+                return;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+        panic!("generation slot was not released when the socket closed");
+    }
+    #[tokio::test]
+    async fn test_idle_socket_releases_its_generation_slot() {
+        let (url, shared_state) = serve(1, 4).await;
+
+        // The client stays connected but never sends its request.
+        let (_idle, _) = tokio_tungstenite::connect_async(&url).await.unwrap();
+        assert_eq!(shared_state.stream_slots.available_permits(), 0);
+        tokio::time::sleep(FIRST_MESSAGE_TIMEOUT).await;
+        for _ in 0..50 {
+            if shared_state.stream_slots.available_permits() == 1 {
+                return;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+        panic!("idle socket kept its generation slot past the first-message timeout");
+    }
+}
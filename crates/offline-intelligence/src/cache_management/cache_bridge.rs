@@ -1,10 +1,73 @@
-﻿use tracing::debug;
+﻿use serde::{Deserialize, Serialize};
+use tracing::debug;
 /
 pub struct CacheContextBridge {
     cache_history: Vec<CacheTransition>,
     _max_history: usize,
 
     max_transition_history: usize,
+    templates: BridgeTemplates,
+}
+/
+/
+/
+/
+/
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BridgeTemplates {
+    /
+    pub clear: String,
+    /
+    pub retrieval: String,
+    /
+    pub restore: String,
+}
+impl Default for BridgeTemplates {
+    fn default() -> Self {
+        Self {
+            clear: "[Cache Management] Cleared {count} entries from cache, preserved {preserved} important entries related to: {keywords}. Continuing with optimized context.".to_string(),
+            retrieval: "[Memory Retrieval] Retrieved {count} entries from {source} for {keywords}{similarity}. Integrating into current context.".to_string(),
+            restore: "[Cache Restoration] Restored {count} entries from previous snapshot{age}. Context has been expanded.".to_string(),
+        }
+    }
+}
+const CLEAR_PLACEHOLDERS: &[&str] = &["count", "preserved", "keywords"];
+const RETRIEVAL_PLACEHOLDERS: &[&str] = &["count", "source", "keywords", "similarity"];
+const RESTORE_PLACEHOLDERS: &[&str] = &["count", "age"];
+impl BridgeTemplates {
+    /
+    pub fn validate(&self) -> anyhow::Result<()> {
+        validate_template("clear", &self.clear, CLEAR_PLACEHOLDERS)?;
+        validate_template("retrieval", &self.retrieval, RETRIEVAL_PLACEHOLDERS)?;
+        validate_template("restore", &self.restore, RESTORE_PLACEHOLDERS)?;
+        Ok(())
+    }
+}
+fn validate_template(name: &str, template: &str, allowed: &[&str]) -> anyhow::Result<()> {
+    let mut rest = template;
+    while let Some(open) = rest.find(['{', '}']) {
+        if rest[open..].starts_with('}') {
+            return Err(anyhow::anyhow!("Unmatched '}}' in {} bridge template", name));
+        }
+        let after = &rest[open + 1..];
+        let close = after.find('}')
+            .ok_or_else(|| anyhow::anyhow!("Unclosed '{{' in {} bridge template", name))?;
+        let placeholder = &after[..close];
+        if !allowed.contains(&placeholder) {
+            return Err(anyhow::anyhow!(
+                "Unknown placeholder '{{{}}}' in {} bridge template (allowed: {})",
+                placeholder, name, allowed.join(", ")
+            ));
+        }
+        rest = &after[close + 1..];
+    }
+    Ok(())
+}
+fn render_template(template: &str, values: &[(&str, &str)]) -> String {
+    values.iter().fold(template.to_string(), |text, (placeholder, value)| {
+        text.replace(&format!("{{{}}}", placeholder), value)
+    })
 }
 #[derive(Debug, Clone)]
 pub struct CacheTransition {
@@ -30,12 +93,14 @@ pub struct CacheBridgeStats {
 }
 impl CacheContextBridge {
     /
-    pub fn new(max_history: usize) -> Self {
-        Self {
+    pub fn new(max_history: usize, templates: BridgeTemplates) -> anyhow::Result<Self> {
+        templates.validate()?;
+        Ok(Self {
             cache_history: Vec::new(),
             _max_history: max_history,
             max_transition_history: 50,
-        }
+            templates,
+        })
     }
     /
     pub fn create_clear_bridge(
@@ -57,10 +122,11 @@ impl CacheContextBridge {
             keywords.iter().take(3).cloned().collect::<Vec<_>>().join(", ")
         };
 
-        format!(
-            "[Cache Management] Cleared {} entries from cache, preserved {} important entries related to: {}. Continuing with optimized context.",
-            cleared_count, preserved_count, keyword_list
-        )
+        render_template(&self.templates.clear, &[
+            ("count", &cleared_count.to_string()),
+            ("preserved", &preserved_count.to_string()),
+            ("keywords", &keyword_list),
+        ])
     }
     /
     pub fn create_retrieval_bridge(
@@ -94,10 +160,12 @@ impl CacheContextBridge {
             format!("'{}'", keywords.iter().take(3).cloned().collect::<Vec<_>>().join("', '"))
         };
 
-        format!(
-            "[Memory Retrieval] Retrieved {} entries from {} for {}{}. Integrating into current context.",
-            retrieved_count, source_desc, keyword_list, similarity_text
-        )
+        render_template(&self.templates.retrieval, &[
+            ("count", &retrieved_count.to_string()),
+            ("source", source_desc),
+            ("keywords", &keyword_list),
+            ("similarity", &similarity_text),
+        ])
     }
     /
     pub fn create_restore_bridge(
@@ -123,10 +191,10 @@ impl CacheContextBridge {
             })
             .unwrap_or_default();
 
-        format!(
-            "[Cache Restoration] Restored {} entries from previous snapshot{}. Context has been expanded.",
-            restored_count, age_text
-        )
+        render_template(&self.templates.restore, &[
+            ("count", &restored_count.to_string()),
+            ("age", &age_text),
+        ])
     }
     fn record_transition(
         &mut self,
@@ -176,4 +244,37 @@ impl CacheContextBridge {
         self.cache_history.shrink_to_fit();
     }
 }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_default_templates_render_original_wording() {
+        let mut bridge = CacheContextBridge::new(20, BridgeTemplates::default()).unwrap();
+        let text = bridge.create_clear_bridge(10, 3, &["rust".to_string()]);
+        assert_eq!(
+            text,
+            "[Cache Management] Cleared 10 entries from cache, preserved 3 important entries related to: rust. Continuing with optimized context."
+        );
+    }
+    #[test]
+    fn test_custom_templates_and_validation() {
+        let templates = BridgeTemplates {
+            restore: "[Reprise] {count} entrées restaurées{age}".to_string(),
+            ..Default::default()
+        };
+        let mut bridge = CacheContextBridge::new(20, templates).unwrap();
+        assert_eq!(bridge.create_restore_bridge(5, None), "[Reprise] 5 entrées restaurées");
+
+        let unknown = BridgeTemplates {
+            clear: "Cleared {total}".to_string(),
+            ..Default::default()
+        };
+        assert!(CacheContextBridge::new(20, unknown).is_err());
 
+        let unclosed = BridgeTemplates {
+            retrieval: "Retrieved {count".to_string(),
+            ..Default::default()
+        };
+        assert!(CacheContextBridge::new(20, unclosed).is_err());
+    }
+}
@@ -1,4 +1,5 @@
 ﻿use serde::{Deserialize, Serialize};
+use crate::cache_management::cache_bridge::BridgeTemplates;
 /
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct KVCacheConfig {
@@ -37,6 +38,24 @@ pub struct KVCacheConfig {
 
     /
     pub snapshot_strategy: SnapshotStrategy,
+
+    /
+    pub quantize_embeddings: bool,
+
+    /
+    #[serde(default)]
+    pub bridge_templates: BridgeTemplates,
+
+    /
+    #[serde(default = "default_max_tracked_sessions")]
+    pub max_tracked_sessions: usize,
+
+    /
+    #[serde(default)]
+    pub tier_weights: TierWeights,
+}
+fn default_max_tracked_sessions() -> usize {
+    1024
 }
 impl Default for KVCacheConfig {
     fn default() -> Self {
@@ -56,6 +75,40 @@ impl Default for KVCacheConfig {
                 interval_conversations: 4,
                 max_snapshots: 4,
             },
+            quantize_embeddings: false,
+            bridge_templates: BridgeTemplates::default(),
+            max_tracked_sessions: default_max_tracked_sessions(),
+            tier_weights: TierWeights::default(),
+        }
+    }
+}
+/
+/
+/
+/
+/
+/
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TierWeights {
+    pub tier1: f32,
+    pub tier2: f32,
+    pub tier3: f32,
+}
+impl Default for TierWeights {
+    fn default() -> Self {
+        // Tier 3 scores are keyword matches over whole messages; a perfect match
+        // there ranks level with a 0.7 live-cache hit rather than above it.
+        Self { tier1: 1.0, tier2: 0.85, tier3: 0.7 }
+    }
+}
+impl TierWeights {
+    /
+    pub fn weight(&self, tier: u8) -> f32 {
+        match tier {
+            1 => self.tier1,
+            2 => self.tier2,
+            3 => self.tier3,
+            _ => 1.0,
         }
     }
 }
@@ -75,6 +128,35 @@ pub enum RetrievalStrategy {
         keyword_weight: f32,
         semantic_weight: f32,
     },
+    /
+    Exhaustive,
+}
+/
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetrievalPlan {
+    /
+    pub tier_order: Vec<u8>,
+    /
+    pub early_exit: bool,
+    pub keyword_weight: f32,
+    pub semantic_weight: f32,
+}
+impl RetrievalStrategy {
+    /
+    pub fn plan(&self) -> RetrievalPlan {
+        let (tier_order, early_exit, keyword_weight, semantic_weight) = match self {
+            RetrievalStrategy::KeywordOnly => (vec![1, 2, 3], true, 1.0, 0.0),
+            RetrievalStrategy::SemanticOnly => (vec![1, 2, 3], true, 0.0, 1.0),
+            RetrievalStrategy::KeywordThenSemantic => (vec![1, 2, 3], true, 0.7, 0.3),
+            // Persisted messages carry stored embeddings, so search them first.
+            RetrievalStrategy::SemanticThenKeyword => (vec![3, 2, 1], true, 0.3, 0.7),
+            RetrievalStrategy::Hybrid { keyword_weight, semantic_weight } => {
+                (vec![1, 2, 3], true, *keyword_weight, *semantic_weight)
+            }
+            RetrievalStrategy::Exhaustive => (vec![1, 2, 3], false, 0.5, 0.5),
+        };
+        RetrievalPlan { tier_order, early_exit, keyword_weight, semantic_weight }
+    }
 }
 /
 #[derive(Debug, Clone, Serialize, Deserialize)]
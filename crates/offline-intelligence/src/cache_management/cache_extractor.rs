@@ -1,6 +1,6 @@
 ﻿use regex::Regex;
 use std::collections::HashMap;
-use tracing::{debug, trace};
+use tracing::{debug, info, trace};
 /
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum CacheEntryType {
@@ -44,6 +44,13 @@ pub struct KVEntry {
     pub access_count: i32,
     pub last_accessed: chrono::DateTime<chrono::Utc>,
 }
+impl KVEntry {
+    /
+    /
+    pub fn position(&self) -> (i32, i32) {
+        kv_position(self.layer_index, self.head_index)
+    }
+}
 /
 #[derive(Debug, Clone)]
 pub struct ExtractedCacheEntry {
@@ -57,6 +64,16 @@ pub struct ExtractedCacheEntry {
     pub access_count: i32,
     pub keywords: Vec<String>,
 }
+impl ExtractedCacheEntry {
+    /
+    pub fn position(&self) -> (i32, i32) {
+        kv_position(self.layer_index, self.head_index)
+    }
+}
+/
+fn kv_position(layer_index: i32, head_index: Option<i32>) -> (i32, i32) {
+    (layer_index, head_index.unwrap_or(-1))
+}
 /
 pub struct CacheExtractor {
     patterns: HashMap<CacheEntryType, Regex>,
@@ -65,7 +82,11 @@ pub struct CacheExtractor {
 #[derive(Debug, Clone)]
 pub struct CacheExtractorConfig {
     pub min_value_size: usize,
+    /
     pub max_value_size: usize,
+    /
+    /
+    pub truncate_oversized: bool,
     pub extract_keywords: bool,
     pub keyword_min_length: usize,
 }
@@ -74,6 +95,7 @@ impl Default for CacheExtractorConfig {
         Self {
             min_value_size: 10,
             max_value_size: 10000,
+            truncate_oversized: false,
             extract_keywords: true,
             keyword_min_length: 3,
         }
@@ -132,13 +154,29 @@ impl CacheExtractor {
         scorer: &impl CacheEntryScorer,
     ) -> Vec<ExtractedCacheEntry> {
         let mut extracted = Vec::new();
+        let (mut skipped_empty, mut skipped_small, mut skipped_oversized, mut truncated) = (0, 0, 0, 0);
 
         for entry in entries {
 
-            if entry.value_data.len() < self.config.min_value_size
-                || entry.value_data.len() > self.config.max_value_size {
+            // Empty values carry nothing to preserve and only add zero-similarity noise.
+            if entry.value_data.is_empty() {
+                skipped_empty += 1;
                 continue;
             }
+            if entry.value_data.len() < self.config.min_value_size {
+                skipped_small += 1;
+                continue;
+            }
+            let value_data = if entry.value_data.len() > self.config.max_value_size {
+                if !self.config.truncate_oversized {
+                    skipped_oversized += 1;
+                    continue;
+                }
+                truncated += 1;
+                entry.value_data[..self.config.max_value_size].to_vec()
+            } else {
+                entry.value_data.clone()
+            };
 
 
             let entry_type = self.classify_entry(entry);
@@ -154,7 +192,7 @@ impl CacheExtractor {
                 entry_type,
                 key_hash: entry.key_hash.clone(),
                 key_data: entry.key_data.clone(),
-                value_data: entry.value_data.clone(),
+                value_data,
                 layer_index: entry.layer_index,
                 head_index: entry.head_index,
                 importance_score: entry.importance_score,
@@ -172,7 +210,13 @@ impl CacheExtractor {
         extracted.sort_by(|a, b| b.importance_score.partial_cmp(&a.importance_score)
             .unwrap_or(std::cmp::Ordering::Equal));
 
-        debug!("Extracted {} important cache entries", extracted.len());
+        if skipped_empty + skipped_oversized + truncated > 0 {
+            info!(
+                "Cache extraction: skipped {} empty and {} oversized entries, truncated {} (max {} bytes)",
+                skipped_empty, skipped_oversized, truncated, self.config.max_value_size
+            );
+        }
+        debug!("Extracted {} important cache entries ({} below minimum size)", extracted.len(), skipped_small);
         extracted
     }
 
@@ -230,4 +274,59 @@ impl CacheExtractor {
             .collect()
     }
 }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct NoKeywords;
+    impl CacheEntryScorer for NoKeywords {
+        fn extract_keywords(&self, _key_data: Option<&[u8]>) -> Vec<String> {
+            Vec::new()
+        }
+    }
+
+    fn entry(key_hash: &str, value_len: usize) -> KVEntry {
+        KVEntry {
+            key_hash: key_hash.to_string(),
+            key_data: None,
+            value_data: vec![7; value_len],
+            key_type: "attention_key".to_string(),
+            layer_index: 0,
+            head_index: None,
+            importance_score: 0.5,
+            access_count: 1,
+            last_accessed: chrono::Utc::now(),
+        }
+    }
 
+    #[test]
+    fn test_empty_values_are_skipped_even_without_minimum() {
+        let extractor = CacheExtractor::new(CacheExtractorConfig {
+            min_value_size: 0,
+            ..Default::default()
+        });
+        let extracted = extractor.extract_entries(&[entry("empty", 0), entry("kept", 16)], &NoKeywords);
+        let hashes: Vec<_> = extracted.iter().map(|e| e.key_hash.as_str()).collect();
+        assert_eq!(hashes, vec!["kept"]);
+    }
+
+    #[test]
+    fn test_oversized_values_are_skipped_or_truncated() {
+        let entries = [entry("big", 64), entry("fits", 32)];
+        let config = CacheExtractorConfig {
+            min_value_size: 1,
+            max_value_size: 32,
+            ..Default::default()
+        };
+
+        let skipping = CacheExtractor::new(config.clone());
+        let extracted = skipping.extract_entries(&entries, &NoKeywords);
+        assert_eq!(extracted.len(), 1);
+        assert_eq!(extracted[0].key_hash, "fits");
+
+        let truncating = CacheExtractor::new(CacheExtractorConfig { truncate_oversized: true, ..config });
+        let extracted = truncating.extract_entries(&entries, &NoKeywords);
+        assert_eq!(extracted.len(), 2);
+        assert!(extracted.iter().all(|e| e.value_data.len() == 32));
+    }
+}
@@ -1,12 +1,14 @@
-﻿use crate::memory::Message;
+﻿use crate::memory::{Message, Role};
 use crate::memory_db::MemoryDatabase;
-use crate::cache_management::cache_config::{KVCacheConfig, SnapshotStrategy};
+use crate::memory_db::embedding_store::cosine_similarity;
+use crate::worker_threads::LLMWorker;
+use crate::cache_management::cache_config::{KVCacheConfig, RetrievalPlan, SnapshotStrategy};
 use crate::cache_management::cache_extractor::{CacheExtractor, ExtractedCacheEntry, KVEntry};
 use crate::cache_management::cache_scorer::{CacheEntryScorer, CacheScoringConfig};
 use crate::cache_management::cache_bridge::CacheContextBridge;
 use std::sync::Arc;
-use std::collections::HashMap;
-use tracing::{info, debug};
+use std::collections::{HashMap, VecDeque};
+use tracing::{info, debug, warn};
 use chrono::{Utc, DateTime};
 use serde::Serialize;
 /
@@ -18,8 +20,12 @@ pub struct KVCacheManager {
     context_bridge: CacheContextBridge,
     statistics: CacheStatistics,
     session_state: HashMap<String, SessionCacheState>,
+    // The entries the backend last reported for each tracked session, after any clear or restore.
+    live_entries: HashMap<String, Vec<KVEntry>>,
+    session_lru: VecDeque<String>,
+    llm_worker: Option<Arc<LLMWorker>>,
 }
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct KvSnapshot {
     pub id: i64,
     pub session_id: String,
@@ -32,16 +38,32 @@ pub struct KvSnapshot {
 pub struct SessionCacheState {
     pub session_id: String,
     pub conversation_count: usize,
+    pub user_message_count: usize,
     pub last_cleared_at: Option<DateTime<Utc>>,
     pub last_snapshot_id: Option<i64>,
     pub cache_size_bytes: usize,
     pub entry_count: usize,
     pub metadata: HashMap<String, String>,
 }
+impl SessionCacheState {
+    fn new(session_id: &str) -> Self {
+        Self {
+            session_id: session_id.to_string(),
+            conversation_count: 0,
+            user_message_count: 0,
+            last_cleared_at: None,
+            last_snapshot_id: None,
+            cache_size_bytes: 0,
+            entry_count: 0,
+            metadata: HashMap::new(),
+        }
+    }
+}
 #[derive(Debug, Clone, Serialize, Default)]
 pub struct CacheStatistics {
     pub total_clears: usize,
     pub total_retrievals: usize,
+    pub total_snapshots: usize,
     pub entries_preserved: usize,
     pub entries_cleared: usize,
     pub entries_retrieved: usize,
@@ -94,6 +116,8 @@ pub struct RetrievedEntry {
     pub source_tier: u8,
     pub matched_keywords: Vec<String>,
     pub retrieval_time: DateTime<Utc>,
+    /
+    pub snapshot_id: Option<i64>,
 }
 #[derive(Debug, Clone)]
 pub struct CacheProcessingResult {
@@ -115,7 +139,7 @@ impl KVCacheManager {
         let scoring_config = CacheScoringConfig::default();
         let cache_scorer = CacheEntryScorer::new(scoring_config);
 
-        let context_bridge = CacheContextBridge::new(20);
+        let context_bridge = CacheContextBridge::new(20, config.bridge_templates.clone())?;
 
         Ok(Self {
             config,
@@ -125,21 +149,61 @@ impl KVCacheManager {
             context_bridge,
             statistics: CacheStatistics::new(),
             session_state: HashMap::new(),
+            live_entries: HashMap::new(),
+            session_lru: VecDeque::new(),
+            llm_worker: None,
         })
     }
 
     /
-    fn get_or_create_session_state(&mut self, session_id: &str) -> &mut SessionCacheState {
+    pub fn set_llm_worker(&mut self, worker: Arc<LLMWorker>) {
+        self.llm_worker = Some(worker);
+    }
+
+    /
+    async fn get_or_create_session_state(&mut self, session_id: &str) -> &mut SessionCacheState {
+        if !self.session_state.contains_key(session_id) {
+            let state = match self.database.load_kv_cache_metadata(session_id).await {
+                Ok(Some(state)) => {
+                    debug!("Rehydrated cache state for session {}", session_id);
+                    state
+                }
+                Ok(None) => SessionCacheState::new(session_id),
+                Err(e) => {
+                    warn!("Failed to load cache state for session {}: {}", session_id, e);
+                    SessionCacheState::new(session_id)
+                }
+            };
+            self.session_state.insert(session_id.to_string(), state);
+        }
+        self.touch_session(session_id);
+        self.evict_excess_sessions().await;
         self.session_state.entry(session_id.to_string())
-            .or_insert_with(|| SessionCacheState {
-                session_id: session_id.to_string(),
-                conversation_count: 0,
-                last_cleared_at: None,
-                last_snapshot_id: None,
-                cache_size_bytes: 0,
-                entry_count: 0,
-                metadata: HashMap::new(),
-            })
+            .or_insert_with(|| SessionCacheState::new(session_id))
+    }
+
+    fn touch_session(&mut self, session_id: &str) {
+        if let Some(pos) = self.session_lru.iter().position(|id| id == session_id) {
+            self.session_lru.remove(pos);
+        }
+        self.session_lru.push_back(session_id.to_string());
+    }
+
+    /
+    async fn evict_excess_sessions(&mut self) {
+        let capacity = self.config.max_tracked_sessions.max(1);
+        while self.session_state.len() > capacity {
+            let Some(oldest) = self.session_lru.pop_front() else {
+                break;
+            };
+            self.live_entries.remove(&oldest);
+            if let Some(state) = self.session_state.remove(&oldest) {
+                if let Err(e) = self.update_session_metadata(&oldest, &state).await {
+                    warn!("Failed to persist evicted cache state for session {}: {}", oldest, e);
+                }
+                debug!("Evicted cache state for session {}", oldest);
+            }
+        }
     }
 
     /
@@ -154,17 +218,22 @@ impl KVCacheManager {
         debug!("Processing conversation for session: {}", session_id);
 
 
-        let current_conversation_count = self.session_state
-            .get(session_id)
-            .map(|s| s.conversation_count)
-            .unwrap_or(0);
+        // Only a new user message opens a round; passes made after the reply, retries of
+        // the same turn and retrieval-only passes must not advance the count toward
+        // clear_after_conversations.
+        let user_message_count = messages.iter().filter(|m| m.role == Role::User).count();
+        let state = self.get_or_create_session_state(session_id).await;
+        let starts_user_turn = user_message_count > state.user_message_count;
+        let conversation_count = state.conversation_count + usize::from(starts_user_turn);
 
-        let should_clear_by_conversation = self.should_clear_by_conversation(current_conversation_count + 1);
+        let should_clear_by_conversation = starts_user_turn && self.should_clear_by_conversation(conversation_count);
         let should_clear_by_memory = self.should_clear_by_memory(current_cache_size_bytes, max_cache_size_bytes);
 
 
-        let session_state = self.get_or_create_session_state(session_id);
-        session_state.conversation_count += 1;
+        self.live_entries.insert(session_id.to_string(), current_kv_entries.to_vec());
+        let session_state = self.get_or_create_session_state(session_id).await;
+        session_state.conversation_count = conversation_count;
+        session_state.user_message_count = user_message_count;
         session_state.cache_size_bytes = current_cache_size_bytes;
         session_state.entry_count = current_kv_entries.len();
 
@@ -205,7 +274,7 @@ impl KVCacheManager {
         if should_retrieve {
             let last_user_message = messages.iter()
                 .rev()
-                .find(|m| m.role == "user")
+                .find(|m| m.role == Role::User)
                 .map(|m| &m.content)
                 .map_or("", |v| v);
 
@@ -251,7 +320,7 @@ impl KVCacheManager {
         }
 
 
-        if let Some(last_user) = messages.iter().rev().find(|m| m.role == "user") {
+        if let Some(last_user) = messages.iter().rev().find(|m| m.role == Role::User) {
             let content = &last_user.content;
 
             content.contains('?') ||
@@ -280,16 +349,25 @@ impl KVCacheManager {
         let extracted = self.cache_extractor.extract_entries(current_entries, &self.cache_scorer);
 
 
-        let to_preserve = self.cache_extractor.filter_preserved_entries(
+        let mut to_preserve = self.cache_extractor.filter_preserved_entries(
             &extracted,
             self.config.min_importance_to_preserve,
             self.config.preserve_system_prompts,
             self.config.preserve_code_entries,
         );
+        // Filtering may reorder by importance; KV reuse needs layer/head order back.
+        to_preserve.sort_by_key(ExtractedCacheEntry::position);
 
 
-        let snapshot_id = if self.should_create_snapshot(&reason) {
-            Some(self.create_snapshot(session_id, &to_preserve).await?)
+        let snapshot_id = if self.should_create_snapshot(&reason, &to_preserve) {
+            let snapshot_id = self.create_snapshot(session_id, &to_preserve).await?;
+            if let SnapshotStrategy::Adaptive { max_snapshots, .. } = &self.config.snapshot_strategy {
+                let pruned = self.database.prune_session_kv_snapshots(session_id, *max_snapshots).await?;
+                if pruned > 0 {
+                    debug!("Pruned {} adaptive snapshots for session {}", pruned, session_id);
+                }
+            }
+            Some(snapshot_id)
         } else {
             None
         };
@@ -316,6 +394,7 @@ impl KVCacheManager {
         );
 
 
+        self.live_entries.insert(session_id.to_string(), Self::to_kv_entries(&to_preserve));
         if let Some(state) = self.session_state.get_mut(session_id) {
             state.entry_count = to_preserve.len();
             state.last_snapshot_id = snapshot_id;
@@ -337,7 +416,7 @@ impl KVCacheManager {
     }
 
     /
-    fn should_create_snapshot(&self, reason: &ClearReason) -> bool {
+    fn should_create_snapshot(&self, reason: &ClearReason, preserved: &[ExtractedCacheEntry]) -> bool {
         if !self.config.enabled {
             return false;
         }
@@ -348,20 +427,38 @@ impl KVCacheManager {
             SnapshotStrategy::Incremental { interval_conversations: _, max_snapshots: _ } => {
                 matches!(reason, ClearReason::ConversationLimit)
             }
-            SnapshotStrategy::Adaptive { min_importance_threshold: _, max_snapshots: _ } => true,
+            SnapshotStrategy::Adaptive { min_importance_threshold, max_snapshots } => {
+                if *max_snapshots == 0 || preserved.is_empty() {
+                    return false;
+                }
+                let mean_importance = preserved.iter().map(|e| e.importance_score).sum::<f32>()
+                    / preserved.len() as f32;
+                mean_importance > *min_importance_threshold
+            }
         }
     }
 
     /
     async fn create_snapshot(
-        &self,
+        &mut self,
         session_id: &str,
         preserved_entries: &[ExtractedCacheEntry],
     ) -> anyhow::Result<i64> {
         debug!("Creating KV snapshot for session: {}", session_id);
 
 
-        let db_entries: Vec<KVEntry> = preserved_entries.iter()
+        let db_entries = Self::to_kv_entries(preserved_entries);
+        let snapshot_id = self.database.create_kv_snapshot(session_id, &db_entries).await?;
+
+        self.statistics.record_snapshot(snapshot_id, db_entries.len(), session_id);
+
+        info!("Created KV snapshot {} with {} entries", snapshot_id, db_entries.len());
+        Ok(snapshot_id)
+    }
+
+    /
+    fn to_kv_entries(preserved_entries: &[ExtractedCacheEntry]) -> Vec<KVEntry> {
+        let mut entries: Vec<KVEntry> = preserved_entries.iter()
             .map(|entry| {
                 KVEntry {
                     key_hash: entry.key_hash.clone(),
@@ -376,12 +473,8 @@ impl KVCacheManager {
                 }
             })
             .collect();
-
-
-        let snapshot_id = self.database.create_kv_snapshot(session_id, &db_entries).await?;
-
-        info!("Created KV snapshot {} with {} entries", snapshot_id, db_entries.len());
-        Ok(snapshot_id)
+        entries.sort_by_key(KVEntry::position);
+        entries
     }
 
     /
@@ -395,33 +488,48 @@ impl KVCacheManager {
 
         let start_time = std::time::Instant::now();
         let keywords = self.extract_keywords(query);
+        let plan = self.config.retrieval_strategy.plan();
 
         let mut results = Vec::new();
         let mut searched_tiers = Vec::new();
 
-
-        if !current_cache_entries.is_empty() {
-            searched_tiers.push(1);
-            let tier1_results = self.search_tier1(current_cache_entries, &keywords).await?;
-            results.extend(tier1_results);
-        }
-
-
-        if results.len() < 5 {
-            searched_tiers.push(2);
-            let tier2_results = self.search_tier2(session_id, &keywords).await?;
-            results.extend(tier2_results);
+        // With early exit, skip the second tier once 5 results are found and the third
+        // once 3 are found, matching the original waterfall thresholds.
+        let early_exit_thresholds = [5, 3];
+        for (position, tier) in plan.tier_order.iter().enumerate() {
+            if plan.early_exit && position > 0 {
+                if let Some(&threshold) = early_exit_thresholds.get(position - 1) {
+                    if results.len() >= threshold {
+                        break;
+                    }
+                }
+            }
+            match tier {
+                1 if !current_cache_entries.is_empty() => {
+                    searched_tiers.push(1);
+                    results.extend(self.search_tier1(current_cache_entries, &keywords).await?);
+                }
+                2 => {
+                    searched_tiers.push(2);
+                    results.extend(self.search_tier2(session_id, &keywords).await?);
+                }
+                3 => {
+                    searched_tiers.push(3);
+                    results.extend(self.search_tier3(session_id, &keywords).await?);
+                }
+                _ => {}
+            }
         }
 
-
-        if results.len() < 3 {
-            searched_tiers.push(3);
-            let tier3_results = self.search_tier3(session_id, &keywords).await?;
-            results.extend(tier3_results);
+        if plan.semantic_weight > 0.0 {
+            self.apply_semantic_scores(query, &mut results, &plan).await;
         }
 
-
-        results.sort_by(|a, b| b.similarity_score.partial_cmp(&a.similarity_score)
+        // similarity_score stays the raw match strength; only the ordering is
+        // weighted by tier so hot-cache hits are not buried under cold matches.
+        let weights = self.config.tier_weights;
+        let ranked = |r: &RetrievedEntry| r.similarity_score * weights.weight(r.source_tier);
+        results.sort_by(|a, b| ranked(b).partial_cmp(&ranked(a))
             .unwrap_or(std::cmp::Ordering::Equal));
 
 
@@ -431,6 +539,7 @@ impl KVCacheManager {
         for result in &results {
             self.cache_scorer.update_engagement(&result.entry.key_hash, true);
         }
+        self.record_snapshot_accesses(&mut results).await;
 
 
         let bridge_message = if !results.is_empty() {
@@ -472,6 +581,42 @@ impl KVCacheManager {
         })
     }
 
+    /
+    async fn apply_semantic_scores(&self, query: &str, results: &mut [RetrievedEntry], plan: &RetrievalPlan) {
+        let Some(ref llm_worker) = self.llm_worker else {
+            debug!("No LLM worker for semantic scoring, using keyword scores");
+            return;
+        };
+        if results.is_empty() {
+            return;
+        }
+        let mut texts = Vec::with_capacity(results.len() + 1);
+        texts.push(query.to_string());
+        texts.extend(results.iter().map(|r| {
+            String::from_utf8_lossy(r.entry.key_data.as_deref().unwrap_or(&r.entry.value_data)).into_owned()
+        }));
+        let embeddings = match llm_worker.generate_embeddings(texts).await {
+            Ok(embeddings) if embeddings.len() == results.len() + 1 => embeddings,
+            Ok(_) => {
+                debug!("Embedding count mismatch, using keyword scores");
+                return;
+            }
+            Err(e) => {
+                debug!("Semantic scoring unavailable, using keyword scores: {}", e);
+                return;
+            }
+        };
+        let total_weight = plan.keyword_weight + plan.semantic_weight;
+        if total_weight <= 0.0 {
+            return;
+        }
+        for (result, embedding) in results.iter_mut().zip(&embeddings[1..]) {
+            let semantic = cosine_similarity(&embeddings[0], embedding);
+            result.similarity_score = (plan.keyword_weight * result.similarity_score
+                + plan.semantic_weight * semantic) / total_weight;
+        }
+    }
+
     /
     async fn search_tier1(
         &self,
@@ -490,6 +635,7 @@ impl KVCacheManager {
                     source_tier: 1,
                     matched_keywords,
                     retrieval_time: Utc::now(),
+                    snapshot_id: None,
                 });
             }
         }
@@ -507,6 +653,30 @@ impl KVCacheManager {
         Ok(results)
     }
 
+    /
+    /
+    async fn record_snapshot_accesses(&self, results: &mut [RetrievedEntry]) {
+        let accesses: Vec<_> = results
+            .iter()
+            .filter_map(|r| r.snapshot_id.map(|id| {
+                (id, r.entry.key_hash.clone(), r.entry.layer_index, r.entry.head_index)
+            }))
+            .collect();
+        if accesses.is_empty() {
+            return;
+        }
+        match self.database.record_kv_entry_accesses(accesses).await {
+            Ok(_) => {
+                let now = Utc::now();
+                for result in results.iter_mut().filter(|r| r.snapshot_id.is_some()) {
+                    result.entry.access_count += 1;
+                    result.entry.last_accessed = now;
+                }
+            }
+            Err(e) => warn!("Failed to persist cache entry access counts: {}", e),
+        }
+    }
+
     /
     async fn search_tier2(
         &self,
@@ -532,6 +702,7 @@ impl KVCacheManager {
                         source_tier: 2,
                         matched_keywords,
                         retrieval_time: Utc::now(),
+                        snapshot_id: Some(snapshot.id),
                     });
                 }
             }
@@ -587,6 +758,7 @@ impl KVCacheManager {
                     source_tier: 3,
                     matched_keywords: keywords.to_vec(),
                     retrieval_time: Utc::now(),
+                    snapshot_id: None,
                 });
             }
         }
@@ -699,9 +871,13 @@ impl KVCacheManager {
     ) -> anyhow::Result<Vec<KVEntry>> {
         info!("Restoring cache from snapshot {} for session {}", snapshot_id, session_id);
 
-        let entries: Vec<KVEntry> = self.database.get_kv_snapshot_entries(snapshot_id).await?;
+        let mut entries: Vec<KVEntry> = self.database.get_kv_snapshot_entries(snapshot_id).await?;
+        // Snapshots written before entries were stored in position order still
+        // restore deterministically; the stable sort keeps ties in stored order.
+        entries.sort_by_key(KVEntry::position);
 
 
+        self.live_entries.insert(session_id.to_string(), entries.clone());
         if let Some(state) = self.session_state.get_mut(session_id) {
             state.entry_count = entries.len();
             state.last_snapshot_id = Some(snapshot_id);
@@ -721,6 +897,38 @@ impl KVCacheManager {
         Ok(entries)
     }
 
+    /
+    /
+    pub async fn create_manual_snapshot(
+        &mut self,
+        session_id: &str,
+        entries: &[KVEntry],
+    ) -> anyhow::Result<i64> {
+        // create_kv_snapshot rewrites the metadata row as if the cache had been
+        // cleared; load the current state first so it can be written back intact.
+        self.get_or_create_session_state(session_id).await;
+        let snapshot_id = self.database.create_kv_snapshot(session_id, entries).await?;
+        self.statistics.record_snapshot(snapshot_id, entries.len(), session_id);
+
+        let state = self.get_or_create_session_state(session_id).await;
+        state.last_snapshot_id = Some(snapshot_id);
+        state.metadata.insert("last_snapshot_reason".to_string(), "Manual".to_string());
+        let state = state.clone();
+        self.update_session_metadata(session_id, &state).await?;
+
+        info!("Created manual KV snapshot {} with {} entries for session {}", snapshot_id, entries.len(), session_id);
+        Ok(snapshot_id)
+    }
+    /
+    /
+    pub async fn snapshot_live_cache(&mut self, session_id: &str) -> anyhow::Result<Option<(i64, Vec<KVEntry>)>> {
+        let entries = match self.live_entries.get(session_id) {
+            Some(entries) if !entries.is_empty() => entries.clone(),
+            _ => return Ok(None),
+        };
+        let snapshot_id = self.create_manual_snapshot(session_id, &entries).await?;
+        Ok(Some((snapshot_id, entries)))
+    }
     /
     pub async fn manual_clear_cache(
         &mut self,
@@ -756,7 +964,8 @@ impl KVCacheManager {
         }
 
 
-        if let SnapshotStrategy::Incremental { max_snapshots, .. } = &self.config.snapshot_strategy {
+        if let SnapshotStrategy::Incremental { max_snapshots, .. }
+            | SnapshotStrategy::Adaptive { max_snapshots, .. } = &self.config.snapshot_strategy {
             let pruned = self.prune_old_snapshots(*max_snapshots).await?;
             result.snapshots_pruned = pruned;
         }
@@ -767,6 +976,8 @@ impl KVCacheManager {
     /
     async fn cleanup_session(&mut self, session_id: &str) -> anyhow::Result<()> {
         self.session_state.remove(session_id);
+        self.live_entries.remove(session_id);
+        self.session_lru.retain(|id| id != session_id);
         self.database.cleanup_session_snapshots(session_id).await?;
         Ok(())
     }
@@ -781,6 +992,7 @@ impl KVCacheManager {
         CacheStatisticsExport {
             total_clears: self.statistics.total_clears,
             total_retrievals: self.statistics.total_retrievals,
+            total_snapshots: self.statistics.total_snapshots,
             entries_preserved: self.statistics.entries_preserved,
             entries_cleared: self.statistics.entries_cleared,
             entries_retrieved: self.statistics.entries_retrieved,
@@ -819,6 +1031,15 @@ impl CacheStatistics {
     pub fn new() -> Self {
         Self::default()
     }
+    /
+    pub fn recent_operations(&self, session_id: Option<&str>, limit: usize) -> Vec<CacheOperation> {
+        self.operation_history.iter()
+            .rev()
+            .filter(|op| session_id.map_or(true, |id| op.session_id == id))
+            .take(limit)
+            .cloned()
+            .collect()
+    }
     pub fn record_clear(
         &mut self,
         total_entries: usize,
@@ -886,6 +1107,9 @@ impl CacheStatistics {
     }
 
     pub fn record_snapshot(&mut self, snapshot_id: i64, entry_count: usize, session_id: &str) {
+        self.total_snapshots += 1;
+        self.last_operation = Some(Utc::now());
+
         self.operation_history.push(CacheOperation {
             operation_type: CacheOperationType::Snapshot,
             timestamp: Utc::now(),
@@ -954,6 +1178,7 @@ impl RetrievalResult {
 pub struct CacheStatisticsExport {
     pub total_clears: usize,
     pub total_retrievals: usize,
+    pub total_snapshots: usize,
     pub entries_preserved: usize,
     pub entries_cleared: usize,
     pub entries_retrieved: usize,
@@ -967,4 +1192,429 @@ pub struct MaintenanceResult {
     pub snapshots_pruned: usize,
     pub errors: Vec<String>,
 }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache_management::cache_config::{RetrievalStrategy, TierWeights};
+    #[tokio::test]
+    async fn test_create_snapshot_records_statistics() {
+        let dir = tempfile::tempdir().unwrap();
+        let database = Arc::new(MemoryDatabase::new(&dir.path().join("cache.db")).unwrap());
+        let session = database.conversations.create_session(None).unwrap();
+        database.conversations.store_messages_batch(
+            &session.id,
+            &[("user".to_string(), "hello".to_string(), 0, 1, 0.5)],
+        ).unwrap();
+
+        let mut manager = KVCacheManager::new(KVCacheConfig::default(), database).unwrap();
+        let snapshot_id = manager.create_snapshot(&session.id, &[]).await.unwrap();
+
+        let stats = manager.get_statistics();
+        assert_eq!(stats.total_snapshots, 1);
+        let last = stats.operation_history.last().unwrap();
+        assert!(matches!(last.operation_type, CacheOperationType::Snapshot));
+        assert_eq!(last.session_id, session.id);
+        assert_eq!(last.details, format!("Snapshot ID: {}", snapshot_id));
+        assert_eq!(manager.export_statistics().total_snapshots, 1);
+    }
+
+    #[test]
+    fn test_recent_operations_filter_by_session_newest_first() {
+        let mut stats = CacheStatistics::new();
+        stats.record_snapshot(1, 4, "a");
+        stats.record_retrieval(2, vec![1, 2], 3, "b");
+        stats.record_clear(10, 3, ClearReason::Manual, "a");
+
+        let all = stats.recent_operations(None, 10);
+        assert_eq!(all.len(), 3);
+        assert!(matches!(all[0].operation_type, CacheOperationType::Clear));
+
+        let session_a = stats.recent_operations(Some("a"), 10);
+        assert_eq!(session_a.len(), 2);
+        assert!(session_a.iter().all(|op| op.session_id == "a"));
+        assert_eq!(stats.recent_operations(Some("a"), 1)[0].entries_affected, 10);
+        assert!(stats.recent_operations(Some("missing"), 10).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_manual_snapshot_keeps_entries_and_records_state() {
+        let dir = tempfile::tempdir().unwrap();
+        let database = Arc::new(MemoryDatabase::new(&dir.path().join("manual.db")).unwrap());
+        let session = database.conversations.create_session(None).unwrap();
+        database.conversations.store_messages_batch(
+            &session.id,
+            &[("user".to_string(), "hello".to_string(), 0, 1, 0.5)],
+        ).unwrap();
+        let mut manager = KVCacheManager::new(KVCacheConfig::default(), database).unwrap();
+        let entries = vec![KVEntry {
+            key_hash: "k1".to_string(),
+            key_data: Some(b"checkpoint".to_vec()),
+            value_data: vec![1, 2, 3],
+            key_type: "attention_key".to_string(),
+            layer_index: 0,
+            head_index: None,
+            importance_score: 0.1,
+            access_count: 1,
+            last_accessed: Utc::now(),
+        }];
+
+        let snapshot_id = manager.create_manual_snapshot(&session.id, &entries).await.unwrap();
+
+        let state = manager.get_session_state(&session.id).unwrap();
+        assert_eq!(state.last_snapshot_id, Some(snapshot_id));
+        assert!(state.last_cleared_at.is_none());
+        let persisted = manager.database.load_kv_cache_metadata(&session.id).await.unwrap().unwrap();
+        assert!(persisted.last_cleared_at.is_none());
+        assert_eq!(persisted.last_snapshot_id, Some(snapshot_id));
+        assert_eq!(manager.get_statistics().total_snapshots, 1);
+        let restored = manager.restore_from_snapshot(&session.id, snapshot_id).await.unwrap();
+        assert_eq!(restored.len(), 1);
+        assert_eq!(restored[0].key_hash, "k1");
+    }
+
+    #[tokio::test]
+    async fn test_live_snapshot_uses_the_entries_the_backend_reported() {
+        let dir = tempfile::tempdir().unwrap();
+        let database = Arc::new(MemoryDatabase::new(&dir.path().join("live.db")).unwrap());
+        let session = database.conversations.create_session(None).unwrap();
+        let config = KVCacheConfig { retrieval_enabled: false, ..Default::default() };
+        let mut manager = KVCacheManager::new(config, database.clone()).unwrap();
+        assert!(manager.snapshot_live_cache(&session.id).await.unwrap().is_none());
+
+        let live = vec![KVEntry {
+            key_hash: "live".to_string(),
+            key_data: None,
+            value_data: vec![7; 4],
+            key_type: "attention_key".to_string(),
+            layer_index: 1,
+            head_index: Some(0),
+            importance_score: 0.4,
+            access_count: 1,
+            last_accessed: Utc::now(),
+        }];
+        let messages = vec![Message { role: Role::User, content: "hello".to_string(), parts: None }];
+        manager.process_conversation(&session.id, &messages, &live, 0, 0).await.unwrap();
+
+        let (snapshot_id, entries) = manager.snapshot_live_cache(&session.id).await.unwrap().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].key_hash, "live");
+        let stored = database.get_kv_snapshot_entries(snapshot_id).await.unwrap();
+        assert_eq!(stored[0].value_data, vec![7; 4]);
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_round_trip_preserves_layer_head_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let database = Arc::new(MemoryDatabase::new(&dir.path().join("order.db")).unwrap());
+        let session = database.conversations.create_session(None).unwrap();
+        database.conversations.store_messages_batch(
+            &session.id,
+            &[("user".to_string(), "hello".to_string(), 0, 1, 0.5)],
+        ).unwrap();
+        let mut manager = KVCacheManager::new(KVCacheConfig::default(), database).unwrap();
+        let entry = |layer: i32, head: Option<i32>, importance: f32| KVEntry {
+            key_hash: format!("L{}H{:?}", layer, head),
+            key_data: None,
+            value_data: vec![layer as u8; 16],
+            key_type: "attention_key".to_string(),
+            layer_index: layer,
+            head_index: head,
+            importance_score: importance,
+            access_count: 1,
+            last_accessed: Utc::now(),
+        };
+        // Importance deliberately disagrees with position so a sort by score would show.
+        let shuffled = vec![
+            entry(3, Some(1), 0.95),
+            entry(0, Some(2), 0.91),
+            entry(3, Some(0), 0.99),
+            entry(0, None, 0.92),
+            entry(1, Some(5), 0.97),
+            entry(0, Some(1), 0.93),
+        ];
+        let expected = ["L0HNone", "L0HSome(1)", "L0HSome(2)", "L1HSome(5)", "L3HSome(0)", "L3HSome(1)"];
+
+        let cleared = manager.clear_cache(&session.id, &shuffled, ClearReason::ConversationLimit).await.unwrap();
+        let kept: Vec<_> = cleared.entries_to_keep.iter().map(|e| e.key_hash.as_str()).collect();
+        assert_eq!(kept, expected);
+        let restored = manager.restore_from_snapshot(&session.id, cleared.snapshot_id.unwrap()).await.unwrap();
+        let restored_order: Vec<_> = restored.iter().map(|e| (e.layer_index, e.head_index)).collect();
+        let kept_order: Vec<_> = cleared.entries_to_keep.iter().map(|e| (e.layer_index, e.head_index)).collect();
+        assert_eq!(restored_order, kept_order);
+
+        let manual_id = manager.create_manual_snapshot(&session.id, &shuffled).await.unwrap();
+        let manual: Vec<_> = manager.restore_from_snapshot(&session.id, manual_id).await.unwrap()
+            .into_iter().map(|e| e.key_hash).collect();
+        assert_eq!(manual, expected);
+    }
+
+    fn adaptive_entries(importance: &[f32]) -> Vec<KVEntry> {
+        importance.iter().enumerate().map(|(i, &score)| KVEntry {
+            key_hash: format!("adaptive-{}", i),
+            key_data: None,
+            value_data: vec![i as u8; 16],
+            key_type: "attention_key".to_string(),
+            layer_index: i as i32,
+            head_index: None,
+            importance_score: score,
+            access_count: 1,
+            last_accessed: Utc::now(),
+        }).collect()
+    }
+
+    fn adaptive_manager(database: Arc<MemoryDatabase>, threshold: f32, max_snapshots: usize) -> KVCacheManager {
+        let config = KVCacheConfig {
+            min_importance_to_preserve: 0.5,
+            snapshot_strategy: SnapshotStrategy::Adaptive { min_importance_threshold: threshold, max_snapshots },
+            ..Default::default()
+        };
+        KVCacheManager::new(config, database).unwrap()
+    }
 
+    #[tokio::test]
+    async fn test_adaptive_skips_snapshot_below_importance_threshold() {
+        let dir = tempfile::tempdir().unwrap();
+        let database = Arc::new(MemoryDatabase::new(&dir.path().join("adaptive_low.db")).unwrap());
+        let session = database.conversations.create_session(None).unwrap();
+        let mut manager = adaptive_manager(database.clone(), 0.8, 3);
+
+        // Both entries survive preservation but average 0.7, under the 0.8 threshold.
+        let cleared = manager.clear_cache(&session.id, &adaptive_entries(&[0.6, 0.8]), ClearReason::MemoryThreshold).await.unwrap();
+        assert_eq!(cleared.entries_to_keep.len(), 2);
+        assert!(cleared.snapshot_id.is_none());
+        assert!(database.get_recent_kv_snapshots(&session.id, 10).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_adaptive_snapshots_above_threshold_and_prunes_to_max() {
+        let dir = tempfile::tempdir().unwrap();
+        let database = Arc::new(MemoryDatabase::new(&dir.path().join("adaptive_high.db")).unwrap());
+        let session = database.conversations.create_session(None).unwrap();
+        database.conversations.store_messages_batch(
+            &session.id,
+            &[("user".to_string(), "hello".to_string(), 0, 1, 0.5)],
+        ).unwrap();
+        let mut manager = adaptive_manager(database.clone(), 0.8, 2);
+
+        let mut snapshot_ids = Vec::new();
+        for _ in 0..3 {
+            let cleared = manager.clear_cache(&session.id, &adaptive_entries(&[0.9, 0.95]), ClearReason::MemoryThreshold).await.unwrap();
+            snapshot_ids.push(cleared.snapshot_id.expect("mean importance is above threshold"));
+        }
+
+        let remaining: Vec<i64> = database.get_recent_kv_snapshots(&session.id, 10).await.unwrap()
+            .into_iter().map(|s| s.id).collect();
+        assert_eq!(remaining.len(), 2);
+        assert!(!remaining.contains(&snapshot_ids[0]));
+    }
+
+    #[tokio::test]
+    async fn test_retrieval_persists_snapshot_access_counts() {
+        let dir = tempfile::tempdir().unwrap();
+        let database = Arc::new(MemoryDatabase::new(&dir.path().join("access.db")).unwrap());
+        let session = database.conversations.create_session(None).unwrap();
+        database.conversations.store_messages_batch(
+            &session.id,
+            &[("user".to_string(), "hello".to_string(), 0, 1, 0.5)],
+        ).unwrap();
+        let mut manager = KVCacheManager::new(KVCacheConfig::default(), database.clone()).unwrap();
+        let entry = KVEntry {
+            key_hash: "deploy".to_string(),
+            key_data: Some(b"deployment pipeline rollback".to_vec()),
+            value_data: vec![1; 16],
+            key_type: "attention_key".to_string(),
+            layer_index: 0,
+            head_index: Some(0),
+            importance_score: 0.9,
+            access_count: 0,
+            last_accessed: Utc::now(),
+        };
+        let snapshot_id = manager.create_manual_snapshot(&session.id, &[entry]).await.unwrap();
+
+        for expected in 1..=2 {
+            let retrieved = manager.retrieve_context(&session.id, "deployment pipeline", &[]).await.unwrap();
+            assert!(retrieved.retrieved_entries.iter().any(|r| r.snapshot_id == Some(snapshot_id)));
+            let stored = database.get_kv_snapshot_entries(snapshot_id).await.unwrap();
+            assert_eq!(stored[0].access_count, expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_evicted_session_state_is_rehydrated() {
+        let dir = tempfile::tempdir().unwrap();
+        let database = Arc::new(MemoryDatabase::new(&dir.path().join("cache.db")).unwrap());
+        let first = database.conversations.create_session(None).unwrap();
+        let second = database.conversations.create_session(None).unwrap();
+
+        let config = KVCacheConfig { max_tracked_sessions: 1, ..Default::default() };
+        let mut manager = KVCacheManager::new(config, database).unwrap();
+        {
+            let state = manager.get_or_create_session_state(&first.id).await;
+            state.conversation_count = 7;
+            state.entry_count = 42;
+            state.cache_size_bytes = 4096;
+            state.last_snapshot_id = Some(3);
+            state.last_cleared_at = Some(Utc::now());
+            state.metadata.insert("last_clear_reason".to_string(), "Manual".to_string());
+        }
+        let original = manager.get_session_state(&first.id).unwrap().clone();
+
+        manager.get_or_create_session_state(&second.id).await;
+        assert!(manager.get_session_state(&first.id).is_none());
+        assert_eq!(manager.get_all_session_states().len(), 1);
+
+        let restored = manager.get_or_create_session_state(&first.id).await.clone();
+        assert!(manager.get_session_state(&second.id).is_none());
+        assert_eq!(restored.conversation_count, original.conversation_count);
+        assert_eq!(restored.entry_count, original.entry_count);
+        assert_eq!(restored.cache_size_bytes, original.cache_size_bytes);
+        assert_eq!(restored.last_snapshot_id, original.last_snapshot_id);
+        assert_eq!(
+            restored.last_cleared_at.map(|dt| dt.timestamp()),
+            original.last_cleared_at.map(|dt| dt.timestamp()),
+        );
+        assert_eq!(restored.metadata, original.metadata);
+    }
+
+    #[tokio::test]
+    async fn test_conversation_count_advances_only_on_user_turns() {
+        let dir = tempfile::tempdir().unwrap();
+        let database = Arc::new(MemoryDatabase::new(&dir.path().join("turns.db")).unwrap());
+        let session = database.conversations.create_session(None).unwrap();
+        let config = KVCacheConfig {
+            clear_after_conversations: 2,
+            retrieval_enabled: false,
+            ..Default::default()
+        };
+        let mut manager = KVCacheManager::new(config, database).unwrap();
+        let message = |role: Role, content: &str| Message { role, content: content.to_string(), parts: None };
+        let mut messages = vec![message(Role::User, "first question")];
+
+        let result = manager.process_conversation(&session.id, &messages, &[], 0, 0).await.unwrap();
+        assert_eq!(result.updated_session_state.conversation_count, 1);
+
+        // The post-reply pass belongs to the same round.
+        messages.push(message(Role::Assistant, "first answer"));
+        let result = manager.process_conversation(&session.id, &messages, &[], 0, 0).await.unwrap();
+        assert!(!result.should_clear_cache);
+        assert_eq!(result.updated_session_state.conversation_count, 1);
+
+        // Regenerating the reply resends the same user turn.
+        messages.pop();
+        let result = manager.process_conversation(&session.id, &messages, &[], 0, 0).await.unwrap();
+        assert!(!result.should_clear_cache);
+        assert_eq!(result.updated_session_state.conversation_count, 1);
+
+        messages.push(message(Role::Assistant, "another answer"));
+        messages.push(message(Role::User, "second question"));
+        let result = manager.process_conversation(&session.id, &messages, &[], 0, 0).await.unwrap();
+        assert!(result.should_clear_cache);
+        assert_eq!(result.updated_session_state.conversation_count, 0);
+        assert_eq!(result.updated_session_state.user_message_count, 2);
+
+        // The count survives a restart, so resending the history does not open a round.
+        let database = Arc::new(MemoryDatabase::new(&dir.path().join("turns.db")).unwrap());
+        let mut manager = KVCacheManager::new(KVCacheConfig { clear_after_conversations: 2, retrieval_enabled: false, ..Default::default() }, database).unwrap();
+        let result = manager.process_conversation(&session.id, &messages, &[], 0, 0).await.unwrap();
+        assert_eq!(result.updated_session_state.conversation_count, 0);
+    }
+
+    fn strategy_manager(strategy: RetrievalStrategy, dir: &tempfile::TempDir) -> KVCacheManager {
+        let database = Arc::new(MemoryDatabase::new(&dir.path().join("strategy.db")).unwrap());
+        let config = KVCacheConfig { retrieval_strategy: strategy, ..Default::default() };
+        KVCacheManager::new(config, database).unwrap()
+    }
+
+    fn matching_entries(count: usize) -> Vec<KVEntry> {
+        (0..count).map(|i| KVEntry {
+            key_hash: format!("entry_{}", i),
+            key_data: Some(format!("database migration rollback step {}", i).into_bytes()),
+            value_data: Vec::new(),
+            key_type: "attention_key".to_string(),
+            layer_index: 0,
+            head_index: None,
+            importance_score: 0.5,
+            access_count: 1,
+            last_accessed: Utc::now(),
+        }).collect()
+    }
+
+    #[tokio::test]
+    async fn test_tier_weights_decide_cross_tier_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let database = Arc::new(MemoryDatabase::new(&dir.path().join("tiers.db")).unwrap());
+        let session = database.conversations.create_session(None).unwrap();
+        database.conversations.store_messages_batch(
+            &session.id,
+            &[("user".to_string(), "database migration rollback checklist".to_string(), 0, 5, 0.5)],
+        ).unwrap();
+        let hot = vec![KVEntry {
+            key_hash: "hot".to_string(),
+            key_data: Some(b"database migration notes".to_vec()),
+            ..matching_entries(1).remove(0)
+        }];
+        let top_tier = |tier_weights: TierWeights| {
+            let database = database.clone();
+            let (session_id, hot) = (session.id.clone(), hot.clone());
+            async move {
+                let config = KVCacheConfig {
+                    retrieval_strategy: RetrievalStrategy::Exhaustive,
+                    tier_weights,
+                    ..Default::default()
+                };
+                let mut manager = KVCacheManager::new(config, database).unwrap();
+                let result = manager.retrieve_context(&session_id, "database migration rollback", &hot).await.unwrap();
+                let tiers: Vec<u8> = result.retrieved_entries.iter().map(|r| r.source_tier).collect();
+                assert!(tiers.contains(&1) && tiers.contains(&3), "{:?}", tiers);
+                tiers[0]
+            }
+        };
+
+        let unweighted = TierWeights { tier1: 1.0, tier2: 1.0, tier3: 1.0 };
+        assert_eq!(top_tier(unweighted).await, 3);
+        let favour_hot = TierWeights { tier1: 1.0, tier2: 0.85, tier3: 0.6 };
+        assert_eq!(top_tier(favour_hot).await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_keyword_only_exits_after_first_tier() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut manager = strategy_manager(RetrievalStrategy::KeywordOnly, &dir);
+        let result = manager.retrieve_context("s1", "database migration rollback", &matching_entries(6)).await.unwrap();
+        assert_eq!(result.tiers_searched, vec![1]);
+        assert_eq!(result.retrieved_entries.len(), 6);
+    }
+
+    #[tokio::test]
+    async fn test_exhaustive_searches_every_tier() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut manager = strategy_manager(RetrievalStrategy::Exhaustive, &dir);
+        let result = manager.retrieve_context("s1", "database migration rollback", &matching_entries(6)).await.unwrap();
+        assert_eq!(result.tiers_searched, vec![1, 2, 3]);
+    }
+
+    #[tokio::test]
+    async fn test_semantic_then_keyword_searches_persisted_tier_first() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut manager = strategy_manager(RetrievalStrategy::SemanticThenKeyword, &dir);
+        let result = manager.retrieve_context("s1", "database migration rollback", &matching_entries(6)).await.unwrap();
+        assert_eq!(result.tiers_searched, vec![3, 2, 1]);
+    }
+
+    #[tokio::test]
+    async fn test_semantic_strategies_fall_back_to_keyword_scores() {
+        for strategy in [
+            RetrievalStrategy::SemanticOnly,
+            RetrievalStrategy::KeywordThenSemantic,
+            RetrievalStrategy::Hybrid { keyword_weight: 0.5, semantic_weight: 0.5 },
+        ] {
+            let dir = tempfile::tempdir().unwrap();
+            let mut manager = strategy_manager(strategy.clone(), &dir);
+            // Nothing listens on the discard port, so embedding requests fail fast.
+            manager.set_llm_worker(Arc::new(LLMWorker::new_with_backend("http://127.0.0.1:9".to_string())));
+            let result = manager.retrieve_context("s1", "database migration rollback", &matching_entries(6)).await.unwrap();
+            assert_eq!(result.tiers_searched, vec![1], "{:?}", strategy);
+            assert!(result.retrieved_entries.iter().all(|r| (r.similarity_score - 1.0).abs() < f32::EPSILON));
+        }
+    }
+}
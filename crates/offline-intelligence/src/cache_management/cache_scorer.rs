@@ -1,6 +1,7 @@
 ﻿use regex::Regex;
 use std::collections::HashMap;
 use lazy_static::lazy_static;
+use crate::utils::TextUtils;
 lazy_static! {
     static ref KEY_PATTERNS: HashMap<&'static str, Regex> = {
         let mut m = HashMap::new();
@@ -50,6 +51,21 @@ pub struct CacheEntryParams<'a> {
     pub value_size_bytes: usize,
 }
 /
+/
+#[derive(Debug, Clone, Copy, PartialEq, Default, serde::Serialize)]
+pub struct ScoreBreakdown {
+    pub recency: f32,
+    pub access_count: f32,
+    /
+    pub key_pattern: f32,
+    pub layer: f32,
+    pub head: f32,
+    pub value_size: f32,
+    pub engagement: f32,
+    /
+    pub total: f32,
+}
+/
 pub struct CacheEntryScorer {
     key_engagement: HashMap<String, f32>,
     config: CacheScoringConfig,
@@ -91,15 +107,29 @@ impl CacheEntryScorer {
     }
     /
     pub fn score_entry(&self, params: CacheEntryParams) -> f32 {
-        let mut score = 0.0;
-        score += self.score_recency(params.last_accessed_seconds_ago);
-        score += self.score_access_count(params.access_count);
-        score += self.score_key_patterns(params.key_data, params.key_type);
-        score += self.score_layer_position(params.layer_index);
-        score += self.score_head_position(params.head_index);
-        score += self.score_value_size(params.value_size_bytes);
-        score += self.score_key_engagement(params.key_hash);
-        score.clamp(0.0, 1.0)
+        self.score_breakdown(&params).total
+    }
+    /
+    pub fn score_breakdown(&self, params: &CacheEntryParams) -> ScoreBreakdown {
+        let mut breakdown = ScoreBreakdown {
+            recency: self.score_recency(params.last_accessed_seconds_ago),
+            access_count: self.score_access_count(params.access_count),
+            key_pattern: self.score_key_patterns(params.key_data, params.key_type),
+            layer: self.score_layer_position(params.layer_index),
+            head: self.score_head_position(params.head_index),
+            value_size: self.score_value_size(params.value_size_bytes),
+            engagement: self.score_key_engagement(params.key_hash),
+            total: 0.0,
+        };
+        breakdown.total = (breakdown.recency
+            + breakdown.access_count
+            + breakdown.key_pattern
+            + breakdown.layer
+            + breakdown.head
+            + breakdown.value_size
+            + breakdown.engagement)
+            .clamp(0.0, 1.0);
+        breakdown
     }
     fn score_recency(&self, seconds_ago: f32) -> f32 {
         let recency_factor = 1.0 / (1.0 + seconds_ago / 3600.0);
@@ -213,7 +243,7 @@ impl CacheEntryScorer {
 
         if let Some(data) = key_data {
             if let Ok(key_str) = std::str::from_utf8(data) {
-
+                let key_str = TextUtils::strip_markup(key_str);
                 let words: Vec<&str> = key_str.split_whitespace().collect();
                 for word in words.iter().filter(|w| w.len() > 3) {
                     let word_lower = word.to_lowercase();
@@ -249,4 +279,46 @@ impl crate::cache_management::cache_extractor::CacheEntryScorer for CacheEntrySc
         self.extract_keywords(key_data)
     }
 }
-
+#[cfg(test)]
+mod tests {
+    use super::*;
+    fn params<'a>(key_hash: &'a str, key_data: Option<&'a [u8]>, key_type: &'a str) -> CacheEntryParams<'a> {
+        CacheEntryParams {
+            key_hash,
+            key_data,
+            key_type,
+            layer_index: 5,
+            head_index: Some(2),
+            access_count: 50,
+            last_accessed_seconds_ago: 3600.0,
+            value_size_bytes: 5000,
+        }
+    }
+    fn approx(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-5
+    }
+    #[test]
+    fn test_score_breakdown_pins_formula() {
+        let scorer = CacheEntryScorer::new(CacheScoringConfig::default());
+        let b = scorer.score_breakdown(&params("k1", None, "attention_key"));
+        assert!(approx(b.recency, 0.5 * 0.3));
+        assert!(approx(b.access_count, 0.5 * 0.2));
+        assert!(approx(b.key_pattern, 0.1 * 0.25));
+        assert!(approx(b.layer, 0.9 * 0.1));
+        assert!(approx(b.head, 0.8 * 0.05));
+        assert!(approx(b.value_size, 0.5 * 0.1));
+        assert_eq!(b.engagement, 0.0);
+        assert!(approx(b.total, 0.15 + 0.1 + 0.025 + 0.09 + 0.04 + 0.05));
+        assert_eq!(scorer.score_entry(params("k1", None, "attention_key")), b.total);
+    }
+    #[test]
+    fn test_score_breakdown_engagement_and_clamp() {
+        let mut scorer = CacheEntryScorer::new(CacheScoringConfig::default());
+        scorer.update_engagement("k1", true);
+        let b = scorer.score_breakdown(&params("k1", Some(b"important system prompt: explain the code"), "attention_key"));
+        assert!(approx(b.engagement, 0.45 * 0.3));
+        assert!(approx(b.key_pattern, 0.25));
+        let unclamped = b.recency + b.access_count + b.key_pattern + b.layer + b.head + b.value_size + b.engagement;
+        assert!(unclamped > 0.0 && approx(b.total, unclamped.min(1.0)));
+    }
+}
@@ -5,15 +5,15 @@ pub mod cache_config;
 pub mod cache_extractor;
 pub mod cache_manager;
 pub mod cache_scorer;
-pub use cache_bridge::{CacheContextBridge, CacheBridgeStats, CacheTransition, TransitionType};
-pub use cache_config::{KVCacheConfig, RetrievalStrategy, SnapshotStrategy, CachePreservationConfig};
+pub use cache_bridge::{BridgeTemplates, CacheContextBridge, CacheBridgeStats, CacheTransition, TransitionType};
+pub use cache_config::{KVCacheConfig, RetrievalPlan, RetrievalStrategy, SnapshotStrategy, CachePreservationConfig, TierWeights};
 pub use cache_extractor::{CacheExtractor, CacheExtractorConfig, ExtractedCacheEntry, CacheEntryType, KVEntry};
 pub use cache_manager::{
     KVCacheManager, SessionCacheState, CacheStatistics, CacheOperation, CacheOperationType,
     ClearReason, CacheClearResult, RetrievalResult, RetrievedEntry, CacheProcessingResult,
     CacheStatisticsExport, MaintenanceResult
 };
-pub use cache_scorer::{CacheEntryScorer, CacheScoringConfig};
+pub use cache_scorer::{CacheEntryScorer, CacheScoringConfig, ScoreBreakdown};
 /
 pub fn create_default_cache_manager(
     config: KVCacheConfig,
@@ -1,10 +1,34 @@
 ﻿
 use anyhow::{Context, Result};
+use std::collections::HashMap;
 use std::env;
+use std::fmt::Display;
 use std::net::SocketAddr;
+use std::str::FromStr;
 use tracing::{info, warn};
 use nvml_wrapper::Nvml;
 use sysinfo::System;
+use crate::worker_threads::{BackendApi, DEFAULT_MODEL_NAME};
+use crate::memory_db::SynchronousMode;
+/
+pub const CONFIG_FILE_ENV: &str = "OFFLINE_INTELLIGENCE_CONFIG";
+const DEFAULT_CONFIG_FILE: &str = "offline-intelligence.toml";
+/
+const MAX_AUTO_CTX_SIZE: u32 = 32768;
+/
+const PROFILE_KEYS: &[&str] = &[
+    "LLAMA_BIN", "MODEL_PATH", "THREADS", "GPU_LAYERS", "CTX_SIZE", "BATCH_SIZE",
+    "LLAMA_HOST", "LLAMA_PORT", "LLAMA_SLOTS", "HEALTH_TIMEOUT_SECONDS", "HOT_SWAP_GRACE_SECONDS",
+    "MAX_CONCURRENT_STREAMS", "PROMETHEUS_HOST", "PROMETHEUS_PORT", "API_HOST", "API_PORT", "REQUESTS_PER_SECOND",
+    "GENERATE_TIMEOUT_SECONDS", "STREAM_TIMEOUT_SECONDS", "SSE_KEEP_ALIVE_SECONDS", "HEALTH_CHECK_TIMEOUT_SECONDS",
+    "QUEUE_SIZE", "QUEUE_TIMEOUT_SECONDS", "TENANT_ISOLATION", "DEFAULT_SYSTEM_PROMPT",
+    "DB_BUSY_TIMEOUT_MS", "SESSION_LOG_DIR", "SESSION_LOG_MAX_FILES", "SESSION_LOG_MAX_BYTES",
+    "CONFIG_STRICT", "BACKEND_API", "EMBEDDING_BACKEND_URL", "EMBEDDING_MODEL", "DB_POOL_MAX_SIZE", "DB_POOL_TIMEOUT_SECONDS",
+    "DB_STATEMENT_TIMEOUT_MS", "DB_SYNCHRONOUS", "DB_WAL_AUTOCHECKPOINT",
+    "CORS_ALLOWED_ORIGINS", "CORS_ALLOWED_METHODS", "CORS_ALLOWED_HEADERS", "MAX_REQUEST_BODY_BYTES",
+    "MAX_CONTEXT_TOKENS", "CACHE_MAX_ENTRIES", "CACHE_MEMORY_THRESHOLD_PERCENT", "PERSIST_TIER1",
+    "API_AUTH_ENABLED", "API_KEYS",
+];
 #[allow(dead_code)]
 #[derive(Debug, Clone)]
 pub struct Config {
@@ -19,26 +43,202 @@ pub struct Config {
     pub health_timeout_seconds: u64,
     pub hot_swap_grace_seconds: u64,
     pub max_concurrent_streams: u32,
+    /
+    pub prometheus_host: String,
     pub prometheus_port: u16,
     pub api_host: String,
     pub api_port: u16,
     pub requests_per_second: u32,
     pub generate_timeout_seconds: u64,
     pub stream_timeout_seconds: u64,
+    pub sse_keep_alive_seconds: u64,
+    pub cors_allowed_origins: Vec<String>,
+    pub cors_allowed_methods: Vec<String>,
+    pub cors_allowed_headers: Vec<String>,
+    pub max_request_body_bytes: usize,
     pub health_check_timeout_seconds: u64,
     pub queue_size: usize,
     pub queue_timeout_seconds: u64,
     pub backend_url: String,
+    pub embedding_backend_url: String,
+    pub embedding_model: String,
+    pub tenant_isolation: bool,
+    pub persist_tier1: bool,
+    pub default_system_prompt: Option<String>,
+    pub db_busy_timeout_ms: u64,
+    pub db_pool_max_size: u32,
+    pub db_pool_timeout_seconds: u64,
+    pub db_statement_timeout_ms: u64,
+    pub db_synchronous: SynchronousMode,
+    pub db_wal_autocheckpoint: u32,
+    pub api_auth_enabled: bool,
+    /
+    pub api_keys: Vec<String>,
+    pub max_context_tokens: usize,
+    pub cache_max_entries: usize,
+    pub cache_memory_threshold_percent: f32,
+    pub llama_slots: u32,
+    pub backend_api: BackendApi,
+    pub session_log_dir: Option<String>,
+    pub session_log_max_files: usize,
+    pub session_log_max_bytes: u64,
+    pub config_strict: bool,
+    pub assumptions: Vec<String>,
+    /
+    pub profile: Option<String>,
+}
+/
+/
+#[derive(Debug)]
+pub struct ConfigReload {
+    pub config: Config,
+    pub applied: Vec<&'static str>,
+    pub requires_restart: Vec<&'static str>,
+}
+/
+/
+/
+struct Fallbacks {
+    strict: bool,
+    assumptions: Vec<String>,
+}
+impl Fallbacks {
+    fn new(strict: bool) -> Self {
+        Self { strict, assumptions: Vec::new() }
+    }
+    fn assume(&mut self, assumption: impl Into<String>) -> Result<()> {
+        let assumption = assumption.into();
+        if self.strict {
+            return Err(anyhow::anyhow!("{} (refusing to fall back because CONFIG_STRICT=true)", assumption));
+        }
+        warn!("Configuration assumption: {}", assumption);
+        self.assumptions.push(assumption);
+        Ok(())
+    }
+    fn parse_or<T: FromStr + Display>(&mut self, key: &str, value: &str, default: T) -> Result<T> {
+        match value.trim().parse() {
+            Ok(parsed) => Ok(parsed),
+            Err(_) => {
+                self.assume(format!("{}={:?} is not a valid value; using {}", key, value, default))?;
+                Ok(default)
+            }
+        }
+    }
+}
+/
+fn split_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(str::to_string)
+        .collect()
 }
 impl Config {
     pub fn from_env() -> Result<Self> {
+        Self::load(&HashMap::new())
+    }
+    /
+    /
+    /
+    /
+    pub fn from_profile(name: &str) -> Result<Self> {
+        let path = env::var(CONFIG_FILE_ENV).unwrap_or_else(|_| DEFAULT_CONFIG_FILE.into());
+        let text = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read config file {}", path))?;
+        let overrides = Self::parse_profile(&text, name)
+            .with_context(|| format!("Invalid profile '{}' in {}", name, path))?;
+        info!("Using configuration profile '{}' from {} ({} overrides)", name, path, overrides.len());
+        let mut config = Self::load(&overrides)?;
+        config.profile = Some(name.to_string());
+        Ok(config)
+    }
+    /
+    /
+    pub fn reload(&self) -> Result<Self> {
+        if let Err(e) = dotenvy::dotenv_override() {
+            warn!("Failed to re-read .env file: {}. Using current environment variables.", e);
+        }
+        match self.profile {
+            Some(ref profile) => Self::from_profile(profile),
+            None => Self::from_env(),
+        }
+    }
+    /
+    /
+    /
+    pub fn merge_reloadable(&self, reloaded: &Config) -> ConfigReload {
+        let mut config = self.clone();
+        let mut applied = Vec::new();
+        let mut requires_restart = Vec::new();
+        macro_rules! hot_swap {
+            ($($field:ident),* $(,)?) => {$(
+                if config.$field != reloaded.$field {
+                    config.$field = reloaded.$field.clone();
+                    applied.push(stringify!($field));
+                }
+            )*};
+        }
+        macro_rules! restart_only {
+            ($($field:ident),* $(,)?) => {$(
+                if self.$field != reloaded.$field {
+                    requires_restart.push(stringify!($field));
+                }
+            )*};
+        }
+        hot_swap!(
+            generate_timeout_seconds, stream_timeout_seconds, sse_keep_alive_seconds, requests_per_second,
+            default_system_prompt, max_context_tokens, cache_max_entries, cache_memory_threshold_percent,
+        );
+        // backend_url is replaced by the runtime endpoint at startup, so it is not compared.
+        restart_only!(
+            model_path, llama_bin, llama_host, llama_port, ctx_size, batch_size, threads, gpu_layers,
+            health_timeout_seconds, health_check_timeout_seconds, queue_timeout_seconds, hot_swap_grace_seconds, max_concurrent_streams, prometheus_port,
+            prometheus_host, api_host, api_port, cors_allowed_origins, cors_allowed_methods, cors_allowed_headers,
+            max_request_body_bytes, queue_size, embedding_backend_url, embedding_model, tenant_isolation,
+            persist_tier1, db_busy_timeout_ms, db_pool_max_size, db_pool_timeout_seconds, db_statement_timeout_ms,
+            db_synchronous, db_wal_autocheckpoint, api_auth_enabled, api_keys, llama_slots, backend_api, session_log_dir, session_log_max_files, session_log_max_bytes,
+        );
+        ConfigReload { config, applied, requires_restart }
+    }
+    /
+    /
+    fn parse_profile(text: &str, name: &str) -> Result<HashMap<String, String>> {
+        let document: toml::Table = text.parse()?;
+        let profile = document
+            .get("profiles")
+            .and_then(|profiles| profiles.get(name))
+            .and_then(|profile| profile.as_table())
+            .ok_or_else(|| anyhow::anyhow!("no [profiles.{}] section", name))?;
+        let mut overrides = HashMap::new();
+        for (key, value) in profile {
+            let var_name = key.to_uppercase();
+            if !PROFILE_KEYS.contains(&var_name.as_str()) {
+                return Err(anyhow::anyhow!("unknown setting '{}'", key));
+            }
+            let value = match value {
+                toml::Value::String(s) => s.clone(),
+                toml::Value::Integer(i) => i.to_string(),
+                toml::Value::Float(f) => f.to_string(),
+                toml::Value::Boolean(b) => b.to_string(),
+                other => return Err(anyhow::anyhow!("unsupported value for '{}': {}", key, other)),
+            };
+            overrides.insert(var_name, value);
+        }
+        Ok(overrides)
+    }
+    fn load(overrides: &HashMap<String, String>) -> Result<Self> {
+        let var = |key: &str| match overrides.get(key) {
+            Some(value) => Ok(value.clone()),
+            None => env::var(key),
+        };
         if let Err(e) = dotenvy::dotenv() {
             warn!("Failed to load .env file: {}. Using system environment variables.", e);
         } else {
             info!("Loaded environment variables from .env file");
         }
 
-        let llama_bin = env::var("LLAMA_BIN")
+        let llama_bin = var("LLAMA_BIN")
             .context("LLAMA_BIN environment variable not set. Please set it in your .env file")?;
 
 
@@ -51,39 +251,44 @@ impl Config {
 
         info!("Using llama binary from .env: {}", llama_bin);
 
-        let model_path = Self::get_model_path_with_fallback()?;
+        let config_strict: bool = var("CONFIG_STRICT")
+            .unwrap_or_else(|_| "false".into())
+            .parse()?;
+        let mut fallbacks = Fallbacks::new(config_strict);
 
-        let threads = if env::var("THREADS").unwrap_or_else(|_| "auto".into()) == "auto" {
-            Self::auto_detect_threads()
-        } else {
-            env::var("THREADS").unwrap_or_else(|_| "6".into()).parse().unwrap_or(6)
+        let model_path = Self::get_model_path_with_fallback(&var, &mut fallbacks)?;
+
+        let threads = match var("THREADS") {
+            Ok(value) if value != "auto" => fallbacks.parse_or("THREADS", &value, 6)?,
+            _ => Self::auto_detect_threads(),
         };
 
-        let gpu_layers = if env::var("GPU_LAYERS").unwrap_or_else(|_| "auto".into()) == "auto" {
-            Self::auto_detect_gpu_layers()
-        } else {
-            env::var("GPU_LAYERS").unwrap_or_else(|_| "20".into()).parse().unwrap_or(20)
+        let gpu_layers = match var("GPU_LAYERS") {
+            Ok(value) if value != "auto" => fallbacks.parse_or("GPU_LAYERS", &value, 20)?,
+            _ => Self::auto_detect_gpu_layers(&mut fallbacks)?,
         };
 
-        let ctx_size = if env::var("CTX_SIZE").unwrap_or_else(|_| "auto".into()) == "auto" {
-            Self::auto_detect_ctx_size(&model_path)
-        } else {
-            env::var("CTX_SIZE").unwrap_or_else(|_| "8192".into()).parse().unwrap_or(8192)
+        let ctx_size = match var("CTX_SIZE") {
+            Ok(value) if value != "auto" => fallbacks.parse_or("CTX_SIZE", &value, 8192)?,
+            _ => Self::auto_detect_ctx_size(&model_path, &mut fallbacks)?,
         };
 
-        let batch_size = if env::var("BATCH_SIZE").unwrap_or_else(|_| "auto".into()) == "auto" {
-            Self::auto_detect_batch_size(gpu_layers, ctx_size)
-        } else {
-            env::var("BATCH_SIZE").unwrap_or_else(|_| "256".into()).parse().unwrap_or(256)
+        let batch_size = match var("BATCH_SIZE") {
+            Ok(value) if value != "auto" => fallbacks.parse_or("BATCH_SIZE", &value, 256)?,
+            _ => Self::auto_detect_batch_size(gpu_layers, ctx_size),
         };
 
-        let llama_host = env::var("LLAMA_HOST").unwrap_or_else(|_| "127.0.0.1".into());
-        let llama_port = env::var("LLAMA_PORT").unwrap_or_else(|_| "8081".into()).parse()?;
+        let llama_host = var("LLAMA_HOST").unwrap_or_else(|_| "127.0.0.1".into());
+        let llama_port = var("LLAMA_PORT").unwrap_or_else(|_| "8081".into()).parse()?;
         let backend_url = format!("http:
         info!(
             "Resource Configuration: {} GPU layers, {} threads, batch size: {}, context: {}",
             gpu_layers, threads, batch_size, ctx_size
         );
+        let embedding_backend_url = var("EMBEDDING_BACKEND_URL")
+            .ok()
+            .filter(|url| !url.trim().is_empty())
+            .unwrap_or_else(|| backend_url.clone());
         Ok(Self {
             model_path,
             llama_bin,
@@ -93,50 +298,129 @@ impl Config {
             batch_size,
             threads,
             gpu_layers,
-            health_timeout_seconds: env::var("HEALTH_TIMEOUT_SECONDS")
+            health_timeout_seconds: var("HEALTH_TIMEOUT_SECONDS")
                 .unwrap_or_else(|_| "60".into())
                 .parse()?,
-            hot_swap_grace_seconds: env::var("HOT_SWAP_GRACE_SECONDS")
+            hot_swap_grace_seconds: var("HOT_SWAP_GRACE_SECONDS")
                 .unwrap_or_else(|_| "25".into())
                 .parse()?,
-            max_concurrent_streams: env::var("MAX_CONCURRENT_STREAMS")
+            max_concurrent_streams: var("MAX_CONCURRENT_STREAMS")
                 .unwrap_or_else(|_| "4".into())
                 .parse()?,
-            prometheus_port: env::var("PROMETHEUS_PORT")
+            prometheus_host: var("PROMETHEUS_HOST").unwrap_or_else(|_| "127.0.0.1".into()),
+            prometheus_port: var("PROMETHEUS_PORT")
                 .unwrap_or_else(|_| "9000".into())
                 .parse()?,
-            api_host: env::var("API_HOST").unwrap_or_else(|_| "127.0.0.1".into()),
-            api_port: env::var("API_PORT").unwrap_or_else(|_| "8000".into()).parse()?,
-            requests_per_second: env::var("REQUESTS_PER_SECOND")
+            api_host: var("API_HOST").unwrap_or_else(|_| "127.0.0.1".into()),
+            api_port: var("API_PORT").unwrap_or_else(|_| "8000".into()).parse()?,
+            requests_per_second: var("REQUESTS_PER_SECOND")
                 .unwrap_or_else(|_| "24".into())
                 .parse()?,
-            generate_timeout_seconds: env::var("GENERATE_TIMEOUT_SECONDS")
+            generate_timeout_seconds: var("GENERATE_TIMEOUT_SECONDS")
                 .unwrap_or_else(|_| "300".into())
                 .parse()?,
-            stream_timeout_seconds: env::var("STREAM_TIMEOUT_SECONDS")
+            stream_timeout_seconds: var("STREAM_TIMEOUT_SECONDS")
                 .unwrap_or_else(|_| "600".into())
                 .parse()?,
-            health_check_timeout_seconds: env::var("HEALTH_CHECK_TIMEOUT_SECONDS")
+            sse_keep_alive_seconds: var("SSE_KEEP_ALIVE_SECONDS")
+                .unwrap_or_else(|_| "15".into())
+                .parse()?,
+            cors_allowed_origins: split_list(&var("CORS_ALLOWED_ORIGINS").unwrap_or_else(|_| "*".into())),
+            cors_allowed_methods: split_list(&var("CORS_ALLOWED_METHODS").unwrap_or_else(|_| "GET,POST,PUT,DELETE".into())),
+            cors_allowed_headers: split_list(&var("CORS_ALLOWED_HEADERS").unwrap_or_else(|_| "*".into())),
+            max_request_body_bytes: var("MAX_REQUEST_BODY_BYTES")
+                .unwrap_or_else(|_| "4194304".into())
+                .parse()?,
+            health_check_timeout_seconds: var("HEALTH_CHECK_TIMEOUT_SECONDS")
                 .unwrap_or_else(|_| "90".into())
                 .parse()?,
-            queue_size: env::var("QUEUE_SIZE")
+            queue_size: var("QUEUE_SIZE")
                 .unwrap_or_else(|_| "100".into())
                 .parse()?,
-            queue_timeout_seconds: env::var("QUEUE_TIMEOUT_SECONDS")
+            queue_timeout_seconds: var("QUEUE_TIMEOUT_SECONDS")
                 .unwrap_or_else(|_| "30".into())
                 .parse()?,
             backend_url,
+            embedding_backend_url,
+            embedding_model: var("EMBEDDING_MODEL")
+                .ok()
+                .filter(|model| !model.trim().is_empty())
+                .unwrap_or_else(|| DEFAULT_MODEL_NAME.into()),
+            tenant_isolation: var("TENANT_ISOLATION")
+                .unwrap_or_else(|_| "false".into())
+                .parse()?,
+            persist_tier1: var("PERSIST_TIER1")
+                .unwrap_or_else(|_| "false".into())
+                .parse()?,
+            default_system_prompt: var("DEFAULT_SYSTEM_PROMPT")
+                .ok()
+                .filter(|p| !p.trim().is_empty()),
+            db_busy_timeout_ms: var("DB_BUSY_TIMEOUT_MS")
+                .unwrap_or_else(|_| "5000".into())
+                .parse()?,
+            db_pool_max_size: var("DB_POOL_MAX_SIZE")
+                .unwrap_or_else(|_| "10".into())
+                .parse()?,
+            db_pool_timeout_seconds: var("DB_POOL_TIMEOUT_SECONDS")
+                .unwrap_or_else(|_| "30".into())
+                .parse()?,
+            db_statement_timeout_ms: var("DB_STATEMENT_TIMEOUT_MS")
+                .unwrap_or_else(|_| "30000".into())
+                .parse()?,
+            db_synchronous: var("DB_SYNCHRONOUS")
+                .unwrap_or_else(|_| "NORMAL".into())
+                .parse()?,
+            db_wal_autocheckpoint: var("DB_WAL_AUTOCHECKPOINT")
+                .unwrap_or_else(|_| "1000".into())
+                .parse()?,
+            api_auth_enabled: var("API_AUTH_ENABLED")
+                .unwrap_or_else(|_| "false".into())
+                .parse()?,
+            api_keys: split_list(&var("API_KEYS").unwrap_or_default()),
+            max_context_tokens: var("MAX_CONTEXT_TOKENS")
+                .unwrap_or_else(|_| "4000".into())
+                .parse()?,
+            cache_max_entries: var("CACHE_MAX_ENTRIES")
+                .unwrap_or_else(|_| "1000".into())
+                .parse()?,
+            cache_memory_threshold_percent: var("CACHE_MEMORY_THRESHOLD_PERCENT")
+                .unwrap_or_else(|_| "0.6".into())
+                .parse()?,
+            llama_slots: var("LLAMA_SLOTS")
+                .unwrap_or_else(|_| "1".into())
+                .parse()?,
+            backend_api: var("BACKEND_API")
+                .unwrap_or_else(|_| "openai".into())
+                .parse()?,
+            session_log_dir: var("SESSION_LOG_DIR")
+                .ok()
+                .filter(|d| !d.trim().is_empty()),
+            session_log_max_files: var("SESSION_LOG_MAX_FILES")
+                .unwrap_or_else(|_| "100".into())
+                .parse()?,
+            session_log_max_bytes: var("SESSION_LOG_MAX_BYTES")
+                .unwrap_or_else(|_| "10485760".into())
+                .parse()?,
+            config_strict,
+            assumptions: fallbacks.assumptions,
+            profile: None,
         })
     }
-    fn get_model_path_with_fallback() -> Result<String> {
+    fn get_model_path_with_fallback(
+        var: &impl Fn(&str) -> Result<String, env::VarError>,
+        fallbacks: &mut Fallbacks,
+    ) -> Result<String> {
 
-        if let Ok(model_path) = env::var("MODEL_PATH") {
+        if let Ok(model_path) = var("MODEL_PATH") {
 
             if std::path::Path::new(&model_path).exists() {
                 info!("Using model from MODEL_PATH: {}", model_path);
                 return Ok(model_path);
             } else {
-                warn!("MODEL_PATH set but file doesn't exist: {}", model_path);
+                fallbacks.assume(format!(
+                    "MODEL_PATH {} does not exist; searching for a bundled model instead",
+                    model_path
+                ))?;
             }
         }
 
@@ -200,7 +484,7 @@ impl Config {
             _ => 16,
         }
     }
-    fn auto_detect_gpu_layers() -> u32 {
+    fn auto_detect_gpu_layers(fallbacks: &mut Fallbacks) -> Result<u32> {
         if let Ok(nvml) = Nvml::init() {
             if let Ok(device_count) = nvml.device_count() {
                 if device_count > 0 {
@@ -215,27 +499,43 @@ impl Config {
                                 _ => 50,
                             };
                             info!("Autoâ€‘detected GPU layers: {} ({} GB VRAM)", layers, vram_gb);
-                            return layers;
+                            return Ok(layers);
                         }
                     }
                 }
             }
         }
-        warn!("Failed to detect GPU, using default 20 layers");
-        20
-    }
-    fn auto_detect_ctx_size(model_path: &str) -> u32 {
-        let inferred = Self::read_ctx_size_from_model_path(model_path)
-            .unwrap_or_else(|| {
-                info!("Falling back to default context size (8192)");
-                8192
-            });
+        fallbacks.assume("GPU_LAYERS=auto but no GPU was detected; assuming 20 GPU layers")?;
+        Ok(20)
+    }
+    fn auto_detect_ctx_size(model_path: &str, fallbacks: &mut Fallbacks) -> Result<u32> {
+        let inferred = match Self::read_ctx_size_from_header(model_path) {
+            Some(ctx_size) => ctx_size,
+            None => match Self::guess_ctx_size_from_filename(model_path) {
+                Some(ctx_size) => {
+                    fallbacks.assume(format!(
+                        "CTX_SIZE=auto but the model header has no context length; guessed {} from the filename",
+                        ctx_size
+                    ))?;
+                    ctx_size
+                }
+                None => {
+                    fallbacks.assume("CTX_SIZE=auto but the context length could not be determined; assuming 8192")?;
+                    8192
+                }
+            },
+        };
         let adjusted = Self::adjust_ctx_size_for_system(inferred);
         info!("Final context size: {} (inferred: {})", adjusted, inferred);
-        adjusted
+        Ok(adjusted)
     }
-    fn read_ctx_size_from_model_path(model_path: &str) -> Option<u32> {
-
+    fn read_ctx_size_from_header(model_path: &str) -> Option<u32> {
+        let info = crate::model_runtime::gguf_runtime::read_gguf_metadata(std::path::Path::new(model_path)).ok()?;
+        let context_length = info.context_length?;
+        info!("Model header reports a trained context length of {}", context_length);
+        Some(context_length.min(MAX_AUTO_CTX_SIZE))
+    }
+    fn guess_ctx_size_from_filename(model_path: &str) -> Option<u32> {
         let path_lower = model_path.to_lowercase();
         if path_lower.contains("32k") {
             Some(32768)
@@ -252,8 +552,7 @@ impl Config {
         } else if path_lower.contains("34b") || path_lower.contains("70b") {
             Some(8192)
         } else {
-
-            Some(8192)
+            None
         }
     }
     fn adjust_ctx_size_for_system(inferred_ctx: u32) -> u32 {
@@ -317,21 +616,58 @@ impl Config {
         info!("- GPU Layers: {}", self.gpu_layers);
         info!("- Max Streams: {}", self.max_concurrent_streams);
         info!("- API: {}:{}", self.api_host, self.api_port);
+        info!("- Metrics: {}:{}", self.prometheus_host, self.prometheus_port);
         info!("- Backend: {}:{}", self.llama_host, self.llama_port);
         info!("- Queue Size: {}", self.queue_size);
         info!("- Queue Timeout: {}s", self.queue_timeout_seconds);
         info!("- Backend URL: {}", self.backend_url);
+        info!("- Embeddings: {} via {}", self.embedding_model, self.embedding_backend_url);
+        info!("- Tenant Isolation: {}", self.tenant_isolation);
+        info!("- Persist Tier 1: {}", self.persist_tier1);
+        info!("- Default System Prompt: {}", if self.default_system_prompt.is_some() { "set" } else { "none" });
+        info!("- DB Busy Timeout: {}ms", self.db_busy_timeout_ms);
+        info!("- DB Pool: {} connections, {}s checkout timeout", self.db_pool_max_size, self.db_pool_timeout_seconds);
+        info!("- DB Statement Timeout: {}ms", self.db_statement_timeout_ms);
+        info!("- DB Synchronous: {}, WAL autocheckpoint every {} pages", self.db_synchronous, self.db_wal_autocheckpoint);
+        info!("- Max Context Tokens: {}", self.max_context_tokens);
+        info!("- KV Cache: max {} entries, eviction at {:.0}% memory",
+            self.cache_max_entries, self.cache_memory_threshold_percent * 100.0);
+        info!("- Llama Slots: {}", self.llama_slots);
+        info!("- Backend API: {}", self.backend_api);
+        info!("- SSE Keep-Alive: {}s", self.sse_keep_alive_seconds);
+        info!("- CORS: origins [{}], methods [{}], headers [{}]",
+            self.cors_allowed_origins.join(", "),
+            self.cors_allowed_methods.join(", "),
+            self.cors_allowed_headers.join(", "));
+        info!("- Max Request Body: {} bytes", self.max_request_body_bytes);
+        if self.api_auth_enabled {
+            info!("- API Auth: enabled ({} keys)", self.api_keys.len());
+        } else {
+            info!("- API Auth: disabled");
+        }
+        match self.session_log_dir {
+            Some(ref dir) => info!("- Session Logs: {} (max {} files, {} bytes each)",
+                dir, self.session_log_max_files, self.session_log_max_bytes),
+            None => info!("- Session Logs: disabled"),
+        }
+        info!("- Strict Config: {}", self.config_strict);
+        for assumption in &self.assumptions {
+            warn!("- Assumed: {}", assumption);
+        }
     }
     pub fn api_addr(&self) -> SocketAddr {
         format!("{}:{}", self.api_host, self.api_port).parse().unwrap()
     }
+    pub fn metrics_addr(&self) -> SocketAddr {
+        format!("{}:{}", self.prometheus_host, self.prometheus_port).parse().unwrap()
+    }
 }
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::*;
 
     /
-    fn create_test_config() -> Config {
+    pub(crate) fn create_test_config() -> Config {
         Config {
             model_path: "/test/model.gguf".to_string(),
             llama_bin: "/test/llama-server".to_string(),
@@ -344,16 +680,46 @@ mod tests {
             health_timeout_seconds: 600,
             hot_swap_grace_seconds: 25,
             max_concurrent_streams: 2,
+            prometheus_host: "127.0.0.1".to_string(),
             prometheus_port: 9000,
             api_host: "127.0.0.1".to_string(),
             api_port: 8000,
             requests_per_second: 24,
             generate_timeout_seconds: 300,
             stream_timeout_seconds: 600,
+            sse_keep_alive_seconds: 15,
+            cors_allowed_origins: vec!["*".to_string()],
+            cors_allowed_methods: vec!["GET".to_string(), "POST".to_string(), "PUT".to_string(), "DELETE".to_string()],
+            cors_allowed_headers: vec!["*".to_string()],
+            max_request_body_bytes: 4 * 1024 * 1024,
             health_check_timeout_seconds: 900,
             queue_size: 1000,
             queue_timeout_seconds: 300,
+            tenant_isolation: false,
+            persist_tier1: false,
+            default_system_prompt: None,
+            db_busy_timeout_ms: 5000,
+            db_pool_max_size: 10,
+            db_pool_timeout_seconds: 30,
+            db_statement_timeout_ms: 30000,
+            db_synchronous: SynchronousMode::Normal,
+            db_wal_autocheckpoint: 1000,
+            api_auth_enabled: false,
+            api_keys: Vec::new(),
+            max_context_tokens: 4000,
+            cache_max_entries: 1000,
+            cache_memory_threshold_percent: 0.6,
+            llama_slots: 1,
+            backend_api: BackendApi::OpenAiChat,
+            session_log_dir: None,
+            session_log_max_files: 100,
+            session_log_max_bytes: 10 * 1024 * 1024,
+            config_strict: false,
+            assumptions: Vec::new(),
+            profile: None,
             backend_url: "http:
+            embedding_backend_url: "http:
+            embedding_model: DEFAULT_MODEL_NAME.to_string(),
         }
     }
 
@@ -367,6 +733,28 @@ mod tests {
         assert_eq!(config.llama_port, 8001);
     }
     #[test]
+    fn test_parse_profile_overrides() {
+        let text = r#"
+[profiles.dev]
+model_path = "/models/small.gguf"
+gpu_layers = 0
+ctx_size = "auto"
+
+[profiles.prod]
+model_path = "/models/big.gguf"
+tenant_isolation = true
+"#;
+        let dev = Config::parse_profile(text, "dev").unwrap();
+        assert_eq!(dev.get("MODEL_PATH").map(String::as_str), Some("/models/small.gguf"));
+        assert_eq!(dev.get("GPU_LAYERS").map(String::as_str), Some("0"));
+        assert_eq!(dev.get("CTX_SIZE").map(String::as_str), Some("auto"));
+        assert!(!dev.contains_key("TENANT_ISOLATION"));
+        let prod = Config::parse_profile(text, "prod").unwrap();
+        assert_eq!(prod.get("TENANT_ISOLATION").map(String::as_str), Some("true"));
+        assert!(Config::parse_profile(text, "staging").is_err());
+        assert!(Config::parse_profile("[profiles.dev]\nmodel_pth = \"x\"", "dev").is_err());
+    }
+    #[test]
     fn test_config_clone() {
         let config1 = create_test_config();
         let config2 = config1.clone();
@@ -393,6 +781,14 @@ mod tests {
         assert_eq!(addr.port(), 3000);
     }
     #[test]
+    fn test_metrics_addr_binds_separately_from_api() {
+        let mut config = create_test_config();
+        config.api_host = "0.0.0.0".to_string();
+
+        assert_eq!(config.metrics_addr().to_string(), "127.0.0.1:9000");
+        assert_eq!(config.api_addr().to_string(), "0.0.0.0:8000");
+    }
+    #[test]
     fn test_api_addr_with_zero_address() {
         let mut config = create_test_config();
         config.api_host = "0.0.0.0".to_string();
@@ -570,10 +966,26 @@ mod tests {
     }
     #[test]
     fn test_auto_detect_gpu_layers_non_negative() {
-        let layers = Config::auto_detect_gpu_layers();
+        let layers = Config::auto_detect_gpu_layers(&mut Fallbacks::new(false)).unwrap();
         assert!(layers <= 512);
     }
     #[test]
+    fn test_strict_mode_rejects_fallbacks() {
+        let mut lenient = Fallbacks::new(false);
+        assert_eq!(lenient.parse_or("THREADS", "six", 6).unwrap(), 6);
+        assert_eq!(lenient.parse_or("CTX_SIZE", " 4096 ", 8192).unwrap(), 4096);
+        let ctx_size = Config::auto_detect_ctx_size("/missing/llama-7b.gguf", &mut lenient).unwrap();
+        assert!(ctx_size <= 4096);
+        assert_eq!(lenient.assumptions.len(), 2);
+        assert!(lenient.assumptions[1].contains("filename"));
+
+        let mut strict = Fallbacks::new(true);
+        let err = strict.parse_or("THREADS", "six", 6).unwrap_err();
+        assert!(err.to_string().contains("CONFIG_STRICT"));
+        assert!(Config::auto_detect_ctx_size("/missing/model.gguf", &mut strict).is_err());
+        assert!(strict.assumptions.is_empty());
+    }
+    #[test]
     fn test_apply_batch_limits_small_context() {
 
         let batch = Config::apply_batch_limits(1024, 1024, false);
@@ -662,4 +1074,24 @@ mod tests {
         assert!(config.api_port > 0);
         assert!(config.llama_port > 0);
     }
+    #[test]
+    fn test_merge_reloadable_applies_only_hot_fields() {
+        let current = create_test_config();
+        let mut reloaded = current.clone();
+        reloaded.stream_timeout_seconds = 120;
+        reloaded.sse_keep_alive_seconds = 30;
+        reloaded.max_context_tokens = 6000;
+        reloaded.queue_timeout_seconds = 5;
+        reloaded.api_port = 9100;
+        reloaded.model_path = "/test/other.gguf".to_string();
+        reloaded.backend_url = "http://127.0.0.1:9999".to_string();
+
+        let reload = current.merge_reloadable(&reloaded);
+        assert_eq!(reload.applied, vec!["stream_timeout_seconds", "sse_keep_alive_seconds", "max_context_tokens"]);
+        assert_eq!(reload.requires_restart, vec!["model_path", "queue_timeout_seconds", "api_port"]);
+        assert_eq!((reload.config.sse_keep_alive_seconds, reload.config.max_context_tokens), (30, 6000));
+        assert_eq!(reload.config.api_port, current.api_port);
+        assert_eq!(reload.config.model_path, current.model_path);
+        assert_eq!(reload.config.backend_url, current.backend_url);
+    }
 }
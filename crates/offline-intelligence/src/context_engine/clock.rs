@@ -0,0 +1,34 @@
+use chrono::{DateTime, Utc};
+use std::fmt;
+use std::sync::Arc;
+/
+/
+#[derive(Clone)]
+pub struct Clock(Arc<dyn Fn() -> DateTime<Utc> + Send + Sync>);
+impl Clock {
+    /
+    pub fn system() -> Self {
+        Self(Arc::new(Utc::now))
+    }
+    /
+    pub fn fixed(at: DateTime<Utc>) -> Self {
+        Self(Arc::new(move || at))
+    }
+    /
+    pub fn from_fn(now: impl Fn() -> DateTime<Utc> + Send + Sync + 'static) -> Self {
+        Self(Arc::new(now))
+    }
+    pub fn now(&self) -> DateTime<Utc> {
+        (self.0)()
+    }
+}
+impl Default for Clock {
+    fn default() -> Self {
+        Self::system()
+    }
+}
+impl fmt::Debug for Clock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Clock").field(&self.now()).finish()
+    }
+}
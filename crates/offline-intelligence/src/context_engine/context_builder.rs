@@ -1,9 +1,23 @@
-﻿use crate::memory::Message;
-use crate::memory_db::{StoredMessage, Summary as DbSummary};
+﻿use crate::memory::{Message, Role};
+use crate::utils::TextUtils;
+use crate::memory_db::{Detail, StoredMessage, Summary as DbSummary};
+use crate::memory_db::embedding_store::cosine_similarity;
+use crate::worker_threads::LLMWorker;
+use crate::context_engine::clock::Clock;
+use moka::sync::Cache;
+use std::ops::Range;
+use std::sync::Arc;
 use tracing::{info, debug};
 /
+const MAX_INJECTED_SUMMARY_CHARS: usize = 800;
+/
+const SUMMARY_EMBEDDING_CACHE_CAPACITY: u64 = 1024;
+/
 pub struct ContextBuilder {
     config: ContextBuilderConfig,
+    llm_worker: Option<Arc<LLMWorker>>,
+    clock: Clock,
+    summary_embeddings: Cache<String, Arc<Vec<f32>>>,
 }
 /
 #[derive(Debug, Clone)]
@@ -11,6 +25,7 @@ pub struct ContextBuilderConfig {
     pub max_total_tokens: usize,
     pub min_current_context_ratio: f32,
     pub max_summary_ratio: f32,
+    pub max_cross_session_ratio: f32,
     pub preserve_system_messages: bool,
     pub enable_detail_injection: bool,
     pub detail_injection_threshold: f32,
@@ -21,6 +36,7 @@ impl Default for ContextBuilderConfig {
             max_total_tokens: 4000,
             min_current_context_ratio: 0.4,
             max_summary_ratio: 0.4,
+            max_cross_session_ratio: 0.15,
             preserve_system_messages: true,
             enable_detail_injection: true,
             detail_injection_threshold: 0.7,
@@ -32,8 +48,27 @@ impl ContextBuilder {
     pub fn new(config: ContextBuilderConfig) -> Self {
         Self {
             config,
+            llm_worker: None,
+            clock: Clock::system(),
+            summary_embeddings: Cache::new(SUMMARY_EMBEDDING_CACHE_CAPACITY),
         }
     }
+    /
+    pub fn set_llm_worker(&mut self, worker: Arc<LLMWorker>) {
+        self.llm_worker = Some(worker);
+    }
+    /
+    pub fn set_clock(&mut self, clock: Clock) {
+        self.clock = clock;
+    }
+    /
+    pub fn config(&self) -> &ContextBuilderConfig {
+        &self.config
+    }
+    /
+    pub fn set_max_total_tokens(&mut self, max_total_tokens: usize) {
+        self.config.max_total_tokens = max_total_tokens;
+    }
 
     /
     pub async fn build_context(
@@ -43,6 +78,7 @@ impl ContextBuilder {
         tier2_summaries: Option<Vec<DbSummary>>,
         tier3_messages: Option<Vec<StoredMessage>>,
         cross_session_messages: Option<Vec<StoredMessage>>,
+        extracted_details: Option<Vec<Detail>>,
         user_query: Option<&str>,
     ) -> anyhow::Result<Vec<Message>> {
         info!("Building context from {} current messages", current_messages.len());
@@ -63,13 +99,17 @@ impl ContextBuilder {
         }
 
 
-        if let Some(ref full_messages) = tier3_messages {
-            self.add_specific_details(&mut context, full_messages, user_query)
-                .await?;
+        if tier3_messages.is_some() || extracted_details.is_some() {
+            self.add_specific_details(
+                &mut context,
+                tier3_messages.as_deref().unwrap_or(&[]),
+                extracted_details.as_deref().unwrap_or(&[]),
+                user_query,
+            ).await?;
         }
 
 
-        self.trim_to_token_limit(&mut context);
+        self.trim_to_token_limit(&mut context, current_messages);
 
 
         self.add_bridging(&mut context, current_messages, tier2_summaries.as_ref())
@@ -90,19 +130,32 @@ impl ContextBuilder {
             return Ok(());
         }
 
+        let max_cross_tokens = (self.config.max_total_tokens as f32 * self.config.max_cross_session_ratio) as usize;
+        let mut remaining = max_cross_tokens;
+        let mut injected = Vec::new();
+        for message in cross_messages.iter().take(3) {
+            let Some(content) = fit_cross_session(&message.content, &mut remaining) else {
+                continue;
+            };
+            injected.push(Message {
+                role: Role::from(message.role.as_str()),
+                content,
+            });
+        }
+        if injected.is_empty() {
+            debug!("No cross-session messages fit the {} token budget", max_cross_tokens);
+            return Ok(());
+        }
 
         let bridge = Message {
-            role: "system".to_string(),
+            role: Role::System,
             content: "[Context from previous conversations]".to_string(),
+            parts: None,
         };
         context.insert(0, bridge);
 
 
-        for message in cross_messages.iter().take(3) {
-            let cross_msg = Message {
-                role: message.role.clone(),
-                content: format!("[From earlier: {}]", message.content),
-            };
+        for cross_msg in injected {
             context.insert(1, cross_msg);
         }
 
@@ -119,17 +172,23 @@ impl ContextBuilder {
 
 
         if self.config.preserve_system_messages {
-            for message in current_messages.iter().filter(|m| m.role == "system") {
+            for message in current_messages.iter().filter(|m| m.role == Role::System) {
                 context.push(message.clone());
             }
         }
 
 
-        if let Some(tier1_messages) = tier1_content {
-            context.extend(tier1_messages);
-        } else {
-            let recent_messages = self.select_recent_messages(current_messages);
-            context.extend(recent_messages);
+        let conversation = match tier1_content {
+            Some(tier1_messages) => tier1_messages,
+            None => self.select_recent_messages(current_messages),
+        };
+        let preserved_system = context.len();
+        for message in conversation {
+            let duplicate_system = message.role == Role::System
+                && context[..preserved_system].iter().any(|m| m.content == message.content);
+            if !duplicate_system {
+                context.push(message);
+            }
         }
 
         context
@@ -164,7 +223,13 @@ impl ContextBuilder {
             return Ok(());
         }
 
-        let relevant_summaries = self.select_relevant_summaries(summaries, current_messages, user_query);
+        let semantic_scores = self.semantic_summary_scores(summaries, user_query).await;
+        let relevant_summaries = self.select_relevant_summaries(
+            summaries,
+            current_messages,
+            user_query,
+            semantic_scores.as_deref(),
+        );
 
         for summary in &relevant_summaries {
             let summary_message = self.summary_to_message(summary, current_messages);
@@ -174,23 +239,67 @@ impl ContextBuilder {
         Ok(())
     }
 
+    /
+    async fn semantic_summary_scores(&self, summaries: &[DbSummary], user_query: Option<&str>) -> Option<Vec<f32>> {
+        let llm_worker = self.llm_worker.as_ref()?;
+        let query = user_query.filter(|q| !q.trim().is_empty())?;
+
+        // Embeddings are cached by summary text, so repeat turns only embed the query.
+        let mut summary_embeddings: Vec<_> = summaries.iter()
+            .map(|s| self.summary_embeddings.get(&s.summary_text))
+            .collect();
+        let missing: Vec<usize> = (0..summaries.len()).filter(|&i| summary_embeddings[i].is_none()).collect();
+        let mut texts = Vec::with_capacity(missing.len() + 1);
+        texts.push(query.to_string());
+        texts.extend(missing.iter().map(|&i| summaries[i].summary_text.clone()));
+
+        match llm_worker.generate_embeddings(texts).await {
+            Ok(embeddings) if embeddings.len() == missing.len() + 1 => {
+                let mut embeddings = embeddings.into_iter();
+                let query_embedding = embeddings.next()?;
+                for (idx, embedding) in missing.into_iter().zip(embeddings) {
+                    let embedding = Arc::new(embedding);
+                    self.summary_embeddings.insert(summaries[idx].summary_text.clone(), embedding.clone());
+                    summary_embeddings[idx] = Some(embedding);
+                }
+                Some(summary_embeddings.iter()
+                    .map(|e| e.as_ref().map_or(0.0, |e| cosine_similarity(&query_embedding, e).max(0.0)))
+                    .collect())
+            }
+            Ok(_) => {
+                debug!("Embedding count mismatch for summaries, using topic matching");
+                None
+            }
+            Err(e) => {
+                debug!("Summary embedding failed, using topic matching: {}", e);
+                None
+            }
+        }
+    }
+
     fn select_relevant_summaries<'a>(
         &self,
         summaries: &'a [DbSummary],
         current_messages: &[Message],
         user_query: Option<&str>,
+        semantic_scores: Option<&[f32]>,
     ) -> Vec<&'a DbSummary> {
         let mut relevant = Vec::new();
         let current_topics = self.extract_topics(current_messages);
 
         let mut scored: Vec<(&DbSummary, f32)> = summaries.iter()
-            .map(|summary| {
-                let score = self.score_summary_relevance(summary, &current_topics, user_query);
+            .enumerate()
+            .map(|(idx, summary)| {
+                let similarity = semantic_scores.and_then(|scores| scores.get(idx).copied());
+                let score = self.score_summary_relevance(summary, &current_topics, user_query, similarity);
                 (summary, score)
             })
             .collect();
 
-        scored.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+        // Equal scores fall back to newest, then highest id, so ties never depend on input order.
+        scored.sort_by(|a, b| b.1.total_cmp(&a.1)
+            .then_with(|| b.0.generated_at.cmp(&a.0.generated_at))
+            .then_with(|| b.0.id.cmp(&a.0.id)));
 
         let mut total_tokens = 0;
         let max_summary_tokens = (self.config.max_total_tokens as f32 * self.config.max_summary_ratio) as usize;
@@ -198,7 +307,7 @@ impl ContextBuilder {
         for (summary, score) in scored {
             if score < 0.3 { continue; }
 
-            let summary_tokens = summary.summary_text.len() / 4;
+            let summary_tokens = TextUtils::estimate_tokens(&summary.summary_text);
 
             if total_tokens + summary_tokens > max_summary_tokens { break; }
 
@@ -209,28 +318,37 @@ impl ContextBuilder {
         relevant
     }
 
-    fn score_summary_relevance(&self, summary: &DbSummary, current_topics: &[String], user_query: Option<&str>) -> f32 {
+    fn score_summary_relevance(
+        &self,
+        summary: &DbSummary,
+        current_topics: &[String],
+        user_query: Option<&str>,
+        similarity: Option<f32>,
+    ) -> f32 {
         let mut score = 0.0;
 
-
-        for topic in current_topics {
-            if summary.key_topics.iter().any(|t| t.to_lowercase().contains(&topic.to_lowercase())) {
-                score += 0.4;
+        if let Some(similarity) = similarity {
+            score += similarity * 0.9;
+        } else {
+            for topic in current_topics {
+                if summary.key_topics.iter().any(|t| t.to_lowercase().contains(&topic.to_lowercase())) {
+                    score += 0.4;
+                }
             }
-        }
 
 
-        if let Some(query) = user_query {
-            let query_lower = query.to_lowercase();
-            for topic in &summary.key_topics {
-                if query_lower.contains(&topic.to_lowercase()) {
-                    score += 0.5;
+            if let Some(query) = user_query {
+                let query_lower = query.to_lowercase();
+                for topic in &summary.key_topics {
+                    if query_lower.contains(&topic.to_lowercase()) {
+                        score += 0.5;
+                    }
                 }
             }
         }
 
 
-        let age_hours = chrono::Utc::now().signed_duration_since(summary.generated_at).num_hours();
+        let age_hours = self.clock.now().signed_duration_since(summary.generated_at).num_hours();
         let recency_score = 1.0 / (1.0 + age_hours as f32 / 24.0);
         score += recency_score * 0.3;
 
@@ -241,20 +359,23 @@ impl ContextBuilder {
     }
 
     fn summary_to_message(&self, summary: &DbSummary, current_messages: &[Message]) -> Message {
+        let text = TextUtils::truncate_at_sentence(&summary.summary_text, MAX_INJECTED_SUMMARY_CHARS);
+        let ellipsis = if text.len() < summary.summary_text.trim_end().len() { "…" } else { "" };
         let content = if current_messages.len() > 5 {
-            format!("[Summary of earlier conversation: {}]", summary.summary_text)
+            format!("[Summary of earlier conversation: {}{}]", text, ellipsis)
         } else {
-            format!("[Earlier: {}]", summary.summary_text)
+            format!("[Earlier: {}{}]", text, ellipsis)
         };
-        Message { role: "system".to_string(), content }
+        Message { role: Role::System, content, parts: None }
     }
     async fn add_specific_details(
         &mut self,
         context: &mut Vec<Message>,
         full_messages: &[StoredMessage],
+        extracted_details: &[Detail],
         user_query: Option<&str>
     ) -> anyhow::Result<()> {
-        if !self.config.enable_detail_injection || full_messages.is_empty() {
+        if !self.config.enable_detail_injection || (full_messages.is_empty() && extracted_details.is_empty()) {
             return Ok(());
         }
 
@@ -263,15 +384,28 @@ impl ContextBuilder {
             return Ok(());
         }
 
-        let relevant_messages = self.find_relevant_details(full_messages, &detail_requests);
-        for message in &relevant_messages {
-            let detail_message = Message {
-                role: message.role.clone(),
-                content: format!("[Earlier detail: {}]", message.content),
-            };
-
+        let matched_details = self.find_matching_extracted_details(extracted_details, &detail_requests);
+        let detail_messages: Vec<Message> = if !matched_details.is_empty() {
+            matched_details.iter()
+                .map(|detail| Message {
+                    role: Role::System,
+                    content: format!("[Earlier detail ({}): {} - \"{}\"]", detail.detail_type, detail.content, detail.context),
+                    parts: None,
+                })
+                .collect()
+        } else {
+            self.find_relevant_details(full_messages, &detail_requests)
+                .iter()
+                .map(|message| Message {
+                    role: Role::from(message.role.as_str()),
+                    content: format!("[Earlier detail: {}]", message.content),
+                    parts: None,
+                })
+                .collect()
+        };
 
-            if let Some(pos) = context.iter().rposition(|m| m.role == "user") {
+        for detail_message in detail_messages {
+            if let Some(pos) = context.iter().rposition(|m| m.role == Role::User) {
                 context.insert(pos, detail_message);
             } else {
                 context.insert(0, detail_message);
@@ -304,6 +438,27 @@ impl ContextBuilder {
         requests.dedup();
         requests
     }
+    fn find_matching_extracted_details<'a>(
+        &self,
+        details: &'a [Detail],
+        detail_requests: &[String]
+    ) -> Vec<&'a Detail> {
+        details.iter()
+            .filter(|detail| {
+                let content_lower = detail.content.to_lowercase();
+                let context_lower = detail.context.to_lowercase();
+                detail_requests.iter().any(|request| {
+                    let request_lower = request.to_lowercase();
+                    context_lower.contains(&request_lower)
+                        || request_lower.contains(&content_lower)
+                        || request_lower.split_whitespace()
+                            .filter(|w| TextUtils::is_significant_word(w, 3))
+                            .any(|w| context_lower.contains(w))
+                })
+            })
+            .take(5)
+            .collect()
+    }
     fn find_relevant_details<'a>(
         &self,
         messages: &'a [StoredMessage],
@@ -328,24 +483,47 @@ impl ContextBuilder {
 
         relevant
     }
-    fn trim_to_token_limit(&self, context: &mut Vec<Message>) {
-        let mut total_tokens = 0;
-        let mut to_remove = Vec::new();
+    fn trim_to_token_limit(&self, context: &mut Vec<Message>, current_messages: &[Message]) {
+        let is_pinned = |message: &Message| {
+            self.config.preserve_system_messages
+                && message.role == Role::System
+                && current_messages.iter().any(|m| m.role == Role::System && m.content == message.content)
+        };
 
-        for (idx, message) in context.iter().enumerate() {
-            let message_tokens = message.content.len() / 4;
+        let max_tokens = self.config.max_total_tokens;
+        let mut total_tokens: usize = context.iter()
+            .filter(|m| is_pinned(m))
+            .map(|m| TextUtils::estimate_tokens(&m.content))
+            .sum();
+        let mut kept: Vec<Option<String>> = vec![None; context.len()];
+
+        // Reserve room for the most recent code first so the snippet being iterated on
+        // survives even when older history fills the budget.
+        let latest_code_idx = context.iter()
+            .rposition(|m| !is_pinned(m) && !code_block_spans(&m.content).is_empty());
+        if let Some(idx) = latest_code_idx {
+            kept[idx] = fit_message(&context[idx].content, &mut total_tokens, max_tokens);
+        }
 
-            if total_tokens + message_tokens > self.config.max_total_tokens {
-                to_remove.push(idx);
-            } else {
-                total_tokens += message_tokens;
+        for (idx, message) in context.iter().enumerate() {
+            if is_pinned(message) {
+                kept[idx] = Some(message.content.clone());
+                continue;
             }
+            if Some(idx) == latest_code_idx {
+                continue;
+            }
+            kept[idx] = fit_message(&message.content, &mut total_tokens, max_tokens);
         }
 
-
-        for idx in to_remove.iter().rev() {
-            context.remove(*idx);
-        }
+        let mut kept = kept.into_iter();
+        context.retain_mut(|message| match kept.next().flatten() {
+            Some(content) => {
+                message.content = content;
+                true
+            }
+            None => false,
+        });
     }
     /
     async fn add_bridging(
@@ -362,15 +540,16 @@ impl ContextBuilder {
 
         if transition_idx > 0 && transition_idx < context.len() {
             let summary_count = context[..transition_idx].iter()
-                .filter(|m| m.role == "system" &&
+                .filter(|m| m.role == Role::System &&
                         (m.content.starts_with("[Summary") || m.content.starts_with("[Earlier:")))
                 .count();
 
             if summary_count > 0 {
                 let bridge_message = Message {
-                    role: "system".to_string(),
+                    role: Role::System,
                     content: format!("[Continuing from earlier conversation with {} summary{}]",
                         summary_count, if summary_count > 1 { "s" } else { "" }),
+                    parts: None,
                 };
 
                 context.insert(transition_idx, bridge_message);
@@ -380,14 +559,19 @@ impl ContextBuilder {
         Ok(())
     }
 
+    // Summaries and cross-session messages are each inserted at the front, so
+    // they can interleave; the transition follows the last of them wherever it sits.
     fn find_transition_point(&self, context: &[Message]) -> usize {
-        for (idx, message) in context.iter().enumerate() {
-            if !(message.role == "system" &&
-                 (message.content.starts_with("[Summary") || message.content.starts_with("[Earlier:") || message.content.starts_with("[Context"))) {
-                return idx;
-            }
-        }
-        context.len()
+        context.iter()
+            .rposition(Self::is_injected_history)
+            .map_or(0, |idx| idx + 1)
+    }
+
+    fn is_injected_history(message: &Message) -> bool {
+        let content = message.content.as_str();
+        (message.role == Role::System
+            && (content.starts_with("[Summary") || content.starts_with("[Earlier:") || content.starts_with("[Context")))
+            || content.starts_with("[From earlier:")
     }
 
     fn extract_topics(&self, messages: &[Message]) -> Vec<String> {
@@ -425,16 +609,313 @@ impl ContextBuilder {
             }
         }
 
-        topics.dedup();
+        // Keep first occurrences so topic order only depends on message order.
+        let mut seen = std::collections::HashSet::new();
+        topics.retain(|topic| seen.insert(topic.clone()));
         topics.truncate(3);
         topics
     }
 }
+/
+fn fit_message(content: &str, total_tokens: &mut usize, max_tokens: usize) -> Option<String> {
+    let tokens = TextUtils::estimate_tokens(content);
+    if *total_tokens + tokens <= max_tokens {
+        *total_tokens += tokens;
+        return Some(content.to_string());
+    }
+
+    // Too large as a whole: keep complete code blocks (newest first) and drop the prose.
+    let mut blocks: Vec<&str> = Vec::new();
+    let mut reduced_tokens = 0;
+    for span in code_block_spans(content).into_iter().rev() {
+        let mut candidate = blocks.clone();
+        candidate.insert(0, content[span].trim_end());
+        let candidate_tokens = TextUtils::estimate_tokens(&candidate.join("\n\n"));
+        if *total_tokens + candidate_tokens <= max_tokens {
+            blocks = candidate;
+            reduced_tokens = candidate_tokens;
+        }
+    }
+    if blocks.is_empty() {
+        return None;
+    }
+    *total_tokens += reduced_tokens;
+    Some(blocks.join("\n\n"))
+}
+/
+/
+fn fit_cross_session(content: &str, remaining: &mut usize) -> Option<String> {
+    const PREFIX: &str = "[From earlier:";
+    const TRUNCATED_SUFFIX: &str = "...]";
+    let full = format!("{} {}]", PREFIX, content);
+    let tokens = TextUtils::estimate_tokens(&full);
+    if tokens <= *remaining {
+        *remaining -= tokens;
+        return Some(full);
+    }
+
+    let overhead = TextUtils::estimate_tokens(PREFIX) + TextUtils::estimate_tokens(TRUNCATED_SUFFIX);
+    let mut budget = remaining.checked_sub(overhead)?;
+    let mut used = overhead;
+    let mut words = Vec::new();
+    for word in content.split_whitespace() {
+        let word_tokens = TextUtils::estimate_tokens(word);
+        if word_tokens > budget {
+            break;
+        }
+        budget -= word_tokens;
+        used += word_tokens;
+        words.push(word);
+    }
+    if words.is_empty() {
+        return None;
+    }
+    *remaining -= used;
+    Some(format!("{} {} {}", PREFIX, words.join(" "), TRUNCATED_SUFFIX))
+}
+/
+fn code_block_spans(content: &str) -> Vec<Range<usize>> {
+    let mut spans = Vec::new();
+    let mut open: Option<usize> = None;
+    let mut offset = 0;
+    for line in content.split_inclusive('\n') {
+        if line.trim_start().starts_with("```") {
+            match open.take() {
+                Some(start) => spans.push(start..offset + line.len()),
+                None => open = Some(offset),
+            }
+        }
+        offset += line.len();
+    }
+    if let Some(start) = open {
+        spans.push(start..content.len());
+    }
+    spans
+}
 impl Clone for ContextBuilder {
     fn clone(&self) -> Self {
         Self {
             config: self.config.clone(),
+            llm_worker: self.llm_worker.clone(),
+            clock: self.clock.clone(),
+            summary_embeddings: self.summary_embeddings.clone(),
         }
     }
 }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    fn message(role: Role, content: String) -> Message {
+        Message { role, content, parts: None }
+    }
+    #[tokio::test]
+    async fn test_summary_embeddings_are_reused_across_turns() {
+        use axum::{routing::post, Json, Router};
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        let embedded = Arc::new(AtomicUsize::new(0));
+        let counter = embedded.clone();
+        let app = Router::new().route("/v1/embeddings", post(move |Json(body): Json<serde_json::Value>| {
+            let counter = counter.clone();
+            async move {
+                let inputs = body["input"].as_array().cloned().unwrap_or_default();
+                counter.fetch_add(inputs.len(), Ordering::SeqCst);
+                let data: Vec<_> = inputs.iter().map(|_| serde_json::json!({ "embedding": [0.1, 0.2, 0.3] })).collect();
+                Json(serde_json::json!({ "data": data }))
+            }
+        }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let backend = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let mut builder = ContextBuilder::new(ContextBuilderConfig::default());
+        builder.set_llm_worker(Arc::new(LLMWorker::new_with_backend(backend)));
+        let summary = |id: i64, text: &str| DbSummary {
+            id,
+            session_id: "current".to_string(),
+            message_range_start: 0,
+            message_range_end: 10,
+            summary_text: text.to_string(),
+            compression_ratio: 0.2,
+            key_topics: Vec::new(),
+            generated_at: chrono::Utc::now(),
+        };
+        let mut summaries = vec![summary(1, "Chose the queue library"), summary(2, "Planned the rollout")];
+
+        let scores = builder.semantic_summary_scores(&summaries, Some("what did we choose?")).await.unwrap();
+        assert_eq!(scores.len(), 2);
+        assert_eq!(embedded.load(Ordering::SeqCst), 3);
+
+        summaries.push(summary(3, "Reviewed the incident"));
+        let scores = builder.semantic_summary_scores(&summaries, Some("and after that?")).await.unwrap();
+        assert_eq!(scores.len(), 3);
+        assert_eq!(embedded.load(Ordering::SeqCst), 5);
+    }
+    #[tokio::test]
+    async fn test_bridge_follows_interleaved_summaries_and_cross_session_messages() {
+        let mut builder = ContextBuilder::new(ContextBuilderConfig::default());
+        let conversation = vec![
+            message(Role::System, "You are a helpful assistant.".to_string()),
+            message(Role::User, "Where did we leave the migration?".to_string()),
+            message(Role::Assistant, "At the index step.".to_string()),
+            message(Role::User, "Continue from there.".to_string()),
+        ];
+        let stored = |id: i64, role: &str, content: &str| StoredMessage {
+            id,
+            session_id: "other".to_string(),
+            message_index: id as i32,
+            role: role.to_string(),
+            content: content.to_string(),
+            tokens: 5,
+            timestamp: chrono::Utc::now(),
+            importance_score: 0.5,
+            embedding_generated: false,
+        };
+        let summary = DbSummary {
+            id: 1,
+            session_id: "current".to_string(),
+            message_range_start: 0,
+            message_range_end: 10,
+            summary_text: "Planned the schema migration".to_string(),
+            compression_ratio: 0.2,
+            key_topics: vec!["migration".to_string()],
+            generated_at: chrono::Utc::now(),
+        };
+
+        let mut context = conversation.clone();
+        builder.add_cross_session_context(&mut context, &[
+            stored(1, "user", "How do indexes work?"),
+            stored(2, "assistant", "They speed up lookups."),
+        ], None).await.unwrap();
+        context.insert(0, builder.summary_to_message(&summary, &conversation));
+        builder.add_bridging(&mut context, &conversation, Some(&vec![summary])).await.unwrap();
+
+        let bridge_idx = context.iter()
+            .position(|m| m.content.starts_with("[Continuing from earlier conversation"))
+            .expect("bridge inserted");
+        assert_eq!(bridge_idx, 4);
+        assert_eq!(context[bridge_idx].content, "[Continuing from earlier conversation with 1 summary]");
+        assert!(context[..bridge_idx].iter().all(ContextBuilder::is_injected_history));
+        let tail: Vec<_> = context[bridge_idx + 1..].iter().map(|m| m.content.as_str()).collect();
+        let expected: Vec<_> = conversation.iter().map(|m| m.content.as_str()).collect();
+        assert_eq!(tail, expected);
+    }
+    #[test]
+    fn test_summary_ties_are_ordered_independently_of_input() {
+        let now = chrono::DateTime::parse_from_rfc3339("2026-03-01T12:00:00Z").unwrap().with_timezone(&chrono::Utc);
+        let mut builder = ContextBuilder::new(ContextBuilderConfig::default());
+        builder.set_clock(Clock::fixed(now));
+        let summary = |id: i64| DbSummary {
+            id,
+            session_id: "s".to_string(),
+            message_range_start: 0,
+            message_range_end: 10,
+            summary_text: format!("Summary {} of the release plan", id),
+            compression_ratio: 0.2,
+            key_topics: vec!["release".to_string()],
+            generated_at: now - chrono::Duration::hours(6),
+        };
+        let conversation = vec![message(Role::User, "What about the release plan?".to_string())];
+        let forward = vec![summary(1), summary(2), summary(3)];
+        let reversed = vec![summary(3), summary(2), summary(1)];
+
+        let ids = |summaries: &[DbSummary]| -> Vec<i64> {
+            builder.select_relevant_summaries(summaries, &conversation, Some("release"), None)
+                .iter().map(|s| s.id).collect()
+        };
+        assert_eq!(ids(&forward), vec![3, 2, 1]);
+        assert_eq!(ids(&forward), ids(&reversed));
+    }
+    #[test]
+    fn test_injected_summary_is_cut_at_sentence_boundary() {
+        let builder = ContextBuilder::new(ContextBuilderConfig::default());
+        let summary = DbSummary {
+            id: 1,
+            session_id: "s".to_string(),
+            message_range_start: 0,
+            message_range_end: 40,
+            summary_text: "The team agreed to ship on Friday. ".repeat(40),
+            compression_ratio: 0.1,
+            key_topics: vec!["release".to_string()],
+            generated_at: chrono::Utc::now(),
+        };
+        let conversation = vec![message(Role::User, "When do we ship?".to_string())];
+
+        let injected = builder.summary_to_message(&summary, &conversation).content;
+        assert!(injected.starts_with("[Earlier: The team agreed"));
+        assert!(injected.ends_with("on Friday.…]"));
+        assert!(injected.chars().count() <= MAX_INJECTED_SUMMARY_CHARS + "[Earlier: …]".chars().count());
+    }
+    #[tokio::test]
+    async fn test_oversized_cross_session_hits_leave_room_for_current_messages() {
+        let mut builder = ContextBuilder::new(ContextBuilderConfig {
+            max_total_tokens: 600,
+            ..Default::default()
+        });
+        let current: Vec<Message> = (0..6)
+            .map(|i| {
+                let role = if i % 2 == 0 { Role::User } else { Role::Assistant };
+                message(role, format!("Current turn {} about the deployment plan and rollout.", i))
+            })
+            .collect();
+        let cross: Vec<StoredMessage> = (0..3)
+            .map(|id| StoredMessage {
+                id,
+                session_id: "other".to_string(),
+                message_index: id as i32,
+                role: "assistant".to_string(),
+                content: "An extremely long answer from another session. ".repeat(150),
+                tokens: 1200,
+                timestamp: chrono::Utc::now(),
+                importance_score: 0.5,
+                embedding_generated: false,
+            })
+            .collect();
+
+        let context = builder.build_context(&current, Some(current.clone()), None, None, Some(cross), None, None)
+            .await
+            .unwrap();
+
+        let cross_tokens: usize = context.iter()
+            .filter(|m| m.content.starts_with("[From earlier:"))
+            .map(|m| TextUtils::estimate_tokens(&m.content))
+            .sum();
+        assert!(cross_tokens > 0);
+        assert!(cross_tokens <= (600.0 * builder.config().max_cross_session_ratio) as usize);
+        for m in &current {
+            assert!(context.iter().any(|c| c.content == m.content), "current message dropped: {}", m.content);
+        }
+    }
+    #[test]
+    fn test_latest_code_block_survives_trimming_intact() {
+        let builder = ContextBuilder::new(ContextBuilderConfig {
+            max_total_tokens: 600,
+            ..Default::default()
+        });
+        let old_code = format!("```rust\n{}```", "fn old() {}\n".repeat(40));
+        let latest_code = format!("```python\n{}```", "total = compute(values) * 2\n".repeat(20));
 
+        let mut context = Vec::new();
+        for i in 0..30 {
+            let filler = format!("Message {} discussing the refactor in some detail. ", i).repeat(4);
+            let role = if i % 2 == 0 { Role::User } else { Role::Assistant };
+            context.push(message(role, filler));
+            if i == 5 {
+                context.push(message(Role::Assistant, format!("Here is a first draft:\n{}", old_code)));
+            }
+        }
+        context.push(message(
+            Role::User,
+            format!("{}\nI changed it like this, why does it fail?\n{}", "Long explanation. ".repeat(120), latest_code),
+        ));
+        let current = context.clone();
+
+        builder.trim_to_token_limit(&mut context, &current);
+
+        let total: usize = context.iter().map(|m| TextUtils::estimate_tokens(&m.content)).sum();
+        assert!(total <= 600);
+        assert!(context.iter().any(|m| m.content.contains(&latest_code)));
+        for m in &context {
+            assert_eq!(m.content.matches("```").count() % 2, 0, "partial code block kept: {}", m.content);
+        }
+    }
+}
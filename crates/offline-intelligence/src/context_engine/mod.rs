@@ -2,10 +2,12 @@
 pub mod tier_manager;
 pub mod context_builder;
 pub mod orchestrator;
+pub mod clock;
 pub use retrieval_planner::{RetrievalPlanner, RetrievalPlan};
 pub use tier_manager::{TierManager, TierManagerConfig, TierStats};
 pub use context_builder::{ContextBuilder, ContextBuilderConfig};
-pub use orchestrator::{ContextOrchestrator, OrchestratorConfig, SessionStats, CleanupStats};
+pub use clock::Clock;
+pub use orchestrator::{ContextOrchestrator, OrchestratorConfig, OptimizationStats, SessionStats, CleanupStats, BackfillStats, ContextDecision};
 /
 pub async fn create_default_orchestrator(
     database: std::sync::Arc<crate::memory_db::MemoryDatabase>,
@@ -1,17 +1,30 @@
-﻿use crate::memory::Message;
+﻿use crate::memory::{Message, Role};
 use crate::memory_db::MemoryDatabase;
-use crate::memory_db::schema::Embedding;
+use crate::memory_db::schema::{Embedding, Summary as DbSummary};
 use crate::context_engine::{
     retrieval_planner::RetrievalPlan,
     retrieval_planner::RetrievalPlanner,
     tier_manager::{TierManager, TierManagerConfig},
     context_builder::{ContextBuilder, ContextBuilderConfig},
+    clock::Clock,
 };
 use crate::worker_threads::LLMWorker;
-use std::sync::Arc;
+use crate::utils::{TextUtils, ExtractedDetail, TopicExtractor};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use serde::Serialize;
 use tracing::{info, debug, warn};
 use tokio::sync::RwLock;
 /
+const MAX_SEARCH_TOPICS: usize = 8;
+/
+const SUMMARY_CHUNK_MESSAGES: usize = 40;
+/
+const SUMMARY_KEEP_RECENT: usize = 20;
+/
+const SUMMARY_MAX_TOKENS: u32 = 256;
+/
 pub struct ContextOrchestrator {
     database: Arc<MemoryDatabase>,
     retrieval_planner: Arc<RwLock<RetrievalPlanner>>,
@@ -20,15 +33,23 @@ pub struct ContextOrchestrator {
     config: OrchestratorConfig,
     /
     llm_worker: Option<Arc<LLMWorker>>,
+    optimization_stats: Arc<Mutex<OptimizationStats>>,
+    clock: Clock,
 }
 /
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct OrchestratorConfig {
     pub enabled: bool,
     pub max_context_tokens: usize,
     pub auto_optimize: bool,
     pub enable_metrics: bool,
     pub session_timeout_seconds: u64,
+    pub tenant_isolation: bool,
+    pub persist_tier1: bool,
+    /
+    pub relevance_weight: f32,
+    /
+    pub recency_weight: f32,
 }
 impl Default for OrchestratorConfig {
     fn default() -> Self {
@@ -36,8 +57,13 @@ impl Default for OrchestratorConfig {
             enabled: true,
             max_context_tokens: 4000,
             auto_optimize: true,
-            enable_metrics: true,
+            // Recording costs a second tokenize round-trip per optimized turn, so it is opt-in.
+            enable_metrics: false,
             session_timeout_seconds: 3600,
+            tenant_isolation: false,
+            persist_tier1: false,
+            relevance_weight: 0.7,
+            recency_weight: 0.3,
         }
     }
 }
@@ -47,11 +73,24 @@ impl ContextOrchestrator {
         database: Arc<MemoryDatabase>,
         config: OrchestratorConfig,
     ) -> anyhow::Result<Self> {
+        Self::new_with_clock(database, config, Clock::system()).await
+    }
+    /
+    /
+    pub async fn new_with_clock(
+        database: Arc<MemoryDatabase>,
+        config: OrchestratorConfig,
+        clock: Clock,
+    ) -> anyhow::Result<Self> {
 
         let retrieval_planner = Arc::new(RwLock::new(RetrievalPlanner::new(database.clone())));
 
 
-        let tier_manager_config = TierManagerConfig::default();
+        let tier_manager_config = TierManagerConfig {
+            tenant_isolation: config.tenant_isolation,
+            persist_tier1: config.persist_tier1,
+            ..Default::default()
+        };
         let tier_manager = TierManager::new(
             database.clone(),
             tier_manager_config,
@@ -59,8 +98,9 @@ impl ContextOrchestrator {
         let tier_manager = Arc::new(RwLock::new(tier_manager));
 
 
-        let context_builder_config = ContextBuilderConfig::default();
-        let context_builder = Arc::new(RwLock::new(ContextBuilder::new(context_builder_config)));
+        let mut context_builder = ContextBuilder::new(builder_config(&config));
+        context_builder.set_clock(clock.clone());
+        let context_builder = Arc::new(RwLock::new(context_builder));
 
         let orchestrator = Self {
             database,
@@ -69,12 +109,15 @@ impl ContextOrchestrator {
             context_builder,
             config,
             llm_worker: None,
+            optimization_stats: Arc::new(Mutex::new(OptimizationStats::default())),
+            clock,
         };
         info!("Context orchestrator initialized successfully");
         Ok(orchestrator)
     }
     /
-    pub fn set_llm_worker(&mut self, worker: Arc<LLMWorker>) {
+    pub async fn set_llm_worker(&mut self, worker: Arc<LLMWorker>) {
+        self.context_builder.write().await.set_llm_worker(worker.clone());
         self.llm_worker = Some(worker);
         info!("Context orchestrator: LLM worker set for semantic search");
     }
@@ -85,15 +128,92 @@ impl ContextOrchestrator {
     }
 
     /
+    pub fn config(&self) -> &OrchestratorConfig {
+        &self.config
+    }
+
+    /
+    /
+    pub fn with_config(&self, config: OrchestratorConfig) -> Self {
+        if config.tenant_isolation != self.config.tenant_isolation {
+            warn!("tenant_isolation cannot change on reload; keeping {}", self.config.tenant_isolation);
+        }
+        if config.persist_tier1 != self.config.persist_tier1 {
+            warn!("persist_tier1 cannot change on reload; keeping {}", self.config.persist_tier1);
+        }
+        let config = OrchestratorConfig {
+            tenant_isolation: self.config.tenant_isolation,
+            persist_tier1: self.config.persist_tier1,
+            ..config
+        };
+        let mut context_builder = ContextBuilder::new(builder_config(&config));
+        if let Some(ref worker) = self.llm_worker {
+            context_builder.set_llm_worker(worker.clone());
+        }
+        context_builder.set_clock(self.clock.clone());
+        info!("Context orchestrator reconfigured (max_context_tokens={})", config.max_context_tokens);
+        Self {
+            database: self.database.clone(),
+            retrieval_planner: Arc::new(RwLock::new(RetrievalPlanner::new(self.database.clone()))),
+            tier_manager: self.tier_manager.clone(),
+            context_builder: Arc::new(RwLock::new(context_builder)),
+            config,
+            llm_worker: self.llm_worker.clone(),
+            optimization_stats: self.optimization_stats.clone(),
+            clock: self.clock.clone(),
+        }
+    }
+
+    /
+    #[tracing::instrument(name = "context", skip_all, fields(session_id = %session_id))]
     pub async fn process_conversation(
         &self,
         session_id: &str,
         messages: &[Message],
         user_query: Option<&str>,
     ) -> anyhow::Result<Vec<Message>> {
+        self.process_conversation_with_budget(session_id, messages, user_query, None).await
+    }
+
+    /
+    /
+    pub async fn process_conversation_with_budget(
+        &self,
+        session_id: &str,
+        messages: &[Message],
+        user_query: Option<&str>,
+        max_context_tokens: Option<usize>,
+    ) -> anyhow::Result<Vec<Message>> {
+        self.process_conversation_with_decision(session_id, messages, user_query, max_context_tokens).await
+            .map(|(context, _)| context)
+    }
+
+    /
+    /
+    pub async fn process_conversation_with_decision(
+        &self,
+        session_id: &str,
+        messages: &[Message],
+        user_query: Option<&str>,
+        max_context_tokens: Option<usize>,
+    ) -> anyhow::Result<(Vec<Message>, ContextDecision)> {
+        let max_context_tokens = max_context_tokens.unwrap_or(self.config.max_context_tokens);
+        // Persistence is independent of optimization: the last user turn is stored
+        // even when the context engine is disabled.
+        if let Some(last_message) = messages.last() {
+            if last_message.role == Role::User {
+                match self.persist_messages(session_id, std::slice::from_ref(last_message)).await {
+                    Ok(_) => {
+                        info!("âœ… Persisted user query to database for session {}", session_id);
+                    }
+                    Err(e) => warn!("Failed to persist user query to database: {}", e),
+                }
+            }
+        }
+
         if !self.config.enabled || messages.is_empty() {
             debug!("Context engine disabled or no messages");
-            return Ok(messages.to_vec());
+            return Ok((messages.to_vec(), ContextDecision::default()));
         }
 
         info!("Processing conversation for session {} ({} messages)", session_id, messages.len());
@@ -105,64 +225,44 @@ impl ContextOrchestrator {
         }
 
 
-        if let Some(last_message) = messages.last() {
-            if last_message.role == "user" {
-                let tier_manager = self.tier_manager.read().await;
-                if let Err(e) = tier_manager.store_tier3_content(session_id, std::slice::from_ref(last_message)).await {
-                    warn!("Failed to persist user query to database: {}", e);
-                } else {
-                    info!("âœ… Persisted user query to database for session {}", session_id);
-                }
-            }
-        }
-
-
-        let plan = {
-            let retrieval_planner = self.retrieval_planner.read().await;
-
-
-
-            let has_past_refs = if let Some(query) = user_query {
-                retrieval_planner.has_past_references_in_text(query)
-            } else {
-                false
-            };
-
-
-            retrieval_planner.create_plan(
-                session_id,
-                messages,
-                self.config.max_context_tokens,
-                user_query,
-                has_past_refs,
-            ).await?
-        };
+        let current_tokens = self.count_tokens(messages).await;
+        let plan = self.create_plan(session_id, messages, current_tokens, user_query, max_context_tokens).await?;
 
         if !plan.needs_retrieval {
             debug!("No retrieval needed, returning current messages");
-            return Ok(messages.to_vec());
+            return Ok((messages.to_vec(), ContextDecision::default()));
         }
 
 
+        let retrieval_started = Instant::now();
         let retrieved_content = self.execute_retrieval_plan(session_id, &plan, user_query).await?;
+        let retrieval_latency = retrieval_started.elapsed();
+        let decision = ContextDecision::from_retrieved(&retrieved_content);
 
 
         let optimized_context = {
             let mut context_builder = self.context_builder.write().await;
-            context_builder.build_context(
+            // The builder is shared, so the per-call budget is restored before
+            // the write lock is released.
+            let default_budget = context_builder.config().max_total_tokens;
+            context_builder.set_max_total_tokens(max_context_tokens);
+            let built = context_builder.build_context(
                 messages,
                 retrieved_content.tier1,
                 retrieved_content.tier2,
                 retrieved_content.tier3,
                 retrieved_content.cross_session,
+                retrieved_content.details,
                 user_query,
-            ).await?
+            ).await;
+            context_builder.set_max_total_tokens(default_budget);
+            built?
         };
 
 
         if let Some(query) = user_query {
             if let Some(response) = optimized_context.last() {
-                if response.role == "assistant" {
+                if response.role == Role::Assistant {
                     self.update_engagement(query, &response.content).await;
                 }
             }
@@ -174,27 +274,287 @@ impl ContextOrchestrator {
             optimized_context.len()
         );
 
-        Ok(optimized_context)
+        if self.config.enable_metrics {
+            let output_tokens = self.count_tokens(&optimized_context).await;
+            self.record_optimization(
+                &plan,
+                messages.len(),
+                optimized_context.len(),
+                current_tokens,
+                output_tokens,
+                retrieval_latency,
+            );
+        }
+
+        Ok((optimized_context, decision))
+    }
+
+    /
+    pub async fn plan_only(
+        &self,
+        session_id: &str,
+        messages: &[Message],
+        user_query: Option<&str>,
+    ) -> anyhow::Result<RetrievalPlan> {
+        let current_tokens = self.count_tokens(messages).await;
+        self.create_plan(session_id, messages, current_tokens, user_query, self.config.max_context_tokens).await
+    }
+
+    async fn create_plan(
+        &self,
+        session_id: &str,
+        messages: &[Message],
+        current_tokens: usize,
+        user_query: Option<&str>,
+        max_context_tokens: usize,
+    ) -> anyhow::Result<RetrievalPlan> {
+        let retrieval_planner = self.retrieval_planner.read().await;
+        let has_past_refs = if let Some(query) = user_query {
+            retrieval_planner.has_past_references_in_text(query)
+        } else {
+            false
+        };
+
+        retrieval_planner.create_plan(
+            session_id,
+            messages,
+            current_tokens,
+            max_context_tokens,
+            user_query,
+            has_past_refs,
+        ).await
+    }
+
+    async fn count_tokens(&self, messages: &[Message]) -> usize {
+        match self.llm_worker {
+            Some(ref llm_worker) => llm_worker.count_tokens(messages).await,
+            None => LLMWorker::estimate_tokens(messages),
+        }
+    }
+
+    /
+    /
+    pub async fn store_summary(
+        &self,
+        session_id: &str,
+        original: &[Message],
+        message_range: (i32, i32),
+        summary_text: String,
+        key_topics: Vec<String>,
+    ) -> anyhow::Result<DbSummary> {
+        let original_tokens = self.count_tokens(original).await;
+        let summary_message = Message { role: Role::System, content: summary_text.clone(), parts: None };
+        let summary_tokens = self.count_tokens(std::slice::from_ref(&summary_message)).await;
+        let compression_ratio = if original_tokens == 0 {
+            1.0
+        } else {
+            summary_tokens as f32 / original_tokens as f32
+        };
+        let summary = DbSummary {
+            id: 0,
+            session_id: session_id.to_string(),
+            message_range_start: message_range.0,
+            message_range_end: message_range.1,
+            summary_text,
+            compression_ratio,
+            key_topics,
+            generated_at: self.clock.now(),
+        };
+        self.tier_manager.read().await.store_tier2_content(&summary).await?;
+        debug!(
+            "Stored summary for session {} ({} -> {} tokens, ratio {:.2})",
+            session_id, original_tokens, summary_tokens, compression_ratio
+        );
+        Ok(summary)
+    }
+
+    /
+    /
+    /
+    pub async fn summarize_backlog(&self, session_id: &str) -> anyhow::Result<Option<DbSummary>> {
+        let Some(ref llm_worker) = self.llm_worker else {
+            return Ok(None);
+        };
+        let database = Arc::clone(&self.database);
+        let owned_session_id = session_id.to_string();
+        let pending = tokio::task::spawn_blocking(move || -> anyhow::Result<Vec<crate::memory_db::StoredMessage>> {
+            let next_start = database.summaries.get_session_summaries(&owned_session_id)?
+                .iter()
+                .map(|s| s.message_range_end + 1)
+                .max()
+                .unwrap_or(0);
+            let total = database.conversations.get_session_message_count(&owned_session_id)?;
+            if total < next_start as usize + SUMMARY_CHUNK_MESSAGES + SUMMARY_KEEP_RECENT {
+                return Ok(Vec::new());
+            }
+            let chunk = database.conversations.get_session_messages(
+                &owned_session_id,
+                Some(SUMMARY_CHUNK_MESSAGES as i32),
+                Some(next_start),
+            )?;
+            Ok(chunk.into_iter().filter(|m| m.message_index >= next_start).collect())
+        })
+        .await
+        .map_err(|e| anyhow::anyhow!("Database task failed: {}", e))??;
+        let (Some(first), Some(last)) = (pending.first(), pending.last()) else {
+            return Ok(None);
+        };
+        let range = (first.message_index, last.message_index);
+        let original: Vec<Message> = pending.iter()
+            .map(|m| Message { role: Role::from(m.role.as_str()), content: m.content.clone(), parts: None })
+            .collect();
+        let summary_text = llm_worker.generate_summary(&original, SUMMARY_MAX_TOKENS).await?;
+        let key_topics = TopicExtractor::new(5, 4).extract_from_messages(&original, original.len());
+        self.store_summary(session_id, &original, range, summary_text, key_topics).await.map(Some)
     }
 
     /
+    #[tracing::instrument(name = "context", skip_all, fields(session_id = %session_id))]
     pub async fn save_assistant_response(
         &self,
         session_id: &str,
         response: &str,
-    ) -> anyhow::Result<()> {
+    ) -> anyhow::Result<Vec<crate::memory_db::StoredMessage>> {
         let assistant_message = Message {
-            role: "assistant".to_string(),
+            role: Role::Assistant,
             content: response.to_string(),
+            parts: None,
         };
 
-        let tier_manager = self.tier_manager.read().await;
-        tier_manager.store_tier3_content(session_id, &[assistant_message]).await
+        self.persist_messages(session_id, &[assistant_message]).await
+    }
+
+    /
+    pub async fn persist_messages(
+        &self,
+        session_id: &str,
+        messages: &[Message],
+    ) -> anyhow::Result<Vec<crate::memory_db::StoredMessage>> {
+        let stored = {
+            let tier_manager = self.tier_manager.read().await;
+            tier_manager.store_tier3_content(session_id, messages).await?
+        };
+        self.persist_details(session_id, &stored).await;
+        Ok(stored)
+    }
+
+    fn record_optimization(
+        &self,
+        plan: &RetrievalPlan,
+        input_messages: usize,
+        output_messages: usize,
+        input_tokens: usize,
+        output_tokens: usize,
+        retrieval_latency: std::time::Duration,
+    ) {
+        let tiers: Vec<&str> = [
+            (plan.use_tier1, "tier1"),
+            (plan.use_tier2, "tier2"),
+            (plan.use_tier3, "tier3"),
+            (plan.cross_session_search, "cross_session"),
+        ]
+        .into_iter()
+        .filter_map(|(used, tier)| used.then_some(tier))
+        .collect();
+        let tokens_saved = input_tokens.saturating_sub(output_tokens);
+
+        crate::metrics::record_context_optimization(
+            input_messages,
+            output_messages,
+            tokens_saved,
+            &tiers,
+            retrieval_latency.as_secs_f64(),
+        );
+
+        if let Ok(mut stats) = self.optimization_stats.lock() {
+            stats.optimizations += 1;
+            stats.input_messages += input_messages as u64;
+            stats.output_messages += output_messages as u64;
+            stats.input_tokens += input_tokens as u64;
+            stats.output_tokens += output_tokens as u64;
+            stats.tokens_saved += tokens_saved as u64;
+            for tier in &tiers {
+                *stats.tier_usage.entry(tier.to_string()).or_insert(0) += 1;
+            }
+            stats.total_retrieval_ms += retrieval_latency.as_millis() as u64;
+            stats.last_retrieval_ms = retrieval_latency.as_millis() as u64;
+        }
+    }
+
+    /
+    pub fn optimization_stats(&self) -> OptimizationStats {
+        self.optimization_stats.lock()
+            .map(|stats| stats.clone())
+            .unwrap_or_default()
+    }
+
+    async fn persist_details(&self, session_id: &str, stored: &[crate::memory_db::StoredMessage]) {
+        let database = Arc::clone(&self.database);
+        let owned_session_id = session_id.to_string();
+        let contents: Vec<(i64, String)> = stored.iter().map(|m| (m.id, m.content.clone())).collect();
+        // The regex extraction and the batch insert are both synchronous; keep them off the runtime.
+        let result = tokio::task::spawn_blocking(move || -> anyhow::Result<usize> {
+            let extracted: Vec<(i64, ExtractedDetail)> = contents.iter()
+                .flat_map(|(id, content)| TextUtils::extract_details(content).into_iter().map(move |d| (*id, d)))
+                .collect();
+            if extracted.is_empty() {
+                return Ok(0);
+            }
+            let rows: Vec<(&str, i64, &str, &str, &str, f32)> = extracted.iter()
+                .map(|(message_id, d)| (owned_session_id.as_str(), *message_id, d.detail_type, d.content.as_str(), d.context.as_str(), d.importance_score))
+                .collect();
+            database.conversations.store_details_batch(&rows)?;
+            Ok(rows.len())
+        })
+        .await
+        .map_err(|e| anyhow::anyhow!("Database task failed: {}", e))
+        .and_then(|result| result);
+        match result {
+            Ok(0) => {}
+            Ok(count) => debug!("Extracted {} details for session {}", count, session_id),
+            Err(e) => warn!("Failed to persist extracted details: {}", e),
+        }
     }
 
     /
     /
     /
+    /
+    /
+    /
+    fn rank_by_relevance_and_recency(
+        &self,
+        messages: Vec<crate::memory_db::StoredMessage>,
+        relevance: &HashMap<i64, f32>,
+        max_messages: usize,
+    ) -> Vec<crate::memory_db::StoredMessage> {
+        let (Some(oldest), Some(newest)) = (
+            messages.iter().map(|m| m.timestamp).min(),
+            messages.iter().map(|m| m.timestamp).max(),
+        ) else {
+            return messages;
+        };
+        let span_ms = (newest - oldest).num_milliseconds() as f32;
+        let mut scored: Vec<(f32, crate::memory_db::StoredMessage)> = messages
+            .into_iter()
+            .map(|m| {
+                let recency = if span_ms > 0.0 {
+                    (m.timestamp - oldest).num_milliseconds() as f32 / span_ms
+                } else {
+                    1.0
+                };
+                let relevance = relevance.get(&m.id).copied().unwrap_or(0.0);
+                let score = relevance * self.config.relevance_weight + recency * self.config.recency_weight;
+                (score, m)
+            })
+            .collect();
+        scored.sort_by(|a, b| b.0.total_cmp(&a.0)
+            .then_with(|| b.1.timestamp.cmp(&a.1.timestamp))
+            .then_with(|| b.1.id.cmp(&a.1.id)));
+        scored.truncate(max_messages);
+        scored.into_iter().map(|(_, m)| m).collect()
+    }
+
     async fn execute_retrieval_plan(
         &self,
         session_id: &str,
@@ -210,7 +570,7 @@ impl ContextOrchestrator {
 
         if plan.use_tier2 {
             let tier_manager = self.tier_manager.read().await;
-            retrieved.tier2 = tier_manager.get_tier2_content(session_id).await;
+            retrieved.tier2 = Some(tier_manager.get_tier2_content(session_id).await?);
         }
 
 
@@ -220,10 +580,18 @@ impl ContextOrchestrator {
 
 
         let mut semantic_results: Vec<crate::memory_db::StoredMessage> = Vec::new();
+        let mut relevance: HashMap<i64, f32> = HashMap::new();
         let has_embeddings = self.database.embeddings.get_stats()
             .map(|s| s.total_embeddings > 0)
             .unwrap_or(false);
-        if plan.semantic_search && has_embeddings {
+        let trivial_query = match user_query {
+            Some(query) => self.retrieval_planner.read().await.is_trivial_query(query),
+            None => true,
+        };
+        if trivial_query && plan.semantic_search {
+            debug!("Skipping semantic search for trivial query");
+        }
+        if plan.semantic_search && has_embeddings && !trivial_query {
             if let (Some(ref llm_worker), Some(query)) = (&self.llm_worker, user_query) {
                 match llm_worker.generate_embeddings(vec![query.to_string()]).await {
                     Ok(query_embeddings) if !query_embeddings.is_empty() => {
@@ -231,14 +599,16 @@ impl ContextOrchestrator {
 
                         match self.database.embeddings.find_similar_embeddings(
                             query_vec,
-                            "llama-server",
+                            llm_worker.embedding_model(),
                             (plan.max_messages * 2) as i32,
                             0.3,
                         ) {
                             Ok(similar) if !similar.is_empty() => {
+                                retrieved.semantic = true;
                                 info!("Semantic search found {} similar messages for context retrieval", similar.len());
 
-                                for (message_id, _similarity) in &similar {
+                                for (message_id, similarity) in &similar {
+                                    relevance.insert(*message_id, *similarity);
 
                                     let conn = self.database.conversations.get_conn_public();
                                     if let Ok(conn) = conn {
@@ -271,7 +641,11 @@ impl ContextOrchestrator {
                                     }
                                 }
                             }
-                            Ok(_) => debug!("Semantic search: no results above threshold"),
+                            Ok(_) => {
+                                // The search ran; it just found nothing close enough.
+                                retrieved.semantic = true;
+                                debug!("Semantic search: no results above threshold");
+                            }
                             Err(e) => debug!("Semantic search failed: {}", e),
                         }
                     }
@@ -284,25 +658,38 @@ impl ContextOrchestrator {
         if plan.use_tier3 {
             let tier_manager = self.tier_manager.read().await;
             if plan.keyword_search && !plan.search_topics.is_empty() {
-                for topic in &plan.search_topics {
-                    let limit_per_topic = plan.max_messages / plan.search_topics.len().max(1);
-                    if let Ok(results) = tier_manager.search_tier3_content(
-                        session_id,
-                        topic,
-                        limit_per_topic,
-                    ).await {
-
-                        let semantic_ids: std::collections::HashSet<i64> = semantic_results.iter().map(|m| m.id).collect();
-                        let mut merged = semantic_results.clone();
-                        for msg in results {
-                            if !semantic_ids.contains(&msg.id) {
-                                merged.push(msg);
+                let topics = &plan.search_topics[..plan.search_topics.len().min(MAX_SEARCH_TOPICS)];
+                if topics.len() < plan.search_topics.len() {
+                    debug!("Searching {} of {} topics", topics.len(), plan.search_topics.len());
+                }
+                let limit_per_topic = (plan.max_messages / topics.len()).max(1);
+                let mut seen: std::collections::HashSet<i64> = semantic_results.iter().map(|m| m.id).collect();
+                let mut merged = semantic_results.clone();
+                let mut keyword_matches = 0;
+                let mut searched = false;
+                for topic in topics {
+                    match tier_manager.search_tier3_content(session_id, topic, limit_per_topic).await {
+                        Ok(results) => {
+                            searched = true;
+                            for msg in results {
+                                if keyword_matches >= plan.max_messages {
+                                    break;
+                                }
+                                let score = topic_coverage(&msg.content, topics);
+                                let entry = relevance.entry(msg.id).or_insert(0.0);
+                                *entry = entry.max(score);
+                                if seen.insert(msg.id) {
+                                    merged.push(msg);
+                                    keyword_matches += 1;
+                                }
                             }
                         }
-                        retrieved.tier3 = Some(merged);
-                        break;
+                        Err(e) => debug!("Tier 3 search for topic '{}' failed: {}", topic, e),
                     }
                 }
+                if searched {
+                    retrieved.tier3 = Some(self.rank_by_relevance_and_recency(merged, &relevance, plan.max_messages));
+                }
 
                 if retrieved.tier3.is_none() && !semantic_results.is_empty() {
                     retrieved.tier3 = Some(semantic_results.clone());
@@ -334,6 +721,21 @@ impl ContextOrchestrator {
                 retrieved.cross_session = Some(cross_session_results);
             }
         }
+        if plan.use_tier3 {
+            let database = Arc::clone(&self.database);
+            let owned_session_id = session_id.to_string();
+            let details = tokio::task::spawn_blocking(move || {
+                database.conversations.get_details(&owned_session_id, None)
+            })
+            .await
+            .map_err(|e| anyhow::anyhow!("Database task failed: {}", e))
+            .and_then(|result| result);
+            match details {
+                Ok(details) if !details.is_empty() => retrieved.details = Some(details),
+                Ok(_) => {}
+                Err(e) => debug!("Detail lookup failed: {}", e),
+            }
+        }
         Ok(retrieved)
     }
 
@@ -371,20 +773,104 @@ impl ContextOrchestrator {
         &self,
         session_id: Option<&str>,
         keywords: &[String],
+        filter: &crate::memory_db::MessageSearchFilter,
         limit: usize,
     ) -> anyhow::Result<Vec<crate::memory_db::StoredMessage>> {
         if keywords.is_empty() {
             return Ok(Vec::new());
         }
+        // Without a session or owner to scope to, a search would read every tenant's messages.
+        if self.config.tenant_isolation && session_id.is_none() && filter.user_id.is_none() {
+            return Err(anyhow::anyhow!("Cross-session search requires a user_id when tenant isolation is enabled"));
+        }
 
-        if let Some(sid) = session_id {
-
-            self.database.search_messages_by_keywords(sid, keywords, limit).await
-        } else {
-
+        self.database.search_messages_filtered(session_id, keywords, filter, limit).await
+    }
 
-            Ok(Vec::new())
+    /
+    /
+    /
+    /
+    pub async fn backfill_embeddings(
+        &self,
+        batch_size: usize,
+        max_batches: usize,
+        pause: Duration,
+    ) -> anyhow::Result<BackfillStats> {
+        let llm_worker = self.llm_worker.clone()
+            .ok_or_else(|| anyhow::anyhow!("Embedding backfill requires an LLM worker"))?;
+        let batch_size = batch_size.max(1);
+        let mut stats = BackfillStats::default();
+        let mut cursor = 0;
+        while stats.batches < max_batches {
+            let database = Arc::clone(&self.database);
+            let batch = tokio::task::spawn_blocking(move || {
+                database.conversations.get_messages_without_embeddings(cursor, batch_size)
+            })
+            .await
+            .map_err(|e| anyhow::anyhow!("Database task failed: {}", e))??;
+            let Some(last) = batch.last() else { break };
+            cursor = last.id;
+            if stats.batches > 0 && !pause.is_zero() {
+                tokio::time::sleep(pause).await;
+            }
+            stats.batches += 1;
+
+            let texts = batch.iter().map(|m| m.content.clone()).collect();
+            let embeddings = match llm_worker.generate_embeddings(texts).await {
+                Ok(embeddings) if embeddings.len() == batch.len() => embeddings,
+                Ok(embeddings) => {
+                    warn!("Backfill batch returned {} embeddings for {} messages", embeddings.len(), batch.len());
+                    stats.failed += batch.len();
+                    continue;
+                }
+                Err(e) => {
+                    warn!("Backfill batch failed: {}", e);
+                    stats.failed += batch.len();
+                    continue;
+                }
+            };
+            let now = self.clock.now();
+            let database = Arc::clone(&self.database);
+            let embedding_model = llm_worker.embedding_model().to_string();
+            let message_ids: Vec<i64> = batch.iter().map(|m| m.id).collect();
+            // Each store is a SQLite write plus an index insert; keep the whole batch off the runtime.
+            let (embedded, failed) = tokio::task::spawn_blocking(move || {
+                let mut outcome = (0usize, 0usize);
+                for (message_id, embedding) in message_ids.into_iter().zip(embeddings) {
+                    let stored = database.embeddings.store_embedding(&Embedding {
+                        id: 0,
+                        message_id,
+                        embedding,
+                        embedding_model: embedding_model.clone(),
+                        generated_at: now,
+                    }).and_then(|_| database.conversations.mark_embedding_generated(message_id));
+                    match stored {
+                        Ok(()) => outcome.0 += 1,
+                        Err(e) => {
+                            debug!("Failed to store backfilled embedding for msg {}: {}", message_id, e);
+                            outcome.1 += 1;
+                        }
+                    }
+                }
+                outcome
+            })
+            .await
+            .map_err(|e| anyhow::anyhow!("Database task failed: {}", e))?;
+            stats.embedded += embedded;
+            stats.failed += failed;
         }
+        let database = Arc::clone(&self.database);
+        stats.remaining = tokio::task::spawn_blocking(move || {
+            database.conversations.count_messages_without_embeddings()
+        })
+        .await
+        .map_err(|e| anyhow::anyhow!("Database task failed: {}", e))??;
+        info!(
+            "Embedding backfill: {} embedded, {} failed in {} batch(es), {} remaining",
+            stats.embedded, stats.failed, stats.batches, stats.remaining
+        );
+        Ok(stats)
     }
 
     pub fn set_enabled(&mut self, enabled: bool) {
@@ -405,6 +891,12 @@ impl ContextOrchestrator {
         &self.tier_manager
     }
 }
+fn builder_config(config: &OrchestratorConfig) -> ContextBuilderConfig {
+    ContextBuilderConfig {
+        max_total_tokens: config.max_context_tokens,
+        ..Default::default()
+    }
+}
 impl Clone for ContextOrchestrator {
     fn clone(&self) -> Self {
         Self {
@@ -414,15 +906,65 @@ impl Clone for ContextOrchestrator {
             context_builder: self.context_builder.clone(),
             config: self.config.clone(),
             llm_worker: self.llm_worker.clone(),
+            optimization_stats: self.optimization_stats.clone(),
+            clock: self.clock.clone(),
         }
     }
 }
+/
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct OptimizationStats {
+    pub optimizations: u64,
+    pub input_messages: u64,
+    pub output_messages: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub tokens_saved: u64,
+    pub tier_usage: std::collections::BTreeMap<String, u64>,
+    pub total_retrieval_ms: u64,
+    pub last_retrieval_ms: u64,
+}
+/
+fn topic_coverage(content: &str, topics: &[String]) -> f32 {
+    if topics.is_empty() {
+        return 0.0;
+    }
+    let content = content.to_lowercase();
+    let matched = topics.iter().filter(|t| content.contains(&t.to_lowercase())).count();
+    matched as f32 / topics.len() as f32
+}
 #[derive(Debug, Default)]
 struct RetrievedContent {
     tier1: Option<Vec<Message>>,
     tier2: Option<Vec<crate::memory_db::Summary>>,
     tier3: Option<Vec<crate::memory_db::StoredMessage>>,
     cross_session: Option<Vec<crate::memory_db::StoredMessage>>,
+    details: Option<Vec<crate::memory_db::Detail>>,
+    semantic: bool,
+}
+/
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ContextDecision {
+    /
+    pub retrieved: usize,
+    /
+    pub tiers: Vec<u8>,
+    /
+    pub semantic: bool,
+}
+impl ContextDecision {
+    fn from_retrieved(content: &RetrievedContent) -> Self {
+        let len = |items: Option<usize>| items.unwrap_or(0);
+        let tier1 = len(content.tier1.as_ref().map(Vec::len));
+        let tier2 = len(content.tier2.as_ref().map(Vec::len));
+        let tier3 = len(content.tier3.as_ref().map(Vec::len)) + len(content.cross_session.as_ref().map(Vec::len));
+        let tiers = [(1, tier1), (2, tier2), (3, tier3)]
+            .into_iter()
+            .filter(|(_, count)| *count > 0)
+            .map(|(tier, _)| tier)
+            .collect();
+        Self { retrieved: tier2 + tier3, tiers, semantic: content.semantic }
+    }
 }
 #[derive(Debug, Clone)]
 pub struct SessionStats {
@@ -430,9 +972,330 @@ pub struct SessionStats {
     pub tier_stats: crate::context_engine::tier_manager::TierStats,
     pub database_stats: crate::memory_db::schema::DatabaseStats,
 }
+/
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct BackfillStats {
+    pub batches: usize,
+    pub embedded: usize,
+    pub failed: usize,
+    pub remaining: usize,
+}
 #[derive(Debug, Clone)]
 pub struct CleanupStats {
     pub sessions_cleaned: usize,
     pub cache_entries_cleaned: usize,
 }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[tokio::test]
+    async fn test_plan_only_does_not_persist_messages() {
+        let dir = tempfile::tempdir().unwrap();
+        let database = Arc::new(MemoryDatabase::new(&dir.path().join("plan.db")).unwrap());
+        let orchestrator = ContextOrchestrator::new(database.clone(), OrchestratorConfig::default()).await.unwrap();
+        let messages = vec![Message { role: Role::User, content: "What did we discuss earlier about the release?".to_string(), parts: None }];
+        let plan = orchestrator
+            .plan_only("plan-session", &messages, Some("What did we discuss earlier about the release?"))
+            .await
+            .unwrap();
+        assert!(plan.needs_retrieval);
+        let stored: i64 = database.conversations.get_conn_public().unwrap()
+            .query_row("SELECT COUNT(*) FROM messages", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(stored, 0);
+    }
+    #[tokio::test]
+    async fn test_keyword_search_covers_every_topic() {
+        let dir = tempfile::tempdir().unwrap();
+        let database = Arc::new(MemoryDatabase::new(&dir.path().join("topics.db")).unwrap());
+        let orchestrator = ContextOrchestrator::new(database.clone(), OrchestratorConfig::default()).await.unwrap();
+        let session = database.conversations.create_session(None).unwrap();
+        database.conversations.store_messages_batch(&session.id, &[
+            ("user".to_string(), "alpha release notes".to_string(), 0, 3, 0.5),
+            ("assistant".to_string(), "beta rollout plan".to_string(), 1, 3, 0.5),
+            ("user".to_string(), "gamma migration steps".to_string(), 2, 3, 0.5),
+        ]).unwrap();
+        let plan = RetrievalPlan {
+            use_tier1: false,
+            use_tier3: true,
+            keyword_search: true,
+            max_messages: 9,
+            search_topics: vec!["alpha".to_string(), "beta".to_string(), "gamma".to_string()],
+            ..Default::default()
+        };
+        let retrieved = orchestrator.execute_retrieval_plan(&session.id, &plan, None).await.unwrap();
+        let mut contents: Vec<String> = retrieved.tier3.unwrap().into_iter().map(|m| m.content).collect();
+        contents.sort();
+        assert_eq!(contents, vec!["alpha release notes", "beta rollout plan", "gamma migration steps"]);
+    }
+    #[tokio::test]
+    async fn test_global_search_is_scoped_under_tenant_isolation() {
+        let dir = tempfile::tempdir().unwrap();
+        let database = Arc::new(MemoryDatabase::new(&dir.path().join("isolated-search.db")).unwrap());
+        let config = OrchestratorConfig { tenant_isolation: true, ..Default::default() };
+        let orchestrator = ContextOrchestrator::new(database.clone(), config).await.unwrap();
+        for user in ["alice", "bob"] {
+            let metadata = crate::memory_db::SessionMetadata { user_id: Some(user.to_string()), ..Default::default() };
+            let session = database.conversations.create_session(Some(metadata)).unwrap();
+            database.conversations.store_messages_batch(&session.id, &[
+                ("user".to_string(), format!("release notes from {}", user), 0, 4, 0.5),
+            ]).unwrap();
+        }
+        let keywords = vec!["release".to_string()];
+
+        let unscoped = crate::memory_db::MessageSearchFilter::default();
+        assert!(orchestrator.search_messages(None, &keywords, &unscoped, 10).await.is_err());
+        let scoped = crate::memory_db::MessageSearchFilter { user_id: Some("bob".to_string()), ..Default::default() };
+        let found = orchestrator.search_messages(None, &keywords, &scoped, 10).await.unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].content, "release notes from bob");
+    }
+    #[tokio::test]
+    async fn test_merged_results_follow_configured_weights() {
+        let dir = tempfile::tempdir().unwrap();
+        let database = Arc::new(MemoryDatabase::new(&dir.path().join("weights.db")).unwrap());
+        let session = database.conversations.create_session(None).unwrap();
+        database.conversations.store_messages_batch(&session.id, &[
+            ("user".to_string(), "old note about alpha and beta".to_string(), 0, 6, 0.5),
+            ("user".to_string(), "recent note about alpha".to_string(), 1, 4, 0.5),
+        ]).unwrap();
+        database.conversations.get_conn_public().unwrap().execute(
+            "UPDATE messages SET timestamp = ?1 WHERE message_index = 0",
+            [(chrono::Utc::now() - chrono::Duration::days(30)).to_rfc3339()],
+        ).unwrap();
+        let plan = RetrievalPlan {
+            use_tier1: false,
+            use_tier3: true,
+            keyword_search: true,
+            max_messages: 4,
+            search_topics: vec!["alpha".to_string(), "beta".to_string()],
+            ..Default::default()
+        };
+        let top = |config: OrchestratorConfig| {
+            let database = database.clone();
+            let plan = plan.clone();
+            let session_id = session.id.clone();
+            async move {
+                let orchestrator = ContextOrchestrator::new(database, config).await.unwrap();
+                let tier3 = orchestrator.execute_retrieval_plan(&session_id, &plan, None).await.unwrap().tier3.unwrap();
+                assert_eq!(tier3.len(), 2);
+                tier3[0].content.clone()
+            }
+        };
+
+        let by_relevance = top(OrchestratorConfig { relevance_weight: 1.0, recency_weight: 0.0, ..Default::default() }).await;
+        assert_eq!(by_relevance, "old note about alpha and beta");
+        let by_recency = top(OrchestratorConfig { relevance_weight: 0.0, recency_weight: 1.0, ..Default::default() }).await;
+        assert_eq!(by_recency, "recent note about alpha");
+    }
+    #[tokio::test]
+    async fn test_summary_stores_measured_compression_ratio() {
+        let dir = tempfile::tempdir().unwrap();
+        let database = Arc::new(MemoryDatabase::new(&dir.path().join("summary.db")).unwrap());
+        let orchestrator = ContextOrchestrator::new(database.clone(), OrchestratorConfig::default()).await.unwrap();
+        let session = database.conversations.create_session(None).unwrap();
+        let original: Vec<Message> = (0..6).map(|i| Message {
+            role: if i % 2 == 0 { Role::User } else { Role::Assistant },
+            content: format!("Turn {} walks through the deployment checklist, rollback steps and the on-call rota in detail.", i),
+            parts: None,
+        }).collect();
+        let summary_text = "Deployment checklist, rollback and on-call rota discussed.".to_string();
+
+        let summary = orchestrator.store_summary(&session.id, &original, (0, 5), summary_text.clone(), vec!["deployment".to_string()]).await.unwrap();
+
+        let original_tokens = LLMWorker::estimate_tokens(&original) as f32;
+        let summary_tokens = LLMWorker::estimate_tokens(&[Message { role: Role::System, content: summary_text, parts: None }]) as f32;
+        let stored = database.summaries.get_session_summaries(&session.id).unwrap();
+        assert_eq!(stored.len(), 1);
+        assert!((stored[0].compression_ratio - summary_tokens / original_tokens).abs() < 1e-6);
+        assert_eq!(stored[0].compression_ratio, summary.compression_ratio);
+        assert!(stored[0].compression_ratio < 0.5);
+    }
+    #[tokio::test]
+    async fn test_backfill_embeds_legacy_messages_in_batches() {
+        use axum::{routing::post, Json, Router};
+        let app = Router::new().route("/v1/embeddings", post(|Json(body): Json<serde_json::Value>| async move {
+            let inputs = body["input"].as_array().cloned().unwrap_or_default();
+            let data: Vec<_> = inputs.iter().map(|_| serde_json::json!({ "embedding": [0.1, 0.2, 0.3] })).collect();
+            Json(serde_json::json!({ "data": data }))
+        }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let backend = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let dir = tempfile::tempdir().unwrap();
+        let database = Arc::new(MemoryDatabase::new(&dir.path().join("backfill.db")).unwrap());
+        let session = database.conversations.create_session(None).unwrap();
+        let legacy: Vec<_> = (0..5)
+            .map(|i| ("user".to_string(), format!("legacy message {}", i), i, 3, 0.5))
+            .collect();
+        database.conversations.store_messages_batch(&session.id, &legacy).unwrap();
+        let mut orchestrator = ContextOrchestrator::new(database.clone(), OrchestratorConfig::default()).await.unwrap();
+        orchestrator.set_llm_worker(Arc::new(LLMWorker::new_with_backend(backend))).await;
+
+        let first = orchestrator.backfill_embeddings(2, 2, Duration::ZERO).await.unwrap();
+        assert_eq!((first.batches, first.embedded, first.failed, first.remaining), (2, 4, 0, 1));
+        let resumed = orchestrator.backfill_embeddings(2, 10, Duration::ZERO).await.unwrap();
+        assert_eq!((resumed.batches, resumed.embedded, resumed.remaining), (1, 1, 0));
+
+        let messages = database.conversations.get_session_messages(&session.id, None, None).unwrap();
+        assert!(messages.iter().all(|m| m.embedding_generated));
+        let model = orchestrator.llm_worker.as_ref().unwrap().embedding_model();
+        assert!(database.embeddings.get_embedding_by_message_id(messages[4].id, model).unwrap().is_some());
+    }
+    #[tokio::test]
+    async fn test_backlog_is_summarized_in_chunks_behind_recent_turns() {
+        use axum::{routing::post, Json, Router};
+        let app = Router::new().route("/v1/chat/completions", post(|| async {
+            Json(serde_json::json!({
+                "choices": [{ "index": 0, "message": { "role": "assistant", "content": "Rollout steps agreed." } }],
+            }))
+        }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let backend = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let dir = tempfile::tempdir().unwrap();
+        let database = Arc::new(MemoryDatabase::new(&dir.path().join("backlog.db")).unwrap());
+        let session = database.conversations.create_session(None).unwrap();
+        let turns: Vec<_> = (0..70)
+            .map(|i| ("user".to_string(), format!("Turn {} covers the rollout checklist and rollback plan in detail.", i), i, 12, 0.5))
+            .collect();
+        database.conversations.store_messages_batch(&session.id, &turns).unwrap();
+        let mut orchestrator = ContextOrchestrator::new(database.clone(), OrchestratorConfig::default()).await.unwrap();
+        orchestrator.set_llm_worker(Arc::new(LLMWorker::new_with_backend(backend))).await;
+
+        let summary = orchestrator.summarize_backlog(&session.id).await.unwrap().unwrap();
+        assert_eq!((summary.message_range_start, summary.message_range_end), (0, 39));
+        assert!(summary.compression_ratio > 0.0 && summary.compression_ratio < 0.1);
+        // The 30 newer messages stay verbatim until enough of them accumulate.
+        assert!(orchestrator.summarize_backlog(&session.id).await.unwrap().is_none());
+        assert_eq!(database.summaries.get_session_summaries(&session.id).unwrap().len(), 1);
+    }
+    #[test]
+    fn test_context_decision_summarizes_retrieved_tiers() {
+        let stored = |id: i64| crate::memory_db::StoredMessage {
+            id,
+            session_id: "s".to_string(),
+            message_index: id as i32,
+            role: "user".to_string(),
+            content: format!("past {}", id),
+            tokens: 2,
+            timestamp: chrono::Utc::now(),
+            importance_score: 0.5,
+            embedding_generated: true,
+        };
+        let content = RetrievedContent {
+            tier1: Some(vec![Message { role: Role::User, content: "now".to_string(), parts: None }]),
+            tier2: Some(Vec::new()),
+            tier3: Some(vec![stored(1), stored(2)]),
+            cross_session: Some(vec![stored(3)]),
+            semantic: true,
+            ..Default::default()
+        };
+        let decision = ContextDecision::from_retrieved(&content);
+        assert_eq!(decision, ContextDecision { retrieved: 3, tiers: vec![1, 3], semantic: true });
+        assert_eq!(ContextDecision::from_retrieved(&RetrievedContent::default()), ContextDecision::default());
+    }
+    #[tokio::test]
+    async fn test_trivial_query_skips_embedding_request() {
+        use axum::{routing::post, Json, Router};
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+        let app = Router::new().route("/v1/embeddings", post(move || {
+            let counter = counter.clone();
+            async move {
+                counter.fetch_add(1, Ordering::SeqCst);
+                Json(serde_json::json!({ "data": [{ "embedding": [0.1, 0.2, 0.3] }] }))
+            }
+        }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let backend = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let dir = tempfile::tempdir().unwrap();
+        let database = Arc::new(MemoryDatabase::new(&dir.path().join("trivial.db")).unwrap());
+        let session = database.conversations.create_session(None).unwrap();
+        let stored = database.conversations.store_messages_batch(
+            &session.id,
+            &[("user".to_string(), "We picked Postgres for the billing service".to_string(), 0, 8, 0.5)],
+        ).unwrap();
+        let llm_worker = Arc::new(LLMWorker::new_with_backend(backend));
+        database.embeddings.store_embedding(&Embedding {
+            id: 0,
+            message_id: stored[0].id,
+            embedding: vec![0.1, 0.2, 0.3],
+            embedding_model: llm_worker.embedding_model().to_string(),
+            generated_at: chrono::Utc::now(),
+        }).unwrap();
+        let mut orchestrator = ContextOrchestrator::new(database.clone(), OrchestratorConfig::default()).await.unwrap();
+        orchestrator.set_llm_worker(llm_worker).await;
+        let plan = RetrievalPlan { semantic_search: true, use_tier1: false, ..Default::default() };
+
+        let skipped = orchestrator.execute_retrieval_plan(&session.id, &plan, Some("thanks")).await.unwrap();
+        assert_eq!(requests.load(Ordering::SeqCst), 0);
+        assert!(!skipped.semantic);
+        let searched = orchestrator.execute_retrieval_plan(&session.id, &plan, Some("Which database did we pick for billing?")).await.unwrap();
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+        assert!(searched.semantic);
+
+        // An unreachable embedding backend means no semantic search actually happened.
+        orchestrator.set_llm_worker(Arc::new(LLMWorker::new_with_backend("http://127.0.0.1:1".to_string()))).await;
+        let failed = orchestrator.execute_retrieval_plan(&session.id, &plan, Some("Which database did we pick for billing?")).await.unwrap();
+        assert!(!failed.semantic);
+    }
+    #[tokio::test]
+    async fn test_user_turn_is_persisted_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let database = Arc::new(MemoryDatabase::new(&dir.path().join("orchestrator.db")).unwrap());
+        let orchestrator = ContextOrchestrator::new(
+            database.clone(),
+            OrchestratorConfig { enabled: false, ..Default::default() },
+        ).await.unwrap();
+        let session_id = "persist-once";
+        let messages = vec![Message { role: Role::User, content: "How do I rotate the logs?".to_string(), parts: None }];
+
+        orchestrator.process_conversation(session_id, &messages, Some("How do I rotate the logs?")).await.unwrap();
+        orchestrator.process_conversation(session_id, &messages, Some("How do I rotate the logs?")).await.unwrap();
+        orchestrator.save_assistant_response(session_id, "Use logrotate.").await.unwrap();
+
+        let stored = database.conversations.get_session_messages(session_id, None, None).unwrap();
+        let roles: Vec<&str> = stored.iter().map(|m| m.role.as_str()).collect();
+        assert_eq!(roles, vec!["user", "assistant"]);
+    }
+
+    #[tokio::test]
+    async fn test_injected_clock_stamps_summaries_across_reloads() {
+        let at = chrono::DateTime::parse_from_rfc3339("2026-01-02T03:04:05Z").unwrap().with_timezone(&chrono::Utc);
+        let database = Arc::new(MemoryDatabase::new_in_memory().unwrap());
+        let session = database.conversations.create_session(None).unwrap();
+        let orchestrator = ContextOrchestrator::new_with_clock(database, OrchestratorConfig::default(), Clock::fixed(at)).await.unwrap()
+            .with_config(OrchestratorConfig::default());
+        let original = vec![Message { role: Role::User, content: "Plan the rollout".to_string(), parts: None }];
+
+        let summary = orchestrator.store_summary(&session.id, &original, (0, 0), "Rollout planned.".to_string(), Vec::new()).await.unwrap();
+
+        assert_eq!(summary.generated_at, at);
+        assert_eq!(orchestrator.clock.now(), at);
+    }
+    #[tokio::test]
+    async fn test_with_config_preserves_tier_caches() {
+        let dir = tempfile::tempdir().unwrap();
+        let database = Arc::new(MemoryDatabase::new(&dir.path().join("reload.db")).unwrap());
+        let orchestrator = ContextOrchestrator::new(database, OrchestratorConfig::default()).await.unwrap();
+        let messages = vec![Message { role: Role::User, content: "Keep this in tier one".to_string(), parts: None }];
+        orchestrator.tier_manager.read().await.store_tier1_content("warm", &messages).await;
+
+        let reloaded = orchestrator.with_config(OrchestratorConfig {
+            max_context_tokens: 8000,
+            ..Default::default()
+        });
+
+        assert_eq!(reloaded.config().max_context_tokens, 8000);
+        assert_eq!(reloaded.context_builder.read().await.config().max_total_tokens, 8000);
+        let tier1 = reloaded.tier_manager.read().await.get_tier1_content("warm").await.unwrap();
+        assert_eq!(tier1[0].content, "Keep this in tier one");
+    }
+}
 
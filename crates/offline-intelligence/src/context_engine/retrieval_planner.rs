@@ -1,9 +1,11 @@
-﻿use crate::memory::Message;
+﻿use crate::memory::{Message, Role};
 use crate::memory_db::MemoryDatabase;
+use crate::utils::TextUtils;
+use serde::Serialize;
 use std::sync::Arc;
 use tracing::{debug, info};
 /
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct RetrievalPlan {
     /
     pub needs_retrieval: bool,
@@ -50,6 +52,16 @@ impl Default for RetrievalPlan {
     }
 }
 /
+/
+const TRIVIAL_QUERY_COMPLEXITY: f32 = 0.2;
+/
+const MIN_SEMANTIC_QUERY_CHARS: usize = 12;
+/
+const ACKNOWLEDGEMENTS: &[&str] = &[
+    "ok", "okay", "thanks", "thank", "you", "thx", "great", "cool", "nice", "perfect",
+    "good", "sounds", "got", "it", "yes", "no", "sure", "awesome", "cheers", "much",
+];
+/
 pub struct RetrievalPlanner {
     database: Arc<MemoryDatabase>,
     recent_threshold_messages: usize,
@@ -70,6 +82,7 @@ impl RetrievalPlanner {
         &self,
         session_id: &str,
         current_messages: &[Message],
+        current_tokens: usize,
         max_context_tokens: usize,
         user_query: Option<&str>,
         has_past_refs: bool,
@@ -97,7 +110,7 @@ impl RetrievalPlanner {
             has_past_references_in_query = true;
         }
 
-        if !plan.needs_retrieval && !self.needs_retrieval(current_messages, max_context_tokens) {
+        if !plan.needs_retrieval && !self.needs_retrieval(current_messages, current_tokens, max_context_tokens) {
 
             if has_past_references_in_query {
                 plan.needs_retrieval = true;
@@ -128,7 +141,7 @@ impl RetrievalPlanner {
         }
 
 
-        self.adjust_limits(&mut plan, current_messages, max_context_tokens);
+        self.adjust_limits(&mut plan, current_tokens, max_context_tokens);
 
         info!(
             "Created retrieval plan: Tiers({}{}{}), CrossSession({}), Search({}{}{}), PastRefs={}",
@@ -146,17 +159,12 @@ impl RetrievalPlanner {
     }
 
     /
-    fn needs_retrieval(&self, messages: &[Message], max_tokens: usize) -> bool {
-        if messages.len() <= 1 {
+    fn needs_retrieval(&self, messages: &[Message], current_tokens: usize, max_tokens: usize) -> bool {
+        if messages.is_empty() {
             return false;
         }
 
-
-        let estimated_tokens: usize = messages.iter()
-            .map(|m| m.content.len() / 4)
-            .sum();
-
-        estimated_tokens > max_tokens
+        current_tokens > max_tokens
     }
     /
     fn is_cross_session_query(&self, query: &str, _current_session_id: &str) -> bool {
@@ -186,9 +194,10 @@ impl RetrievalPlanner {
     }
     /
     fn extract_topics_from_query(&self, query: &str) -> Vec<String> {
+        let query = TextUtils::strip_markup(query);
         let words: Vec<&str> = query.split_whitespace().collect();
         if words.len() < 3 {
-            return vec![query.to_string()];
+            return vec![words.join(" ")];
         }
 
 
@@ -236,15 +245,17 @@ impl RetrievalPlanner {
         session_id: &str,
         has_past_references_in_query: bool,
     ) -> anyhow::Result<()> {
-        let has_summaries = self.database.summaries
-            .get_session_summaries(session_id)
-            .map(|summaries| !summaries.is_empty())
-            .unwrap_or_else(|e| {
-                debug!("Database error checking summaries: {}", e);
-                false
-            });
+        let database = Arc::clone(&self.database);
+        let owned_session_id = session_id.to_string();
+        let summary_ratios: Vec<f32> = tokio::task::spawn_blocking(move || {
+            database.summaries
+                .get_session_summaries(&owned_session_id)
+                .map(|summaries| summaries.iter().map(|s| s.compression_ratio).collect())
+        })
+        .await
+        .map_err(|e| anyhow::anyhow!("Database task failed: {}", e))??;
 
-        plan.use_tier2 = has_summaries;
+        plan.use_tier2 = !summary_ratios.is_empty();
 
 
         let has_db_messages = self.check_if_session_has_db_messages(session_id).await?;
@@ -280,7 +291,11 @@ impl RetrievalPlanner {
             debug!("Past references in messages, using Tier 3");
         }
 
-        if analysis.conversation_length > 100 {
+        // Prefer the ratio this session's summaries actually achieved; the
+        // heuristic only applies before anything has been summarized.
+        if !summary_ratios.is_empty() {
+            plan.target_compression = summary_ratios.iter().sum::<f32>() / summary_ratios.len() as f32;
+        } else if analysis.conversation_length > 100 {
             plan.target_compression = 0.2;
         }
 
@@ -289,8 +304,17 @@ impl RetrievalPlanner {
 
     /
     async fn check_if_session_has_db_messages(&self, session_id: &str) -> anyhow::Result<bool> {
-
-        match self.database.conversations.get_session_messages(session_id, Some(1), Some(0)) {
+        // rusqlite is synchronous; keep the lookup off the async worker threads so
+        // pool waits and busy_timeout retries never stall other streaming turns.
+        let database = Arc::clone(&self.database);
+        let owned_session_id = session_id.to_string();
+        let result = tokio::task::spawn_blocking(move || {
+            database.conversations.get_session_messages(&owned_session_id, Some(1), Some(0))
+        })
+        .await
+        .map_err(|e| anyhow::anyhow!("Database task failed: {}", e))
+        .and_then(|result| result);
+        match result {
             Ok(messages) => Ok(!messages.is_empty()),
             Err(e) => {
                 debug!("Error checking DB for session {}: {}", session_id, e);
@@ -308,6 +332,9 @@ impl RetrievalPlanner {
     ) {
 
         plan.semantic_search = analysis.query_complexity > 0.5 || (analysis.extracted_topics.is_empty() && !plan.cross_session_search);
+        if user_query.is_some_and(|query| self.is_trivial_query(query)) {
+            plan.semantic_search = false;
+        }
 
 
         plan.keyword_search = analysis.requires_specific_details
@@ -323,13 +350,9 @@ impl RetrievalPlanner {
     fn adjust_limits(
         &self,
         plan: &mut RetrievalPlan,
-        current_messages: &[Message],
+        current_tokens: usize,
         max_context_tokens: usize,
     ) {
-        let current_tokens: usize = current_messages.iter()
-            .map(|m| m.content.len() / 4)
-            .sum();
-
         let available_for_retrieval = max_context_tokens.saturating_sub(current_tokens);
 
 
@@ -341,8 +364,9 @@ impl RetrievalPlanner {
     fn extract_topics(&self, messages: &[Message]) -> Vec<String> {
         let mut topics = Vec::new();
 
-        for message in messages.iter().rev().filter(|m| m.role == "user").take(3) {
-            let words: Vec<&str> = message.content.split_whitespace().collect();
+        for message in messages.iter().rev().filter(|m| m.role == Role::User).take(3) {
+            let content = TextUtils::strip_markup(&message.content);
+            let words: Vec<&str> = content.split_whitespace().collect();
 
             for i in 0..words.len().saturating_sub(2) {
                 let word_lower = words[i].to_lowercase();
@@ -373,7 +397,9 @@ impl RetrievalPlanner {
             }
         }
 
-        topics.dedup();
+        // Keep first occurrences so topic order only depends on message order.
+        let mut seen = std::collections::HashSet::new();
+        topics.retain(|topic| seen.insert(topic.clone()));
         topics.truncate(3);
 
         topics
@@ -408,6 +434,23 @@ impl RetrievalPlanner {
         detail_patterns.iter().any(|p| query_lower.contains(p))
     }
 
+    /
+    /
+    pub fn is_trivial_query(&self, query: &str) -> bool {
+        if self.assess_query_complexity(query) > TRIVIAL_QUERY_COMPLEXITY {
+            return false;
+        }
+        let mut words = query
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|w| !w.is_empty())
+            .map(str::to_lowercase)
+            .peekable();
+        let chars = query.chars().filter(|c| c.is_alphanumeric()).count();
+        chars < MIN_SEMANTIC_QUERY_CHARS
+            || words.peek().is_none()
+            || words.all(|w| ACKNOWLEDGEMENTS.contains(&w.as_str()))
+    }
+
     /
     fn assess_query_complexity(&self, query: &str) -> f32 {
         let words: Vec<&str> = query.split_whitespace().collect();
@@ -491,3 +534,95 @@ impl Clone for RetrievalPlanner {
         }
     }
 }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_trivial_queries_skip_semantic_search() {
+        let dir = tempfile::tempdir().unwrap();
+        let planner = RetrievalPlanner::new(Arc::new(MemoryDatabase::new(&dir.path().join("trivial.db")).unwrap()));
+        for query in ["thanks", "ok", "ok thank you", "got it", "  ?! "] {
+            assert!(planner.is_trivial_query(query), "{:?} should be trivial", query);
+        }
+        for query in ["Docker networking?", "How did we configure the database pool?"] {
+            assert!(!planner.is_trivial_query(query), "{:?} should not be trivial", query);
+        }
+
+        let mut plan = RetrievalPlan::default();
+        planner.plan_search_strategies(&mut plan, &ConversationAnalysis::default(), Some("thanks"));
+        assert!(!plan.semantic_search);
+        planner.plan_search_strategies(&mut plan, &ConversationAnalysis::default(), Some("What did we decide about caching?"));
+        assert!(plan.semantic_search);
+    }
+    #[tokio::test]
+    async fn test_single_oversized_message_triggers_retrieval() {
+        let dir = tempfile::tempdir().unwrap();
+        let database = Arc::new(MemoryDatabase::new(&dir.path().join("planner.db")).unwrap());
+        let planner = RetrievalPlanner::new(database);
+
+        let document = "lorem ipsum dolor sit amet ".repeat(2000);
+        let messages = vec![Message { role: Role::User, content: document.clone(), parts: None }];
+        let tokens = TextUtils::estimate_tokens(&document);
+        assert!(tokens >= 10_000, "expected a 10k-token message, got {}", tokens);
+
+        let plan = planner
+            .create_plan("session", &messages, tokens, 4096, Some("summarize this"), false)
+            .await
+            .unwrap();
+        assert!(plan.needs_retrieval);
+        assert!(plan.max_tokens <= 4096);
+    }
+    #[tokio::test]
+    async fn test_planning_against_a_busy_pool_does_not_stall_the_executor() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use std::time::{Duration, Instant};
+        let dir = tempfile::tempdir().unwrap();
+        let settings = crate::memory_db::PoolSettings { max_size: 1, ..Default::default() };
+        let database = Arc::new(MemoryDatabase::new_with_pool_settings(
+            &dir.path().join("busy.db"),
+            Duration::from_secs(5),
+            settings,
+        ).unwrap());
+        let planner = RetrievalPlanner::new(database.clone());
+
+        // Hold the only pooled connection so both planner lookups have to wait for it.
+        let contention = Duration::from_millis(300);
+        let held = database.conversations.get_conn_public().unwrap();
+        let holder = std::thread::spawn(move || {
+            std::thread::sleep(contention);
+            drop(held);
+        });
+
+        // The test runtime has a single worker thread: a lookup that blocked it would
+        // show up as one gap between ticks as long as the contention itself.
+        let done = AtomicBool::new(false);
+        let messages = vec![Message { role: Role::User, content: "What did we decide earlier?".to_string(), parts: None }];
+        let started = Instant::now();
+        let plan = async {
+            let plan = planner.create_plan("session", &messages, 10, 4096, None, true).await;
+            done.store(true, Ordering::SeqCst);
+            plan
+        };
+        let ticker = async {
+            let mut longest = Duration::ZERO;
+            let mut last = Instant::now();
+            while !done.load(Ordering::SeqCst) {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+                longest = longest.max(last.elapsed());
+                last = Instant::now();
+            }
+            longest
+        };
+        let (plan, longest_stall) = tokio::join!(plan, ticker);
+        holder.join().unwrap();
+
+        assert!(plan.unwrap().needs_retrieval);
+        assert!(started.elapsed() >= contention, "planning should have waited for the pooled connection");
+        assert!(
+            longest_stall < contention / 3,
+            "executor stalled for {:?} while planning waited {:?} on the pool",
+            longest_stall,
+            contention
+        );
+    }
+}
@@ -1,9 +1,9 @@
-﻿use crate::memory::Message;
-use crate::memory_db::{MemoryDatabase, StoredMessage, Summary as DbSummary, SessionMetadata};
+﻿use crate::memory::{Message, Role};
+use crate::memory_db::{ImportanceWeights, MemoryDatabase, StoredMessage, Summary as DbSummary, SessionMetadata};
 use moka::sync::Cache;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
-use tracing::{debug, info};
+use tracing::{debug, info, warn};
 /
 #[derive(Debug, Clone)]
 pub struct TierManagerConfig {
@@ -11,6 +11,11 @@ pub struct TierManagerConfig {
     pub tier2_max_summaries: usize,
     pub tier2_cache_ttl_seconds: u64,
     pub enable_tier3_persistence: bool,
+    /
+    /
+    pub persist_tier1: bool,
+    pub tenant_isolation: bool,
+    pub importance_weights: ImportanceWeights,
 }
 impl Default for TierManagerConfig {
     fn default() -> Self {
@@ -19,6 +24,9 @@ impl Default for TierManagerConfig {
             tier2_max_summaries: 20,
             tier2_cache_ttl_seconds: 3600,
             enable_tier3_persistence: true,
+            persist_tier1: false,
+            tenant_isolation: false,
+            importance_weights: ImportanceWeights::default(),
         }
     }
 }
@@ -65,29 +73,66 @@ impl TierManager {
         self.tier1_cache.insert(session_id.to_string(), (messages_to_store.to_vec(), Instant::now()));
     }
     pub async fn get_tier1_content(&self, session_id: &str) -> Option<Vec<Message>> {
-        self.tier1_cache.get(session_id).map(|(m, _)| m)
+        if let Some((messages, _)) = self.tier1_cache.get(session_id) {
+            return Some(messages);
+        }
+        if !self.config.persist_tier1 {
+            return None;
+        }
+        // Every turn is already in the messages table, so the tail of it is the hot context.
+        let database = Arc::clone(&self.database);
+        let owned_session_id = session_id.to_string();
+        let limit = self.config.tier1_max_messages;
+        let rehydrated = tokio::task::spawn_blocking(move || Self::rehydrate_tier1(&database, &owned_session_id, limit))
+            .await
+            .unwrap_or_else(|e| Err(anyhow::anyhow!("Database task failed: {}", e)));
+        match rehydrated {
+            Ok(messages) if !messages.is_empty() => {
+                debug!("Rehydrated {} tier 1 messages for session {}", messages.len(), session_id);
+                self.tier1_cache.insert(session_id.to_string(), (messages.clone(), Instant::now()));
+                Some(messages)
+            }
+            Ok(_) => None,
+            Err(e) => {
+                warn!("Failed to rehydrate tier 1 content for session {}: {}", session_id, e);
+                None
+            }
+        }
+    }
+    /
+    fn rehydrate_tier1(database: &MemoryDatabase, session_id: &str, max_messages: usize) -> anyhow::Result<Vec<Message>> {
+        let count = database.conversations.get_session_message_count(session_id)?;
+        let limit = max_messages.min(count);
+        let stored = database.conversations.get_session_messages(
+            session_id,
+            Some(limit as i32),
+            Some((count - limit) as i32),
+        )?;
+        Ok(stored.into_iter()
+            .map(|m| Message { role: Role::from(m.role.as_str()), content: m.content, parts: None })
+            .collect())
     }
 
-    pub async fn get_tier2_content(&self, session_id: &str) -> Option<Vec<DbSummary>> {
+    /
+    pub async fn store_tier2_content(&self, summary: &DbSummary) -> anyhow::Result<()> {
+        self.database.summaries.store_summary(summary)?;
+        self.tier2_cache.invalidate(&summary.session_id);
+        Ok(())
+    }
+    /
+    /
+    pub async fn get_tier2_content(&self, session_id: &str) -> anyhow::Result<Vec<DbSummary>> {
 
         if let Some((summaries, _)) = self.tier2_cache.get(session_id) {
-            return Some(summaries);
+            return Ok(summaries);
         }
 
 
-        match self.database.summaries.get_session_summaries(session_id) {
-            Ok(summaries) => {
-
-                if !summaries.is_empty() {
-                    self.tier2_cache.insert(session_id.to_string(), (summaries.clone(), Instant::now()));
-                }
-                Some(summaries)
-            }
-            Err(e) => {
-                debug!("Error getting summaries from database: {}", e);
-                None
-            }
+        let summaries = self.database.summaries.get_session_summaries(session_id)?;
+        if !summaries.is_empty() {
+            self.tier2_cache.insert(session_id.to_string(), (summaries.clone(), Instant::now()));
         }
+        Ok(summaries)
     }
 
     pub async fn get_tier3_content(
@@ -114,12 +159,17 @@ impl TierManager {
 
         Ok(filtered)
     }
-    pub async fn store_tier3_content(&self, session_id: &str, messages: &[Message]) -> anyhow::Result<()> {
+    pub async fn store_tier3_content(&self, session_id: &str, messages: &[Message]) -> anyhow::Result<Vec<StoredMessage>> {
         if !self.config.enable_tier3_persistence || messages.is_empty() {
-            return Ok(());
+            return Ok(Vec::new());
+        }
+        self.persist_new_messages(session_id, messages).await
+    }
+    /
+    async fn persist_new_messages(&self, session_id: &str, messages: &[Message]) -> anyhow::Result<Vec<StoredMessage>> {
+        if messages.is_empty() {
+            return Ok(Vec::new());
         }
-
-
         self.ensure_session_exists(session_id, None).await?;
 
 
@@ -132,14 +182,14 @@ impl TierManager {
             .filter(|new_msg| {
                 !existing_messages.iter().any(|existing| {
                     existing.content == new_msg.content &&
-                    existing.role == new_msg.role
+                    Role::from(existing.role.as_str()) == new_msg.role
                 })
             })
             .collect();
 
         if new_messages.is_empty() {
             debug!("No new messages to save, all already exist in database");
-            return Ok(());
+            return Ok(Vec::new());
         }
 
         let start_index = existing_messages.len() as i32;
@@ -149,20 +199,18 @@ impl TierManager {
             .iter()
             .enumerate()
             .map(|(offset, m)| (
-                m.role.clone(),
+                m.role.to_string(),
                 m.content.clone(),
                 start_index + offset as i32,
                 (m.content.len() / 4) as i32,
-                0.5
+                self.config.importance_weights.score(&m.role, &m.content),
             ))
             .collect();
 
-        if !batch_data.is_empty() {
-            self.database.conversations.store_messages_batch(session_id, &batch_data)?;
-            info!("ðŸ“ Stored {} new messages to database for session {}", batch_data.len(), session_id);
-        }
+        let stored = self.database.conversations.store_messages_batch(session_id, &batch_data)?;
+        info!("ðŸ“ Stored {} new messages to database for session {}", batch_data.len(), session_id);
 
-        Ok(())
+        Ok(stored)
     }
 
     /
@@ -179,10 +227,21 @@ impl TierManager {
             return Ok(vec![]);
         }
 
+        let tenant = if self.config.tenant_isolation {
+            // An unowned session has no tenant to share with, so it only sees itself.
+            match self.database.conversations.get_session(current_session_id)?.and_then(|s| s.metadata.user_id) {
+                Some(user_id) => Some(user_id),
+                None => return Ok(vec![]),
+            }
+        } else {
+            None
+        };
+
         self.database.conversations.search_messages_by_topic_across_sessions(
             &keywords,
             limit,
             Some(current_session_id),
+            tenant.as_deref(),
         ).await
     }
     fn extract_keywords(&self, text: &str) -> Vec<String> {
@@ -208,9 +267,13 @@ impl TierManager {
             .map(|m| m.len())
             .unwrap_or(0);
 
-        let tier2_count = self.get_tier2_content(session_id).await
-            .map(|s| s.len())
-            .unwrap_or(0);
+        let tier2_count = match self.get_tier2_content(session_id).await {
+            Ok(summaries) => summaries.len(),
+            Err(e) => {
+                warn!("Failed to load tier 2 summaries for session {}: {}", session_id, e);
+                0
+            }
+        };
 
         let tier3_count = match self.database.conversations.get_session_messages(session_id, Some(10000), None) {
             Ok(messages) => messages.len(),
@@ -222,6 +285,11 @@ impl TierManager {
             tier3_count
         }
     }
+    /
+    pub fn evict_session(&self, session_id: &str) {
+        self.tier1_cache.invalidate(session_id);
+        self.tier2_cache.invalidate(session_id);
+    }
     pub async fn cleanup_cache(&self, _older_than_seconds: u64) -> usize {
         let count = self.tier1_cache.entry_count() + self.tier2_cache.entry_count();
 
@@ -266,4 +334,70 @@ impl Clone for TierManager {
         }
     }
 }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[tokio::test]
+    async fn test_tier1_rehydrates_after_restart_when_persisted() {
+        let database = Arc::new(MemoryDatabase::new_in_memory().unwrap());
+        let config = TierManagerConfig { persist_tier1: true, tier1_max_messages: 2, ..Default::default() };
+        let session = database.conversations.create_session(None).unwrap();
+        database.conversations.store_messages_batch(&session.id, &[
+            ("user".to_string(), "Old question".to_string(), 0, 2, 0.5),
+            ("user".to_string(), "Deploy plan?".to_string(), 1, 2, 0.5),
+            ("assistant".to_string(), "Blue-green.".to_string(), 2, 2, 0.5),
+        ]).unwrap();
+        let messages = vec![
+            Message { role: Role::System, content: "Be terse.".to_string(), parts: None },
+            Message { role: Role::User, content: "Deploy plan?".to_string(), parts: None },
+        ];
+        TierManager::new(database.clone(), config.clone()).store_tier1_content(&session.id, &messages).await;
+        // Caching tier 1 writes nothing; the conversation is persisted by the normal turn path.
+        assert_eq!(database.conversations.get_session_message_count(&session.id).unwrap(), 3);
 
+        let restarted = TierManager::new(database.clone(), config);
+        let tier1 = restarted.get_tier1_content(&session.id).await.unwrap();
+        let contents: Vec<_> = tier1.iter().map(|m| m.content.as_str()).collect();
+        assert_eq!(contents, vec!["Deploy plan?", "Blue-green."]);
+
+        let without_persistence = TierManager::new(database, TierManagerConfig::default());
+        assert!(without_persistence.get_tier1_content(&session.id).await.is_none());
+    }
+    #[tokio::test]
+    async fn test_cross_session_search_respects_tenant_isolation() {
+        let database = Arc::new(MemoryDatabase::new_in_memory().unwrap());
+        let owned_by = |user: &str| Some(SessionMetadata { user_id: Some(user.to_string()), ..Default::default() });
+        let alice = database.conversations.create_session(owned_by("alice")).unwrap();
+        let alice_other = database.conversations.create_session(owned_by("alice")).unwrap();
+        let bob = database.conversations.create_session(owned_by("bob")).unwrap();
+        let anonymous = database.conversations.create_session(None).unwrap();
+        let anonymous_other = database.conversations.create_session(None).unwrap();
+        for session in [&alice_other, &bob, &anonymous_other] {
+            database.conversations.store_messages_batch(&session.id, &[
+                ("user".to_string(), format!("kubernetes rollout in {}", session.id), 0, 4, 0.5),
+            ]).unwrap();
+        }
+        let isolated = TierManager::new(database.clone(), TierManagerConfig { tenant_isolation: true, ..Default::default() });
+
+        let found = isolated.search_cross_session_content(&alice.id, "kubernetes rollout", 10).await.unwrap();
+        let sessions: Vec<_> = found.iter().map(|m| m.session_id.as_str()).collect();
+        assert_eq!(sessions, vec![alice_other.id.as_str()]);
+        // Unowned sessions are not one shared tenant.
+        assert!(isolated.search_cross_session_content(&anonymous.id, "kubernetes rollout", 10).await.unwrap().is_empty());
+
+        let shared = TierManager::new(database, TierManagerConfig::default());
+        assert_eq!(shared.search_cross_session_content(&anonymous.id, "kubernetes rollout", 10).await.unwrap().len(), 3);
+    }
+    #[tokio::test]
+    async fn test_tier2_database_error_is_propagated() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("tier2.db");
+        let database = Arc::new(MemoryDatabase::new(&db_path).unwrap());
+        let tier_manager = TierManager::new(database, TierManagerConfig::default());
+        assert!(tier_manager.get_tier2_content("s1").await.unwrap().is_empty());
+
+        let conn = rusqlite::Connection::open(&db_path).unwrap();
+        conn.execute_batch("DROP TABLE summaries;").unwrap();
+        assert!(tier_manager.get_tier2_content("s1").await.is_err());
+    }
+}
@@ -16,7 +16,7 @@ pub mod thread_pool;
 pub mod worker_threads;
 pub mod thread_server;
 pub mod model_runtime;
-pub use memory::{Message, MemoryStore, InMemoryMemoryStore};
+pub use memory::{Message, MessageContent, ContentPart, Role, MemoryStore, InMemoryMemoryStore};
 pub use config::Config;
 pub use thread_server::run_thread_server;
 pub use api::{
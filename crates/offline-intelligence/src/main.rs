@@ -4,11 +4,25 @@ use offline_intelligence::{config::Config, run_thread_server};
 #[cfg(feature = "cli")]
 use dotenvy::dotenv;
 #[cfg(feature = "cli")]
+use clap::Parser;
+#[cfg(feature = "cli")]
+#[derive(Parser)]
+#[command(version)]
+struct Cli {
+    /// Configuration profile to load from the [profiles] section of the config file
+    #[arg(long)]
+    profile: Option<String>,
+}
+#[cfg(feature = "cli")]
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
     dotenv().ok();
 
-    let cfg = Config::from_env()?;
+    let cfg = match cli.profile {
+        Some(ref profile) => Config::from_profile(profile)?,
+        None => Config::from_env()?,
+    };
 
     println!("ðŸš€ Starting with thread-based architecture (only mode available)");
     run_thread_server(cfg).await
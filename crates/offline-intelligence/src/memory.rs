@@ -1,11 +1,163 @@
 ﻿
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use dashmap::DashMap;
+use std::fmt;
+use std::str::FromStr;
 use std::sync::Arc;
+/
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Role {
+    System,
+    User,
+    Assistant,
+    Tool,
+    /
+    Other(String),
+}
+impl Role {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Role::System => "system",
+            Role::User => "user",
+            Role::Assistant => "assistant",
+            Role::Tool => "tool",
+            Role::Other(role) => role,
+        }
+    }
+}
+impl FromStr for Role {
+    type Err = std::convert::Infallible;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.trim().to_ascii_lowercase().as_str() {
+            "system" => Role::System,
+            "user" => Role::User,
+            "assistant" => Role::Assistant,
+            "tool" => Role::Tool,
+            _ => Role::Other(s.to_string()),
+        })
+    }
+}
+impl From<&str> for Role {
+    fn from(s: &str) -> Self {
+        match s.parse() {
+            Ok(role) => role,
+            Err(never) => match never {},
+        }
+    }
+}
+impl From<String> for Role {
+    fn from(s: String) -> Self {
+        Role::from(s.as_str())
+    }
+}
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+impl Serialize for Role {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+impl<'de> Deserialize<'de> for Role {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        Ok(Role::from(s))
+    }
+}
+/
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ContentPart {
+    Text { text: String },
+    ImageUrl { image_url: ImageUrl },
+}
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ImageUrl {
+    pub url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+/
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum MessageContent {
+    Text(String),
+    Parts(Vec<ContentPart>),
+}
+impl MessageContent {
+    /
+    pub fn text(&self) -> String {
+        match self {
+            MessageContent::Text(text) => text.clone(),
+            MessageContent::Parts(parts) => text_of_parts(parts),
+        }
+    }
+}
+impl From<String> for MessageContent {
+    fn from(text: String) -> Self {
+        MessageContent::Text(text)
+    }
+}
+impl From<&str> for MessageContent {
+    fn from(text: &str) -> Self {
+        MessageContent::Text(text.to_string())
+    }
+}
+fn text_of_parts(parts: &[ContentPart]) -> String {
+    parts.iter()
+        .filter_map(|part| match part {
+            ContentPart::Text { text } => Some(text.as_str()),
+            ContentPart::ImageUrl { .. } => None,
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+/
+/
+/
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "WireMessage", into = "WireMessage")]
 pub struct Message {
-    pub role: String,
+    pub role: Role,
     pub content: String,
+    /
+    pub parts: Option<Vec<ContentPart>>,
+}
+impl Message {
+    /
+    pub fn wire_content(&self) -> MessageContent {
+        match &self.parts {
+            Some(parts) => MessageContent::Parts(parts.clone()),
+            None => MessageContent::Text(self.content.clone()),
+        }
+    }
+}
+#[derive(Serialize, Deserialize)]
+struct WireMessage {
+    role: Role,
+    content: MessageContent,
+}
+impl From<WireMessage> for Message {
+    fn from(wire: WireMessage) -> Self {
+        match wire.content {
+            MessageContent::Text(content) => Message { role: wire.role, content, parts: None },
+            MessageContent::Parts(parts) => Message {
+                role: wire.role,
+                content: text_of_parts(&parts),
+                parts: Some(parts),
+            },
+        }
+    }
+}
+impl From<Message> for WireMessage {
+    fn from(message: Message) -> Self {
+        WireMessage {
+            content: message.wire_content(),
+            role: message.role,
+        }
+    }
 }
 pub trait MemoryStore: Send + Sync {
     fn get_history(&self, session_id: &str) -> Vec<Message>;
@@ -43,4 +195,36 @@ impl MemoryStore for InMemoryMemoryStore {
         self.store.remove(session_id);
     }
 }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_role_parsing_is_case_insensitive_and_keeps_unknown() {
+        assert_eq!(Role::from("User"), Role::User);
+        assert_eq!(Role::from(" ASSISTANT "), Role::Assistant);
+        assert_eq!(Role::from("tool"), Role::Tool);
+        assert_eq!(Role::from("function"), Role::Other("function".to_string()));
 
+        let msg: Message = serde_json::from_str(r#"{"role":"System","content":"hi"}"#).unwrap();
+        assert_eq!(msg.role, Role::System);
+        assert_eq!(serde_json::to_value(&msg).unwrap()["role"], "system");
+    }
+    #[test]
+    fn test_structured_content_keeps_parts_and_exposes_text_only() {
+        let msg: Message = serde_json::from_str(r#"{"role":"user","content":[
+            {"type":"text","text":"What is in"},
+            {"type":"image_url","image_url":{"url":"data:image/png;base64,AAAA"}},
+            {"type":"text","text":"this picture?"}
+        ]}"#).unwrap();
+        assert_eq!(msg.content, "What is in\nthis picture?");
+        assert_eq!(msg.parts.as_ref().map(Vec::len), Some(3));
+
+        let wire = serde_json::to_value(&msg).unwrap();
+        assert_eq!(wire["content"][1]["type"], "image_url");
+        assert_eq!(wire["content"][1]["image_url"]["url"], "data:image/png;base64,AAAA");
+
+        let plain: Message = serde_json::from_str(r#"{"role":"user","content":"hi"}"#).unwrap();
+        assert!(plain.parts.is_none());
+        assert_eq!(serde_json::to_value(&plain).unwrap()["content"], "hi");
+    }
+}
@@ -1,5 +1,5 @@
 ﻿use crate::memory_db::schema::*;
-use rusqlite::{params, Result, Row, Connection};
+use rusqlite::{params, Result, Row, Connection, TransactionBehavior, OptionalExtension};
 use chrono::{DateTime, Utc, NaiveDateTime};
 use uuid::Uuid;
 use tracing::{info, debug, warn};
@@ -92,42 +92,75 @@ impl ConversationStore {
     ) -> anyhow::Result<Vec<StoredMessage>> {
         let mut conn = self.get_conn()?;
 
-        self.update_session_access_with_conn(&conn, session_id)?;
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        self.update_session_access_with_conn(&tx, session_id)?;
+        let stored_messages = Self::insert_messages(&tx, session_id, messages)?;
+        tx.commit()?;
 
+        debug!("Stored {} messages in batch for session {}", messages.len(), session_id);
+        Ok(stored_messages)
+    }
+    fn insert_messages(
+        tx: &rusqlite::Transaction<'_>,
+        session_id: &str,
+        messages: &[(String, String, i32, i32, f32)],
+    ) -> anyhow::Result<Vec<StoredMessage>> {
         let now = Utc::now();
         let now_str = now.to_rfc3339();
-        let mut stored_messages = Vec::new();
-
-        let tx = conn.transaction()?;
-        {
-            for (role, content, message_index, tokens, importance_score) in messages.iter() {
-                tx.execute(
-                    "INSERT INTO messages
-                     (session_id, message_index, role, content, tokens, timestamp, importance_score, embedding_generated)
-                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
-                    params![session_id, message_index, role, content, tokens, &now_str, importance_score, false],
-                )?;
-
-                let id = tx.last_insert_rowid();
-
-                stored_messages.push(StoredMessage {
-                    id,
-                    session_id: session_id.to_string(),
-                    message_index: *message_index,
-                    role: role.clone(),
-                    content: content.clone(),
-                    tokens: *tokens,
-                    timestamp: now,
-                    importance_score: *importance_score,
-                    embedding_generated: false,
-                });
-
+        let mut stored_messages = Vec::with_capacity(messages.len());
+        for (role, content, message_index, tokens, importance_score) in messages.iter() {
+            tx.execute(
+                "INSERT INTO messages
+                 (session_id, message_index, role, content, tokens, timestamp, importance_score, embedding_generated)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                params![session_id, message_index, role, content, tokens, &now_str, importance_score, false],
+            )?;
 
-            }
+            stored_messages.push(StoredMessage {
+                id: tx.last_insert_rowid(),
+                session_id: session_id.to_string(),
+                message_index: *message_index,
+                role: role.clone(),
+                content: content.clone(),
+                tokens: *tokens,
+                timestamp: now,
+                importance_score: *importance_score,
+                embedding_generated: false,
+            });
         }
+        Ok(stored_messages)
+    }
+    /
+    /
+    /
+    pub fn append_messages(
+        &self,
+        session_id: &str,
+        messages: &[(String, String, i32, f32)],
+    ) -> anyhow::Result<Vec<StoredMessage>> {
+        let mut conn = self.get_conn()?;
+        let now_str = Utc::now().to_rfc3339();
+        // Immediate so two appenders cannot read the same next index before either inserts.
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        tx.execute(
+            "INSERT OR IGNORE INTO sessions (id, created_at, last_accessed, metadata) VALUES (?1, ?2, ?2, ?3)",
+            params![session_id, &now_str, serde_json::to_string(&SessionMetadata::default())?],
+        )?;
+        let start_index: i32 = tx.query_row(
+            "SELECT COALESCE(MAX(message_index) + 1, 0) FROM messages WHERE session_id = ?1",
+            [session_id],
+            |row| row.get(0),
+        )?;
+        self.update_session_access_with_conn(&tx, session_id)?;
+        let batch: Vec<(String, String, i32, i32, f32)> = messages
+            .iter()
+            .enumerate()
+            .map(|(offset, (role, content, tokens, importance))| {
+                (role.clone(), content.clone(), start_index + offset as i32, *tokens, *importance)
+            })
+            .collect();
+        let stored_messages = Self::insert_messages(&tx, session_id, &batch)?;
         tx.commit()?;
-
-        debug!("Stored {} messages in batch for session {}", messages.len(), session_id);
         Ok(stored_messages)
     }
     /
@@ -139,7 +172,7 @@ impl ConversationStore {
 
         let mut conn = self.get_conn()?;
         let now = Utc::now().to_rfc3339();
-        let tx = conn.transaction()?;
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
 
         for (session_id, message_id, detail_type, content, context, importance_score) in details {
             tx.execute(
@@ -154,6 +187,19 @@ impl ConversationStore {
         debug!("Stored {} details in batch", details.len());
         Ok(())
     }
+    /
+    pub fn get_details(&self, session_id: &str, detail_type: Option<&str>) -> anyhow::Result<Vec<Detail>> {
+        let conn = self.get_conn()?;
+        let mut stmt = conn.prepare_cached(
+            "SELECT id, session_id, message_id, detail_type, content, context, importance_score, accessed_count, last_accessed
+             FROM details WHERE session_id = ?1 AND (?2 IS NULL OR detail_type = ?2)
+             ORDER BY importance_score DESC, id DESC"
+        )?;
+        let mut rows = stmt.query(params![session_id, detail_type])?;
+        let mut details = Vec::new();
+        while let Some(row) = rows.next()? { details.push(self.row_to_detail(row)?); }
+        Ok(details)
+    }
 
     pub fn create_session(&self, metadata: Option<SessionMetadata>) -> anyhow::Result<Session> {
         let session_id = Uuid::new_v4().to_string();
@@ -185,67 +231,103 @@ impl ConversationStore {
         Ok(Session { id: session_id.to_string(), created_at: now, last_accessed: now, metadata })
     }
     /
-    pub fn update_session_title(&self, session_id: &str, title: &str) -> anyhow::Result<()> {
-        let conn = self.get_conn()?;
-
-
-        let mut stmt = conn.prepare("SELECT metadata FROM sessions WHERE id = ?1")?;
-        let mut rows = stmt.query([session_id])?;
-
-        if let Some(row) = rows.next()? {
-            let metadata_json: String = row.get(0)?;
-            let mut metadata: SessionMetadata = serde_json::from_str(&metadata_json)
-                .unwrap_or_default();
-
-
-            metadata.title = Some(title.to_string());
-            let updated_metadata_json = serde_json::to_string(&metadata)?;
-
-
-            let now = Utc::now();
+    /
+    pub fn get_or_create_session(
+        &self,
+        session_id: &str,
+        metadata: Option<SessionMetadata>,
+    ) -> anyhow::Result<(Session, bool)> {
+        let now = Utc::now();
+        let metadata_json = serde_json::to_string(&metadata.unwrap_or_default())?;
+        let inserted = {
+            let conn = self.get_conn()?;
             conn.execute(
-                "UPDATE sessions SET metadata = ?1, last_accessed = ?2 WHERE id = ?3",
-                params![updated_metadata_json, now.to_rfc3339(), session_id],
-            )?;
-
-            info!("Updated session {} title to: {}", session_id, title);
-            Ok(())
-        } else {
-            Err(anyhow::anyhow!("Session {} not found", session_id))
+                "INSERT OR IGNORE INTO sessions (id, created_at, last_accessed, metadata) VALUES (?1, ?2, ?3, ?4)",
+                params![session_id, now.to_rfc3339(), now.to_rfc3339(), metadata_json],
+            )?
+        };
+        if inserted > 0 {
+            info!("Created session with ID: {}", session_id);
         }
+        let session = self.get_session(session_id)?
+            .ok_or_else(|| anyhow::anyhow!("Session {} vanished after creation", session_id))?;
+        Ok((session, inserted > 0))
+    }
+    /
+    pub fn update_session_title(&self, session_id: &str, title: &str) -> anyhow::Result<()> {
+        self.modify_session_metadata(session_id, true, |metadata| {
+            metadata.title = Some(title.to_string());
+        })?;
+        info!("Updated session {} title to: {}", session_id, title);
+        Ok(())
     }
     pub fn update_session_pinned(&self, session_id: &str, pinned: bool) -> anyhow::Result<()> {
-        let conn = self.get_conn()?;
-
-
-        let mut stmt = conn.prepare("SELECT metadata FROM sessions WHERE id = ?1")?;
-        let mut rows = stmt.query([session_id])?;
-
-        if let Some(row) = rows.next()? {
-            let metadata_json: String = row.get(0)?;
-            let mut metadata: SessionMetadata = serde_json::from_str(&metadata_json)
-                .unwrap_or_default();
-
-
+        self.modify_session_metadata(session_id, false, |metadata| {
             metadata.pinned = pinned;
-            let updated_metadata_json = serde_json::to_string(&metadata)?;
-
-
-
-            conn.execute(
-                "UPDATE sessions SET metadata = ?1 WHERE id = ?2",
-                params![updated_metadata_json, session_id],
-            )?;
-
-            info!("Updated session {} pinned status to: {}", session_id, pinned);
-            Ok(())
-        } else {
-            Err(anyhow::anyhow!("Session {} not found", session_id))
+        })?;
+        info!("Updated session {} pinned status to: {}", session_id, pinned);
+        Ok(())
+    }
+    /
+    /
+    pub fn set_session_metadata(&self, session_id: &str, key: &str, value: &str) -> anyhow::Result<SessionMetadata> {
+        let metadata = self.modify_session_metadata(session_id, false, |metadata| {
+            metadata.user_defined.insert(key.to_string(), value.to_string());
+        })?;
+        info!("Set metadata '{}' on session {}", key, session_id);
+        Ok(metadata)
+    }
+    /
+    pub fn update_session_folder(&self, session_id: &str, folder: Option<&str>) -> anyhow::Result<()> {
+        self.modify_session_metadata(session_id, false, |metadata| {
+            metadata.folder = folder.map(str::to_string);
+        })?;
+        info!("Moved session {} to folder {:?}", session_id, folder);
+        Ok(())
+    }
+    /
+    pub fn list_folders(&self, user_id: Option<&str>) -> anyhow::Result<Vec<(String, usize)>> {
+        let conn = self.get_conn()?;
+        let mut stmt = conn.prepare_cached(
+            "SELECT json_extract(metadata, '$.folder') AS folder, COUNT(*) FROM sessions
+             WHERE folder IS NOT NULL AND (?1 IS NULL OR json_extract(metadata, '$.user_id') = ?1)
+             GROUP BY folder ORDER BY folder"
+        )?;
+        let folders = stmt
+            .query_map([user_id], |row| Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)? as usize)))?
+            .collect::<Result<Vec<_>>>()?;
+        Ok(folders)
+    }
+    fn modify_session_metadata(
+        &self,
+        session_id: &str,
+        touch: bool,
+        update: impl FnOnce(&mut SessionMetadata),
+    ) -> anyhow::Result<SessionMetadata> {
+        let mut conn = self.get_conn()?;
+        // Immediate so concurrent writers to other keys cannot lose this update.
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let metadata_json: Option<String> = tx
+            .query_row("SELECT metadata FROM sessions WHERE id = ?1", [session_id], |row| row.get(0))
+            .optional()?;
+        let Some(metadata_json) = metadata_json else {
+            return Err(anyhow::anyhow!("Session {} not found", session_id));
+        };
+        let mut metadata: SessionMetadata = serde_json::from_str(&metadata_json).unwrap_or_default();
+        update(&mut metadata);
+        tx.execute(
+            "UPDATE sessions SET metadata = ?1 WHERE id = ?2",
+            params![serde_json::to_string(&metadata)?, session_id],
+        )?;
+        if touch {
+            self.update_session_access_with_conn(&tx, session_id)?;
         }
+        tx.commit()?;
+        Ok(metadata)
     }
     pub fn get_session(&self, session_id: &str) -> anyhow::Result<Option<Session>> {
         let conn = self.get_conn()?;
-        let mut stmt = conn.prepare("SELECT id, created_at, last_accessed, metadata FROM sessions WHERE id = ?1")?;
+        let mut stmt = conn.prepare_cached("SELECT id, created_at, last_accessed, metadata FROM sessions WHERE id = ?1")?;
         let mut rows = stmt.query([session_id])?;
 
         if let Some(row) = rows.next()? {
@@ -255,9 +337,118 @@ impl ConversationStore {
         }
     }
     /
+    pub fn export_session(&self, session_id: &str) -> anyhow::Result<Option<SessionExport>> {
+        let session = match self.get_session(session_id)? {
+            Some(session) => session,
+            None => return Ok(None),
+        };
+        let messages = self.get_session_messages(session_id, None, None)?;
+        Ok(Some(SessionExport { session, messages }))
+    }
+    /
+    /
+    /
+    pub fn import_session(&self, export: &SessionExport, remap_on_conflict: bool) -> anyhow::Result<String> {
+        let mut conn = self.get_conn()?;
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+
+        let exists: bool = tx.query_row(
+            "SELECT EXISTS(SELECT 1 FROM sessions WHERE id = ?1)",
+            [&export.session.id],
+            |row| row.get(0),
+        )?;
+        let session_id = match (exists, remap_on_conflict) {
+            (false, _) => export.session.id.clone(),
+            (true, true) => Uuid::new_v4().to_string(),
+            (true, false) => return Err(anyhow::anyhow!("Session {} already exists", export.session.id)),
+        };
+
+        tx.execute(
+            "INSERT INTO sessions (id, created_at, last_accessed, metadata) VALUES (?1, ?2, ?3, ?4)",
+            params![
+                &session_id,
+                export.session.created_at.to_rfc3339(),
+                export.session.last_accessed.to_rfc3339(),
+                serde_json::to_string(&export.session.metadata)?,
+            ],
+        )?;
+
+        let mut messages: Vec<&StoredMessage> = export.messages.iter().collect();
+        messages.sort_by_key(|m| (m.message_index, m.timestamp));
+        for (index, message) in messages.iter().enumerate() {
+            tx.execute(
+                "INSERT INTO messages
+                 (session_id, message_index, role, content, tokens, timestamp, importance_score, embedding_generated)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                params![
+                    &session_id,
+                    index as i32,
+                    &message.role,
+                    &message.content,
+                    message.tokens,
+                    message.timestamp.to_rfc3339(),
+                    message.importance_score,
+                    false,
+                ],
+            )?;
+        }
+        tx.commit()?;
+
+        info!("Imported session {} as {} with {} messages", export.session.id, session_id, messages.len());
+        Ok(session_id)
+    }
+    /
+    /
+    /
+    pub fn fork_session(&self, source_id: &str, up_to_message_id: i64) -> anyhow::Result<Option<String>> {
+        let mut conn = self.get_conn()?;
+        // One immediate transaction: the fork either appears with all its messages or not at all.
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let metadata_json: Option<String> = tx
+            .query_row("SELECT metadata FROM sessions WHERE id = ?1", [source_id], |row| row.get(0))
+            .optional()?;
+        let Some(metadata_json) = metadata_json else {
+            return Ok(None);
+        };
+        let cutoff: Option<i32> = tx
+            .query_row(
+                "SELECT message_index FROM messages WHERE id = ?1 AND session_id = ?2",
+                params![up_to_message_id, source_id],
+                |row| row.get(0),
+            )
+            .optional()?;
+        let Some(cutoff_index) = cutoff else {
+            return Ok(None);
+        };
+
+        let mut metadata: SessionMetadata = serde_json::from_str(&metadata_json).unwrap_or_default();
+        metadata.pinned = false;
+        metadata.user_defined.insert("forked_from".to_string(), source_id.to_string());
+        let session_id = Uuid::new_v4().to_string();
+        let now = Utc::now().to_rfc3339();
+        tx.execute(
+            "INSERT INTO sessions (id, created_at, last_accessed, metadata) VALUES (?1, ?2, ?3, ?4)",
+            params![&session_id, &now, &now, serde_json::to_string(&metadata)?],
+        )?;
+        let count = tx.execute(
+            "INSERT INTO messages
+             (session_id, message_index, role, content, tokens, timestamp, importance_score, embedding_generated)
+             SELECT ?1, ROW_NUMBER() OVER (ORDER BY message_index, id) - 1,
+                    role, content, tokens, timestamp, importance_score, 0
+             FROM messages
+             WHERE session_id = ?2 AND (message_index < ?3 OR (message_index = ?3 AND id <= ?4))
+             ORDER BY message_index, id",
+            params![&session_id, source_id, cutoff_index, up_to_message_id],
+        )?;
+        tx.commit()?;
+
+        info!("Forked session {} into {} with {} messages", source_id, session_id, count);
+        Ok(Some(session_id))
+    }
+    /
     pub fn get_all_sessions(&self) -> anyhow::Result<Vec<Session>> {
         let conn = self.get_conn()?;
-        let mut stmt = conn.prepare(
+        let mut stmt = conn.prepare_cached(
             "SELECT id, created_at, last_accessed, metadata FROM sessions ORDER BY last_accessed DESC"
         )?;
         let mut rows = stmt.query([])?;
@@ -270,7 +461,7 @@ impl ConversationStore {
         Ok(sessions)
     }
 
-    fn parse_datetime_safe(datetime_str: &str) -> Option<DateTime<Utc>> {
+    pub(crate) fn parse_datetime_safe(datetime_str: &str) -> Option<DateTime<Utc>> {
         if let Ok(dt) = DateTime::parse_from_rfc3339(datetime_str) {
             return Some(dt.with_timezone(&Utc));
         }
@@ -315,9 +506,26 @@ impl ConversationStore {
         })
     }
 
+    fn row_to_detail(&self, row: &Row) -> anyhow::Result<Detail> {
+        let last_accessed = Self::parse_datetime_safe(&row.get::<_, String>(8)?)
+            .unwrap_or_else(|| { warn!("Failed parse detail timestamp"); Utc::now() });
+
+        Ok(Detail {
+            id: row.get(0)?,
+            session_id: row.get(1)?,
+            message_id: row.get(2)?,
+            detail_type: row.get(3)?,
+            content: row.get(4)?,
+            context: row.get(5)?,
+            importance_score: row.get(6)?,
+            accessed_count: row.get(7)?,
+            last_accessed,
+        })
+    }
+
     pub fn get_session_messages(&self, session_id: &str, limit: Option<i32>, offset: Option<i32>) -> anyhow::Result<Vec<StoredMessage>> {
         let conn = self.get_conn()?;
-        let mut stmt = conn.prepare(
+        let mut stmt = conn.prepare_cached(
             "SELECT id, session_id, message_index, role, content, tokens, timestamp, importance_score, embedding_generated
              FROM messages WHERE session_id = ?1 ORDER BY message_index LIMIT ?2 OFFSET ?3"
         )?;
@@ -335,17 +543,196 @@ impl ConversationStore {
         )?;
         Ok(count as usize)
     }
+    pub fn get_message(&self, message_id: i64) -> anyhow::Result<Option<StoredMessage>> {
+        let conn = self.get_conn()?;
+        let mut stmt = conn.prepare_cached(
+            "SELECT id, session_id, message_index, role, content, tokens, timestamp, importance_score, embedding_generated
+             FROM messages WHERE id = ?1"
+        )?;
+        let mut rows = stmt.query([message_id])?;
+        match rows.next()? {
+            Some(row) => Ok(Some(self.row_to_stored_message(row)?)),
+            None => Ok(None),
+        }
+    }
+    /
+    /
+    pub fn update_message_content(&self, message_id: i64, content: &str, tokens: i32) -> anyhow::Result<bool> {
+        let mut conn = self.get_conn()?;
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let previous: Option<String> = tx.query_row(
+            "SELECT content FROM messages WHERE id = ?1",
+            [message_id],
+            |row| row.get(0),
+        ).optional()?;
+        let Some(previous) = previous else {
+            return Ok(false);
+        };
+        let next_revision: i32 = tx.query_row(
+            "SELECT COALESCE(MAX(revision), 0) + 1 FROM message_revisions WHERE message_id = ?1",
+            [message_id],
+            |row| row.get(0),
+        )?;
+        tx.execute(
+            "INSERT INTO message_revisions (message_id, revision, content, revised_at) VALUES (?1, ?2, ?3, ?4)",
+            params![message_id, next_revision, previous, Utc::now().to_rfc3339()],
+        )?;
+        tx.execute(
+            "UPDATE messages SET content = ?1, tokens = ?2, embedding_generated = FALSE WHERE id = ?3",
+            params![content, tokens, message_id],
+        )?;
+        // The stored vectors describe the old text; backfill re-embeds the message.
+        tx.execute("DELETE FROM embeddings WHERE message_id = ?1", [message_id])?;
+        tx.commit()?;
+        debug!("Stored revision {} for message {}", next_revision, message_id);
+        Ok(true)
+    }
+    /
+    pub fn get_message_history(&self, message_id: i64) -> anyhow::Result<Vec<MessageRevision>> {
+        let conn = self.get_conn()?;
+        let mut stmt = conn.prepare_cached(
+            "SELECT message_id, revision, content, revised_at
+             FROM message_revisions WHERE message_id = ?1 ORDER BY revision"
+        )?;
+        let mut rows = stmt.query([message_id])?;
+        let mut revisions = Vec::new();
+        while let Some(row) = rows.next()? {
+            let revised_at = Self::parse_datetime_safe(&row.get::<_, String>(3)?)
+                .unwrap_or_else(|| { warn!("Failed parse revision timestamp"); Utc::now() });
+            revisions.push(MessageRevision {
+                message_id: row.get(0)?,
+                revision: row.get(1)?,
+                content: row.get(2)?,
+                revised_at,
+            });
+        }
+        Ok(revisions)
+    }
+    /
+    /
+    pub fn get_messages_without_embeddings(&self, after_id: i64, limit: usize) -> anyhow::Result<Vec<StoredMessage>> {
+        let conn = self.get_conn()?;
+        let mut stmt = conn.prepare_cached(
+            "SELECT id, session_id, message_index, role, content, tokens, timestamp, importance_score, embedding_generated
+             FROM messages WHERE embedding_generated = FALSE AND id > ?1 ORDER BY id LIMIT ?2"
+        )?;
+        let mut rows = stmt.query(params![after_id, limit as i64])?;
+        let mut messages = Vec::new();
+        while let Some(row) = rows.next()? { messages.push(self.row_to_stored_message(row)?); }
+        Ok(messages)
+    }
+    /
+    pub fn count_messages_without_embeddings(&self) -> anyhow::Result<usize> {
+        let conn = self.get_conn()?;
+        let count: i64 = conn.query_row(
+            "SELECT COUNT(*) FROM messages WHERE embedding_generated = FALSE",
+            [],
+            |row| row.get(0),
+        )?;
+        Ok(count as usize)
+    }
     pub fn mark_embedding_generated(&self, message_id: i64) -> anyhow::Result<()> {
         let conn = self.get_conn()?;
         conn.execute("UPDATE messages SET embedding_generated = TRUE WHERE id = ?1", [message_id])?;
         Ok(())
     }
+    /
+    pub fn set_generation_outcome(
+        &self,
+        message_id: i64,
+        finish_reason: Option<&str>,
+        completion_tokens: Option<u32>,
+    ) -> anyhow::Result<()> {
+        let conn = self.get_conn()?;
+        conn.execute(
+            "UPDATE messages SET finish_reason = ?1, completion_tokens = ?2 WHERE id = ?3",
+            params![finish_reason, completion_tokens, message_id],
+        )?;
+        Ok(())
+    }
+    /
+    pub fn get_generation_outcomes(&self, session_id: &str) -> anyhow::Result<std::collections::HashMap<i64, GenerationOutcome>> {
+        let conn = self.get_conn()?;
+        let mut stmt = conn.prepare_cached(
+            "SELECT id, finish_reason, completion_tokens FROM messages
+             WHERE session_id = ?1 AND (finish_reason IS NOT NULL OR completion_tokens IS NOT NULL)"
+        )?;
+        let outcomes = stmt.query_map([session_id], |row| {
+            Ok((row.get::<_, i64>(0)?, GenerationOutcome {
+                finish_reason: row.get(1)?,
+                completion_tokens: row.get(2)?,
+            }))
+        })?.collect::<Result<_>>()?;
+        Ok(outcomes)
+    }
+    /
+    pub fn store_alternates(&self, message_id: i64, alternates: &[MessageAlternate]) -> anyhow::Result<()> {
+        let mut conn = self.get_conn()?;
+        let tx = conn.transaction()?;
+        {
+            let mut stmt = tx.prepare_cached(
+                "INSERT OR REPLACE INTO message_alternates
+                 (message_id, choice_index, content, finish_reason, completion_tokens)
+                 VALUES (?1, ?2, ?3, ?4, ?5)"
+            )?;
+            for alternate in alternates {
+                stmt.execute(params![
+                    message_id,
+                    alternate.choice_index,
+                    alternate.content,
+                    alternate.finish_reason,
+                    alternate.completion_tokens,
+                ])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+    /
+    pub fn get_session_alternates(&self, session_id: &str) -> anyhow::Result<std::collections::HashMap<i64, Vec<MessageAlternate>>> {
+        let conn = self.get_conn()?;
+        let mut stmt = conn.prepare_cached(
+            "SELECT a.message_id, a.choice_index, a.content, a.finish_reason, a.completion_tokens
+             FROM message_alternates a JOIN messages m ON m.id = a.message_id
+             WHERE m.session_id = ?1 ORDER BY a.message_id, a.choice_index"
+        )?;
+        let mut alternates: std::collections::HashMap<i64, Vec<MessageAlternate>> = std::collections::HashMap::new();
+        let mut rows = stmt.query([session_id])?;
+        while let Some(row) = rows.next()? {
+            alternates.entry(row.get(0)?).or_default().push(MessageAlternate {
+                choice_index: row.get(1)?,
+                content: row.get(2)?,
+                finish_reason: row.get(3)?,
+                completion_tokens: row.get(4)?,
+            });
+        }
+        Ok(alternates)
+    }
     pub fn delete_session(&self, session_id: &str) -> anyhow::Result<usize> {
         let conn = self.get_conn()?;
         let deleted = conn.execute("DELETE FROM sessions WHERE id = ?1", [session_id])?;
         info!("Deleted session {}", session_id);
         Ok(deleted)
     }
+    /
+    /
+    /
+    pub fn clear_session_messages(&self, session_id: &str) -> anyhow::Result<Vec<i64>> {
+        let mut conn = self.get_conn()?;
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let message_ids = {
+            let mut stmt = tx.prepare_cached("SELECT id FROM messages WHERE session_id = ?1 ORDER BY id")?;
+            let ids = stmt.query_map([session_id], |row| row.get(0))?
+                .collect::<Result<Vec<i64>, _>>()?;
+            ids
+        };
+        // Summaries describe the removed messages, so they would resurface cleared content.
+        tx.execute("DELETE FROM summaries WHERE session_id = ?1", [session_id])?;
+        tx.execute("DELETE FROM messages WHERE session_id = ?1", [session_id])?;
+        tx.commit()?;
+        info!("Cleared {} messages from session {}", message_ids.len(), session_id);
+        Ok(message_ids)
+    }
 
     /
     pub async fn search_messages_by_keywords(
@@ -402,6 +789,7 @@ impl ConversationStore {
         topic_keywords: &[String],
         limit: usize,
         session_id_filter: Option<&str>,
+        user_id_filter: Option<&str>,
     ) -> anyhow::Result<Vec<StoredMessage>> {
         let conn = self.get_conn()?;
 
@@ -425,6 +813,10 @@ impl ConversationStore {
             query.push_str(" AND m.session_id != ?");
             params.push(Box::new(session_id.to_string()));
         }
+        if let Some(user_id) = user_id_filter {
+            query.push_str(" AND json_extract(s.metadata, '$.user_id') = ?");
+            params.push(Box::new(user_id.to_string()));
+        }
 
 
         for pattern in &patterns {
@@ -476,4 +868,226 @@ impl ConversationStore {
         Ok(messages)
     }
 }
+#[cfg(test)]
+mod tests {
+    use crate::memory_db::{GenerationOutcome, MemoryDatabase, SessionMetadata};
+    #[test]
+    fn test_set_session_metadata_merges_keys() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = MemoryDatabase::new(&dir.path().join("metadata.db")).unwrap();
+        let store = &db.conversations;
+        let session = store.create_session(None).unwrap();
+        store.set_session_metadata(&session.id, "folder", "work").unwrap();
+        store.set_session_metadata(&session.id, "color", "blue").unwrap();
+        store.set_session_metadata(&session.id, "folder", "personal").unwrap();
+
+        let metadata = store.get_session(&session.id).unwrap().unwrap().metadata;
+        assert_eq!(metadata.user_defined.get("folder").map(String::as_str), Some("personal"));
+        assert_eq!(metadata.user_defined.get("color").map(String::as_str), Some("blue"));
+        assert!(store.set_session_metadata("missing", "folder", "work").is_err());
+    }
+    #[test]
+    fn test_concurrent_title_and_pin_updates_keep_both() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = std::sync::Arc::new(MemoryDatabase::new(&dir.path().join("concurrent.db")).unwrap());
+        let session = db.conversations.create_session(None).unwrap();
+        let handles: Vec<_> = (0..8).map(|i| {
+            let (db, id) = (db.clone(), session.id.clone());
+            std::thread::spawn(move || match i % 4 {
+                0 => db.conversations.update_session_title(&id, "Renamed").unwrap(),
+                1 => db.conversations.update_session_pinned(&id, true).unwrap(),
+                2 => db.conversations.update_session_folder(&id, Some("work")).unwrap(),
+                _ => { db.conversations.set_session_metadata(&id, "color", "blue").unwrap(); }
+            })
+        }).collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
 
+        let metadata = db.conversations.get_session(&session.id).unwrap().unwrap().metadata;
+        assert_eq!(metadata.title.as_deref(), Some("Renamed"));
+        assert!(metadata.pinned);
+        assert_eq!(metadata.folder.as_deref(), Some("work"));
+        assert_eq!(metadata.user_defined.get("color").map(String::as_str), Some("blue"));
+        assert!(db.conversations.update_session_pinned("missing", true).is_err());
+    }
+    #[test]
+    fn test_list_folders_counts_sessions() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = MemoryDatabase::new(&dir.path().join("folders.db")).unwrap();
+        let store = &db.conversations;
+        let first = store.create_session(None).unwrap();
+        let second = store.create_session(None).unwrap();
+        let third = store.create_session(None).unwrap();
+        store.create_session(None).unwrap();
+        store.update_session_folder(&first.id, Some("work")).unwrap();
+        store.update_session_folder(&second.id, Some("work")).unwrap();
+        store.update_session_folder(&third.id, Some("archive")).unwrap();
+        assert_eq!(store.list_folders(None).unwrap(), vec![("archive".to_string(), 1), ("work".to_string(), 2)]);
+
+        store.update_session_folder(&third.id, None).unwrap();
+        assert_eq!(store.list_folders(None).unwrap(), vec![("work".to_string(), 2)]);
+
+        let owned = store.create_session(Some(SessionMetadata { user_id: Some("team-a".to_string()), ..Default::default() })).unwrap();
+        store.update_session_folder(&owned.id, Some("private")).unwrap();
+        assert_eq!(store.list_folders(Some("team-a")).unwrap(), vec![("private".to_string(), 1)]);
+        assert!(store.list_folders(Some("team-b")).unwrap().is_empty());
+    }
+    #[test]
+    fn test_export_import_roundtrip_remaps_on_conflict() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = MemoryDatabase::new(&dir.path().join("import.db")).unwrap();
+        let store = &db.conversations;
+        let session = store.create_session(None).unwrap();
+        store.store_messages_batch(&session.id, &[
+            ("user".to_string(), "first".to_string(), 0, 1, 0.5),
+            ("assistant".to_string(), "second".to_string(), 1, 1, 0.5),
+        ]).unwrap();
+
+        let export = store.export_session(&session.id).unwrap().unwrap();
+        assert!(store.import_session(&export, false).is_err());
+
+        let imported_id = store.import_session(&export, true).unwrap();
+        assert_ne!(imported_id, session.id);
+        let imported = store.get_session_messages(&imported_id, None, None).unwrap();
+        let contents: Vec<_> = imported.iter().map(|m| (m.message_index, m.content.as_str())).collect();
+        assert_eq!(contents, vec![(0, "first"), (1, "second")]);
+    }
+    #[test]
+    fn test_get_or_create_session_is_idempotent() {
+        let db = MemoryDatabase::new_in_memory().unwrap();
+        let metadata = SessionMetadata { title: Some("First".to_string()), ..Default::default() };
+        let (created, was_created) = db.conversations.get_or_create_session("retry-me", Some(metadata)).unwrap();
+        assert!(was_created);
+        let retry = SessionMetadata { title: Some("Second".to_string()), ..Default::default() };
+        let (existing, was_created) = db.conversations.get_or_create_session("retry-me", Some(retry)).unwrap();
+        assert!(!was_created);
+        assert_eq!(existing.id, created.id);
+        assert_eq!(existing.metadata.title.as_deref(), Some("First"));
+        assert_eq!(db.conversations.get_all_sessions().unwrap().len(), 1);
+    }
+    #[test]
+    fn test_fork_copies_messages_up_to_cutoff() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = MemoryDatabase::new(&dir.path().join("fork.db")).unwrap();
+        let store = &db.conversations;
+        let session = store.create_session(None).unwrap();
+        let stored = store.store_messages_batch(&session.id, &[
+            ("user".to_string(), "one".to_string(), 4, 1, 0.5),
+            ("assistant".to_string(), "two".to_string(), 5, 1, 0.5),
+            ("user".to_string(), "three".to_string(), 6, 1, 0.5),
+        ]).unwrap();
+
+        let fork_id = store.fork_session(&session.id, stored[1].id).unwrap().unwrap();
+        assert_ne!(fork_id, session.id);
+        let forked: Vec<_> = store.get_session_messages(&fork_id, None, None).unwrap()
+            .into_iter()
+            .map(|m| (m.message_index, m.content))
+            .collect();
+        assert_eq!(forked, vec![(0, "one".to_string()), (1, "two".to_string())]);
+        let metadata = store.get_session(&fork_id).unwrap().unwrap().metadata;
+        assert_eq!(metadata.user_defined.get("forked_from"), Some(&session.id));
+        assert_eq!(store.get_session_message_count(&session.id).unwrap(), 3);
+
+        let other = store.create_session(None).unwrap();
+        assert!(store.fork_session(&other.id, stored[0].id).unwrap().is_none());
+        assert!(store.fork_session("missing", stored[0].id).unwrap().is_none());
+
+        // A failed copy must not leave an empty fork behind.
+        let sessions = store.get_all_sessions().unwrap().len();
+        store.get_conn_public().unwrap().execute_batch(&format!(
+            "CREATE TRIGGER reject_fork BEFORE INSERT ON messages WHEN NEW.session_id != '{}'
+             BEGIN SELECT RAISE(ABORT, 'fork rejected'); END;",
+            session.id,
+        )).unwrap();
+        assert!(store.fork_session(&session.id, stored[1].id).is_err());
+        assert_eq!(store.get_all_sessions().unwrap().len(), sessions);
+    }
+    #[test]
+    fn test_clear_session_keeps_session_and_removes_messages() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = MemoryDatabase::new(&dir.path().join("clear.db")).unwrap();
+        let store = &db.conversations;
+        let metadata = SessionMetadata { title: Some("Keep me".to_string()), ..Default::default() };
+        let session = store.create_session(Some(metadata)).unwrap();
+        let other = store.create_session(None).unwrap();
+        let stored = store.store_messages_batch(&session.id, &[
+            ("user".to_string(), "one".to_string(), 0, 1, 0.5),
+            ("assistant".to_string(), "two".to_string(), 1, 1, 0.5),
+        ]).unwrap();
+        store.store_messages_batch(&other.id, &[
+            ("user".to_string(), "untouched".to_string(), 0, 1, 0.5),
+        ]).unwrap();
+
+        let removed = store.clear_session_messages(&session.id).unwrap();
+        assert_eq!(removed, stored.iter().map(|m| m.id).collect::<Vec<_>>());
+        assert_eq!(store.get_session_message_count(&session.id).unwrap(), 0);
+        assert_eq!(store.get_session(&session.id).unwrap().unwrap().metadata.title.as_deref(), Some("Keep me"));
+        assert_eq!(store.get_session_message_count(&other.id).unwrap(), 1);
+        assert!(store.clear_session_messages(&session.id).unwrap().is_empty());
+    }
+    #[test]
+    fn test_generation_outcome_is_recorded_per_message() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = MemoryDatabase::new(&dir.path().join("outcome.db")).unwrap();
+        let store = &db.conversations;
+        let session = store.create_session(None).unwrap();
+        let stored = store.store_messages_batch(&session.id, &[
+            ("user".to_string(), "Write a long essay".to_string(), 0, 4, 0.5),
+            ("assistant".to_string(), "Once upon".to_string(), 1, 2, 0.5),
+        ]).unwrap();
+
+        store.set_generation_outcome(stored[1].id, Some("length"), Some(512)).unwrap();
+
+        let outcomes = store.get_generation_outcomes(&session.id).unwrap();
+        assert_eq!(outcomes.len(), 1);
+        assert_eq!(outcomes[&stored[1].id], GenerationOutcome {
+            finish_reason: Some("length".to_string()),
+            completion_tokens: Some(512),
+        });
+    }
+    #[test]
+    fn test_message_edits_keep_revision_history() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = MemoryDatabase::new(&dir.path().join("revisions.db")).unwrap();
+        let store = &db.conversations;
+        let session = store.create_session(None).unwrap();
+        let stored = store.store_messages_batch(&session.id, &[
+            ("user".to_string(), "first draft".to_string(), 0, 2, 0.5),
+        ]).unwrap();
+        let id = stored[0].id;
+
+        assert!(store.update_message_content(id, "second draft", 2).unwrap());
+        assert!(store.update_message_content(id, "final text", 2).unwrap());
+        assert!(!store.update_message_content(id + 100, "missing", 1).unwrap());
+
+        assert_eq!(store.get_message(id).unwrap().unwrap().content, "final text");
+        let history: Vec<_> = store.get_message_history(id).unwrap()
+            .into_iter()
+            .map(|r| (r.revision, r.content))
+            .collect();
+        assert_eq!(history, vec![(1, "first draft".to_string()), (2, "second draft".to_string())]);
+    }
+    #[test]
+    fn test_keyword_search_uses_session_timestamp_index() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("plan.db");
+        let _db = MemoryDatabase::new(&db_path).unwrap();
+        let conn = rusqlite::Connection::open(&db_path).unwrap();
+        let plan: Vec<String> = conn
+            .prepare(
+                "EXPLAIN QUERY PLAN
+                 SELECT id FROM messages
+                 WHERE session_id = ?1 AND LOWER(content) LIKE ?2
+                 ORDER BY timestamp DESC LIMIT ?3",
+            )
+            .unwrap()
+            .query_map(rusqlite::params!["s1", "%rust%", 10], |row| row.get::<_, String>(3))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        let plan = plan.join("\n");
+        assert!(plan.contains("idx_messages_session_timestamp"), "unexpected plan: {}", plan);
+        assert!(!plan.contains("TEMP B-TREE"), "ORDER BY should be served by the index: {}", plan);
+    }
+}
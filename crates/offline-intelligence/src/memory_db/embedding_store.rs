@@ -3,6 +3,7 @@
 use crate::memory_db::schema::*;
 use rusqlite::{params, Result, Row};
 use std::sync::{Arc, RwLock};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::collections::HashMap;
 use tracing::{info, warn};
 use r2d2::Pool;
@@ -12,6 +13,13 @@ use hora::core::metrics::Metric;
 use hora::index::hnsw_idx::HNSWIndex;
 use hora::index::hnsw_params::HNSWParams;
 /
+const QUANTIZED_I8_MAGIC: &[u8; 4] = b"QI8\0";
+/
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+struct QuantizedEmbedding {
+    scale: f32,
+    values: Vec<i8>,
+}
 #[derive(Debug, Clone, serde::Serialize)]
 pub struct EmbeddingStats {
     pub total_embeddings: usize,
@@ -22,84 +30,117 @@ pub struct EmbeddingStore {
     pool: Arc<Pool<SqliteConnectionManager>>,
 
     ann_index: RwLock<Option<HNSWIndex<f32, i64>>>,
+    /
+    /
+    index_model: RwLock<Option<String>>,
 
     embedding_cache: RwLock<HashMap<i64, Vec<f32>>>,
+
+    quantize: AtomicBool,
 }
 impl EmbeddingStore {
     pub fn new(pool: Arc<Pool<SqliteConnectionManager>>) -> Self {
         Self {
             pool,
             ann_index: RwLock::new(None),
+            index_model: RwLock::new(None),
             embedding_cache: RwLock::new(HashMap::new()),
+            quantize: AtomicBool::new(false),
         }
     }
+    pub fn set_quantization(&self, enabled: bool) {
+        self.quantize.store(enabled, Ordering::Relaxed);
+        info!("Embedding int8 quantization {}", if enabled { "enabled" } else { "disabled" });
+    }
+    pub fn is_quantized(&self) -> bool {
+        self.quantize.load(Ordering::Relaxed)
+    }
     fn get_conn(&self) -> anyhow::Result<r2d2::PooledConnection<SqliteConnectionManager>> {
         self.pool.get().map_err(|e| anyhow::anyhow!("Failed to get connection from pool: {}", e))
     }
     pub fn initialize_index(&self, model: &str) -> anyhow::Result<()> {
         let conn = self.get_conn()?;
 
-        let mut stmt = conn.prepare(
+        let mut stmt = conn.prepare_cached(
             "SELECT id, message_id, embedding FROM embeddings WHERE embedding_model = ?1"
         )?;
 
         let mut rows = stmt.query([model])?;
-
-
-        let params = HNSWParams {
-
-            n_neighbor: 16,
-
-            ef_build: 100,
-
-            ef_search: 50,
-            ..Default::default()
-        };
-
-
-        let mut index = HNSWIndex::<f32, i64>::new(
-            384,
-            &params,
-        );
+        // The dimension comes from the data, so it follows whatever embedding model is configured.
+        let mut index: Option<HNSWIndex<f32, i64>> = None;
 
         let mut cache = self.embedding_cache.write().unwrap();
 
         while let Some(row) = rows.next()? {
             let message_id: i64 = row.get(1)?;
             let embedding_bytes: Vec<u8> = row.get(2)?;
-            let embedding: Vec<f32> = bincode::deserialize(&embedding_bytes)
+            let embedding = decode_embedding(&embedding_bytes)
                 .map_err(|e| anyhow::anyhow!("Deserialization error: {}", e))?;
 
-
-            let _ = index.add(&embedding, message_id);
+            add_to_index(index.get_or_insert_with(|| new_index(embedding.len())), &embedding, message_id)?;
             cache.insert(message_id, embedding);
         }
 
+        if let Some(ref mut index) = index {
+            index.build(Metric::CosineSimilarity)
+                .map_err(|e| anyhow::anyhow!("Failed to build index: {}", e))?;
+        }
 
-        index.build(Metric::CosineSimilarity)
-            .map_err(|e| anyhow::anyhow!("Failed to build index: {}", e))?;
-
-        *self.ann_index.write().unwrap() = Some(index);
-        info!("ANN index initialized with {} embeddings", cache.len());
+        *self.ann_index.write().unwrap() = index;
+        *self.index_model.write().unwrap() = Some(model.to_string());
+        info!("ANN index initialized for {} with {} embeddings", model, cache.len());
         Ok(())
     }
+    fn is_indexed_model(&self, model: &str) -> bool {
+        self.index_model.read().unwrap().as_deref() == Some(model)
+    }
     pub fn store_embedding(&self, embedding: &Embedding) -> anyhow::Result<()> {
-        let embedding_bytes = bincode::serialize(&embedding.embedding)?;
+        let (embedding_bytes, stored_vector) = if self.is_quantized() {
+            let quantized = quantize_embedding(&embedding.embedding);
+            let mut bytes = QUANTIZED_I8_MAGIC.to_vec();
+            bytes.extend(bincode::serialize(&quantized)?);
+            (bytes, dequantize_embedding(&quantized))
+        } else {
+            (bincode::serialize(&embedding.embedding)?, embedding.embedding.clone())
+        };
         let conn = self.get_conn()?;
+        // Upsert keeps the row id stable, so a retried embedding task replaces
+        // the vector instead of adding a second one.
         conn.execute(
-            "INSERT OR REPLACE INTO embeddings (message_id, embedding, embedding_model, generated_at) VALUES (?1, ?2, ?3, ?4)",
+            "INSERT INTO embeddings (message_id, embedding, embedding_model, generated_at) VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT(message_id, embedding_model)
+             DO UPDATE SET embedding = excluded.embedding, generated_at = excluded.generated_at",
             params![embedding.message_id, embedding_bytes, &embedding.embedding_model, embedding.generated_at.to_rfc3339()],
         )?;
         let mut cache = self.embedding_cache.write().unwrap();
-        cache.insert(embedding.message_id, embedding.embedding.clone());
-        if let Some(ref mut index) = *self.ann_index.write().unwrap() {
-
-            let _ = index.add(&embedding.embedding, embedding.message_id);
-
-
-            index.build(Metric::CosineSimilarity)
-                .map_err(|e| anyhow::anyhow!("Failed to rebuild index: {}", e))?;
+        let replaced = cache.insert(embedding.message_id, stored_vector.clone()).is_some();
+        if !self.is_indexed_model(&embedding.embedding_model) {
+            return Ok(());
         }
+        let mut index_guard = self.ann_index.write().unwrap();
+        let index = match *index_guard {
+            Some(ref mut index) if replaced => {
+                // HNSW cannot remove a node; rebuild so the old vector does not
+                // linger as a duplicate hit for this message.
+                let mut rebuilt = new_index(stored_vector.len());
+                for (message_id, vector) in cache.iter() {
+                    add_to_index(&mut rebuilt, vector, *message_id)?;
+                }
+                *index = rebuilt;
+                index
+            }
+            Some(ref mut index) => {
+                add_to_index(index, &stored_vector, embedding.message_id)?;
+                index
+            }
+            ref mut empty @ None => {
+                let mut index = new_index(stored_vector.len());
+                add_to_index(&mut index, &stored_vector, embedding.message_id)?;
+                empty.insert(index)
+            }
+        };
+        index.build(Metric::CosineSimilarity)
+            .map_err(|e| anyhow::anyhow!("Failed to rebuild index: {}", e))?;
         Ok(())
     }
     pub fn find_similar_embeddings(
@@ -112,7 +153,7 @@ impl EmbeddingStore {
         if model.is_empty() || model.len() > 100 {
             return Err(anyhow::anyhow!("Invalid model name"));
         }
-        {
+        if self.is_indexed_model(model) {
             let index_guard = self.ann_index.read().unwrap();
             if let Some(index) = &*index_guard {
                 let results = index.search(query_embedding, limit as usize);
@@ -142,7 +183,7 @@ impl EmbeddingStore {
         similarity_threshold: f32,
     ) -> anyhow::Result<Vec<(i64, f32)>> {
         let conn = self.get_conn()?;
-        let mut stmt = conn.prepare(
+        let mut stmt = conn.prepare_cached(
             "SELECT message_id, embedding FROM embeddings WHERE embedding_model = ?1"
         )?;
         let mut rows = stmt.query([model])?;
@@ -151,7 +192,7 @@ impl EmbeddingStore {
         while let Some(row) = rows.next()? {
             let message_id: i64 = row.get(0)?;
             let embedding_bytes: Vec<u8> = row.get(1)?;
-            let embedding: Vec<f32> = bincode::deserialize(&embedding_bytes)
+            let embedding = decode_embedding(&embedding_bytes)
                 .map_err(|e| anyhow::anyhow!("Bincode error: {}", e))?;
 
             let sim = cosine_similarity(query_embedding, &embedding);
@@ -166,7 +207,7 @@ impl EmbeddingStore {
     }
     pub fn get_embedding_by_message_id(&self, message_id: i64, model: &str) -> anyhow::Result<Option<Embedding>> {
         let conn = self.get_conn()?;
-        let mut stmt = conn.prepare(
+        let mut stmt = conn.prepare_cached(
             "SELECT id, message_id, embedding, embedding_model, generated_at
              FROM embeddings WHERE message_id = ?1 AND embedding_model = ?2"
         )?;
@@ -180,7 +221,7 @@ impl EmbeddingStore {
     }
     fn row_to_embedding(&self, row: &Row) -> Result<Embedding> {
         let embedding_bytes: Vec<u8> = row.get(2)?;
-        let embedding: Vec<f32> = bincode::deserialize(&embedding_bytes)
+        let embedding = decode_embedding(&embedding_bytes)
             .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
 
         let generated_at_str: String = row.get(4)?;
@@ -196,6 +237,14 @@ impl EmbeddingStore {
             generated_at,
         })
     }
+    /
+    /
+    pub fn forget_messages(&self, message_ids: &[i64]) {
+        let mut cache = self.embedding_cache.write().unwrap();
+        for id in message_ids {
+            cache.remove(id);
+        }
+    }
     pub fn get_stats(&self) -> anyhow::Result<EmbeddingStats> {
         let conn = self.get_conn()?;
         let count: i64 = conn.query_row(
@@ -204,10 +253,10 @@ impl EmbeddingStore {
             |row| row.get(0)
         )?;
 
-        let mut stmt = conn.prepare("SELECT embedding FROM embeddings LIMIT 1")?;
+        let mut stmt = conn.prepare_cached("SELECT embedding FROM embeddings LIMIT 1")?;
         let dimension = if let Some(row) = stmt.query([])?.next()? {
             let embedding_bytes: Vec<u8> = row.get(0)?;
-            let embedding: Vec<f32> = bincode::deserialize(&embedding_bytes)
+            let embedding = decode_embedding(&embedding_bytes)
                 .map_err(|e| anyhow::anyhow!("Deserialization error: {}", e))?;
             embedding.len()
         } else {
@@ -227,10 +276,134 @@ impl EmbeddingStore {
         })
     }
 }
-fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+fn quantize_embedding(embedding: &[f32]) -> QuantizedEmbedding {
+    let max_abs = embedding.iter().fold(0.0f32, |acc, x| acc.max(x.abs()));
+    let scale = if max_abs > 0.0 { max_abs / 127.0 } else { 1.0 };
+    let values = embedding.iter()
+        .map(|x| (x / scale).round().clamp(-127.0, 127.0) as i8)
+        .collect();
+    QuantizedEmbedding { scale, values }
+}
+fn dequantize_embedding(quantized: &QuantizedEmbedding) -> Vec<f32> {
+    quantized.values.iter().map(|&q| q as f32 * quantized.scale).collect()
+}
+/
+fn decode_embedding(bytes: &[u8]) -> std::result::Result<Vec<f32>, bincode::Error> {
+    match bytes.strip_prefix(QUANTIZED_I8_MAGIC.as_slice()) {
+        Some(payload) => {
+            let quantized: QuantizedEmbedding = bincode::deserialize(payload)?;
+            Ok(dequantize_embedding(&quantized))
+        }
+        None => bincode::deserialize(bytes),
+    }
+}
+pub(crate) fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
     if a.len() != b.len() { return 0.0; }
     let dot: f32 = a.iter().zip(b.iter()).map(|(x, y)| x * y).sum();
     let norm_a: f32 = a.iter().map(|x| x * x).sum::<f32>().sqrt();
     let norm_b: f32 = b.iter().map(|x| x * x).sum::<f32>().sqrt();
     if norm_a == 0.0 || norm_b == 0.0 { 0.0 } else { dot / (norm_a * norm_b) }
 }
+fn new_index(dimension: usize) -> HNSWIndex<f32, i64> {
+    let params = HNSWParams {
+        n_neighbor: 16,
+        ef_build: 100,
+        ef_search: 50,
+        ..Default::default()
+    };
+    HNSWIndex::<f32, i64>::new(dimension, &params)
+}
+fn add_to_index(index: &mut HNSWIndex<f32, i64>, vector: &[f32], message_id: i64) -> anyhow::Result<()> {
+    index.add(vector, message_id)
+        .map_err(|e| anyhow::anyhow!("Failed to index embedding for message {}: {}", message_id, e))
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_storing_twice_keeps_one_row_and_index_entry() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = crate::memory_db::MemoryDatabase::new(&dir.path().join("dedupe.db")).unwrap();
+        let session = db.conversations.create_session(None).unwrap();
+        let stored = db.conversations.store_messages_batch(
+            &session.id,
+            &[("user".to_string(), "retried".to_string(), 0, 1, 0.5)],
+        ).unwrap();
+        let message_id = stored[0].id;
+        let vector = |seed: f32| -> Vec<f32> {
+            (0..384).map(|i| ((i as f32) * 0.1 + seed).sin()).collect()
+        };
+        let store = |embedding: Vec<f32>| {
+            db.embeddings.store_embedding(&Embedding {
+                id: 0,
+                message_id,
+                embedding,
+                embedding_model: "llama-server".to_string(),
+                generated_at: chrono::Utc::now(),
+            }).unwrap();
+        };
+        store(vector(0.0));
+        db.embeddings.initialize_index("llama-server").unwrap();
+
+        // A retried embedding task for the same message replaces the vector.
+        store(vector(1.0));
+
+        assert_eq!(db.embeddings.get_stats().unwrap().total_embeddings, 1);
+        let row = db.embeddings.get_embedding_by_message_id(message_id, "llama-server").unwrap().unwrap();
+        assert_eq!(row.embedding, vector(1.0));
+        let hits = db.embeddings.ann_index.read().unwrap().as_ref().unwrap().search(&vector(1.0), 10);
+        assert_eq!(hits, vec![message_id]);
+    }
+    #[test]
+    fn test_index_dimension_follows_the_data_and_mismatches_fail() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = crate::memory_db::MemoryDatabase::new(&dir.path().join("dimension.db")).unwrap();
+        let session = db.conversations.create_session(None).unwrap();
+        let stored = db.conversations.store_messages_batch(&session.id, &[
+            ("user".to_string(), "first".to_string(), 0, 1, 0.5),
+            ("user".to_string(), "second".to_string(), 1, 1, 0.5),
+        ]).unwrap();
+        let embedding = |message_id: i64, embedding: Vec<f32>| Embedding {
+            id: 0,
+            message_id,
+            embedding,
+            embedding_model: "nomic-embed".to_string(),
+            generated_at: chrono::Utc::now(),
+        };
+        // Empty at startup: the index is created by the first stored vector.
+        db.embeddings.initialize_index("nomic-embed").unwrap();
+        db.embeddings.store_embedding(&embedding(stored[0].id, vec![0.5; 768])).unwrap();
+        let hits = db.embeddings.find_similar_embeddings(&[0.5; 768], "nomic-embed", 5, 0.9).unwrap();
+        assert_eq!(hits.iter().map(|(id, _)| *id).collect::<Vec<_>>(), vec![stored[0].id]);
+
+        assert!(db.embeddings.store_embedding(&embedding(stored[1].id, vec![0.5; 384])).is_err());
+    }
+    #[test]
+    fn test_quantized_cosine_within_tolerance() {
+        let dim = 4096;
+        let a: Vec<f32> = (0..dim).map(|i| ((i as f32) * 0.37).sin()).collect();
+        let b: Vec<f32> = (0..dim).map(|i| ((i as f32) * 0.37 + 0.5).sin() * 0.8).collect();
+
+        let exact = cosine_similarity(&a, &b);
+        let qa = dequantize_embedding(&quantize_embedding(&a));
+        let qb = dequantize_embedding(&quantize_embedding(&b));
+        let approx = cosine_similarity(&qa, &qb);
+
+        assert!((exact - approx).abs() < 0.01, "exact {} vs quantized {}", exact, approx);
+    }
+    #[test]
+    fn test_decode_handles_both_formats() {
+        let embedding = vec![0.25f32, -1.0, 0.5, 0.0];
+
+        let raw = bincode::serialize(&embedding).unwrap();
+        assert_eq!(decode_embedding(&raw).unwrap(), embedding);
+
+        let mut quantized = QUANTIZED_I8_MAGIC.to_vec();
+        quantized.extend(bincode::serialize(&quantize_embedding(&embedding)).unwrap());
+        assert!(quantized.len() < raw.len());
+        let decoded = decode_embedding(&quantized).unwrap();
+        for (x, y) in embedding.iter().zip(decoded.iter()) {
+            assert!((x - y).abs() < 0.01);
+        }
+    }
+}
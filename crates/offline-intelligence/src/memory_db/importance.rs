@@ -0,0 +1,115 @@
+﻿use serde::{Deserialize, Serialize};
+use crate::memory::Role;
+use crate::utils::TextUtils;
+/
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ImportanceWeights {
+    /
+    pub base: f32,
+    /
+    pub user_bonus: f32,
+    /
+    pub system_bonus: f32,
+    /
+    pub length_weight: f32,
+    /
+    pub length_saturation_chars: usize,
+    /
+    pub code_bonus: f32,
+    /
+    pub question_bonus: f32,
+    /
+    pub detail_bonus: f32,
+    /
+    pub short_reply_penalty: f32,
+}
+impl Default for ImportanceWeights {
+    fn default() -> Self {
+        Self {
+            base: 0.3,
+            user_bonus: 0.1,
+            system_bonus: 0.2,
+            length_weight: 0.2,
+            length_saturation_chars: 800,
+            code_bonus: 0.2,
+            question_bonus: 0.1,
+            detail_bonus: 0.1,
+            short_reply_penalty: 0.15,
+        }
+    }
+}
+impl ImportanceWeights {
+    /
+    pub fn score(&self, role: &Role, content: &str) -> f32 {
+        let content = content.trim();
+        if content.is_empty() {
+            return 0.0;
+        }
+
+        let mut score = self.base;
+        score += match role {
+            Role::User => self.user_bonus,
+            Role::System => self.system_bonus,
+            _ => 0.0,
+        };
+
+        let saturation = self.length_saturation_chars.max(1) as f32;
+        score += self.length_weight * (content.len() as f32 / saturation).min(1.0);
+
+        if content.contains("```") || looks_like_code(content) {
+            score += self.code_bonus;
+        }
+        if content.contains('?') {
+            score += self.question_bonus;
+        }
+        if !TextUtils::extract_details(content).is_empty() {
+            score += self.detail_bonus;
+        }
+        if TextUtils::count_words(content) < 4 {
+            score -= self.short_reply_penalty;
+        }
+
+        score.clamp(0.0, 1.0)
+    }
+}
+fn looks_like_code(content: &str) -> bool {
+    content.lines().filter(|line| {
+        let line = line.trim_end();
+        line.ends_with(';') || line.ends_with('{') || line.starts_with("    ")
+            || line.trim_start().starts_with("fn ") || line.trim_start().starts_with("def ")
+    }).count() >= 2
+}
+/
+pub fn score_message_importance(role: &Role, content: &str) -> f32 {
+    ImportanceWeights::default().score(role, content)
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_importance_signals() {
+        let ack = score_message_importance(&Role::Assistant, "ok thanks");
+        let question = score_message_importance(&Role::User, "How should I configure the retry budget for the uploader?");
+        let code = score_message_importance(
+            &Role::Assistant,
+            "Try this:\n```rust\nlet retries = 3;\nclient.set_retries(retries);\n```",
+        );
+        let plain = score_message_importance(&Role::Assistant, "The uploader retries failed chunks in the background.");
+
+        assert!(ack < plain, "ack {} plain {}", ack, plain);
+        assert!(question > plain, "question {} plain {}", question, plain);
+        assert!(code > plain, "code {} plain {}", code, plain);
+        assert_eq!(score_message_importance(&Role::User, "   "), 0.0);
+        for score in [ack, question, code, plain] {
+            assert!((0.0..=1.0).contains(&score));
+        }
+    }
+
+    #[test]
+    fn test_weights_are_tunable() {
+        let weights = ImportanceWeights { question_bonus: 0.0, user_bonus: 0.0, ..Default::default() };
+        let text = "Does the uploader retry failed chunks in the background?";
+        assert_eq!(weights.score(&Role::User, text), weights.score(&Role::Assistant, text));
+    }
+}
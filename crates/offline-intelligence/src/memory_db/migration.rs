@@ -51,7 +51,7 @@ impl<'a> MigrationManager<'a> {
                 info!("Applying migration {}...", version);
 
 
-                let tx = self.conn.transaction()?;
+                let tx = self.conn.transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)?;
 
 
                 if let Err(e) = tx.execute_batch(migration_sql) {
@@ -146,6 +146,15 @@ fn get_migrations() -> Vec<(i32, &'static str)> {
         (1, include_str!("migrations/001_initial.sql")),
         (2, include_str!("migrations/002_add_embeddings.sql")),
         (3, include_str!("migrations/003_add_kv_snapshots.sql")),
+        (4, include_str!("migrations/004_kv_cache_metadata_snapshot.sql")),
+        (5, include_str!("migrations/005_message_indexes.sql")),
+        (6, include_str!("migrations/006_message_revisions.sql")),
+        (7, include_str!("migrations/007_saved_searches.sql")),
+        (8, include_str!("migrations/008_cascade_deletes.sql")),
+        (9, include_str!("migrations/009_unique_embeddings.sql")),
+        (10, include_str!("migrations/010_message_generation_outcome.sql")),
+        (11, include_str!("migrations/011_message_alternates.sql")),
+        (12, include_str!("migrations/012_kv_cache_user_messages.sql")),
     ]
 }
 /
@@ -165,6 +174,21 @@ pub fn get_database_stats(conn: &Connection) -> Result<schema::DatabaseStats> {
     let total_summaries = get_table_count(conn, "summaries")?;
     let total_details = get_table_count(conn, "details")?;
     let total_embeddings = get_table_count(conn, "embeddings")?;
+    let snapshot_count = get_table_count(conn, "kv_snapshots")?;
+
+    fn get_byte_total(conn: &Connection, sql: &str) -> i64 {
+        conn.query_row(sql, [], |row| row.get(0))
+            .unwrap_or_else(|e| {
+                warn!("Failed to sum blob sizes ({}): {}", sql, e);
+                0
+            })
+    }
+    let embedding_bytes = get_byte_total(conn, "SELECT COALESCE(SUM(LENGTH(embedding)), 0) FROM embeddings");
+    let snapshot_bytes = get_byte_total(conn, "SELECT COALESCE(SUM(LENGTH(kv_state)), 0) FROM kv_snapshots")
+        + get_byte_total(
+            conn,
+            "SELECT COALESCE(SUM(LENGTH(value_data) + COALESCE(LENGTH(key_data), 0)), 0) FROM kv_cache_entries",
+        );
 
 
     let database_size_bytes: i64 = conn
@@ -182,9 +206,31 @@ pub fn get_database_stats(conn: &Connection) -> Result<schema::DatabaseStats> {
         total_details,
         total_embeddings,
         database_size_bytes,
+        embedding_count: total_embeddings,
+        embedding_bytes,
+        snapshot_count,
+        snapshot_bytes,
+        file_size_bytes: database_file_size(conn),
     })
 }
 /
+fn database_file_size(conn: &Connection) -> i64 {
+    let path: Option<String> = conn
+        .query_row("SELECT file FROM pragma_database_list WHERE name = 'main'", [], |row| row.get(0))
+        .optional()
+        .ok()
+        .flatten()
+        .filter(|path: &String| !path.is_empty());
+    let Some(path) = path else {
+        return 0;
+    };
+    [path.clone(), format!("{}-wal", path)]
+        .iter()
+        .filter_map(|file| std::fs::metadata(file).ok())
+        .map(|meta| meta.len() as i64)
+        .sum()
+}
+/
 /
 pub fn get_database_stats_from_path(db_path: &Path) -> Result<schema::DatabaseStats> {
     let conn = Connection::open(db_path)?;
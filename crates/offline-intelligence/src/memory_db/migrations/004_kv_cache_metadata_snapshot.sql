@@ -0,0 +1,3 @@
+-- Migration 004: Persist the last snapshot id with per-session cache state
+
+ALTER TABLE kv_cache_metadata ADD COLUMN last_snapshot_id INTEGER;
@@ -0,0 +1,5 @@
+-- Migration 005: Indexes for timestamp-ordered message searches and the embedding backfill
+
+CREATE INDEX IF NOT EXISTS idx_messages_session_timestamp ON messages (session_id, timestamp);
+
+CREATE INDEX IF NOT EXISTS idx_messages_embedding_generated ON messages (embedding_generated);
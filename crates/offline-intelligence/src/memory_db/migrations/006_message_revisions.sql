@@ -0,0 +1,13 @@
+-- Migration 006: Keep prior message content when a message is edited
+
+CREATE TABLE IF NOT EXISTS message_revisions (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  message_id INTEGER NOT NULL,
+  revision INTEGER NOT NULL,
+  content TEXT NOT NULL,
+  revised_at TIMESTAMP NOT NULL,
+  FOREIGN KEY (message_id) REFERENCES messages(id) ON DELETE CASCADE,
+  UNIQUE(message_id, revision)
+);
+
+CREATE INDEX IF NOT EXISTS idx_message_revisions_message ON message_revisions (message_id);
@@ -0,0 +1,66 @@
+-- Migration 008: Make deletes of sessions, messages and snapshots reach every dependent row
+--
+-- SQLite cannot add ON DELETE CASCADE to an existing table without rebuilding it,
+-- and rebuilding a parent table inside this transaction would itself cascade.
+-- Tables created before their foreign keys were declared, and connections opened
+-- without PRAGMA foreign_keys, both left orphans behind. The triggers below
+-- perform the same cascade explicitly, independent of either.
+
+-- 1. Remove rows already orphaned by earlier deletes
+DELETE FROM messages WHERE session_id NOT IN (SELECT id FROM sessions);
+DELETE FROM summaries WHERE session_id NOT IN (SELECT id FROM sessions);
+DELETE FROM tier3_content WHERE session_id NOT IN (SELECT id FROM sessions);
+DELETE FROM details
+WHERE session_id NOT IN (SELECT id FROM sessions)
+   OR message_id NOT IN (SELECT id FROM messages);
+DELETE FROM embeddings WHERE message_id NOT IN (SELECT id FROM messages);
+DELETE FROM message_revisions WHERE message_id NOT IN (SELECT id FROM messages);
+DELETE FROM embedding_similarities
+WHERE session_id NOT IN (SELECT id FROM sessions)
+   OR source_embedding_id NOT IN (SELECT id FROM embeddings)
+   OR target_embedding_id NOT IN (SELECT id FROM embeddings);
+DELETE FROM kv_snapshots
+WHERE session_id NOT IN (SELECT id FROM sessions)
+   OR message_id NOT IN (SELECT id FROM messages);
+DELETE FROM kv_metadata WHERE snapshot_id NOT IN (SELECT id FROM kv_snapshots);
+DELETE FROM kv_cache_entries WHERE snapshot_id NOT IN (SELECT id FROM kv_snapshots);
+DELETE FROM kv_cache_metadata WHERE session_id NOT IN (SELECT id FROM sessions);
+
+-- 2. Explicit cascades
+CREATE TRIGGER IF NOT EXISTS trg_sessions_delete_cascade
+AFTER DELETE ON sessions
+BEGIN
+  DELETE FROM messages WHERE session_id = OLD.id;
+  DELETE FROM summaries WHERE session_id = OLD.id;
+  DELETE FROM details WHERE session_id = OLD.id;
+  DELETE FROM tier3_content WHERE session_id = OLD.id;
+  DELETE FROM embedding_similarities WHERE session_id = OLD.id;
+  DELETE FROM kv_snapshots WHERE session_id = OLD.id;
+  DELETE FROM kv_cache_metadata WHERE session_id = OLD.id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS trg_messages_delete_cascade
+AFTER DELETE ON messages
+BEGIN
+  DELETE FROM embeddings WHERE message_id = OLD.id;
+  DELETE FROM details WHERE message_id = OLD.id;
+  DELETE FROM message_revisions WHERE message_id = OLD.id;
+  DELETE FROM kv_snapshots WHERE message_id = OLD.id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS trg_embeddings_delete_cascade
+AFTER DELETE ON embeddings
+BEGIN
+  DELETE FROM embedding_similarities
+  WHERE source_embedding_id = OLD.id OR target_embedding_id = OLD.id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS trg_kv_snapshots_delete_cascade
+AFTER DELETE ON kv_snapshots
+BEGIN
+  DELETE FROM kv_metadata WHERE snapshot_id = OLD.id;
+  DELETE FROM kv_cache_entries WHERE snapshot_id = OLD.id;
+END;
+
+-- Per-message cascades into details would otherwise scan the whole table
+CREATE INDEX IF NOT EXISTS idx_details_message ON details (message_id);
@@ -0,0 +1,13 @@
+-- Migration 009: One embedding per (message, model)
+--
+-- Databases created before the UNIQUE constraint was declared accumulated a
+-- second vector whenever the embedding task ran twice for a message. Keep the
+-- newest row per pair, then enforce uniqueness so store_embedding can upsert.
+
+DELETE FROM embeddings
+WHERE id NOT IN (
+  SELECT MAX(id) FROM embeddings GROUP BY message_id, embedding_model
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_embeddings_message_model_unique
+ON embeddings (message_id, embedding_model);
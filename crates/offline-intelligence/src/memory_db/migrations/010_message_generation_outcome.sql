@@ -0,0 +1,8 @@
+-- Migration 010: Record how streamed assistant responses ended
+--
+-- finish_reason is the backend's reason ("stop", "length", ...); a "length"
+-- response was cut off by max_tokens. Both columns stay NULL for user messages
+-- and for responses stored before this migration.
+
+ALTER TABLE messages ADD COLUMN finish_reason TEXT;
+ALTER TABLE messages ADD COLUMN completion_tokens INTEGER;
@@ -0,0 +1,24 @@
+-- Migration 011: Keep the extra candidates of an n>1 generation beside the primary
+--
+-- Alternates hang off the stored assistant message instead of being written as
+-- further assistant turns, so the conversation history keeps one reply per turn.
+
+CREATE TABLE IF NOT EXISTS message_alternates (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  message_id INTEGER NOT NULL,
+  choice_index INTEGER NOT NULL,
+  content TEXT NOT NULL,
+  finish_reason TEXT,
+  completion_tokens INTEGER,
+  FOREIGN KEY (message_id) REFERENCES messages(id) ON DELETE CASCADE,
+  UNIQUE(message_id, choice_index)
+);
+
+CREATE INDEX IF NOT EXISTS idx_message_alternates_message ON message_alternates (message_id);
+
+-- Same explicit cascade as migration 008, for connections without PRAGMA foreign_keys
+CREATE TRIGGER IF NOT EXISTS trg_messages_delete_alternates
+AFTER DELETE ON messages
+BEGIN
+  DELETE FROM message_alternates WHERE message_id = OLD.id;
+END;
@@ -0,0 +1,6 @@
+-- Migration 012: Remember how many user messages the cache last saw per session
+--
+-- A conversation round is counted only when this number grows, so retries and
+-- post-reply passes over the same history do not advance conversation_count.
+
+ALTER TABLE kv_cache_metadata ADD COLUMN user_message_count INTEGER DEFAULT 0;
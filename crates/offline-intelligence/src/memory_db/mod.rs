@@ -5,39 +5,213 @@ pub mod migration;
 pub mod conversation_store;
 pub mod summary_store;
 pub mod embedding_store;
+pub mod importance;
 pub use schema::*;
 pub use migration::MigrationManager;
 pub use conversation_store::ConversationStore;
 pub use summary_store::SummaryStore;
 pub use embedding_store::{EmbeddingStore, EmbeddingStats};
+pub use importance::{score_message_importance, ImportanceWeights};
 use std::path::Path;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
 use r2d2::Pool;
 use r2d2_sqlite::SqliteConnectionManager;
-use tracing::info;
+use rusqlite::OptionalExtension;
+use tracing::{info, warn};
 use crate::cache_management::cache_extractor::KVEntry;
 use crate::cache_management::cache_manager::SessionCacheState;
+const DEFAULT_BUSY_TIMEOUT: Duration = Duration::from_millis(5000);
+const DEFAULT_WAL_AUTOCHECKPOINT: u32 = 1000;
+const STATEMENT_CACHE_CAPACITY: usize = 64;
+/
+const PROGRESS_OPS: i32 = 1000;
+/
+/
+const STATEMENT_IDLE_GAP: Duration = Duration::from_millis(50);
+/
+#[derive(Debug, Clone, Copy)]
+pub struct PoolSettings {
+    pub max_size: u32,
+    pub connection_timeout: Duration,
+    /
+    pub statement_timeout: Duration,
+}
+impl Default for PoolSettings {
+    fn default() -> Self {
+        Self {
+            max_size: 10,
+            connection_timeout: Duration::from_secs(30),
+            statement_timeout: Duration::from_secs(30),
+        }
+    }
+}
+/
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SynchronousMode {
+    Off,
+    #[default]
+    Normal,
+    Full,
+    Extra,
+}
+impl std::str::FromStr for SynchronousMode {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_uppercase().as_str() {
+            "OFF" | "0" => Ok(SynchronousMode::Off),
+            "NORMAL" | "1" => Ok(SynchronousMode::Normal),
+            "FULL" | "2" => Ok(SynchronousMode::Full),
+            "EXTRA" | "3" => Ok(SynchronousMode::Extra),
+            other => Err(anyhow::anyhow!(
+                "Unknown DB_SYNCHRONOUS '{}' (expected OFF, NORMAL, FULL or EXTRA)", other
+            )),
+        }
+    }
+}
+impl std::fmt::Display for SynchronousMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SynchronousMode::Off => write!(f, "OFF"),
+            SynchronousMode::Normal => write!(f, "NORMAL"),
+            SynchronousMode::Full => write!(f, "FULL"),
+            SynchronousMode::Extra => write!(f, "EXTRA"),
+        }
+    }
+}
+/
+#[derive(Debug, Clone, Copy)]
+pub struct DatabaseConfig {
+    pub pool: PoolSettings,
+    pub busy_timeout: Duration,
+    pub synchronous: SynchronousMode,
+    /
+    pub wal_autocheckpoint: u32,
+}
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        Self {
+            pool: PoolSettings::default(),
+            busy_timeout: DEFAULT_BUSY_TIMEOUT,
+            synchronous: SynchronousMode::default(),
+            wal_autocheckpoint: DEFAULT_WAL_AUTOCHECKPOINT,
+        }
+    }
+}
+impl DatabaseConfig {
+    pub fn from_config(cfg: &crate::config::Config) -> Self {
+        Self {
+            pool: PoolSettings {
+                max_size: cfg.db_pool_max_size,
+                connection_timeout: Duration::from_secs(cfg.db_pool_timeout_seconds),
+                statement_timeout: Duration::from_millis(cfg.db_statement_timeout_ms),
+            },
+            busy_timeout: Duration::from_millis(cfg.db_busy_timeout_ms),
+            synchronous: cfg.db_synchronous,
+            wal_autocheckpoint: cfg.db_wal_autocheckpoint,
+        }
+    }
+    /
+    fn apply_pragmas(&self, conn: &rusqlite::Connection) -> rusqlite::Result<()> {
+        conn.busy_timeout(self.busy_timeout)?;
+        conn.execute_batch(&format!(
+            "PRAGMA foreign_keys = ON;
+             PRAGMA synchronous = {};
+             PRAGMA wal_autocheckpoint = {};",
+            self.synchronous, self.wal_autocheckpoint,
+        ))
+    }
+}
+/
+/
+/
+fn configure_connection(conn: &mut rusqlite::Connection, statement_timeout: Duration) {
+    conn.set_prepared_statement_cache_capacity(STATEMENT_CACHE_CAPACITY);
+    if statement_timeout.is_zero() {
+        return;
+    }
+    // Each connection keeps its own clock: the first callback after a pause starts it,
+    // so time spent idle in the pool or waiting on the caller never counts.
+    let mut started = Instant::now();
+    let mut last_progress = started;
+    conn.progress_handler(PROGRESS_OPS, Some(move || {
+        let now = Instant::now();
+        if now.duration_since(last_progress) > STATEMENT_IDLE_GAP {
+            started = now;
+        }
+        last_progress = now;
+        now.duration_since(started) > statement_timeout
+    }));
+}
+/
+/
+/
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct PoolStats {
+    pub max_size: u32,
+    pub connections: u32,
+    pub idle_connections: u32,
+    pub in_use: u32,
+    pub checkouts: u64,
+    pub checkout_timeouts: u64,
+    pub max_wait_ms: u64,
+    pub total_wait_ms: u64,
+}
+#[derive(Debug, Default)]
+struct PoolCounters {
+    checkouts: AtomicU64,
+    timeouts: AtomicU64,
+    max_wait_ms: AtomicU64,
+    total_wait_ms: AtomicU64,
+}
+#[derive(Debug)]
+struct PoolEvents(Arc<PoolCounters>);
+impl r2d2::HandleEvent for PoolEvents {
+    fn handle_checkout(&self, event: r2d2::event::CheckoutEvent) {
+        let waited = event.duration().as_millis() as u64;
+        self.0.checkouts.fetch_add(1, Ordering::Relaxed);
+        self.0.total_wait_ms.fetch_add(waited, Ordering::Relaxed);
+        self.0.max_wait_ms.fetch_max(waited, Ordering::Relaxed);
+    }
+    fn handle_timeout(&self, event: r2d2::event::TimeoutEvent) {
+        self.0.timeouts.fetch_add(1, Ordering::Relaxed);
+        warn!("Timed out after {:?} waiting for a database connection", event.timeout());
+    }
+}
+fn pool_builder(settings: PoolSettings, counters: &Arc<PoolCounters>) -> r2d2::Builder<SqliteConnectionManager> {
+    Pool::builder()
+        .max_size(settings.max_size.max(1))
+        .connection_timeout(settings.connection_timeout)
+        .event_handler(Box::new(PoolEvents(Arc::clone(counters))))
+}
 /
 pub struct MemoryDatabase {
     pub conversations: ConversationStore,
     pub summaries: SummaryStore,
     pub embeddings: EmbeddingStore,
     pool: Arc<Pool<SqliteConnectionManager>>,
+    pool_counters: Arc<PoolCounters>,
 }
 /
 pub struct Transaction<'a> {
     conn: r2d2::PooledConnection<SqliteConnectionManager>,
+    finished: bool,
     _marker: std::marker::PhantomData<&'a MemoryDatabase>,
 }
 impl<'a> Transaction<'a> {
     /
-    pub fn commit(self) -> anyhow::Result<()> {
-
-        Ok(())
+    pub fn commit(mut self) -> anyhow::Result<()> {
+        self.finish("COMMIT;")
     }
     /
-    pub fn rollback(self) -> anyhow::Result<()> {
-
+    pub fn rollback(mut self) -> anyhow::Result<()> {
+        self.finish("ROLLBACK;")
+    }
+    fn finish(&mut self, sql: &str) -> anyhow::Result<()> {
+        // A failed COMMIT leaves the transaction open; Drop then rolls it back.
+        self.conn.execute_batch(sql)?;
+        self.finished = true;
         Ok(())
     }
     /
@@ -45,9 +219,36 @@ impl<'a> Transaction<'a> {
         &mut self.conn
     }
 }
+impl Drop for Transaction<'_> {
+    fn drop(&mut self) {
+        // Never hand a connection back to the pool mid-transaction.
+        if !self.finished && !self.conn.is_autocommit() {
+            if let Err(e) = self.conn.execute_batch("ROLLBACK;") {
+                tracing::warn!("Failed to roll back abandoned transaction: {}", e);
+            }
+        }
+    }
+}
 impl MemoryDatabase {
     /
     pub fn new(db_path: &Path) -> anyhow::Result<Self> {
+        Self::new_with_busy_timeout(db_path, DEFAULT_BUSY_TIMEOUT)
+    }
+    /
+    /
+    pub fn new_with_busy_timeout(db_path: &Path, busy_timeout: Duration) -> anyhow::Result<Self> {
+        Self::new_with_pool_settings(db_path, busy_timeout, PoolSettings::default())
+    }
+    /
+    pub fn new_with_pool_settings(
+        db_path: &Path,
+        busy_timeout: Duration,
+        pool_settings: PoolSettings,
+    ) -> anyhow::Result<Self> {
+        Self::new_with_config(db_path, DatabaseConfig { pool: pool_settings, busy_timeout, ..DatabaseConfig::default() })
+    }
+    /
+    pub fn new_with_config(db_path: &Path, db_config: DatabaseConfig) -> anyhow::Result<Self> {
         info!("Opening memory database at: {}", db_path.display());
         if let Some(parent) = db_path.parent() {
             std::fs::create_dir_all(parent)?;
@@ -57,9 +258,13 @@ impl MemoryDatabase {
                 rusqlite::OpenFlags::SQLITE_OPEN_READ_WRITE
                 | rusqlite::OpenFlags::SQLITE_OPEN_CREATE
                 | rusqlite::OpenFlags::SQLITE_OPEN_FULL_MUTEX,
-            );
-        let pool = Pool::builder()
-            .max_size(10)
+            )
+            .with_init(move |conn| {
+                configure_connection(conn, db_config.pool.statement_timeout);
+                db_config.apply_pragmas(conn)
+            });
+        let pool_counters = Arc::new(PoolCounters::default());
+        let pool = pool_builder(db_config.pool, &pool_counters)
             .build(manager)
             .map_err(|e| anyhow::anyhow!("Failed to create connection pool: {}", e))?;
 
@@ -68,26 +273,31 @@ impl MemoryDatabase {
             let mut migrator = migration::MigrationManager::new(&mut conn);
             migrator.initialize_database()?;
             conn.execute_batch(
-                "PRAGMA foreign_keys = ON;
-                 PRAGMA journal_mode = WAL;
-                 PRAGMA synchronous = NORMAL;
-                 PRAGMA busy_timeout = 5000;",
+                "PRAGMA journal_mode = WAL;",
             )?;
+            // The migrator sets its own PRAGMAs on this connection; restore the configured ones.
+            db_config.apply_pragmas(&conn)?;
         }
         let pool = Arc::new(pool);
-        info!("Memory database initialized successfully");
+        info!("Memory database initialized successfully (pool size {}, synchronous {})",
+            pool.max_size(), db_config.synchronous);
         Ok(Self {
             conversations: ConversationStore::new(Arc::clone(&pool)),
             summaries: SummaryStore::new(Arc::clone(&pool)),
             embeddings: EmbeddingStore::new(Arc::clone(&pool)),
             pool,
+            pool_counters,
         })
     }
     /
     pub fn new_in_memory() -> anyhow::Result<Self> {
-        let manager = SqliteConnectionManager::memory();
-        let pool = Pool::builder()
-            .max_size(5)
+        let manager = SqliteConnectionManager::memory()
+            .with_init(|conn| {
+                configure_connection(conn, PoolSettings::default().statement_timeout);
+                conn.execute_batch("PRAGMA foreign_keys = ON;")
+            });
+        let pool_counters = Arc::new(PoolCounters::default());
+        let pool = pool_builder(PoolSettings { max_size: 5, ..PoolSettings::default() }, &pool_counters)
             .build(manager)?;
         {
             let conn = pool.get()?;
@@ -99,14 +309,30 @@ impl MemoryDatabase {
             summaries: SummaryStore::new(Arc::clone(&pool)),
             embeddings: EmbeddingStore::new(Arc::clone(&pool)),
             pool,
+            pool_counters,
         })
     }
     /
+    pub fn pool_stats(&self) -> PoolStats {
+        let state = self.pool.state();
+        PoolStats {
+            max_size: self.pool.max_size(),
+            connections: state.connections,
+            idle_connections: state.idle_connections,
+            in_use: state.connections - state.idle_connections,
+            checkouts: self.pool_counters.checkouts.load(Ordering::Relaxed),
+            checkout_timeouts: self.pool_counters.timeouts.load(Ordering::Relaxed),
+            max_wait_ms: self.pool_counters.max_wait_ms.load(Ordering::Relaxed),
+            total_wait_ms: self.pool_counters.total_wait_ms.load(Ordering::Relaxed),
+        }
+    }
+    /
     pub fn begin_transaction(&self) -> anyhow::Result<Transaction<'_>> {
         let conn = self.pool.get()?;
         conn.execute_batch("BEGIN IMMEDIATE TRANSACTION;")?;
         Ok(Transaction {
             conn,
+            finished: false,
             _marker: std::marker::PhantomData,
         })
     }
@@ -128,9 +354,35 @@ impl MemoryDatabase {
         }
     }
     /
+    async fn run_blocking<T, F>(&self, f: F) -> anyhow::Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut rusqlite::Connection) -> anyhow::Result<T> + Send + 'static,
+    {
+        let pool = Arc::clone(&self.pool);
+        tokio::task::spawn_blocking(move || {
+            let mut conn = pool.get()?;
+            f(&mut conn)
+        })
+        .await
+        .map_err(|e| anyhow::anyhow!("Database task failed: {}", e))?
+    }
+    /
     pub fn get_stats(&self) -> anyhow::Result<DatabaseStats> {
         let conn = self.pool.get()?;
-        Ok(migration::get_database_stats(&conn)?)
+        let mut stats = migration::get_database_stats(&conn)?;
+        match self.embeddings.get_stats() {
+            Ok(embedding_stats) => stats.embedding_count = embedding_stats.total_embeddings as i64,
+            Err(e) => warn!("Failed to read embedding stats: {}", e),
+        }
+        Ok(stats)
+    }
+    /
+    /
+    pub fn clear_session_messages(&self, session_id: &str) -> anyhow::Result<usize> {
+        let removed = self.conversations.clear_session_messages(session_id)?;
+        self.embeddings.forget_messages(&removed);
+        Ok(removed.len())
     }
     /
     pub fn cleanup_old_data(&self, older_than_days: i32) -> anyhow::Result<usize> {
@@ -145,68 +397,72 @@ impl MemoryDatabase {
         entries: &[KVEntry],
     ) -> anyhow::Result<i64> {
         use blake3;
-        let mut conn = self.pool.get()?;
-        let tx = conn.transaction()?;
+        let session_id = session_id.to_string();
+        let entries = entries.to_vec();
+        self.run_blocking(move |conn| {
+            let tx = conn.transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)?;
 
 
-        let total_size_bytes: usize = entries.iter()
-            .map(|entry| entry.value_data.len())
-            .sum();
+            let total_size_bytes: usize = entries.iter()
+                .map(|entry| entry.value_data.len())
+                .sum();
 
 
-        let kv_state = bincode::serialize(entries)?;
-        let kv_state_hash = blake3::hash(&kv_state).to_string();
+            let kv_state = bincode::serialize(&entries)?;
+            let kv_state_hash = blake3::hash(&kv_state).to_string();
 
 
-        let message_id: i64 = tx.query_row(
-            "SELECT COALESCE(MAX(id), 0) FROM messages WHERE session_id = ?1",
-            [session_id],
-            |row| row.get(0),
-        )?;
+            let message_id: i64 = tx.query_row(
+                "SELECT COALESCE(MAX(id), 0) FROM messages WHERE session_id = ?1",
+                [&session_id],
+                |row| row.get(0),
+            )?;
 
 
-        tx.execute(
-            "INSERT INTO kv_snapshots
-             (session_id, message_id, kv_state, kv_state_hash, size_bytes)
-             VALUES (?1, ?2, ?3, ?4, ?5)",
-            rusqlite::params![session_id, message_id, kv_state, kv_state_hash, total_size_bytes as i64],
-        )?;
+            tx.execute(
+                "INSERT INTO kv_snapshots
+                 (session_id, message_id, kv_state, kv_state_hash, size_bytes)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                rusqlite::params![session_id, message_id, kv_state, kv_state_hash, total_size_bytes as i64],
+            )?;
 
-        let snapshot_id = tx.last_insert_rowid();
+            let snapshot_id = tx.last_insert_rowid();
 
 
-        for entry in entries {
-            tx.execute(
-                "INSERT INTO kv_cache_entries
-                 (snapshot_id, key_hash, key_data, value_data, key_type,
-                  layer_index, head_index, importance_score, access_count)
-                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
-                rusqlite::params![
-                    snapshot_id,
-                    &entry.key_hash,
-                    entry.key_data.as_deref(),
-                    &entry.value_data,
-                    &entry.key_type,
-                    entry.layer_index,
-                    entry.head_index,
-                    entry.importance_score,
-                    entry.access_count,
-                ],
-            )?;
-        }
+            for entry in &entries {
+                tx.execute(
+                    "INSERT INTO kv_cache_entries
+                     (snapshot_id, key_hash, key_data, value_data, key_type,
+                      layer_index, head_index, importance_score, access_count, last_accessed)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+                    rusqlite::params![
+                        snapshot_id,
+                        &entry.key_hash,
+                        entry.key_data.as_deref(),
+                        &entry.value_data,
+                        &entry.key_type,
+                        entry.layer_index,
+                        entry.head_index,
+                        entry.importance_score,
+                        entry.access_count,
+                        entry.last_accessed.to_rfc3339(),
+                    ],
+                )?;
+            }
 
 
-        let now = chrono::Utc::now().to_rfc3339();
-        tx.execute(
-            "INSERT OR REPLACE INTO kv_cache_metadata
-             (session_id, total_entries, total_size_bytes, last_cleared_at)
-             VALUES (?1, ?2, ?3, ?4)",
-            rusqlite::params![session_id, entries.len() as i64, total_size_bytes as i64, &now],
-        )?;
+            let now = chrono::Utc::now().to_rfc3339();
+            tx.execute(
+                "INSERT OR REPLACE INTO kv_cache_metadata
+                 (session_id, total_entries, total_size_bytes, last_cleared_at)
+                 VALUES (?1, ?2, ?3, ?4)",
+                rusqlite::params![session_id, entries.len() as i64, total_size_bytes as i64, &now],
+            )?;
 
-        tx.commit()?;
+            tx.commit()?;
 
-        Ok(snapshot_id)
+            Ok(snapshot_id)
+        }).await
     }
 
     /
@@ -215,35 +471,36 @@ impl MemoryDatabase {
         session_id: &str,
         limit: usize,
     ) -> anyhow::Result<Vec<crate::cache_management::cache_manager::KvSnapshot>> {
-        let conn = self.pool.get()?;
-        let mut stmt = conn.prepare(
-            "SELECT id, session_id, message_id, snapshot_type, size_bytes, created_at
-             FROM kv_snapshots
-             WHERE session_id = ?1
-             ORDER BY created_at DESC
-             LIMIT ?2"
-        )?;
-
-        let mut rows = stmt.query(rusqlite::params![session_id, limit as i64])?;
-        let mut snapshots = Vec::new();
-
-        while let Some(row) = rows.next()? {
-            let created_at_str: String = row.get(5)?;
-            let created_at = chrono::DateTime::parse_from_rfc3339(&created_at_str)
-                .map_err(|e| anyhow::anyhow!("Failed to parse timestamp: {}", e))?
-                .with_timezone(&chrono::Utc);
-
-            snapshots.push(crate::cache_management::cache_manager::KvSnapshot {
-                id: row.get(0)?,
-                session_id: row.get(1)?,
-                message_id: row.get(2)?,
-                snapshot_type: row.get(3)?,
-                size_bytes: row.get(4)?,
-                created_at,
-            });
-        }
+        let session_id = session_id.to_string();
+        self.run_blocking(move |conn| {
+            let mut stmt = conn.prepare_cached(
+                "SELECT id, session_id, message_id, snapshot_type, size_bytes, created_at
+                 FROM kv_snapshots
+                 WHERE session_id = ?1
+                 ORDER BY created_at DESC
+                 LIMIT ?2"
+            )?;
+
+            let mut rows = stmt.query(rusqlite::params![session_id, limit as i64])?;
+            let mut snapshots = Vec::new();
+
+            while let Some(row) = rows.next()? {
+                let created_at_str: String = row.get(5)?;
+                let created_at = ConversationStore::parse_datetime_safe(&created_at_str)
+                    .ok_or_else(|| anyhow::anyhow!("Failed to parse timestamp: {}", created_at_str))?;
+
+                snapshots.push(crate::cache_management::cache_manager::KvSnapshot {
+                    id: row.get(0)?,
+                    session_id: row.get(1)?,
+                    message_id: row.get(2)?,
+                    snapshot_type: row.get(3)?,
+                    size_bytes: row.get(4)?,
+                    created_at,
+                });
+            }
 
-        Ok(snapshots)
+            Ok(snapshots)
+        }).await
     }
 
     /
@@ -251,37 +508,64 @@ impl MemoryDatabase {
         &self,
         snapshot_id: i64,
     ) -> anyhow::Result<Vec<KVEntry>> {
-        let conn = self.pool.get()?;
-        let mut stmt = conn.prepare(
-            "SELECT key_hash, key_data, value_data, key_type, layer_index,
-                    head_index, importance_score, access_count, last_accessed
-             FROM kv_cache_entries
-             WHERE snapshot_id = ?1"
-        )?;
-
-        let mut rows = stmt.query([snapshot_id])?;
-        let mut entries = Vec::new();
-
-        while let Some(row) = rows.next()? {
-            let last_accessed_str: String = row.get(8)?;
-            let last_accessed = chrono::DateTime::parse_from_rfc3339(&last_accessed_str)
-                .map_err(|e| anyhow::anyhow!("Failed to parse timestamp: {}", e))?
-                .with_timezone(&chrono::Utc);
-
-            entries.push(KVEntry {
-                key_hash: row.get(0)?,
-                key_data: row.get(1)?,
-                value_data: row.get(2)?,
-                key_type: row.get(3)?,
-                layer_index: row.get(4)?,
-                head_index: row.get(5)?,
-                importance_score: row.get(6)?,
-                access_count: row.get(7)?,
-                last_accessed,
-            });
-        }
+        self.run_blocking(move |conn| {
+            let mut stmt = conn.prepare_cached(
+                "SELECT key_hash, key_data, value_data, key_type, layer_index,
+                        head_index, importance_score, access_count, last_accessed
+                 FROM kv_cache_entries
+                 WHERE snapshot_id = ?1
+                 ORDER BY layer_index, COALESCE(head_index, -1), id"
+            )?;
 
-        Ok(entries)
+            let mut rows = stmt.query([snapshot_id])?;
+            let mut entries = Vec::new();
+
+            while let Some(row) = rows.next()? {
+                let last_accessed_str: String = row.get(8)?;
+                let last_accessed = chrono::DateTime::parse_from_rfc3339(&last_accessed_str)
+                    .map_err(|e| anyhow::anyhow!("Failed to parse timestamp: {}", e))?
+                    .with_timezone(&chrono::Utc);
+
+                entries.push(KVEntry {
+                    key_hash: row.get(0)?,
+                    key_data: row.get(1)?,
+                    value_data: row.get(2)?,
+                    key_type: row.get(3)?,
+                    layer_index: row.get(4)?,
+                    head_index: row.get(5)?,
+                    importance_score: row.get(6)?,
+                    access_count: row.get(7)?,
+                    last_accessed,
+                });
+            }
+
+            Ok(entries)
+        }).await
+    }
+
+    /
+    /
+    pub async fn record_kv_entry_accesses(
+        &self,
+        entries: Vec<(i64, String, i32, Option<i32>)>,
+    ) -> anyhow::Result<usize> {
+        self.run_blocking(move |conn| {
+            let tx = conn.transaction()?;
+            let now = chrono::Utc::now().to_rfc3339();
+            let mut updated = 0;
+            {
+                let mut stmt = tx.prepare_cached(
+                    "UPDATE kv_cache_entries
+                     SET access_count = access_count + 1, last_accessed = ?1
+                     WHERE snapshot_id = ?2 AND key_hash = ?3 AND layer_index = ?4 AND head_index IS ?5"
+                )?;
+                for (snapshot_id, key_hash, layer_index, head_index) in &entries {
+                    updated += stmt.execute(rusqlite::params![&now, snapshot_id, key_hash, layer_index, head_index])?;
+                }
+            }
+            tx.commit()?;
+            Ok(updated)
+        }).await
     }
 
     /
@@ -291,62 +575,147 @@ impl MemoryDatabase {
         keywords: &[String],
         limit: usize,
     ) -> anyhow::Result<Vec<StoredMessage>> {
+        self.search_messages_filtered(Some(session_id), keywords, &MessageSearchFilter::default(), limit).await
+    }
 
+    /
+    /
+    pub async fn search_messages_filtered(
+        &self,
+        session_id: Option<&str>,
+        keywords: &[String],
+        filter: &MessageSearchFilter,
+        limit: usize,
+    ) -> anyhow::Result<Vec<StoredMessage>> {
+        let session_id = session_id.map(str::to_string);
         let patterns: Vec<String> = keywords.iter()
             .map(|k| format!("%{}%", k))
             .collect();
+        let filter = filter.clone();
 
-        let conn = self.pool.get()?;
+        self.run_blocking(move |conn| {
+            let mut query = String::from(
+                "SELECT id, session_id, message_index, role, content, tokens,
+                        timestamp, importance_score, embedding_generated
+                 FROM messages
+                 WHERE 1 = 1"
+            );
+            let mut params: Vec<Box<dyn rusqlite::ToSql>> = Vec::new();
 
+            if let Some(session_id) = session_id {
+                query.push_str(" AND session_id = ?");
+                params.push(Box::new(session_id));
+            }
+            for pattern in patterns {
+                query.push_str(" AND content LIKE ?");
+                params.push(Box::new(pattern));
+            }
+            if let Some(role) = filter.role {
+                query.push_str(" AND role = ?");
+                params.push(Box::new(role));
+            }
 
-        let mut query = String::from(
-            "SELECT id, session_id, message_index, role, content, tokens,
-                    timestamp, importance_score, embedding_generated
-             FROM messages
-             WHERE session_id = ?1"
-        );
 
-        for _ in &patterns {
-            query.push_str(" AND content LIKE ?");
-        }
+            if let Some(from) = filter.from {
+                query.push_str(" AND julianday(timestamp) >= julianday(?)");
+                params.push(Box::new(from.to_rfc3339()));
+            }
+            if let Some(to) = filter.to {
+                query.push_str(" AND julianday(timestamp) <= julianday(?)");
+                params.push(Box::new(to.to_rfc3339()));
+            }
+            if let Some(ids) = filter.message_ids {
+                query.push_str(" AND id IN (");
+                query.push_str(&vec!["?"; ids.len()].join(", "));
+                query.push(')');
+                params.extend(ids.into_iter().map(|id| Box::new(id) as Box<dyn rusqlite::ToSql>));
+            }
+            if let Some(user_id) = filter.user_id {
+                query.push_str(" AND session_id IN (SELECT id FROM sessions WHERE json_extract(metadata, '$.user_id') = ?)");
+                params.push(Box::new(user_id));
+            }
 
-        query.push_str(" ORDER BY timestamp DESC LIMIT ?");
+            query.push_str(" ORDER BY timestamp DESC LIMIT ?");
+            params.push(Box::new(limit as i64));
 
-        let mut stmt = conn.prepare(&query)?;
+            let mut stmt = conn.prepare(&query)?;
+            let mut rows = stmt.query(rusqlite::params_from_iter(params.iter().map(|p| p.as_ref())))?;
+            let mut messages = Vec::new();
 
+            while let Some(row) = rows.next()? {
+                let timestamp_str: String = row.get(6)?;
+                let timestamp = chrono::DateTime::parse_from_rfc3339(&timestamp_str)
+                    .map_err(|e| anyhow::anyhow!("Failed to parse timestamp: {}", e))?
+                    .with_timezone(&chrono::Utc);
 
-        let mut params: Vec<&dyn rusqlite::ToSql> = Vec::new();
-        params.push(&session_id);
-        for pattern in &patterns {
-            params.push(pattern);
-        }
+                messages.push(StoredMessage {
+                    id: row.get(0)?,
+                    session_id: row.get(1)?,
+                    message_index: row.get(2)?,
+                    role: row.get(3)?,
+                    content: row.get(4)?,
+                    tokens: row.get(5)?,
+                    timestamp,
+                    importance_score: row.get(7)?,
+                    embedding_generated: row.get(8)?,
+                });
+            }
 
-        let limit_i64 = limit as i64;
-        params.push(&limit_i64);
-
-        let mut rows = stmt.query(rusqlite::params_from_iter(params))?;
-        let mut messages = Vec::new();
-
-        while let Some(row) = rows.next()? {
-            let timestamp_str: String = row.get(6)?;
-            let timestamp = chrono::DateTime::parse_from_rfc3339(&timestamp_str)
-                .map_err(|e| anyhow::anyhow!("Failed to parse timestamp: {}", e))?
-                .with_timezone(&chrono::Utc);
-
-            messages.push(StoredMessage {
-                id: row.get(0)?,
-                session_id: row.get(1)?,
-                message_index: row.get(2)?,
-                role: row.get(3)?,
-                content: row.get(4)?,
-                tokens: row.get(5)?,
-                timestamp,
-                importance_score: row.get(7)?,
-                embedding_generated: row.get(8)?,
-            });
-        }
+            Ok(messages)
+        }).await
+    }
 
-        Ok(messages)
+    /
+    pub async fn save_search(
+        &self,
+        user_id: Option<&str>,
+        name: &str,
+        query: &serde_json::Value,
+    ) -> anyhow::Result<SavedSearch> {
+        let saved = SavedSearch {
+            id: 0,
+            user_id: user_id.map(str::to_string),
+            name: name.to_string(),
+            query: query.clone(),
+            created_at: chrono::Utc::now(),
+        };
+        self.run_blocking(move |conn| {
+            conn.execute(
+                "INSERT INTO saved_searches (user_id, name, query, created_at) VALUES (?1, ?2, ?3, ?4)",
+                rusqlite::params![
+                    saved.user_id,
+                    saved.name,
+                    serde_json::to_string(&saved.query)?,
+                    saved.created_at.to_rfc3339(),
+                ],
+            )?;
+            Ok(SavedSearch { id: conn.last_insert_rowid(), ..saved })
+        }).await
+    }
+    /
+    pub async fn list_saved_searches(&self, user_id: Option<&str>) -> anyhow::Result<Vec<SavedSearch>> {
+        let user_id = user_id.map(str::to_string);
+        self.run_blocking(move |conn| {
+            let mut stmt = conn.prepare_cached(
+                "SELECT id, user_id, name, query, created_at FROM saved_searches
+                 WHERE user_id IS ?1 ORDER BY created_at DESC, id DESC"
+            )?;
+            let mut rows = stmt.query([&user_id])?;
+            let mut searches = Vec::new();
+            while let Some(row) = rows.next()? {
+                let query: String = row.get(3)?;
+                let created_at = ConversationStore::parse_datetime_safe(&row.get::<_, String>(4)?)
+                    .unwrap_or_else(chrono::Utc::now);
+                searches.push(SavedSearch {
+                    id: row.get(0)?,
+                    user_id: row.get(1)?,
+                    name: row.get(2)?,
+                    query: serde_json::from_str(&query)?,
+                    created_at,
+                });
+            }
+            Ok(searches)
+        }).await
     }
 
     /
@@ -355,23 +724,75 @@ impl MemoryDatabase {
         session_id: &str,
         state: &SessionCacheState,
     ) -> anyhow::Result<()> {
-        let conn = self.pool.get()?;
-        let metadata_json = serde_json::to_string(&state.metadata)?;
+        let session_id = session_id.to_string();
+        let state = state.clone();
+        self.run_blocking(move |conn| {
+            let metadata_json = serde_json::to_string(&state.metadata)?;
 
-        conn.execute(
-            "INSERT OR REPLACE INTO kv_cache_metadata
-             (session_id, total_entries, total_size_bytes, conversation_count, metadata)
-             VALUES (?1, ?2, ?3, ?4, ?5)",
-            rusqlite::params![
-                session_id,
-                state.entry_count as i64,
-                state.cache_size_bytes as i64,
-                state.conversation_count as i64,
-                metadata_json,
-            ],
-        )?;
+            conn.execute(
+                "INSERT OR REPLACE INTO kv_cache_metadata
+                 (session_id, total_entries, total_size_bytes, conversation_count, metadata,
+                  last_cleared_at, last_snapshot_id, user_message_count)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                rusqlite::params![
+                    session_id,
+                    state.entry_count as i64,
+                    state.cache_size_bytes as i64,
+                    state.conversation_count as i64,
+                    metadata_json,
+                    state.last_cleared_at.map(|dt| dt.to_rfc3339()),
+                    state.last_snapshot_id,
+                    state.user_message_count as i64,
+                ],
+            )?;
 
-        Ok(())
+            Ok(())
+        }).await
+    }
+
+    /
+    pub async fn load_kv_cache_metadata(
+        &self,
+        session_id: &str,
+    ) -> anyhow::Result<Option<SessionCacheState>> {
+        let session_id = session_id.to_string();
+        self.run_blocking(move |conn| {
+            let row = conn.query_row(
+                "SELECT total_entries, total_size_bytes, conversation_count, metadata,
+                        last_cleared_at, last_snapshot_id, user_message_count
+                 FROM kv_cache_metadata WHERE session_id = ?1",
+                [&session_id],
+                |row| Ok((
+                    row.get::<_, Option<i64>>(0)?,
+                    row.get::<_, Option<i64>>(1)?,
+                    row.get::<_, Option<i64>>(2)?,
+                    row.get::<_, Option<String>>(3)?,
+                    row.get::<_, Option<String>>(4)?,
+                    row.get::<_, Option<i64>>(5)?,
+                    row.get::<_, Option<i64>>(6)?,
+                )),
+            ).optional()?;
+
+            let Some((entries, size_bytes, conversations, metadata, cleared_at, snapshot_id, user_messages)) = row else {
+                return Ok(None);
+            };
+            let metadata = match metadata.as_deref() {
+                Some(json) if !json.is_empty() => serde_json::from_str(json)?,
+                _ => std::collections::HashMap::new(),
+            };
+            Ok(Some(SessionCacheState {
+                session_id,
+                conversation_count: conversations.unwrap_or(0).max(0) as usize,
+                user_message_count: user_messages.unwrap_or(0).max(0) as usize,
+                last_cleared_at: cleared_at
+                    .as_deref()
+                    .and_then(ConversationStore::parse_datetime_safe),
+                last_snapshot_id: snapshot_id,
+                cache_size_bytes: size_bytes.unwrap_or(0).max(0) as usize,
+                entry_count: entries.unwrap_or(0).max(0) as usize,
+                metadata,
+            }))
+        }).await
     }
 
     /
@@ -379,19 +800,43 @@ impl MemoryDatabase {
         &self,
         session_id: &str,
     ) -> anyhow::Result<()> {
-        let conn = self.pool.get()?;
+        let session_id = session_id.to_string();
+        self.run_blocking(move |conn| {
+            conn.execute(
+                "DELETE FROM kv_snapshots WHERE session_id = ?1",
+                [&session_id],
+            )?;
 
-        conn.execute(
-            "DELETE FROM kv_snapshots WHERE session_id = ?1",
-            [session_id],
-        )?;
+            conn.execute(
+                "DELETE FROM kv_cache_metadata WHERE session_id = ?1",
+                [&session_id],
+            )?;
 
-        conn.execute(
-            "DELETE FROM kv_cache_metadata WHERE session_id = ?1",
-            [session_id],
-        )?;
+            Ok(())
+        }).await
+    }
 
-        Ok(())
+    /
+    pub async fn prune_session_kv_snapshots(
+        &self,
+        session_id: &str,
+        keep_max: usize,
+    ) -> anyhow::Result<usize> {
+        let session_id = session_id.to_string();
+        self.run_blocking(move |conn| {
+            let deleted = conn.execute(
+                "DELETE FROM kv_snapshots
+                 WHERE session_id = ?1
+                 AND id NOT IN (
+                     SELECT id FROM kv_snapshots
+                     WHERE session_id = ?1
+                     ORDER BY created_at DESC, id DESC
+                     LIMIT ?2
+                 )",
+                rusqlite::params![session_id, keep_max as i64],
+            )?;
+            Ok(deleted)
+        }).await
     }
 
     /
@@ -399,36 +844,35 @@ impl MemoryDatabase {
         &self,
         keep_max: usize,
     ) -> anyhow::Result<usize> {
-        let conn = self.pool.get()?;
-
-
-        let mut stmt = conn.prepare(
-            "SELECT ks.id
-             FROM kv_snapshots ks
-             WHERE (
-                 SELECT COUNT(*)
-                 FROM kv_snapshots ks2
-                 WHERE ks2.session_id = ks.session_id
-                 AND ks2.created_at >= ks.created_at
-             ) > ?1"
-        )?;
+        self.run_blocking(move |conn| {
+            let mut stmt = conn.prepare_cached(
+                "SELECT ks.id
+                 FROM kv_snapshots ks
+                 WHERE (
+                     SELECT COUNT(*)
+                     FROM kv_snapshots ks2
+                     WHERE ks2.session_id = ks.session_id
+                     AND ks2.created_at >= ks.created_at
+                 ) > ?1"
+            )?;
 
-        let ids_to_delete: Vec<i64> = stmt
-            .query_map([keep_max as i64], |row| row.get(0))?
-            .collect::<rusqlite::Result<Vec<_>>>()?;
+            let ids_to_delete: Vec<i64> = stmt
+                .query_map([keep_max as i64], |row| row.get(0))?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
 
-        if ids_to_delete.is_empty() {
-            return Ok(0);
-        }
+            if ids_to_delete.is_empty() {
+                return Ok(0);
+            }
 
 
-        let placeholders = vec!["?"; ids_to_delete.len()].join(",");
-        let query = format!("DELETE FROM kv_snapshots WHERE id IN ({})", placeholders);
+            let placeholders = vec!["?"; ids_to_delete.len()].join(",");
+            let query = format!("DELETE FROM kv_snapshots WHERE id IN ({})", placeholders);
 
-        let mut stmt = conn.prepare(&query)?;
-        let deleted = stmt.execute(rusqlite::params_from_iter(&ids_to_delete))?;
+            let mut stmt = conn.prepare(&query)?;
+            let deleted = stmt.execute(rusqlite::params_from_iter(&ids_to_delete))?;
 
-        Ok(deleted)
+            Ok(deleted)
+        }).await
     }
 }
 impl Drop for MemoryDatabase {
@@ -440,3 +884,365 @@ impl Drop for MemoryDatabase {
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_transactions_commit_and_roll_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = MemoryDatabase::new(&dir.path().join("tx.db")).unwrap();
+        let count = |db: &MemoryDatabase| -> i64 {
+            db.pool.get().unwrap()
+                .query_row("SELECT COUNT(*) FROM sessions", [], |row| row.get(0))
+                .unwrap()
+        };
+        let insert = |tx: &mut Transaction<'_>, id: &str| -> anyhow::Result<()> {
+            tx.connection().execute(
+                "INSERT INTO sessions (id, created_at, last_accessed) VALUES (?1, ?2, ?2)",
+                rusqlite::params![id, chrono::Utc::now().to_rfc3339()],
+            )?;
+            Ok(())
+        };
+
+        db.with_transaction(|tx| insert(tx, "committed")).unwrap();
+        assert_eq!(count(&db), 1);
+
+        let failed: anyhow::Result<()> = db.with_transaction(|tx| {
+            insert(tx, "rolled-back")?;
+            anyhow::bail!("abort")
+        });
+        assert!(failed.is_err());
+        assert_eq!(count(&db), 1);
+
+        {
+            let mut tx = db.begin_transaction().unwrap();
+            insert(&mut tx, "abandoned").unwrap();
+        }
+        assert_eq!(count(&db), 1);
+        // Nothing may be left holding the write lock after any of the above.
+        let mut tx = db.begin_transaction().unwrap();
+        insert(&mut tx, "after").unwrap();
+        tx.commit().unwrap();
+        assert_eq!(count(&db), 2);
+    }
+    #[test]
+    fn test_concurrent_writes_do_not_lock() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Arc::new(MemoryDatabase::new(&dir.path().join("stress.db")).unwrap());
+        let session = db.conversations.create_session(None).unwrap();
+
+        let workers: Vec<_> = (0..16).map(|worker| {
+            let db = Arc::clone(&db);
+            let session_id = session.id.clone();
+            std::thread::spawn(move || -> anyhow::Result<()> {
+                for round in 0..20 {
+                    let index = worker * 100 + round;
+                    let stored = db.conversations.store_messages_batch(
+                        &session_id,
+                        &[("user".to_string(), format!("message {}", index), index, 2, 0.5)],
+                    )?;
+                    db.embeddings.store_embedding(&Embedding {
+                        id: 0,
+                        message_id: stored[0].id,
+                        embedding: vec![index as f32; 8],
+                        embedding_model: "test".to_string(),
+                        generated_at: chrono::Utc::now(),
+                    })?;
+                }
+                Ok(())
+            })
+        }).collect();
+
+        for worker in workers {
+            worker.join().unwrap().unwrap();
+        }
+        let messages = db.conversations.get_session_messages(&session.id, None, None).unwrap();
+        assert_eq!(messages.len(), 16 * 20);
+    }
+    #[test]
+    fn test_runaway_statement_is_interrupted() {
+        let dir = tempfile::tempdir().unwrap();
+        let settings = PoolSettings { max_size: 1, statement_timeout: Duration::from_millis(50), ..PoolSettings::default() };
+        let db = MemoryDatabase::new_with_pool_settings(&dir.path().join("timeout.db"), DEFAULT_BUSY_TIMEOUT, settings).unwrap();
+
+        let conn = db.pool.get().unwrap();
+        let started = Instant::now();
+        let runaway = conn.query_row(
+            "WITH RECURSIVE c(x) AS (SELECT 1 UNION ALL SELECT x + 1 FROM c) SELECT COUNT(*) FROM c",
+            [],
+            |row| row.get::<_, i64>(0),
+        );
+        assert!(runaway.is_err());
+        assert!(started.elapsed() < Duration::from_secs(5));
+        drop(conn);
+
+        // The same pooled connection keeps serving ordinary queries afterwards.
+        assert!(db.conversations.create_session(None).is_ok());
+    }
+    #[test]
+    fn test_database_config_applies_pool_size_and_pragmas() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_config = DatabaseConfig {
+            pool: PoolSettings { max_size: 3, ..PoolSettings::default() },
+            synchronous: SynchronousMode::Off,
+            wal_autocheckpoint: 250,
+            ..DatabaseConfig::default()
+        };
+        let db = MemoryDatabase::new_with_config(&dir.path().join("tuned.db"), db_config).unwrap();
+        assert_eq!(db.pool_stats().max_size, 3);
+
+        // Check every pooled connection, including the one that ran migrations.
+        let conns: Vec<_> = (0..3).map(|_| db.pool.get().unwrap()).collect();
+        for conn in &conns {
+            let synchronous: i64 = conn.query_row("PRAGMA synchronous", [], |row| row.get(0)).unwrap();
+            let checkpoint: i64 = conn.query_row("PRAGMA wal_autocheckpoint", [], |row| row.get(0)).unwrap();
+            assert_eq!((synchronous, checkpoint), (0, 250));
+        }
+    }
+    #[test]
+    fn test_synchronous_mode_parses_names_and_levels() {
+        assert_eq!("off".parse::<SynchronousMode>().unwrap(), SynchronousMode::Off);
+        assert_eq!(" FULL ".parse::<SynchronousMode>().unwrap(), SynchronousMode::Full);
+        assert_eq!("1".parse::<SynchronousMode>().unwrap(), SynchronousMode::Normal);
+        assert!("fast".parse::<SynchronousMode>().is_err());
+    }
+    #[test]
+    fn test_pool_stats_report_saturation() {
+        let dir = tempfile::tempdir().unwrap();
+        let settings = PoolSettings { max_size: 2, connection_timeout: Duration::from_millis(100), ..PoolSettings::default() };
+        let db = MemoryDatabase::new_with_pool_settings(&dir.path().join("pool.db"), DEFAULT_BUSY_TIMEOUT, settings).unwrap();
+
+        let first = db.pool.get().unwrap();
+        let second = db.pool.get().unwrap();
+        let saturated = db.pool_stats();
+        assert_eq!((saturated.max_size, saturated.in_use, saturated.idle_connections), (2, 2, 0));
+        assert!(db.conversations.create_session(None).is_err());
+        assert_eq!(db.pool_stats().checkout_timeouts, 1);
+
+        drop((first, second));
+        let drained = db.pool_stats();
+        assert_eq!((drained.in_use, drained.idle_connections), (0, 2));
+        assert!(drained.checkouts >= 3);
+    }
+    #[tokio::test]
+    async fn test_deleting_session_leaves_no_orphans() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("cascade.db");
+        let db = MemoryDatabase::new(&db_path).unwrap();
+        let populate = |label: &str| {
+            let session = db.conversations.create_session(None).unwrap();
+            let stored = db.conversations.store_messages_batch(
+                &session.id,
+                &[("user".to_string(), format!("{} message", label), 0, 2, 0.5)],
+            ).unwrap();
+            let message_id = stored[0].id;
+            db.embeddings.store_embedding(&Embedding {
+                id: 0,
+                message_id,
+                embedding: vec![0.5; 8],
+                embedding_model: "test".to_string(),
+                generated_at: chrono::Utc::now(),
+            }).unwrap();
+            db.conversations.store_details_batch(
+                &[(session.id.as_str(), message_id, "fact", "detail", "context", 0.5)],
+            ).unwrap();
+            db.conversations.update_message_content(message_id, "edited", 1).unwrap();
+            db.conversations.store_alternates(message_id, &[MessageAlternate {
+                choice_index: 1,
+                content: "alternate".to_string(),
+                finish_reason: None,
+                completion_tokens: None,
+            }]).unwrap();
+            session.id
+        };
+        let entry = KVEntry {
+            key_hash: "key".to_string(),
+            key_data: None,
+            value_data: vec![1; 16],
+            key_type: "attention_key".to_string(),
+            layer_index: 0,
+            head_index: None,
+            importance_score: 0.5,
+            access_count: 0,
+            last_accessed: chrono::Utc::now(),
+        };
+        let through_pool = populate("pool");
+        db.create_kv_snapshot(&through_pool, &[entry.clone()]).await.unwrap();
+        let without_foreign_keys = populate("raw");
+        db.create_kv_snapshot(&without_foreign_keys, &[entry]).await.unwrap();
+
+        db.conversations.delete_session(&through_pool).unwrap();
+        // A plain connection has foreign key enforcement off by default.
+        let raw = rusqlite::Connection::open(&db_path).unwrap();
+        raw.execute("DELETE FROM sessions WHERE id = ?1", [&without_foreign_keys]).unwrap();
+
+        for table in [
+            "messages", "details", "embeddings", "message_revisions", "message_alternates", "summaries",
+            "kv_snapshots", "kv_metadata", "kv_cache_entries", "kv_cache_metadata",
+        ] {
+            let count: i64 = raw
+                .query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |row| row.get(0))
+                .unwrap();
+            assert_eq!(count, 0, "orphaned rows left in {}", table);
+        }
+    }
+    #[tokio::test]
+    async fn test_prune_session_snapshots_keeps_newest() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = MemoryDatabase::new(&dir.path().join("snapshots.db")).unwrap();
+        let session = db.conversations.create_session(None).unwrap();
+        db.conversations.store_messages_batch(
+            &session.id,
+            &[("user".to_string(), "hello".to_string(), 0, 1, 0.5)],
+        ).unwrap();
+
+        for i in 0..4 {
+            let entry = KVEntry {
+                key_hash: format!("key-{}", i),
+                key_data: None,
+                value_data: vec![i as u8; 16],
+                key_type: "attention_key".to_string(),
+                layer_index: 0,
+                head_index: None,
+                importance_score: 0.5,
+                access_count: 0,
+                last_accessed: chrono::Utc::now(),
+            };
+            db.create_kv_snapshot(&session.id, &[entry]).await.unwrap();
+        }
+
+        assert_eq!(db.get_recent_kv_snapshots(&session.id, 10).await.unwrap().len(), 4);
+        assert_eq!(db.prune_session_kv_snapshots(&session.id, 1).await.unwrap(), 3);
+        assert_eq!(db.get_recent_kv_snapshots(&session.id, 10).await.unwrap().len(), 1);
+    }
+    #[tokio::test]
+    async fn test_search_filters_by_role_and_date_range() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = MemoryDatabase::new(&dir.path().join("search.db")).unwrap();
+        let now = chrono::Utc::now();
+        let message = |index: i32, role: &str, days_ago: i64| StoredMessage {
+            id: 0,
+            session_id: String::new(),
+            message_index: index,
+            role: role.to_string(),
+            content: format!("notes about rust #{}", index),
+            tokens: 4,
+            timestamp: now - chrono::Duration::days(days_ago),
+            importance_score: 0.5,
+            embedding_generated: false,
+        };
+        let export = SessionExport {
+            session: db.conversations.create_session(None).unwrap(),
+            messages: vec![
+                message(0, "user", 10),
+                message(1, "assistant", 10),
+                message(2, "user", 3),
+                message(3, "assistant", 3),
+            ],
+        };
+        let session_id = db.conversations.import_session(&export, true).unwrap();
+        let keywords = vec!["rust".to_string()];
+
+        let filter = MessageSearchFilter {
+            from: Some(now - chrono::Duration::days(7)),
+            to: Some(now),
+            role: Some("assistant".to_string()),
+            ..Default::default()
+        };
+        let results = db.search_messages_filtered(Some(&session_id), &keywords, &filter, 10).await.unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].content, "notes about rust #3");
+
+        let unfiltered = db.search_messages_filtered(None, &keywords, &MessageSearchFilter::default(), 10).await.unwrap();
+        assert_eq!(unfiltered.len(), 4);
+    }
+    #[tokio::test]
+    async fn test_search_filter_scopes_to_owning_user() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = MemoryDatabase::new(&dir.path().join("tenant-search.db")).unwrap();
+        let owned_by = |user: &str| Some(SessionMetadata { user_id: Some(user.to_string()), ..Default::default() });
+        let alice = db.conversations.create_session(owned_by("alice")).unwrap();
+        let bob = db.conversations.create_session(owned_by("bob")).unwrap();
+        let unowned = db.conversations.create_session(None).unwrap();
+        for session in [&alice, &bob, &unowned] {
+            db.conversations.store_messages_batch(&session.id, &[
+                ("user".to_string(), format!("rust notes for {}", session.id), 0, 3, 0.5),
+            ]).unwrap();
+        }
+        let keywords = vec!["rust".to_string()];
+
+        let filter = MessageSearchFilter { user_id: Some("alice".to_string()), ..Default::default() };
+        let results = db.search_messages_filtered(None, &keywords, &filter, 10).await.unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].session_id, alice.id);
+        let cross = db.search_messages_filtered(Some(&bob.id), &keywords, &filter, 10).await.unwrap();
+        assert!(cross.is_empty());
+    }
+    #[tokio::test]
+    async fn test_stats_report_embedding_and_snapshot_sizes() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = MemoryDatabase::new(&dir.path().join("stats.db")).unwrap();
+        let session = db.conversations.create_session(None).unwrap();
+        let stored = db.conversations.store_messages_batch(
+            &session.id,
+            &[("user".to_string(), "hello".to_string(), 0, 1, 0.5)],
+        ).unwrap();
+        db.embeddings.store_embedding(&Embedding {
+            id: 0,
+            message_id: stored[0].id,
+            embedding: vec![0.25; 8],
+            embedding_model: "test".to_string(),
+            generated_at: chrono::Utc::now(),
+        }).unwrap();
+        let entry = KVEntry {
+            key_hash: "key".to_string(),
+            key_data: Some(vec![1; 4]),
+            value_data: vec![2; 64],
+            key_type: "attention_key".to_string(),
+            layer_index: 0,
+            head_index: None,
+            importance_score: 0.5,
+            access_count: 0,
+            last_accessed: chrono::Utc::now(),
+        };
+        db.create_kv_snapshot(&session.id, &[entry]).await.unwrap();
+
+        let stats = db.get_stats().unwrap();
+        assert_eq!(stats.embedding_count, 1);
+        assert!(stats.embedding_bytes >= 8 * 4);
+        assert_eq!(stats.snapshot_count, 1);
+        assert!(stats.snapshot_bytes >= 64 + 4);
+        assert!(stats.file_size_bytes > 0);
+    }
+    #[tokio::test]
+    async fn test_search_within_ids_and_saved_searches() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = MemoryDatabase::new(&dir.path().join("saved.db")).unwrap();
+        let session = db.conversations.create_session(None).unwrap();
+        let stored = db.conversations.store_messages_batch(&session.id, &[
+            ("user".to_string(), "rust borrow checker".to_string(), 0, 3, 0.5),
+            ("assistant".to_string(), "rust lifetimes".to_string(), 1, 2, 0.5),
+            ("user".to_string(), "python typing".to_string(), 2, 2, 0.5),
+        ]).unwrap();
+        let keywords = vec!["rust".to_string()];
+
+        let scoped = MessageSearchFilter {
+            message_ids: Some(vec![stored[1].id, stored[2].id]),
+            ..Default::default()
+        };
+        let results = db.search_messages_filtered(None, &keywords, &scoped, 10).await.unwrap();
+        let ids: Vec<_> = results.iter().map(|m| m.id).collect();
+        assert_eq!(ids, vec![stored[1].id]);
+        let empty_scope = MessageSearchFilter { message_ids: Some(Vec::new()), ..Default::default() };
+        assert!(db.search_messages_filtered(None, &keywords, &empty_scope, 10).await.unwrap().is_empty());
+
+        let query = serde_json::json!({ "query": "rust", "within": { "query": "borrow" } });
+        let saved = db.save_search(Some("alice"), "rust refinements", &query).await.unwrap();
+        db.save_search(None, "anonymous", &serde_json::json!({ "query": "x" })).await.unwrap();
+        let listed = db.list_saved_searches(Some("alice")).await.unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].id, saved.id);
+        assert_eq!(listed[0].query, query);
+        assert_eq!(db.list_saved_searches(None).await.unwrap()[0].name, "anonymous");
+    }
+}
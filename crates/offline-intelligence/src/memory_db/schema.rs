@@ -20,6 +20,10 @@ pub struct SessionMetadata {
     pub user_defined: HashMap<String, String>,
     #[serde(default)]
     pub pinned: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub folder: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_id: Option<String>,
 }
 /
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -35,6 +39,67 @@ pub struct StoredMessage {
     pub embedding_generated: bool,
 }
 /
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct GenerationOutcome {
+    pub finish_reason: Option<String>,
+    pub completion_tokens: Option<u32>,
+}
+/
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MessageAlternate {
+    pub choice_index: u32,
+    pub content: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub finish_reason: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub completion_tokens: Option<u32>,
+}
+/
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MessageRevision {
+    pub message_id: i64,
+    pub revision: i32,
+    pub content: String,
+    pub revised_at: DateTime<Utc>,
+}
+/
+#[derive(Debug, Clone, Default)]
+pub struct MessageSearchFilter {
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub role: Option<String>,
+    /
+    pub message_ids: Option<Vec<i64>>,
+    /
+    pub user_id: Option<String>,
+}
+impl MessageSearchFilter {
+    /
+    pub fn matches(&self, role: &str, timestamp: DateTime<Utc>) -> bool {
+        self.role.as_deref().is_none_or(|r| r == role)
+            && self.from.is_none_or(|from| timestamp >= from)
+            && self.to.is_none_or(|to| timestamp <= to)
+    }
+    pub fn allows_message(&self, message_id: i64) -> bool {
+        self.message_ids.as_ref().is_none_or(|ids| ids.contains(&message_id))
+    }
+}
+/
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedSearch {
+    pub id: i64,
+    pub user_id: Option<String>,
+    pub name: String,
+    pub query: serde_json::Value,
+    pub created_at: DateTime<Utc>,
+}
+/
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionExport {
+    pub session: Session,
+    pub messages: Vec<StoredMessage>,
+}
+/
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Summary {
     pub id: i64,
@@ -103,6 +168,12 @@ pub struct DatabaseStats {
     pub total_details: i64,
     pub total_embeddings: i64,
     pub database_size_bytes: i64,
+    pub embedding_count: i64,
+    pub embedding_bytes: i64,
+    pub snapshot_count: i64,
+    pub snapshot_bytes: i64,
+    /
+    pub file_size_bytes: i64,
 }
 /
 pub const SCHEMA_SQL: &str = "
@@ -124,9 +195,40 @@ CREATE TABLE IF NOT EXISTS messages (
     timestamp TIMESTAMP NOT NULL,
     importance_score REAL NOT NULL DEFAULT 0.5,
     embedding_generated BOOLEAN NOT NULL DEFAULT FALSE,
+    finish_reason TEXT,
+    completion_tokens INTEGER,
     FOREIGN KEY (session_id) REFERENCES sessions(id) ON DELETE CASCADE,
     UNIQUE(session_id, message_index)
 );
+-- Message revisions table
+CREATE TABLE IF NOT EXISTS message_revisions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    message_id INTEGER NOT NULL,
+    revision INTEGER NOT NULL,
+    content TEXT NOT NULL,
+    revised_at TIMESTAMP NOT NULL,
+    FOREIGN KEY (message_id) REFERENCES messages(id) ON DELETE CASCADE,
+    UNIQUE(message_id, revision)
+);
+-- Alternate candidates of n>1 generations
+CREATE TABLE IF NOT EXISTS message_alternates (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    message_id INTEGER NOT NULL,
+    choice_index INTEGER NOT NULL,
+    content TEXT NOT NULL,
+    finish_reason TEXT,
+    completion_tokens INTEGER,
+    FOREIGN KEY (message_id) REFERENCES messages(id) ON DELETE CASCADE,
+    UNIQUE(message_id, choice_index)
+);
+-- Saved searches table
+CREATE TABLE IF NOT EXISTS saved_searches (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id TEXT,
+    name TEXT NOT NULL,
+    query TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL
+);
 -- Summaries table
 CREATE TABLE IF NOT EXISTS summaries (
     id INTEGER PRIMARY KEY AUTOINCREMENT,
@@ -167,8 +269,14 @@ CREATE TABLE IF NOT EXISTS embeddings (
 -- Indexes for performance
 CREATE INDEX IF NOT EXISTS idx_messages_session ON messages (session_id);
 CREATE INDEX IF NOT EXISTS idx_messages_timestamp ON messages (timestamp);
+CREATE INDEX IF NOT EXISTS idx_messages_session_timestamp ON messages (session_id, timestamp);
+CREATE INDEX IF NOT EXISTS idx_messages_embedding_generated ON messages (embedding_generated);
+CREATE INDEX IF NOT EXISTS idx_message_revisions_message ON message_revisions (message_id);
+CREATE INDEX IF NOT EXISTS idx_message_alternates_message ON message_alternates (message_id);
+CREATE INDEX IF NOT EXISTS idx_saved_searches_user ON saved_searches (user_id);
 CREATE INDEX IF NOT EXISTS idx_summaries_session ON summaries (session_id);
 CREATE INDEX IF NOT EXISTS idx_details_session ON details (session_id);
 CREATE INDEX IF NOT EXISTS idx_details_type ON details (detail_type);
+CREATE INDEX IF NOT EXISTS idx_details_message ON details (message_id);
 CREATE INDEX IF NOT EXISTS idx_embeddings_message ON embeddings (message_id);
 ";
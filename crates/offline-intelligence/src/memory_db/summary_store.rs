@@ -53,7 +53,7 @@ impl SummaryStore {
     /
     pub fn get_session_summaries(&self, session_id: &str) -> anyhow::Result<Vec<Summary>> {
         let conn = self.get_conn()?;
-        let mut stmt = conn.prepare(
+        let mut stmt = conn.prepare_cached(
             "SELECT id, session_id, message_range_start, message_range_end, summary_text,
              compression_ratio, key_topics, generated_at
              FROM summaries WHERE session_id = ?1 ORDER BY generated_at DESC"
@@ -71,7 +71,7 @@ impl SummaryStore {
     /
     pub fn get_summary_for_range(&self, session_id: &str, start: i32, end: i32) -> anyhow::Result<Option<Summary>> {
         let conn = self.get_conn()?;
-        let mut stmt = conn.prepare(
+        let mut stmt = conn.prepare_cached(
             "SELECT id, session_id, message_range_start, message_range_end, summary_text,
              compression_ratio, key_topics, generated_at
              FROM summaries WHERE session_id = ?1 AND message_range_start = ?2 AND message_range_end = ?3"
@@ -125,7 +125,7 @@ impl SummaryStore {
         let conn = self.get_conn()?;
 
 
-        let mut stmt = conn.prepare(
+        let mut stmt = conn.prepare_cached(
             "SELECT id FROM summaries
              WHERE session_id = ?1
              ORDER BY generated_at DESC
@@ -155,6 +155,46 @@ impl SummaryStore {
         Ok(deleted)
     }
     /
+    /
+    pub fn search_summaries(
+        &self,
+        session_id: Option<&str>,
+        keywords: &[String],
+        limit: usize,
+    ) -> anyhow::Result<Vec<Summary>> {
+        if keywords.is_empty() {
+            return Ok(Vec::new());
+        }
+        let conn = self.get_conn()?;
+
+        let mut args: Vec<String> = Vec::new();
+        let mut clauses: Vec<String> = Vec::new();
+        for keyword in keywords {
+            args.push(format!("%{}%", keyword.to_lowercase()));
+            let n = args.len();
+            clauses.push(format!("LOWER(summary_text) LIKE ?{n} OR LOWER(key_topics) LIKE ?{n}"));
+        }
+        let mut query = format!(
+            "SELECT id, session_id, message_range_start, message_range_end, summary_text,
+             compression_ratio, key_topics, generated_at
+             FROM summaries WHERE ({})",
+            clauses.join(" OR ")
+        );
+        if let Some(session_id) = session_id {
+            args.push(session_id.to_string());
+            query.push_str(&format!(" AND session_id = ?{}", args.len()));
+        }
+        query.push_str(&format!(" ORDER BY generated_at DESC LIMIT {}", limit));
+
+        let mut stmt = conn.prepare(&query)?;
+        let mut rows = stmt.query(rusqlite::params_from_iter(args))?;
+        let mut summaries = Vec::new();
+        while let Some(row) = rows.next()? {
+            summaries.push(self.row_to_summary(row)?);
+        }
+        Ok(summaries)
+    }
+    /
     fn row_to_summary(&self, row: &Row) -> anyhow::Result<Summary> {
         let key_topics_json: String = row.get(6)?;
         let key_topics: Vec<String> = serde_json::from_str(&key_topics_json)
@@ -176,3 +216,36 @@ impl SummaryStore {
         })
     }
 }
+#[cfg(test)]
+mod tests {
+    use crate::memory_db::{MemoryDatabase, Summary};
+    use chrono::Utc;
+    #[test]
+    fn test_search_summaries_matches_text_or_topics() {
+        let db = MemoryDatabase::new_in_memory().unwrap();
+        let planning = db.conversations.create_session(None).unwrap();
+        let other = db.conversations.create_session(None).unwrap();
+        let summary = |session_id: &str, text: &str, topics: &[&str]| Summary {
+            id: 0,
+            session_id: session_id.to_string(),
+            message_range_start: 0,
+            message_range_end: 40,
+            summary_text: text.to_string(),
+            compression_ratio: 0.1,
+            key_topics: topics.iter().map(|t| t.to_string()).collect(),
+            generated_at: Utc::now(),
+        };
+        db.summaries.store_summary(&summary(&planning.id, "Agreed on a blue-green rollout.", &["Deployment", "release"])).unwrap();
+        db.summaries.store_summary(&summary(&other.id, "Discussed the quarterly plan.", &["budget"])).unwrap();
+        db.summaries.store_summary(&summary(&other.id, "Talked about lunch.", &["food"])).unwrap();
+
+        let keywords = vec!["deployment".to_string(), "plan".to_string()];
+        let hits = db.summaries.search_summaries(None, &keywords, 10).unwrap();
+        assert_eq!(hits.len(), 2);
+
+        let scoped = db.summaries.search_summaries(Some(&planning.id), &keywords, 10).unwrap();
+        assert_eq!(scoped.len(), 1);
+        assert_eq!(scoped[0].key_topics, vec!["Deployment".to_string(), "release".to_string()]);
+        assert!(db.summaries.search_summaries(None, &[], 10).unwrap().is_empty());
+    }
+}
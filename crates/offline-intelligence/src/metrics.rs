@@ -1,9 +1,10 @@
 ﻿
-use prometheus::{Encoder, TextEncoder, Registry, IntCounterVec, IntGauge, Histogram};
+use prometheus::{Encoder, TextEncoder, Registry, IntCounter, IntCounterVec, IntGauge, IntGaugeVec, Histogram};
 use lazy_static::lazy_static;
 use std::sync::OnceLock;
 use axum::response::IntoResponse;
 use axum::http::StatusCode;
+use crate::shared_state::CountersSnapshot;
 lazy_static! {
     static ref REGISTRY: Registry = Registry::new();
 }
@@ -11,6 +12,14 @@ static REQ_COUNTER: OnceLock<IntCounterVec> = OnceLock::new();
 static ACTIVE_SESSIONS: OnceLock<IntGauge> = OnceLock::new();
 static QUEUE_DEPTH: OnceLock<IntGauge> = OnceLock::new();
 static QUEUE_WAIT_TIME: OnceLock<Histogram> = OnceLock::new();
+static SHARED_COUNTERS: OnceLock<IntGaugeVec> = OnceLock::new();
+static CONTEXT_OPTIMIZATIONS: OnceLock<IntCounter> = OnceLock::new();
+static CONTEXT_MESSAGES: OnceLock<IntCounterVec> = OnceLock::new();
+static CONTEXT_TOKENS_SAVED: OnceLock<IntCounter> = OnceLock::new();
+static CONTEXT_TIER_USAGE: OnceLock<IntCounterVec> = OnceLock::new();
+static CONTEXT_RETRIEVAL_LATENCY: OnceLock<Histogram> = OnceLock::new();
+static ACTIVE_STREAMS: OnceLock<IntGauge> = OnceLock::new();
+static STREAM_REJECTIONS: OnceLock<IntCounter> = OnceLock::new();
 pub fn init_metrics() {
 
     let req_counter = REQ_COUNTER.get_or_init(|| {
@@ -34,10 +43,54 @@ pub fn init_metrics() {
             "Time spent waiting in queue"
         )).unwrap()
     });
+    let shared_counters = SHARED_COUNTERS.get_or_init(|| {
+        IntGaugeVec::new(
+            prometheus::opts!("shared_state_counters", "Shared state counter values"),
+            &["counter"]
+        ).unwrap()
+    });
     REGISTRY.register(Box::new(req_counter.clone())).ok();
     REGISTRY.register(Box::new(active_sessions.clone())).ok();
     REGISTRY.register(Box::new(queue_depth.clone())).ok();
     REGISTRY.register(Box::new(queue_wait_time.clone())).ok();
+    let context_optimizations = CONTEXT_OPTIMIZATIONS.get_or_init(|| {
+        IntCounter::new("context_optimizations_total", "Context optimizations performed").unwrap()
+    });
+    let context_messages = CONTEXT_MESSAGES.get_or_init(|| {
+        IntCounterVec::new(
+            prometheus::opts!("context_messages_total", "Messages before and after context optimization"),
+            &["stage"]
+        ).unwrap()
+    });
+    let context_tokens_saved = CONTEXT_TOKENS_SAVED.get_or_init(|| {
+        IntCounter::new("context_tokens_saved_total", "Prompt tokens removed by context optimization").unwrap()
+    });
+    let context_tier_usage = CONTEXT_TIER_USAGE.get_or_init(|| {
+        IntCounterVec::new(
+            prometheus::opts!("context_tier_usage_total", "Memory tiers used by context optimization"),
+            &["tier"]
+        ).unwrap()
+    });
+    let context_retrieval_latency = CONTEXT_RETRIEVAL_LATENCY.get_or_init(|| {
+        Histogram::with_opts(prometheus::HistogramOpts::new(
+            "context_retrieval_latency_seconds",
+            "Time spent retrieving context for optimization"
+        )).unwrap()
+    });
+    REGISTRY.register(Box::new(shared_counters.clone())).ok();
+    REGISTRY.register(Box::new(context_optimizations.clone())).ok();
+    REGISTRY.register(Box::new(context_messages.clone())).ok();
+    REGISTRY.register(Box::new(context_tokens_saved.clone())).ok();
+    REGISTRY.register(Box::new(context_tier_usage.clone())).ok();
+    REGISTRY.register(Box::new(context_retrieval_latency.clone())).ok();
+    let active_streams = ACTIVE_STREAMS.get_or_init(|| {
+        IntGauge::new("active_streams", "MAX_CONCURRENT_STREAMS slots in use, one per backend request").unwrap()
+    });
+    let stream_rejections = STREAM_REJECTIONS.get_or_init(|| {
+        IntCounter::new("stream_rejections_total", "Generations rejected because all stream slots were busy").unwrap()
+    });
+    REGISTRY.register(Box::new(active_streams.clone())).ok();
+    REGISTRY.register(Box::new(stream_rejections.clone())).ok();
 }
 pub fn inc_request(route: &str, status: &str) {
     if let Some(counter) = REQ_COUNTER.get() {
@@ -54,6 +107,21 @@ pub fn dec_sessions() {
         gauge.dec();
     }
 }
+pub fn inc_active_streams(slots: u32) {
+    if let Some(gauge) = ACTIVE_STREAMS.get() {
+        gauge.add(slots as i64);
+    }
+}
+pub fn dec_active_streams(slots: u32) {
+    if let Some(gauge) = ACTIVE_STREAMS.get() {
+        gauge.sub(slots as i64);
+    }
+}
+pub fn inc_stream_rejections() {
+    if let Some(counter) = STREAM_REJECTIONS.get() {
+        counter.inc();
+    }
+}
 pub fn inc_queue() {
     if let Some(gauge) = QUEUE_DEPTH.get() {
         gauge.inc();
@@ -69,6 +137,41 @@ pub fn observe_queue_wait(duration: f64) {
         histogram.observe(duration);
     }
 }
+pub fn record_counters(snapshot: &CountersSnapshot) {
+    if let Some(gauges) = SHARED_COUNTERS.get() {
+        gauges.with_label_values(&["total_requests"]).set(snapshot.total_requests as i64);
+        gauges.with_label_values(&["active_sessions"]).set(snapshot.active_sessions as i64);
+        gauges.with_label_values(&["processed_messages"]).set(snapshot.processed_messages as i64);
+        gauges.with_label_values(&["cache_hits"]).set(snapshot.cache_hits as i64);
+        gauges.with_label_values(&["cache_misses"]).set(snapshot.cache_misses as i64);
+    }
+}
+pub fn record_context_optimization(
+    input_messages: usize,
+    output_messages: usize,
+    tokens_saved: usize,
+    tiers: &[&str],
+    retrieval_seconds: f64,
+) {
+    if let Some(counter) = CONTEXT_OPTIMIZATIONS.get() {
+        counter.inc();
+    }
+    if let Some(counter) = CONTEXT_MESSAGES.get() {
+        counter.with_label_values(&["input"]).inc_by(input_messages as u64);
+        counter.with_label_values(&["output"]).inc_by(output_messages as u64);
+    }
+    if let Some(counter) = CONTEXT_TOKENS_SAVED.get() {
+        counter.inc_by(tokens_saved as u64);
+    }
+    if let Some(counter) = CONTEXT_TIER_USAGE.get() {
+        for tier in tiers {
+            counter.with_label_values(&[tier]).inc();
+        }
+    }
+    if let Some(histogram) = CONTEXT_RETRIEVAL_LATENCY.get() {
+        histogram.observe(retrieval_seconds);
+    }
+}
 pub async fn get_metrics() -> impl IntoResponse {
     let encoder = TextEncoder::new();
     let metric_families = REGISTRY.gather();
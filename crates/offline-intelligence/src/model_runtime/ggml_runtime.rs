@@ -31,7 +31,7 @@ impl ModelRuntime for GGMLRuntime {
     async fn is_ready(&self) -> bool {
         self.inner.is_ready().await
     }
-    async fn health_check(&self) -> anyhow::Result<String> {
+    async fn health_check(&self) -> anyhow::Result<HealthStatus> {
         self.inner.health_check().await
     }
     fn base_url(&self) -> String {
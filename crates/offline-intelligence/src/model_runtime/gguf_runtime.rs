@@ -3,15 +3,32 @@
 //! This adapter spawns the llama-server process and proxies requests via HTTP.
 use async_trait::async_trait;
 use super::runtime_trait::*;
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
 use std::process::{Child, Command, Stdio};
-use std::time::Duration;
-use tracing::{info, warn, error};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{info, warn, error, debug};
 use tokio::time::sleep;
+const MAX_LOG_LINES: usize = 200;
+/
+const MANAGED_FLAGS: &[&str] = &[
+    "--model", "-m",
+    "--host",
+    "--port",
+    "--ctx-size", "-c",
+    "--batch-size", "-b",
+    "--threads", "-t",
+    "--n-gpu-layers", "-ngl", "--gpu-layers",
+];
 pub struct GGUFRuntime {
     config: Option<RuntimeConfig>,
     server_process: Option<Child>,
     http_client: reqwest::Client,
     base_url: String,
+    recent_logs: Arc<Mutex<VecDeque<String>>>,
+    started_at: Option<Instant>,
 }
 impl GGUFRuntime {
     pub fn new() -> Self {
@@ -23,10 +40,37 @@ impl GGUFRuntime {
                 .build()
                 .unwrap_or_default(),
             base_url: String::new(),
+            recent_logs: Arc::new(Mutex::new(VecDeque::with_capacity(MAX_LOG_LINES))),
+            started_at: None,
+        }
+    }
+    /
+    fn forward_output<R: Read + Send + 'static>(&self, stream: R, source: &'static str) {
+        let logs = Arc::clone(&self.recent_logs);
+        let spawned = std::thread::Builder::new()
+            .name(format!("llama-server-{}", source))
+            .spawn(move || {
+                for line in BufReader::new(stream).lines() {
+                    let line = match line {
+                        Ok(line) => line,
+                        Err(_) => break,
+                    };
+                    debug!(target: "llama_server", "[{}] {}", source, line);
+                    if let Ok(mut logs) = logs.lock() {
+                        if logs.len() >= MAX_LOG_LINES {
+                            logs.pop_front();
+                        }
+                        logs.push_back(line);
+                    }
+                }
+            });
+        if let Err(e) = spawned {
+            warn!("Failed to spawn llama-server {} reader: {}", source, e);
         }
     }
     /
     async fn start_server(&mut self, config: &RuntimeConfig) -> anyhow::Result<()> {
+        config.validate_passthrough(MANAGED_FLAGS)?;
         let binary_path = config.runtime_binary.as_ref()
             .ok_or_else(|| anyhow::anyhow!("GGUF runtime requires runtime_binary path"))?;
         if !binary_path.exists() {
@@ -50,11 +94,25 @@ impl GGUFRuntime {
             .arg("--threads").arg(config.threads.to_string())
             .arg("--n-gpu-layers").arg(config.gpu_layers.to_string())
             .stdout(Stdio::piped())
-            .stderr(Stdio::piped());
+            .stderr(Stdio::piped())
+            .args(&config.extra_args)
+            .envs(&config.env);
+        if !config.extra_args.is_empty() {
+            info!("  Extra args: {}", config.extra_args.join(" "));
+        }
 
-        let child = cmd.spawn()
+        let mut child = cmd.spawn()
             .map_err(|e| anyhow::anyhow!("Failed to spawn llama-server: {}", e))?;
+
+
+        if let Some(stdout) = child.stdout.take() {
+            self.forward_output(stdout, "stdout");
+        }
+        if let Some(stderr) = child.stderr.take() {
+            self.forward_output(stderr, "stderr");
+        }
         self.server_process = Some(child);
+        self.started_at = Some(Instant::now());
         self.base_url = format!("http:
         info!("llama-server process started, waiting for health check...");
 
@@ -73,6 +131,115 @@ impl GGUFRuntime {
         Err(anyhow::anyhow!("llama-server failed to start within 60 seconds"))
     }
 }
+/
+fn slot_availability(slots: &[serde_json::Value]) -> (u32, u32) {
+    let busy = slots.iter()
+        .filter(|slot| match slot.get("is_processing") {
+            Some(processing) => processing.as_bool().unwrap_or(false),
+            // Older llama-server builds report `state`, where 0 means idle.
+            None => slot.get("state").and_then(|s| s.as_u64()).unwrap_or(0) != 0,
+        })
+        .count();
+    (slots.len() as u32, (slots.len() - busy) as u32)
+}
+/
+fn deferred_requests(metrics: &str) -> Option<u32> {
+    metrics.lines()
+        .filter(|line| !line.starts_with('#'))
+        .find_map(|line| line.strip_prefix("llamacpp:requests_deferred "))
+        .and_then(|value| value.trim().parse::<f64>().ok())
+        .map(|value| value as u32)
+}
+/
+/
+pub fn read_gguf_metadata(path: &Path) -> anyhow::Result<ModelInfo> {
+    let mut reader = BufReader::new(std::fs::File::open(path)?);
+    let mut magic = [0u8; 4];
+    reader.read_exact(&mut magic)?;
+    if &magic != b"GGUF" {
+        return Err(anyhow::anyhow!("{} is not a GGUF file", path.display()));
+    }
+    let version = read_u32(&mut reader)?;
+    if version < 2 {
+        return Err(anyhow::anyhow!("Unsupported GGUF version {}", version));
+    }
+    let _tensor_count = read_u64(&mut reader)?;
+    let kv_count = read_u64(&mut reader)?;
+
+    let mut info = ModelInfo::default();
+    let mut lengths = std::collections::HashMap::new();
+    for _ in 0..kv_count {
+        let key = read_gguf_string(&mut reader)?;
+        let value_type = read_u32(&mut reader)?;
+        if key == "general.architecture" && value_type == GGUF_TYPE_STRING {
+            info.architecture = Some(read_gguf_string(&mut reader)?);
+        } else if key.ends_with(".context_length") || key.ends_with(".embedding_length") {
+            let value = match value_type {
+                GGUF_TYPE_UINT32 => read_u32(&mut reader)? as u64,
+                GGUF_TYPE_UINT64 => read_u64(&mut reader)?,
+                other => {
+                    skip_gguf_value(&mut reader, other)?;
+                    continue;
+                }
+            };
+            lengths.insert(key, value);
+        } else {
+            skip_gguf_value(&mut reader, value_type)?;
+        }
+        // Architecture keys precede the (large) tokenizer arrays, so stop once they are known.
+        if let Some(ref arch) = info.architecture {
+            info.context_length = lengths.get(&format!("{}.context_length", arch)).and_then(|&v| u32::try_from(v).ok());
+            info.embedding_dim = lengths.get(&format!("{}.embedding_length", arch)).and_then(|&v| u32::try_from(v).ok());
+            if info.context_length.is_some() && info.embedding_dim.is_some() {
+                break;
+            }
+        }
+    }
+    Ok(info)
+}
+const GGUF_TYPE_UINT32: u32 = 4;
+const GGUF_TYPE_STRING: u32 = 8;
+const GGUF_TYPE_ARRAY: u32 = 9;
+const GGUF_TYPE_UINT64: u32 = 10;
+fn read_u32(reader: &mut impl Read) -> std::io::Result<u32> {
+    let mut buf = [0u8; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+fn read_u64(reader: &mut impl Read) -> std::io::Result<u64> {
+    let mut buf = [0u8; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+fn read_gguf_string(reader: &mut impl Read) -> anyhow::Result<String> {
+    let len = read_u64(reader)?;
+    if len > 1 << 20 {
+        return Err(anyhow::anyhow!("GGUF string of {} bytes is implausibly long", len));
+    }
+    let mut buf = vec![0u8; len as usize];
+    reader.read_exact(&mut buf)?;
+    Ok(String::from_utf8_lossy(&buf).into_owned())
+}
+fn skip_gguf_value(reader: &mut BufReader<std::fs::File>, value_type: u32) -> anyhow::Result<()> {
+    let width: i64 = match value_type {
+        0 | 1 | 7 => 1,
+        2 | 3 => 2,
+        4 | 5 | 6 => 4,
+        10 | 11 | 12 => 8,
+        GGUF_TYPE_STRING => read_u64(reader)? as i64,
+        GGUF_TYPE_ARRAY => {
+            let element_type = read_u32(reader)?;
+            let len = read_u64(reader)?;
+            for _ in 0..len {
+                skip_gguf_value(reader, element_type)?;
+            }
+            return Ok(());
+        }
+        other => return Err(anyhow::anyhow!("Unknown GGUF value type {}", other)),
+    };
+    reader.seek_relative(width)?;
+    Ok(())
+}
 impl Default for GGUFRuntime {
     fn default() -> Self {
         Self::new()
@@ -114,7 +281,7 @@ impl ModelRuntime for GGUFRuntime {
             Err(_) => false,
         }
     }
-    async fn health_check(&self) -> anyhow::Result<String> {
+    async fn health_check(&self) -> anyhow::Result<HealthStatus> {
         if self.base_url.is_empty() {
             return Err(anyhow::anyhow!("Runtime not initialized"));
         }
@@ -123,11 +290,33 @@ impl ModelRuntime for GGUFRuntime {
             .send()
             .await
             .map_err(|e| anyhow::anyhow!("Health check failed: {}", e))?;
-        if resp.status().is_success() {
-            Ok("healthy".to_string())
-        } else {
-            Err(anyhow::anyhow!("Health check returned: {}", resp.status()))
+        let mut status = HealthStatus::ready(self.metadata().runtime_name);
+        status.uptime_seconds = self.started_at.map(|started| started.elapsed().as_secs());
+        if resp.status() == reqwest::StatusCode::SERVICE_UNAVAILABLE {
+            // llama-server answers 503 while the model is still loading.
+            status.healthy = false;
+            status.model_loaded = false;
+            return Ok(status);
         }
+        if !resp.status().is_success() {
+            return Err(anyhow::anyhow!("Health check returned: {}", resp.status()));
+        }
+        // /slots and /metrics can be disabled on the server; missing data is left unset.
+        let slots_url = format!("{}/slots", self.base_url);
+        if let Ok(resp) = self.http_client.get(&slots_url).send().await.and_then(|r| r.error_for_status()) {
+            if let Ok(slots) = resp.json::<Vec<serde_json::Value>>().await {
+                let (total, available) = slot_availability(&slots);
+                status.slots_total = Some(total);
+                status.slots_available = Some(available);
+            }
+        }
+        let metrics_url = format!("{}/metrics", self.base_url);
+        if let Ok(resp) = self.http_client.get(&metrics_url).send().await.and_then(|r| r.error_for_status()) {
+            if let Ok(body) = resp.text().await {
+                status.queue_depth = deferred_requests(&body);
+            }
+        }
+        Ok(status)
     }
     fn base_url(&self) -> String {
         self.base_url.clone()
@@ -245,6 +434,36 @@ impl ModelRuntime for GGUFRuntime {
             supports_streaming: true,
         }
     }
+    async fn model_info(&self) -> anyhow::Result<ModelInfo> {
+        let config = self.config.as_ref()
+            .ok_or_else(|| anyhow::anyhow!("Runtime not initialized"))?;
+        let mut info = read_gguf_metadata(&config.model_path).unwrap_or_else(|e| {
+            warn!("Could not read GGUF header of {}: {}", config.model_path.display(), e);
+            ModelInfo::default()
+        });
+        // /props only reports the runtime's own settings; the model's trained
+        // dimensions are exposed as `meta` on the /v1/models entry.
+        let models_url = format!("{}/v1/models", self.base_url);
+        match self.http_client.get(&models_url).send().await {
+            Ok(resp) if resp.status().is_success() => {
+                let body: serde_json::Value = resp.json().await
+                    .map_err(|e| anyhow::anyhow!("Failed to parse /v1/models response: {}", e))?;
+                let meta = &body["data"][0]["meta"];
+                let as_u32 = |v: &serde_json::Value| v.as_u64().and_then(|n| u32::try_from(n).ok());
+                info.context_length = info.context_length.or_else(|| as_u32(&meta["n_ctx_train"]));
+                info.embedding_dim = info.embedding_dim.or_else(|| as_u32(&meta["n_embd"]));
+                info.param_count = meta["n_params"].as_u64();
+            }
+            Ok(resp) => debug!("llama-server /v1/models returned {}", resp.status()),
+            Err(e) => debug!("llama-server /v1/models unavailable: {}", e),
+        }
+        Ok(info)
+    }
+    fn recent_logs(&self) -> Vec<String> {
+        self.recent_logs.lock()
+            .map(|logs| logs.iter().cloned().collect())
+            .unwrap_or_default()
+    }
 }
 impl Drop for GGUFRuntime {
     fn drop(&mut self) {
@@ -254,5 +473,81 @@ impl Drop for GGUFRuntime {
         }
     }
 }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_health_details_from_slots_and_metrics() {
+        let slots: Vec<serde_json::Value> = serde_json::from_str(
+            r#"[{"id":0,"is_processing":true},{"id":1,"is_processing":false},{"id":2,"state":1},{"id":3,"state":0}]"#
+        ).unwrap();
+        assert_eq!(slot_availability(&slots), (4, 2));
+        let metrics = "# HELP llamacpp:requests_deferred Number of requests deferred.\n\
+                       # TYPE llamacpp:requests_deferred gauge\n\
+                       llamacpp:requests_processing 2\n\
+                       llamacpp:requests_deferred 3\n";
+        assert_eq!(deferred_requests(metrics), Some(3));
+        assert_eq!(deferred_requests("llamacpp:requests_processing 1"), None);
+    }
+    #[test]
+    fn test_passthrough_rejects_managed_flags() {
+        let mut config = RuntimeConfig {
+            extra_args: vec!["--parallel".into(), "4".into(), "--flash-attn".into()],
+            ..Default::default()
+        };
+        config.env.insert("CUDA_VISIBLE_DEVICES".into(), "0".into());
+        assert!(config.validate_passthrough(MANAGED_FLAGS).is_ok());
 
+        config.extra_args = vec!["--port".into(), "9000".into()];
+        assert!(config.validate_passthrough(MANAGED_FLAGS).is_err());
+        config.extra_args = vec!["--ctx-size=4096".into()];
+        assert!(config.validate_passthrough(MANAGED_FLAGS).is_err());
+        config.extra_args = vec!["-ngl".into(), "10".into()];
+        assert!(config.validate_passthrough(MANAGED_FLAGS).is_err());
 
+        config.extra_args.clear();
+        config.env.insert("BAD=NAME".into(), "1".into());
+        assert!(config.validate_passthrough(MANAGED_FLAGS).is_err());
+    }
+    fn gguf_string(out: &mut Vec<u8>, s: &str) {
+        out.extend((s.len() as u64).to_le_bytes());
+        out.extend(s.as_bytes());
+    }
+    #[test]
+    fn test_read_gguf_metadata_from_header() {
+        let mut bytes = b"GGUF".to_vec();
+        bytes.extend(3u32.to_le_bytes());
+        bytes.extend(0u64.to_le_bytes());
+        bytes.extend(5u64.to_le_bytes());
+        gguf_string(&mut bytes, "general.architecture");
+        bytes.extend(GGUF_TYPE_STRING.to_le_bytes());
+        gguf_string(&mut bytes, "qwen2");
+        gguf_string(&mut bytes, "general.file_type");
+        bytes.extend(GGUF_TYPE_UINT32.to_le_bytes());
+        bytes.extend(15u32.to_le_bytes());
+        gguf_string(&mut bytes, "tokenizer.ggml.tokens");
+        bytes.extend(GGUF_TYPE_ARRAY.to_le_bytes());
+        bytes.extend(GGUF_TYPE_STRING.to_le_bytes());
+        bytes.extend(2u64.to_le_bytes());
+        gguf_string(&mut bytes, "<s>");
+        gguf_string(&mut bytes, "</s>");
+        gguf_string(&mut bytes, "qwen2.context_length");
+        bytes.extend(GGUF_TYPE_UINT32.to_le_bytes());
+        bytes.extend(32768u32.to_le_bytes());
+        gguf_string(&mut bytes, "qwen2.embedding_length");
+        bytes.extend(GGUF_TYPE_UINT64.to_le_bytes());
+        bytes.extend(896u64.to_le_bytes());
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("model.gguf");
+        std::fs::write(&path, &bytes).unwrap();
+        let info = read_gguf_metadata(&path).unwrap();
+        assert_eq!(info.architecture.as_deref(), Some("qwen2"));
+        assert_eq!(info.context_length, Some(32768));
+        assert_eq!(info.embedding_dim, Some(896));
+        assert_eq!(info.param_count, None);
+
+        std::fs::write(&path, b"GGML").unwrap();
+        assert!(read_gguf_metadata(&path).is_err());
+    }
+}
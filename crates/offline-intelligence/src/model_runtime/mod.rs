@@ -16,7 +16,7 @@ pub mod ggml_runtime;
 pub mod coreml_runtime;
 pub mod format_detector;
 pub mod runtime_manager;
-pub use runtime_trait::{ModelRuntime, ModelFormat, RuntimeConfig, InferenceRequest, InferenceResponse};
+pub use runtime_trait::{ModelRuntime, ModelFormat, ModelInfo, RegisteredModel, HealthStatus, RuntimeConfig, InferenceRequest, InferenceResponse};
 pub use gguf_runtime::GGUFRuntime;
 pub use onnx_runtime::ONNXRuntime;
 pub use tensorrt_runtime::TensorRTRuntime;
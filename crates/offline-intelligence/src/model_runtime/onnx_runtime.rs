@@ -96,7 +96,7 @@ impl ModelRuntime for ONNXRuntime {
             Err(_) => false,
         }
     }
-    async fn health_check(&self) -> anyhow::Result<String> {
+    async fn health_check(&self) -> anyhow::Result<HealthStatus> {
         if self.base_url.is_empty() {
             return Err(anyhow::anyhow!("Runtime not initialized"));
         }
@@ -106,7 +106,7 @@ impl ModelRuntime for ONNXRuntime {
             .await
             .map_err(|e| anyhow::anyhow!("Health check failed: {}", e))?;
         if resp.status().is_success() {
-            Ok("healthy".to_string())
+            Ok(HealthStatus::ready(self.metadata().runtime_name))
         } else {
             Err(anyhow::anyhow!("Health check returned: {}", resp.status()))
         }
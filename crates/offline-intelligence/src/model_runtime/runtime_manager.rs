@@ -94,7 +94,7 @@ impl RuntimeManager {
         }
     }
     /
-    pub async fn health_check(&self) -> anyhow::Result<String> {
+    pub async fn health_check(&self) -> anyhow::Result<HealthStatus> {
         let holder = self.holder.load();
         match holder.runtime.as_ref() {
             Some(r) => r.health_check().await,
@@ -107,6 +107,41 @@ impl RuntimeManager {
         holder.runtime.as_ref().map(|r| r.metadata())
     }
     /
+    pub async fn model_info(&self) -> anyhow::Result<ModelInfo> {
+        let holder = self.holder.load();
+        match holder.runtime.as_ref() {
+            Some(r) => r.model_info().await,
+            None => Err(anyhow::anyhow!("No runtime initialized")),
+        }
+    }
+    /
+    pub async fn registered_models(&self) -> Vec<RegisteredModel> {
+        let holder = self.holder.load();
+        let (Some(runtime), Some(config)) = (holder.runtime.as_ref(), holder.config.as_ref()) else {
+            return Vec::new();
+        };
+        let metadata = runtime.metadata();
+        let id = config.model_path.file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_else(|| config.model_path.display().to_string());
+        // Not every runtime reports metadata; the configured window is the next best answer.
+        let context_length = runtime.model_info().await.ok()
+            .and_then(|info| info.context_length)
+            .or(Some(config.context_size).filter(|&size| size > 0));
+        vec![RegisteredModel {
+            id,
+            format: metadata.format,
+            runtime_name: metadata.runtime_name,
+            context_length,
+            ready: runtime.is_ready().await,
+        }]
+    }
+    /
+    pub fn recent_logs(&self) -> Vec<String> {
+        let holder = self.holder.load();
+        holder.runtime.as_ref().map(|r| r.recent_logs()).unwrap_or_default()
+    }
+    /
     pub async fn shutdown(&self) -> anyhow::Result<()> {
 
         let old_holder = self.holder.swap(Arc::new(RuntimeHolder {
@@ -176,6 +211,11 @@ mod tests {
         assert!(!manager.is_ready().await);
     }
     #[tokio::test]
+    async fn test_no_registered_models_before_initialization() {
+        let manager = RuntimeManager::new();
+        assert!(manager.registered_models().await.is_empty());
+    }
+    #[tokio::test]
     async fn test_format_detection() {
         let manager = RuntimeManager::new();
 
@@ -1,5 +1,6 @@
 ﻿use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::path::PathBuf;
 /
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
@@ -64,6 +65,12 @@ pub struct RuntimeConfig {
     pub runtime_binary: Option<PathBuf>,
     /
     pub extra_config: serde_json::Value,
+    /
+    #[serde(default)]
+    pub extra_args: Vec<String>,
+    /
+    #[serde(default)]
+    pub env: HashMap<String, String>,
 }
 impl Default for RuntimeConfig {
     fn default() -> Self {
@@ -78,9 +85,31 @@ impl Default for RuntimeConfig {
             gpu_layers: 0,
             runtime_binary: None,
             extra_config: serde_json::json!({}),
+            extra_args: Vec::new(),
+            env: HashMap::new(),
         }
     }
 }
+impl RuntimeConfig {
+    /
+    pub fn validate_passthrough(&self, managed_flags: &[&str]) -> anyhow::Result<()> {
+        for arg in &self.extra_args {
+            let flag = arg.split('=').next().unwrap_or(arg);
+            if managed_flags.contains(&flag) {
+                return Err(anyhow::anyhow!(
+                    "extra_args may not override managed flag '{}'",
+                    flag
+                ));
+            }
+        }
+        for key in self.env.keys() {
+            if key.is_empty() || key.contains('=') || key.contains('\0') {
+                return Err(anyhow::anyhow!("Invalid environment variable name: {:?}", key));
+            }
+        }
+        Ok(())
+    }
+}
 /
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct InferenceRequest {
@@ -107,6 +136,29 @@ pub struct InferenceResponse {
     pub finish_reason: Option<String>,
 }
 /
+/
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct HealthStatus {
+    pub healthy: bool,
+    pub model_loaded: bool,
+    pub runtime: String,
+    pub slots_total: Option<u32>,
+    pub slots_available: Option<u32>,
+    pub uptime_seconds: Option<u64>,
+    pub queue_depth: Option<u32>,
+}
+impl HealthStatus {
+    /
+    pub fn ready(runtime: impl Into<String>) -> Self {
+        Self {
+            healthy: true,
+            model_loaded: true,
+            runtime: runtime.into(),
+            ..Default::default()
+        }
+    }
+}
+/
 #[async_trait]
 pub trait ModelRuntime: Send + Sync {
     /
@@ -116,7 +168,7 @@ pub trait ModelRuntime: Send + Sync {
     /
     async fn is_ready(&self) -> bool;
     /
-    async fn health_check(&self) -> anyhow::Result<String>;
+    async fn health_check(&self) -> anyhow::Result<HealthStatus>;
     /
     fn base_url(&self) -> String;
     /
@@ -137,6 +189,14 @@ pub trait ModelRuntime: Send + Sync {
     async fn shutdown(&mut self) -> anyhow::Result<()>;
     /
     fn metadata(&self) -> RuntimeMetadata;
+    /
+    async fn model_info(&self) -> anyhow::Result<ModelInfo> {
+        Err(anyhow::anyhow!("{} does not report model metadata", self.metadata().runtime_name))
+    }
+    /
+    fn recent_logs(&self) -> Vec<String> {
+        Vec::new()
+    }
 }
 /
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -147,5 +207,25 @@ pub struct RuntimeMetadata {
     pub supports_gpu: bool,
     pub supports_streaming: bool,
 }
+/
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RegisteredModel {
+    /
+    pub id: String,
+    pub format: ModelFormat,
+    pub runtime_name: String,
+    pub context_length: Option<u32>,
+    pub ready: bool,
+}
+/
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ModelInfo {
+    /
+    pub context_length: Option<u32>,
+    /
+    pub embedding_dim: Option<u32>,
+    pub architecture: Option<String>,
+    pub param_count: Option<u64>,
+}
 
 
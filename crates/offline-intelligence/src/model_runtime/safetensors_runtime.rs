@@ -85,7 +85,7 @@ impl ModelRuntime for SafetensorsRuntime {
             Err(_) => false,
         }
     }
-    async fn health_check(&self) -> anyhow::Result<String> {
+    async fn health_check(&self) -> anyhow::Result<HealthStatus> {
         if self.base_url.is_empty() {
             return Err(anyhow::anyhow!("Runtime not initialized"));
         }
@@ -93,7 +93,7 @@ impl ModelRuntime for SafetensorsRuntime {
         let resp = self.http_client.get(&health_url).send().await
             .map_err(|e| anyhow::anyhow!("Health check failed: {}", e))?;
         if resp.status().is_success() {
-            Ok("healthy".to_string())
+            Ok(HealthStatus::ready(self.metadata().runtime_name))
         } else {
             Err(anyhow::anyhow!("Health check returned: {}", resp.status()))
         }
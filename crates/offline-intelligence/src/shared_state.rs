@@ -2,14 +2,18 @@
 //! This module provides the core shared memory infrastructure that enables
 //! efficient communication between worker threads while maintaining thread safety.
 use std::sync::{Arc, RwLock, atomic::{AtomicUsize, Ordering}};
-use dashmap::DashMap;
+use arc_swap::ArcSwap;
+use dashmap::{mapref::entry::Entry, DashMap};
+use serde::Serialize;
+use tokio_util::sync::CancellationToken;
 use tracing::info;
 use crate::{
     config::Config,
     context_engine::ContextOrchestrator,
     memory_db::MemoryDatabase,
     cache_management::KVCacheManager,
-    worker_threads::LLMWorker,
+    worker_threads::{DatabaseWorker, LLMWorker},
+    model_runtime::{ModelInfo, RuntimeManager},
 };
 /
 pub struct SharedSystemState {
@@ -18,17 +22,25 @@ pub struct SharedSystemState {
     /
     pub llm_runtime: Arc<RwLock<Option<LLMRuntime>>>,
     /
-    pub cache_manager: Arc<RwLock<Option<Arc<KVCacheManager>>>>,
+    pub cache_manager: Arc<RwLock<Option<Arc<tokio::sync::Mutex<KVCacheManager>>>>>,
     /
     pub database_pool: Arc<MemoryDatabase>,
     /
-    pub config: Arc<Config>,
+    pub config: ArcSwap<Config>,
     /
     pub counters: Arc<AtomicCounters>,
     /
     pub context_orchestrator: Arc<tokio::sync::RwLock<Option<ContextOrchestrator>>>,
     /
     pub llm_worker: Arc<LLMWorker>,
+    /
+    pub runtime_manager: Arc<RwLock<Option<Arc<RuntimeManager>>>>,
+    /
+    pub model_info: Arc<RwLock<Option<ModelInfo>>>,
+    /
+    pub active_generations: DashMap<String, (usize, CancellationToken)>,
+    /
+    pub stream_slots: Arc<tokio::sync::Semaphore>,
 }
 /
 pub struct ConversationHierarchy {
@@ -83,6 +95,24 @@ impl AtomicCounters {
     pub fn inc_cache_miss(&self) -> usize {
         self.cache_misses.fetch_add(1, Ordering::Relaxed) + 1
     }
+    pub fn snapshot(&self) -> CountersSnapshot {
+        CountersSnapshot {
+            total_requests: self.total_requests.load(Ordering::Relaxed),
+            active_sessions: self.active_sessions.load(Ordering::Relaxed),
+            processed_messages: self.processed_messages.load(Ordering::Relaxed),
+            cache_hits: self.cache_hits.load(Ordering::Relaxed),
+            cache_misses: self.cache_misses.load(Ordering::Relaxed),
+        }
+    }
+}
+/
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct CountersSnapshot {
+    pub total_requests: usize,
+    pub active_sessions: usize,
+    pub processed_messages: usize,
+    pub cache_hits: usize,
+    pub cache_misses: usize,
 }
 /
 pub struct LLMRuntime {
@@ -103,23 +133,48 @@ impl SharedSystemState {
             message_queues: DashMap::new(),
             counters: Arc::new(AtomicCounters::new()),
         });
-        let config = Arc::new(config);
         let counters = Arc::new(AtomicCounters::new());
+        let stream_slots = Arc::new(tokio::sync::Semaphore::new(config.max_concurrent_streams.max(1) as usize));
 
         let backend_url = config.backend_url.clone();
-        let llm_worker = Arc::new(LLMWorker::new_with_backend(backend_url));
+        let llm_worker = Arc::new(
+            LLMWorker::new_with_backend(backend_url)
+                .with_slot_count(config.llama_slots)
+                .with_backend_api(config.backend_api)
+                .with_embedding_backend(config.embedding_backend_url.clone(), config.embedding_model.clone())
+        );
         Ok(Self {
             conversations,
             llm_runtime: Arc::new(RwLock::new(None)),
             cache_manager: Arc::new(RwLock::new(None)),
             database_pool: database,
-            config,
+            config: ArcSwap::from_pointee(config),
             counters,
             context_orchestrator: Arc::new(tokio::sync::RwLock::new(None)),
             llm_worker,
+            runtime_manager: Arc::new(RwLock::new(None)),
+            model_info: Arc::new(RwLock::new(None)),
+            active_generations: DashMap::new(),
+            stream_slots,
         })
     }
     /
+    /
+    pub fn clamp_context_tokens(&self, requested: usize) -> usize {
+        let model_limit = self.model_info.read().ok()
+            .and_then(|info| info.as_ref().and_then(|i| i.context_length));
+        let ctx_size = self.config.load().ctx_size;
+        let limit = match model_limit {
+            Some(context_length) => context_length.min(ctx_size),
+            None => ctx_size,
+        };
+        requested.min(limit as usize)
+    }
+    /
+    pub fn database_pool_stats(&self) -> crate::memory_db::PoolStats {
+        self.database_pool.pool_stats()
+    }
+    /
     pub fn set_llm_worker(&self, _worker: Arc<LLMWorker>) {
 
 
@@ -130,18 +185,35 @@ impl SharedSystemState {
     pub fn initialize_llm_runtime(&self) -> anyhow::Result<()> {
         let mut runtime_guard = self.llm_runtime.try_write()
             .map_err(|_| anyhow::anyhow!("Failed to acquire LLM runtime write lock"))?;
+        let config = self.config.load();
         let runtime = LLMRuntime {
-            model_path: self.config.model_path.clone(),
-            context_size: self.config.ctx_size,
-            batch_size: self.config.batch_size,
-            threads: self.config.threads,
-            gpu_layers: self.config.gpu_layers,
+            model_path: config.model_path.clone(),
+            context_size: config.ctx_size,
+            batch_size: config.batch_size,
+            threads: config.threads,
+            gpu_layers: config.gpu_layers,
         };
         *runtime_guard = Some(runtime);
         info!("LLM runtime initialized");
         Ok(())
     }
     /
+    /
+    pub async fn update_orchestrator_config(
+        &self,
+        update: impl FnOnce(&mut crate::context_engine::OrchestratorConfig),
+    ) -> Option<crate::context_engine::OrchestratorConfig> {
+        // Read, modify and swap under one write guard so concurrent updates cannot drop each other's fields.
+        let mut guard = self.context_orchestrator.write().await;
+        let current = guard.as_ref()?;
+        let mut config = current.config().clone();
+        update(&mut config);
+        let reloaded = current.with_config(config);
+        let applied = reloaded.config().clone();
+        *guard = Some(reloaded);
+        Some(applied)
+    }
+    /
     pub async fn get_or_create_session(&self, session_id: &str) -> Arc<RwLock<SessionData>> {
 
         if let Some(session) = self.conversations.sessions.get(session_id) {
@@ -159,6 +231,41 @@ impl SharedSystemState {
         new_session
     }
     /
+    pub fn clear_session_state(&self, session_id: &str) {
+        if let Some(session) = self.conversations.sessions.get(session_id) {
+            if let Ok(mut data) = session.write() {
+                data.messages.clear();
+            }
+        }
+        self.conversations.message_queues.remove(session_id);
+    }
+    /
+    pub fn register_generation(&self, session_id: &str, generation_id: usize) -> Option<CancellationToken> {
+        match self.active_generations.entry(session_id.to_string()) {
+            // Leave the running generation alone; the caller decides whether to stop it.
+            Entry::Occupied(_) => None,
+            Entry::Vacant(slot) => {
+                let token = CancellationToken::new();
+                slot.insert((generation_id, token.clone()));
+                Some(token)
+            }
+        }
+    }
+    /
+    pub fn cancel_generation(&self, session_id: &str) -> bool {
+        match self.active_generations.remove(session_id) {
+            Some((_, (_, token))) => {
+                token.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+    /
+    pub fn finish_generation(&self, session_id: &str, generation_id: usize) {
+        self.active_generations.remove_if(session_id, |_, (id, _)| *id == generation_id);
+    }
+    /
     pub fn queue_message(&self, session_id: &str, message: crate::memory::Message) -> bool {
         let queue = self.conversations.message_queues
             .entry(session_id.to_string())
@@ -196,18 +303,64 @@ pub struct UnifiedAppState {
     pub shared_state: Arc<SharedSystemState>,
     pub context_orchestrator: Arc<tokio::sync::RwLock<Option<ContextOrchestrator>>>,
     pub llm_worker: Arc<LLMWorker>,
+    /
+    pub database_worker: Arc<DatabaseWorker>,
 }
 impl UnifiedAppState {
-    pub fn new(shared_state: Arc<SharedSystemState>) -> Self {
+    pub fn new(shared_state: Arc<SharedSystemState>, database_worker: Arc<DatabaseWorker>) -> Self {
         let context_orchestrator = shared_state.context_orchestrator.clone();
         let llm_worker = shared_state.llm_worker.clone();
         Self {
             shared_state,
             context_orchestrator,
             llm_worker,
+            database_worker,
         }
     }
 }
 pub use self::SharedSystemState as SharedState;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::tests::create_test_config;
 
+    #[test]
+    fn test_context_budget_is_clamped_to_model_context() {
+        let mut config = create_test_config();
+        config.ctx_size = 8192;
+        let database = Arc::new(MemoryDatabase::new_in_memory().unwrap());
+        let state = SharedSystemState::new(config, database).unwrap();
+        assert_eq!(state.clamp_context_tokens(16_000), 8192);
+        assert_eq!(state.clamp_context_tokens(2_000), 2_000);
+
+        *state.model_info.write().unwrap() = Some(ModelInfo { context_length: Some(4096), ..Default::default() });
+        assert_eq!(state.clamp_context_tokens(16_000), 4096);
+    }
+    #[tokio::test]
+    async fn test_concurrent_orchestrator_updates_keep_every_field() {
+        use crate::context_engine::OrchestratorConfig;
+        let database = Arc::new(MemoryDatabase::new_in_memory().unwrap());
+        let state = Arc::new(SharedSystemState::new(create_test_config(), database.clone()).unwrap());
+        assert!(state.update_orchestrator_config(|config| config.enabled = false).await.is_none());
+        let orchestrator = ContextOrchestrator::new(database, OrchestratorConfig::default()).await.unwrap();
+        *state.context_orchestrator.write().await = Some(orchestrator);
 
+        let updates = (0..16).map(|i| {
+            let state = state.clone();
+            tokio::spawn(async move {
+                if i % 2 == 0 {
+                    state.update_orchestrator_config(|config| config.relevance_weight = 0.25).await
+                } else {
+                    state.update_orchestrator_config(|config| config.max_context_tokens = 1234).await
+                }
+            })
+        });
+        for update in updates.collect::<Vec<_>>() {
+            assert!(update.await.unwrap().is_some());
+        }
+        let orchestrator = state.context_orchestrator.read().await;
+        let config = orchestrator.as_ref().unwrap().config();
+        assert_eq!(config.relevance_weight, 0.25);
+        assert_eq!(config.max_context_tokens, 1234);
+    }
+}
@@ -1,6 +1,26 @@
-﻿
-use tracing_subscriber::{fmt, EnvFilter};
+﻿use std::fmt::Write as _;
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write as _;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{sync_channel, SyncSender};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::fmt::time::{FormatTime, UtcTime};
+use tracing_subscriber::layer::{Context, SubscriberExt};
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::{fmt, EnvFilter, Layer};
+use crate::config::Config;
 pub fn init_tracing() {
+    install(None);
+}
+/
+pub fn init_tracing_with_config(config: &Config) {
+    install(SessionLogLayer::from_config(config));
+}
+fn install(session_logs: Option<SessionLogLayer>) {
     let env_filter = std::env::var("RUST_LOG").unwrap_or_else(|_| "info".into());
     let subscriber = tracing_subscriber::fmt()
         .with_env_filter(EnvFilter::new(env_filter))
@@ -8,7 +28,331 @@ pub fn init_tracing() {
         .with_target(true)
         .with_level(true)
         .compact()
-        .finish();
+        .finish()
+        .with(session_logs);
     let _ = tracing::subscriber::set_global_default(subscriber);
 }
+/
+/
+/
+/
+pub struct SessionLogLayer {
+    sender: SyncSender<LogCommand>,
+}
+/
+#[derive(Clone)]
+pub struct SessionLogFlusher {
+    sender: SyncSender<LogCommand>,
+}
+struct SessionIdExt(String);
+enum LogCommand {
+    Line(String, String),
+    Flush(SyncSender<()>),
+}
+/
+const QUEUE_CAPACITY: usize = 4096;
+/
+const MAX_READABLE_ID_CHARS: usize = 64;
+impl SessionLogLayer {
+    pub fn new(dir: impl Into<PathBuf>, max_files: usize, max_bytes: u64) -> std::io::Result<Self> {
+        let mut writer = SessionLogWriter::open(dir.into(), max_files.max(1), max_bytes.max(1))?;
+        let (sender, receiver) = sync_channel(QUEUE_CAPACITY);
+        std::thread::Builder::new()
+            .name("session-logs".to_string())
+            .spawn(move || {
+                for command in receiver {
+                    match command {
+                        // Logging from the writer would recurse, so write failures are dropped.
+                        LogCommand::Line(session_id, line) => {
+                            let _ = writer.append(&session_id, &line);
+                        }
+                        LogCommand::Flush(done) => {
+                            let _ = done.send(());
+                        }
+                    }
+                }
+            })?;
+        Ok(Self { sender })
+    }
+    /
+    pub fn from_config(config: &Config) -> Option<Self> {
+        let dir = config.session_log_dir.as_ref()?;
+        match Self::new(dir, config.session_log_max_files, config.session_log_max_bytes) {
+            Ok(layer) => Some(layer),
+            Err(e) => {
+                eprintln!("Session log directory {} unavailable, per-session logs disabled: {}", dir, e);
+                None
+            }
+        }
+    }
+    pub fn flusher(&self) -> SessionLogFlusher {
+        SessionLogFlusher { sender: self.sender.clone() }
+    }
+}
+impl SessionLogFlusher {
+    /
+    pub fn flush(&self) {
+        let (done, wait) = sync_channel(1);
+        if self.sender.send(LogCommand::Flush(done)).is_ok() {
+            let _ = wait.recv();
+        }
+    }
+}
+/
+/
+/
+fn log_file_stem(session_id: &str) -> String {
+    let mut escaped = String::new();
+    for byte in session_id.bytes() {
+        if byte.is_ascii_alphanumeric() || byte == b'-' || byte == b'_' {
+            escaped.push(byte as char);
+        } else {
+            let _ = write!(escaped, "%{:02X}", byte);
+        }
+    }
+    if escaped.len() <= MAX_READABLE_ID_CHARS {
+        return format!("session-{}", escaped);
+    }
+    // The escaped form is pure ASCII, so any byte offset is a valid cut.
+    format!("session-{}~{:016x}", &escaped[..MAX_READABLE_ID_CHARS], fnv1a(session_id.as_bytes()))
+}
+/
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| (hash ^ *byte as u64).wrapping_mul(0x100000001b3))
+}
+/
+/
+struct SessionLogWriter {
+    dir: PathBuf,
+    max_files: usize,
+    max_bytes: u64,
+    sessions: HashMap<PathBuf, SessionFile>,
+    writes: u64,
+}
+struct SessionFile {
+    file: Option<File>,
+    len: u64,
+    last_write: u64,
+}
+impl SessionLogWriter {
+    fn open(dir: PathBuf, max_files: usize, max_bytes: u64) -> std::io::Result<Self> {
+        fs::create_dir_all(&dir)?;
+        // Files left by an earlier run keep their relative age so pruning still drops the oldest.
+        // A rotated `.log.1` counts toward its session, even when the live `.log` is gone.
+        let mut newest: HashMap<PathBuf, std::time::SystemTime> = HashMap::new();
+        for path in fs::read_dir(&dir)?.filter_map(|entry| entry.ok()).map(|entry| entry.path()) {
+            let Some(session_path) = session_log_path(&path) else { continue };
+            let Some(modified) = fs::metadata(&path).ok().and_then(|meta| meta.modified().ok()) else { continue };
+            let entry = newest.entry(session_path).or_insert(modified);
+            *entry = (*entry).max(modified);
+        }
+        let mut existing: Vec<(std::time::SystemTime, PathBuf)> = newest.into_iter()
+            .map(|(path, modified)| (modified, path))
+            .collect();
+        existing.sort();
+        let writes = existing.len() as u64;
+        let sessions = existing.into_iter()
+            .enumerate()
+            .map(|(age, (_, path))| (path, SessionFile { file: None, len: 0, last_write: age as u64 }))
+            .collect();
+        Ok(Self { dir, max_files, max_bytes, sessions, writes })
+    }
+    fn append(&mut self, session_id: &str, line: &str) -> std::io::Result<()> {
+        let path = self.dir.join(format!("{}.log", log_file_stem(session_id)));
+        if !self.sessions.contains_key(&path) {
+            self.make_room()?;
+        }
+        self.writes += 1;
+        let max_bytes = self.max_bytes;
+        let session = self.sessions.entry(path.clone())
+            .or_insert(SessionFile { file: None, len: 0, last_write: 0 });
+        session.last_write = self.writes;
+        if session.file.is_none() {
+            let file = OpenOptions::new().create(true).append(true).open(&path)?;
+            session.len = file.metadata()?.len();
+            session.file = Some(file);
+        }
+        if session.len > 0 && session.len + line.len() as u64 > max_bytes {
+            session.file = None;
+            fs::rename(&path, path.with_extension("log.1"))?;
+            session.file = Some(OpenOptions::new().create(true).append(true).open(&path)?);
+            session.len = 0;
+        }
+        if let Some(file) = session.file.as_mut() {
+            file.write_all(line.as_bytes())?;
+            session.len += line.len() as u64;
+        }
+        Ok(())
+    }
+    // Called before a new session file is created: drops the least recently
+    // written sessions so at most `max_files` remain afterwards.
+    fn make_room(&mut self) -> std::io::Result<()> {
+        while self.sessions.len() >= self.max_files {
+            let Some(oldest) = self.sessions.iter()
+                .min_by_key(|(_, session)| session.last_write)
+                .map(|(path, _)| path.clone())
+            else {
+                break;
+            };
+            self.sessions.remove(&oldest);
+            let _ = fs::remove_file(oldest.with_extension("log.1"));
+            match fs::remove_file(oldest) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+// Maps a session log or its rotated `.log.1` to the live `.log` path it belongs to.
+fn session_log_path(path: &Path) -> Option<PathBuf> {
+    let name = path.file_name()?.to_str()?;
+    if !name.starts_with("session-") {
+        return None;
+    }
+    let live = name.strip_suffix(".1").unwrap_or(name);
+    live.ends_with(".log").then(|| path.with_file_name(live))
+}
+#[derive(Default)]
+struct EventVisitor {
+    session_id: Option<String>,
+    message: String,
+    fields: String,
+}
+impl Visit for EventVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "session_id" {
+            self.session_id = Some(value.to_string());
+        } else {
+            self.record_debug(field, &value);
+        }
+    }
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        match field.name() {
+            "session_id" => self.session_id = Some(format!("{:?}", value)),
+            "message" => self.message = format!("{:?}", value),
+            name => {
+                let _ = write!(self.fields, " {}={:?}", name, value);
+            }
+        }
+    }
+}
+impl<S> Layer<S> for SessionLogLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let mut visitor = EventVisitor::default();
+        attrs.record(&mut visitor);
+        if let (Some(session_id), Some(span)) = (visitor.session_id, ctx.span(id)) {
+            span.extensions_mut().insert(SessionIdExt(session_id));
+        }
+    }
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let mut visitor = EventVisitor::default();
+        event.record(&mut visitor);
+        let session_id = visitor.session_id.take().or_else(|| {
+            ctx.event_scope(event)?
+                .find_map(|span| span.extensions().get::<SessionIdExt>().map(|ext| ext.0.clone()))
+        });
+        let Some(session_id) = session_id else {
+            return;
+        };
+        let metadata = event.metadata();
+        let mut line = String::new();
+        let _ = UtcTime::rfc_3339().format_time(&mut Writer::new(&mut line));
+        let _ = writeln!(
+            line,
+            " {} {}: {}{}",
+            metadata.level(),
+            metadata.target(),
+            visitor.message,
+            visitor.fields
+        );
+        // A full queue drops the line rather than stalling the thread that logged it.
+        let _ = self.sender.try_send(LogCommand::Line(session_id, line));
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tracing::{info, info_span};
+    use tracing_subscriber::Registry;
+
+    #[test]
+    fn test_session_events_are_teed_per_session() {
+        let dir = tempfile::tempdir().unwrap();
+        let layer = SessionLogLayer::new(dir.path(), 10, 1024 * 1024).unwrap();
+        let flusher = layer.flusher();
+        let subscriber = Registry::default().with(layer);
+        tracing::subscriber::with_default(subscriber, || {
+            info_span!("generation", session_id = %"abc").in_scope(|| {
+                info!(tokens = 3, "from span");
+            });
+            info!(session_id = "d/e f", "from field");
+            info!(session_id = "d_e_f", "lookalike");
+            info!("no session");
+        });
+        flusher.flush();
+
+        let abc = fs::read_to_string(dir.path().join("session-abc.log")).unwrap();
+        assert!(abc.contains("INFO"));
+        assert!(abc.contains("from span tokens=3"));
+        // Ids that only differ in characters a file name cannot hold still get separate files.
+        let escaped = fs::read_to_string(dir.path().join("session-d%2Fe%20f.log")).unwrap();
+        assert!(escaped.contains("from field") && !escaped.contains("lookalike"));
+        let lookalike = fs::read_to_string(dir.path().join("session-d_e_f.log")).unwrap();
+        assert!(lookalike.contains("lookalike"));
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 3);
+    }
 
+    #[test]
+    fn test_long_session_ids_keep_distinct_files() {
+        let shared = "x".repeat(200);
+        let (first, second) = (format!("{}1", shared), format!("{}2", shared));
+        assert_ne!(log_file_stem(&first), log_file_stem(&second));
+        assert!(log_file_stem(&first).len() < 100);
+        assert_eq!(log_file_stem(&first), log_file_stem(&first));
+    }
+
+    #[test]
+    fn test_session_logs_are_bounded() {
+        let dir = tempfile::tempdir().unwrap();
+        let layer = SessionLogLayer::new(dir.path(), 2, 200).unwrap();
+        let flusher = layer.flusher();
+        let subscriber = Registry::default().with(layer);
+        tracing::subscriber::with_default(subscriber, || {
+            for i in 0..10 {
+                info!(session_id = "busy", "line {} padded out to take some room", i);
+            }
+            info!(session_id = "second", "hello");
+            info!(session_id = "third", "hello");
+        });
+        flusher.flush();
+
+        assert!(!dir.path().join("session-busy.log").exists());
+        assert!(!dir.path().join("session-busy.log.1").exists());
+        assert!(dir.path().join("session-second.log").exists());
+        assert!(dir.path().join("session-third.log").exists());
+        for entry in fs::read_dir(dir.path()).unwrap() {
+            assert!(entry.unwrap().metadata().unwrap().len() <= 200);
+        }
+
+        // A rotated file from an older run is tracked after a restart and pruned first.
+        let stale = dir.path().join("session-stale.log.1");
+        fs::write(&stale, "old line\n").unwrap();
+        let an_hour_ago = std::time::SystemTime::now() - std::time::Duration::from_secs(3600);
+        File::options().write(true).open(&stale).unwrap().set_modified(an_hour_ago).unwrap();
+        let layer = SessionLogLayer::new(dir.path(), 2, 200).unwrap();
+        let flusher = layer.flusher();
+        let subscriber = Registry::default().with(layer);
+        tracing::subscriber::with_default(subscriber, || {
+            info!(session_id = "fourth", "hello");
+        });
+        flusher.flush();
+
+        assert!(!stale.exists());
+        assert!(dir.path().join("session-fourth.log").exists());
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 2);
+    }
+}
@@ -10,7 +10,7 @@ use crate::{
     config::Config,
     shared_state::{SharedState, UnifiedAppState},
     thread_pool::{ThreadPool, ThreadPoolConfig},
-    worker_threads::{ContextWorker, CacheWorker, DatabaseWorker, LLMWorker},
+    worker_threads::{DatabaseWorker, LLMWorker},
     memory_db::MemoryDatabase,
 };
 /
@@ -18,32 +18,16 @@ use crate::{
 pub struct ThreadBasedAppState {
     pub shared_state: Arc<SharedState>,
     pub thread_pool: Arc<RwLock<Option<ThreadPool>>>,
-    pub context_worker: Arc<ContextWorker>,
-    pub cache_worker: Arc<CacheWorker>,
     pub database_worker: Arc<DatabaseWorker>,
     pub llm_worker: Arc<LLMWorker>,
 }
 /
-pub async fn run_thread_server(cfg: Config) -> anyhow::Result<()> {
-    crate::telemetry::init_tracing();
+pub async fn run_thread_server(mut cfg: Config) -> anyhow::Result<()> {
+    crate::telemetry::init_tracing_with_config(&cfg);
     crate::metrics::init_metrics();
     cfg.print_config();
     info!("Starting thread-based server architecture");
 
-    let memory_db_path = std::path::Path::new("./data/conversations.db");
-    let memory_database = match MemoryDatabase::new(memory_db_path) {
-        Ok(db) => {
-            info!("Memory database initialized at: {}", memory_db_path.display());
-            Arc::new(db)
-        }
-        Err(e) => {
-            warn!("Failed to initialize memory database: {}. Falling back to in-memory.", e);
-            Arc::new(MemoryDatabase::new_in_memory()?)
-        }
-    };
-
-    let shared_state = Arc::new(SharedState::new(cfg.clone(), memory_database.clone())?);
-
     info!("ðŸš€ Initializing Runtime Manager for multi-format model support");
     let runtime_manager = Arc::new(crate::model_runtime::RuntimeManager::new());
 
@@ -59,33 +43,79 @@ pub async fn run_thread_server(cfg: Config) -> anyhow::Result<()> {
         gpu_layers: cfg.gpu_layers,
         runtime_binary: Some(std::path::PathBuf::from(&cfg.llama_bin)),
         extra_config: serde_json::json!({}),
+        extra_args: vec!["--parallel".to_string(), cfg.llama_slots.to_string()],
+        env: std::collections::HashMap::new(),
     };
 
 
-    match runtime_manager.initialize_auto(runtime_config).await {
+    let runtime_ready = match runtime_manager.initialize_auto(runtime_config).await {
         Ok(base_url) => {
             info!("âœ… Model runtime initialized successfully");
             info!("   Runtime endpoint: {}", base_url);
-
+            cfg.backend_url = base_url;
+            true
         }
         Err(e) => {
             warn!("âš ï¸  Runtime initialization failed: {}", e);
             warn!("   The system will attempt to use the configured backend_url directly");
+            false
         }
+    };
+
+    let memory_db_path = std::path::Path::new("./data/conversations.db");
+    let db_config = crate::memory_db::DatabaseConfig::from_config(&cfg);
+    if cfg.db_pool_max_size <= cfg.max_concurrent_streams {
+        warn!("DB_POOL_MAX_SIZE ({}) does not exceed MAX_CONCURRENT_STREAMS ({}); streams may wait for connections",
+            cfg.db_pool_max_size, cfg.max_concurrent_streams);
+    }
+    let memory_database = match MemoryDatabase::new_with_config(memory_db_path, db_config) {
+        Ok(db) => {
+            info!("Memory database initialized at: {}", memory_db_path.display());
+            Arc::new(db)
+        }
+        Err(e) => {
+            warn!("Failed to initialize memory database: {}. Falling back to in-memory.", e);
+            Arc::new(MemoryDatabase::new_in_memory()?)
+        }
+    };
+
+    let model_info = validate_model_info(&cfg, &runtime_manager, &memory_database).await;
+
+    let shared_state = Arc::new(SharedState::new(cfg.clone(), memory_database.clone())?);
+    if let Ok(mut info_guard) = shared_state.model_info.write() {
+        *info_guard = model_info;
+    }
+    if let Ok(mut runtime_guard) = shared_state.runtime_manager.write() {
+        *runtime_guard = Some(runtime_manager.clone());
     }
 
-    let context_worker: Arc<ContextWorker> = Arc::new(ContextWorker::new(shared_state.clone()));
-    let cache_worker: Arc<CacheWorker> = Arc::new(CacheWorker::new(shared_state.clone()));
+    // Conversation writes from the HTTP handlers are serialized through this worker.
     let database_worker: Arc<DatabaseWorker> = Arc::new(DatabaseWorker::new(shared_state.clone()));
     let llm_worker = shared_state.llm_worker.clone();
+    if runtime_ready {
+        let warmup_worker = llm_worker.clone();
+        tokio::spawn(async move {
+            if let Err(e) = warmup_worker.warmup().await {
+                warn!("LLM warmup failed, first request will load the model: {}", e);
+            }
+        });
+    }
+
+    let cache_config = crate::cache_management::KVCacheConfig {
+        max_cache_entries: cfg.cache_max_entries,
+        memory_threshold_percent: cfg.cache_memory_threshold_percent,
+        ..Default::default()
+    };
+    memory_database.embeddings.set_quantization(cache_config.quantize_embeddings);
 
     let cache_manager = match crate::cache_management::create_default_cache_manager(
-        crate::cache_management::KVCacheConfig::default(),
+        cache_config,
         memory_database.clone(),
     ) {
-        Ok(manager) => {
+        Ok(mut manager) => {
+            manager.set_llm_worker(shared_state.llm_worker.clone());
             info!("Cache manager initialized successfully");
-            Some(Arc::new(manager))
+            Some(Arc::new(tokio::sync::Mutex::new(manager)))
         }
         Err(e) => {
             warn!("Failed to initialize cache manager: {}, cache features disabled", e);
@@ -93,13 +123,20 @@ pub async fn run_thread_server(cfg: Config) -> anyhow::Result<()> {
         }
     };
 
-    let context_orchestrator = match crate::context_engine::create_default_orchestrator(
+    let orchestrator_config = crate::context_engine::OrchestratorConfig {
+        tenant_isolation: cfg.tenant_isolation,
+        persist_tier1: cfg.persist_tier1,
+        max_context_tokens: cfg.max_context_tokens,
+        ..Default::default()
+    };
+    let context_orchestrator = match crate::context_engine::ContextOrchestrator::new(
         memory_database.clone(),
+        orchestrator_config,
     ).await {
         Ok(mut orchestrator) => {
 
 
-            orchestrator.set_llm_worker(shared_state.llm_worker.clone());
+            orchestrator.set_llm_worker(shared_state.llm_worker.clone()).await;
             info!("Context orchestrator initialized with semantic search support");
             Some(orchestrator)
         }
@@ -122,7 +159,7 @@ pub async fn run_thread_server(cfg: Config) -> anyhow::Result<()> {
     }
 
 
-    if let Err(e) = shared_state.database_pool.embeddings.initialize_index("llama-server") {
+    if let Err(e) = shared_state.database_pool.embeddings.initialize_index(&cfg.embedding_model) {
         debug!("Embedding index init: {} (will build on first embedding store)", e);
     } else {
         info!("Embedding HNSW index loaded from existing data");
@@ -133,46 +170,302 @@ pub async fn run_thread_server(cfg: Config) -> anyhow::Result<()> {
         *orch_guard = context_orchestrator;
     }
 
-    let unified_state = UnifiedAppState::new(shared_state.clone());
+    let unified_state = UnifiedAppState::new(shared_state.clone(), database_worker);
 
     info!("Starting HTTP server on {}:{}", cfg.api_host, cfg.api_port);
     let listener = tokio::net::TcpListener::bind(format!("{}:{}", cfg.api_host, cfg.api_port)).await?;
-    let app = build_compatible_router(unified_state);
-    axum::serve(listener, app).await?;
+    let rate_limiter = Arc::new(crate::api::ClientRateLimiter::from_config(&cfg));
+    rate_limiter.clone().spawn_pruning(std::time::Duration::from_secs(60));
+    #[cfg(unix)]
+    spawn_sighup_reload(shared_state.clone(), rate_limiter.clone());
+    if !serves_metrics_on_api(&cfg) {
+        let metrics_addr = format!("{}:{}", cfg.prometheus_host, cfg.prometheus_port);
+        let metrics_listener = tokio::net::TcpListener::bind(&metrics_addr).await?;
+        info!("Starting metrics server on {}", metrics_addr);
+        let metrics_app = build_metrics_router(shared_state.clone());
+        tokio::spawn(async move {
+            if let Err(e) = axum::serve(metrics_listener, metrics_app).await {
+                warn!("Metrics server stopped: {}", e);
+            }
+        });
+    }
+    let app = build_compatible_router(unified_state, rate_limiter, &cfg)?;
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<std::net::SocketAddr>(),
+    ).await?;
+
+    if let Err(e) = runtime_manager.shutdown().await {
+        warn!("Failed to stop model runtime: {}", e);
+    }
+    Ok(())
+}
+/
+#[cfg(unix)]
+fn spawn_sighup_reload(shared_state: Arc<SharedState>, rate_limiter: Arc<crate::api::ClientRateLimiter>) {
+    use tokio::signal::unix::{signal, SignalKind};
+    let mut hangups = match signal(SignalKind::hangup()) {
+        Ok(hangups) => hangups,
+        Err(e) => {
+            warn!("Failed to install SIGHUP handler, config reload disabled: {}", e);
+            return;
+        }
+    };
+    tokio::spawn(async move {
+        while hangups.recv().await.is_some() {
+            info!("SIGHUP received, reloading configuration");
+            if let Err(e) = reload_config(&shared_state, &rate_limiter).await {
+                warn!("Configuration reload failed, keeping current settings: {}", e);
+            }
+        }
+    });
+}
+/
+/
+async fn reload_config(
+    shared_state: &SharedState,
+    rate_limiter: &crate::api::ClientRateLimiter,
+) -> anyhow::Result<()> {
+    let current = shared_state.config.load_full();
+    let reloaded = {
+        let current = current.clone();
+        tokio::task::spawn_blocking(move || current.reload()).await??
+    };
+    let reload = current.merge_reloadable(&reloaded);
+    if !reload.requires_restart.is_empty() {
+        warn!("Changed settings require a restart and were not applied: {}", reload.requires_restart.join(", "));
+    }
+    if reload.applied.is_empty() {
+        info!("Configuration reloaded; no hot-swappable settings changed");
+        return Ok(());
+    }
+    let config = reload.config;
+
+    rate_limiter.set_requests_per_second(config.requests_per_second);
+    shared_state.update_orchestrator_config(|orchestrator_config| {
+        orchestrator_config.max_context_tokens = config.max_context_tokens;
+    }).await;
+    let cache_manager = shared_state.cache_manager.read()
+        .map_err(|_| anyhow::anyhow!("Failed to acquire cache manager read lock"))?
+        .clone();
+    if let Some(cache_manager) = cache_manager {
+        let mut manager = cache_manager.lock().await;
+        let cache_config = crate::cache_management::KVCacheConfig {
+            max_cache_entries: config.cache_max_entries,
+            memory_threshold_percent: config.cache_memory_threshold_percent,
+            ..manager.get_config().clone()
+        };
+        manager.update_config(cache_config);
+    }
+    shared_state.config.store(Arc::new(config));
+    info!("Configuration reloaded; applied: {}", reload.applied.join(", "));
     Ok(())
 }
 /
-fn build_compatible_router(state: UnifiedAppState) -> axum::Router {
+async fn validate_model_info(
+    cfg: &Config,
+    runtime_manager: &crate::model_runtime::RuntimeManager,
+    memory_database: &MemoryDatabase,
+) -> Option<crate::model_runtime::ModelInfo> {
+    let model_info = match runtime_manager.model_info().await {
+        Ok(model_info) => model_info,
+        Err(e) => {
+            debug!("Model metadata unavailable, skipping validation: {}", e);
+            return None;
+        }
+    };
+    info!(
+        "Loaded model: architecture={}, context_length={:?}, embedding_dim={:?}, params={:?}",
+        model_info.architecture.as_deref().unwrap_or("unknown"),
+        model_info.context_length,
+        model_info.embedding_dim,
+        model_info.param_count
+    );
+    if let Some(context_length) = model_info.context_length {
+        if cfg.ctx_size > context_length {
+            warn!(
+                "Configured CTX_SIZE {} exceeds the model's trained context length {}; output quality may degrade",
+                cfg.ctx_size, context_length
+            );
+        }
+    }
+    if let (Some(embedding_dim), Ok(stats)) = (model_info.embedding_dim, memory_database.embeddings.get_stats()) {
+        if stats.dimension != 0 && stats.dimension != embedding_dim as usize {
+            warn!(
+                "Stored embeddings have dimension {} but the loaded model produces {}; semantic search will not match until they are regenerated",
+                stats.dimension, embedding_dim
+            );
+        }
+    }
+    Some(model_info)
+}
+/
+/
+/
+/
+fn serves_metrics_on_api(cfg: &Config) -> bool {
+    cfg.prometheus_host == cfg.api_host && cfg.prometheus_port == cfg.api_port
+}
+/
+fn build_metrics_router(shared_state: Arc<SharedState>) -> axum::Router {
+    axum::Router::new()
+        .route("/metrics", axum::routing::get(crate::api::admin_api::prometheus_metrics))
+        .with_state(shared_state)
+}
+fn cors_layer(cfg: &Config) -> anyhow::Result<tower_http::cors::CorsLayer> {
+    use axum::http::{HeaderName, HeaderValue, Method};
+    use tower_http::cors::{AllowHeaders, AllowOrigin, Any, CorsLayer};
+    let wildcard = |items: &[String]| items.iter().any(|item| item == "*");
+    let origins = if wildcard(&cfg.cors_allowed_origins) {
+        AllowOrigin::from(Any)
+    } else {
+        let origins = cfg.cors_allowed_origins
+            .iter()
+            .map(|origin| HeaderValue::from_str(origin)
+                .map_err(|_| anyhow::anyhow!("Invalid CORS origin: {}", origin)))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        AllowOrigin::list(origins)
+    };
+    let methods = cfg.cors_allowed_methods
+        .iter()
+        .map(|method| Method::from_bytes(method.to_uppercase().as_bytes())
+            .map_err(|_| anyhow::anyhow!("Invalid CORS method: {}", method)))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let headers = if wildcard(&cfg.cors_allowed_headers) {
+        AllowHeaders::from(Any)
+    } else {
+        let headers = cfg.cors_allowed_headers
+            .iter()
+            .map(|header| HeaderName::from_bytes(header.as_bytes())
+                .map_err(|_| anyhow::anyhow!("Invalid CORS header: {}", header)))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        AllowHeaders::list(headers)
+    };
+    Ok(CorsLayer::new()
+        .allow_origin(origins)
+        .allow_methods(methods)
+        .allow_headers(headers)
+        .expose_headers([
+            HeaderName::from_static("x-context-retrieved"),
+            HeaderName::from_static("x-context-tiers"),
+            HeaderName::from_static("x-context-semantic"),
+        ]))
+}
+/
+fn build_compatible_router(
+    state: UnifiedAppState,
+    rate_limiter: Arc<crate::api::ClientRateLimiter>,
+    cfg: &Config,
+) -> anyhow::Result<axum::Router> {
     use axum::{
         Router,
+        extract::DefaultBodyLimit,
+        middleware,
         routing::{get, post, put, delete},
     };
     use tower_http::{
-        cors::{Any, CorsLayer},
+        limit::RequestBodyLimitLayer,
         trace::TraceLayer,
         timeout::TimeoutLayer,
     };
     use std::time::Duration;
-    let cors = CorsLayer::new()
-        .allow_origin(Any)
-        .allow_methods([axum::http::Method::GET, axum::http::Method::POST, axum::http::Method::PUT, axum::http::Method::DELETE])
-        .allow_headers(Any);
-    Router::new()
-
-        .route("/generate/stream", post(crate::api::stream_api::generate_stream))
+    let cors = cors_layer(cfg)?;
+    let limited = || middleware::from_fn_with_state(rate_limiter.clone(), crate::api::rate_limit::rate_limit);
+    let api_key_auth = Arc::new(crate::api::ApiKeyAuth::from_config(cfg)?);
+    let shared_state_routes = Router::new()
+        .route("/memory/optimize", post(crate::api::memory_api::memory_optimize).route_layer(limited()))
+        .route("/memory/optimize/batch", post(crate::api::memory_api::memory_optimize_batch).route_layer(limited()))
+        .route("/memory/plan", post(crate::api::memory_api::memory_plan).route_layer(limited()))
+        .route("/v1/models", get(crate::api::models_api::list_models))
+        .route("/admin/counters", get(crate::api::admin_api::counters))
+        .route("/readyz", get(crate::api::admin_api::readiness))
+        .route("/admin/context-stats", get(crate::api::admin_api::context_stats))
+        .route("/admin/db-pool", get(crate::api::admin_api::db_pool_stats))
+        .route("/admin/cache/history", get(crate::api::admin_api::cache_history))
+        .route("/admin/embeddings/backfill", post(crate::api::admin_api::backfill_embeddings))
+        .route("/admin/context-config", put(crate::api::admin_api::update_context_config))
+        .route("/search/export", get(crate::api::search_api::export_search).route_layer(limited()))
+        .route(
+            "/search/saved",
+            get(crate::api::search_api::list_saved_searches).post(crate::api::search_api::save_search),
+        )
+        .route("/admin/sessions/:id/snapshot", post(crate::api::admin_api::create_session_snapshot))
+        .route(
+            "/admin/sessions/:id/snapshots",
+            get(crate::api::admin_api::session_snapshots)
+                .delete(crate::api::admin_api::prune_session_snapshots),
+        )
+        .with_state(state.shared_state.clone());
+    let shared_state_routes = if serves_metrics_on_api(cfg) {
+        shared_state_routes.merge(build_metrics_router(state.shared_state.clone()))
+    } else {
+        shared_state_routes
+    };
+    let generation_routes = Router::new()
+        .route("/generate/stream", post(crate::api::stream_api::generate_stream).route_layer(limited()))
+        .route("/generate/stop", post(crate::api::stream_api::stop_generation));
+    #[cfg(feature = "websocket")]
+    let generation_routes = generation_routes
+        .route("/generate/ws", get(crate::api::ws_api::generate_ws).route_layer(limited()));
+    Ok(Router::new()
+        .merge(generation_routes)
 
         .route("/generate/title", post(crate::api::title_api::generate_title))
+        .route("/v1/embeddings", post(crate::api::embeddings_api::create_embeddings).route_layer(limited()))
 
-        .route("/conversations", get(crate::api::conversation_api::get_conversations))
+        .route(
+            "/conversations",
+            get(crate::api::conversation_api::get_conversations)
+                .post(crate::api::conversation_api::create_conversation),
+        )
+        .route("/conversations/import", post(crate::api::conversation_api::import_conversation))
         .route("/conversations/:id", get(crate::api::conversation_api::get_conversation))
         .route("/conversations/:id/title", put(crate::api::conversation_api::update_conversation_title))
         .route("/conversations/:id/pinned", post(crate::api::conversation_api::update_conversation_pinned))
+        .route("/conversations/:id/metadata", put(crate::api::conversation_api::set_conversation_metadata))
+        .route("/conversations/:id/folder", put(crate::api::conversation_api::update_conversation_folder))
+        .route("/folders", get(crate::api::conversation_api::list_folders))
+        .route("/conversations/:id/fork", post(crate::api::conversation_api::fork_conversation))
+        .route("/conversations/:id/clear", post(crate::api::conversation_api::clear_conversation))
+        .route("/conversations/:id/messages/:msg_id", put(crate::api::conversation_api::edit_message))
+        .route("/conversations/:id/messages/:msg_id/history", get(crate::api::conversation_api::get_message_history))
         .route("/conversations/:id", delete(crate::api::conversation_api::delete_conversation))
         .route("/healthz", get(|| async { "OK" }))
+        .with_state(state)
+        .merge(shared_state_routes)
+        .layer(middleware::from_fn_with_state(api_key_auth, crate::api::auth::require_api_key))
+        // Oversized bodies are rejected from Content-Length before anything is buffered.
+        .layer(DefaultBodyLimit::max(cfg.max_request_body_bytes))
+        .layer(RequestBodyLimitLayer::new(cfg.max_request_body_bytes))
         .layer(cors)
         .layer(TraceLayer::new_for_http())
-        .layer(TimeoutLayer::new(Duration::from_secs(600)))
-        .with_state(state)
+        .layer(TimeoutLayer::new(Duration::from_secs(600))))
 }
 
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_cors_layer_validates_configured_lists() {
+        let mut cfg = crate::config::tests::create_test_config();
+        assert!(cors_layer(&cfg).is_ok());
+        cfg.cors_allowed_origins = vec!["http://localhost:5173".to_string()];
+        cfg.cors_allowed_headers = vec!["content-type".to_string(), "authorization".to_string()];
+        assert!(cors_layer(&cfg).is_ok());
+        cfg.cors_allowed_origins = vec!["http://bad\norigin".to_string()];
+        assert!(cors_layer(&cfg).is_err());
+        cfg.cors_allowed_origins = vec!["*".to_string()];
+        cfg.cors_allowed_methods = vec!["NOT A METHOD".to_string()];
+        assert!(cors_layer(&cfg).is_err());
+    }
+    #[test]
+    fn test_metrics_share_api_listener_only_when_addresses_match() {
+        let mut cfg = crate::config::tests::create_test_config();
+        assert!(!serves_metrics_on_api(&cfg));
+        cfg.prometheus_port = cfg.api_port;
+        assert!(serves_metrics_on_api(&cfg));
+        cfg.api_host = "0.0.0.0".to_string();
+        assert!(!serves_metrics_on_api(&cfg));
+    }
+}
@@ -0,0 +1,102 @@
+﻿//! Lightweight language identification from scripts and stopwords.
+use serde::Serialize;
+/
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct LangTag {
+    /
+    pub code: &'static str,
+    /
+    pub confidence: f32,
+}
+impl LangTag {
+    /
+    pub const UNDETERMINED: LangTag = LangTag { code: "und", confidence: 0.0 };
+}
+/
+/
+const STOPWORDS: &[(&str, &[&str])] = &[
+    ("en", &["the", "and", "is", "are", "was", "you", "that", "this", "with", "what",
+             "have", "not", "for", "of", "to", "it", "how", "can", "do", "my"]),
+    ("es", &["el", "los", "las", "que", "es", "una", "por", "para", "con", "como",
+             "pero", "del", "está", "qué", "muy", "también", "yo", "tengo", "hay", "y"]),
+    ("fr", &["le", "les", "est", "une", "et", "des", "pour", "dans", "avec", "pas",
+             "je", "vous", "nous", "ce", "sur", "qui", "mais", "très", "du", "au"]),
+    ("de", &["der", "die", "das", "und", "ist", "nicht", "ich", "ein", "eine", "mit",
+             "sie", "zu", "wie", "auf", "für", "auch", "den", "dem", "sind", "wir"]),
+    ("it", &["il", "gli", "che", "è", "per", "non", "sono", "della", "come", "ma",
+             "anche", "questo", "mi", "ho", "più", "io", "di", "un", "alla", "nel"]),
+    ("pt", &["os", "uma", "para", "com", "não", "em", "do", "da", "um", "mas",
+             "você", "isso", "eu", "muito", "também", "ao", "seu", "sua", "são", "é"]),
+    ("nl", &["het", "een", "en", "niet", "ik", "van", "dat", "je", "met", "op",
+             "voor", "zijn", "maar", "ook", "wat", "hoe", "er", "naar", "wij", "de"]),
+];
+/
+/
+const MIN_CONFIDENT_HITS: f32 = 3.0;
+fn script_language(c: char) -> Option<&'static str> {
+    match c as u32 {
+        0x0370..=0x03FF => Some("el"),
+        0x0400..=0x04FF => Some("ru"),
+        0x0590..=0x05FF => Some("he"),
+        0x0600..=0x06FF => Some("ar"),
+        0x0900..=0x097F => Some("hi"),
+        0x0E00..=0x0E7F => Some("th"),
+        0x1100..=0x11FF | 0x3130..=0x318F | 0xAC00..=0xD7AF => Some("ko"),
+        // Kana is unique to Japanese; Han alone is resolved to Chinese below.
+        0x3040..=0x30FF => Some("ja"),
+        0x3400..=0x4DBF | 0x4E00..=0x9FFF => Some("zh"),
+        _ => None,
+    }
+}
+/
+pub(crate) fn detect(text: &str) -> LangTag {
+    let mut latin = 0usize;
+    let mut scripts: Vec<(&'static str, usize)> = Vec::new();
+    for c in text.chars().filter(|c| c.is_alphabetic()) {
+        match script_language(c) {
+            Some(code) => match scripts.iter_mut().find(|(s, _)| *s == code) {
+                Some((_, count)) => *count += 1,
+                None => scripts.push((code, 1)),
+            },
+            None if (c as u32) < 0x0250 => latin += 1,
+            None => {}
+        }
+    }
+    // Japanese text mixes kanji with kana, so Han characters count towards it.
+    if let Some(han) = scripts.iter().position(|(s, _)| *s == "zh") {
+        if let Some(kana) = scripts.iter().position(|(s, _)| *s == "ja") {
+            scripts[kana].1 += scripts[han].1;
+            scripts.remove(han);
+        }
+    }
+    let letters = latin + scripts.iter().map(|(_, n)| n).sum::<usize>();
+    if letters == 0 {
+        return LangTag::UNDETERMINED;
+    }
+    if let Some(&(code, count)) = scripts.iter().max_by_key(|(_, n)| *n) {
+        if count > latin {
+            return LangTag { code, confidence: count as f32 / letters as f32 };
+        }
+    }
+
+    let mut hits = [0usize; STOPWORDS.len()];
+    for word in text.split(|c: char| !c.is_alphabetic()).filter(|w| !w.is_empty()) {
+        let word = word.to_lowercase();
+        for (index, (_, stopwords)) in STOPWORDS.iter().enumerate() {
+            if stopwords.contains(&word.as_str()) {
+                hits[index] += 1;
+            }
+        }
+    }
+    let total: usize = hits.iter().sum();
+    let Some((best, &best_hits)) = hits.iter().enumerate().max_by_key(|(i, n)| (**n, std::cmp::Reverse(*i))) else {
+        return LangTag::UNDETERMINED;
+    };
+    if best_hits == 0 {
+        return LangTag::UNDETERMINED;
+    }
+    let share = best_hits as f32 / total as f32;
+    let support = (best_hits as f32 / MIN_CONFIDENT_HITS).min(1.0);
+    let latin_share = latin as f32 / letters as f32;
+    LangTag { code: STOPWORDS[best].0, confidence: share * support * latin_share }
+}
@@ -1,6 +1,8 @@
 ﻿pub mod text_utils;
 pub mod topic_extractor;
-pub use text_utils::TextUtils;
+pub mod language;
+pub use text_utils::{TextUtils, ExtractedDetail};
+pub use language::LangTag;
 pub use topic_extractor::TopicExtractor;
 
 
@@ -3,6 +3,33 @@ use regex::Regex;
 use lazy_static::lazy_static;
 lazy_static! {
     static ref WHITESPACE_REGEX: Regex = Regex::new(r"\s+").unwrap();
+    static ref DATE_REGEX: Regex = Regex::new(
+        r"(?i)\b(?:\d{4}-\d{2}-\d{2}|\d{1,2}/\d{1,2}/\d{2,4}|(?:jan|feb|mar|apr|may|jun|jul|aug|sep|sept|oct|nov|dec)[a-z]*\.?\s+\d{1,2}(?:st|nd|rd|th)?(?:,?\s+\d{4})?)\b"
+    ).unwrap();
+    static ref NUMBER_REGEX: Regex = Regex::new(r"\b\d[\d,]*(?:\.\d+)?%?").unwrap();
+    static ref NAME_REGEX: Regex = Regex::new(r"\b[A-Z][a-z]+(?:\s+[A-Z][a-z]+)*\b").unwrap();
+    static ref CODE_REGEX: Regex = Regex::new(
+        r"(?s)```[^\n]*\n?(.*?)```|~~~[^\n]*\n?(.*?)~~~|`([^`\n]+)`"
+    ).unwrap();
+    static ref HTML_COMMENT_REGEX: Regex = Regex::new(r"(?s)<!--.*?-->").unwrap();
+    static ref HTML_TAG_REGEX: Regex = Regex::new(r"</?[A-Za-z][A-Za-z0-9-]*(?:\s[^<>]*)?/?>").unwrap();
+    static ref MD_LINK_REGEX: Regex = Regex::new(r"!?\[([^\]]*)\]\([^)]*\)").unwrap();
+    static ref MD_LINE_PREFIX_REGEX: Regex = Regex::new(
+        r"(?m)^[ \t]*(?:#{1,6}[ \t]+|>[ \t]?|[-*+][ \t]+|\d+\.[ \t]+)"
+    ).unwrap();
+    static ref MD_RULE_REGEX: Regex = Regex::new(r"(?m)^[ \t]*(?:[-*_][ \t]*){3,}$").unwrap();
+    static ref MD_EMPHASIS_REGEX: Regex = Regex::new(
+        r"\*\*([^*]+)\*\*|__([^_]+)__|~~([^~]+)~~|\*([^*\s][^*]*)\*|\b_([^_\s][^_]*)_\b"
+    ).unwrap();
+    static ref HORIZONTAL_SPACE_REGEX: Regex = Regex::new(r"[ \t]+").unwrap();
+}
+/
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExtractedDetail {
+    pub detail_type: &'static str,
+    pub content: String,
+    pub context: String,
+    pub importance_score: f32,
 }
 /
 pub struct TextUtils;
@@ -67,6 +94,23 @@ impl TextUtils {
         text.split_whitespace().count()
     }
 
+    /
+    pub fn estimate_tokens(text: &str) -> usize {
+        text.split_whitespace()
+            .map(|word| {
+                let chars = word.chars().count();
+                let punctuation = word.chars().filter(|c| c.is_ascii_punctuation()).count();
+                chars.saturating_sub(punctuation).div_ceil(4).max(1) + punctuation
+            })
+            .sum()
+    }
+
+    /
+    /
+    pub fn detect_language(text: &str) -> super::language::LangTag {
+        super::language::detect(text)
+    }
+
     /
     pub fn truncate_with_ellipsis(text: &str, max_len: usize) -> Cow<'_, str> {
         if text.len() <= max_len {
@@ -81,6 +125,182 @@ impl TextUtils {
         }
     }
 
+    /
+    /
+    pub fn truncate_at_sentence(text: &str, max_chars: usize) -> Cow<'_, str> {
+        let Some((limit, _)) = text.char_indices().nth(max_chars) else {
+            return Cow::Borrowed(text);
+        };
+        let head = &text[..limit];
+        let ends_sentence = |pos: usize, c: char| match c {
+            '.' | '!' | '?' => text[pos + c.len_utf8()..].chars().next().is_none_or(char::is_whitespace),
+            '。' | '！' | '？' => true,
+            _ => false,
+        };
+        // A sentence break that would drop most of the budget reads worse than a word break.
+        let sentence_end = head.char_indices()
+            .filter(|&(pos, c)| ends_sentence(pos, c))
+            .map(|(pos, c)| pos + c.len_utf8())
+            .next_back()
+            .filter(|&end| head[..end].chars().count() * 2 >= max_chars);
+        let word_end = || if text[limit..].starts_with(char::is_whitespace) {
+            Some(limit)
+        } else {
+            head.rfind(char::is_whitespace).filter(|&pos| pos > 0)
+        };
+        let end = sentence_end.or_else(word_end).unwrap_or(limit);
+        Cow::Borrowed(head[..end].trim_end())
+    }
+
+    /
+    pub fn highlight_snippet(content: &str, keywords: &[String], radius: usize) -> String {
+        let content = Self::normalize_whitespace(content);
+        let lower = content.to_lowercase();
+        // Lowercasing can change byte lengths for some scripts; only trust offsets when it doesn't.
+        let first_match = if lower.len() == content.len() {
+            keywords.iter()
+                .filter_map(|k| lower.find(k.as_str()))
+                .min()
+        } else {
+            None
+        };
+        let center = first_match.unwrap_or(0);
+        let mut start = center.saturating_sub(radius);
+        while !content.is_char_boundary(start) {
+            start -= 1;
+        }
+        // Extend forward only to a sentence (or word) break so snippets do not stop mid-word.
+        let end = center + Self::truncate_at_sentence(&content[center..], radius).len();
+        let window = &content[start..end];
+        let window_lower = &lower[start..end];
+
+        let mut ranges: Vec<(usize, usize)> = Vec::new();
+        if first_match.is_some() {
+            for keyword in keywords {
+                for (pos, _) in window_lower.match_indices(keyword.as_str()) {
+                    ranges.push((pos, pos + keyword.len()));
+                }
+            }
+        }
+        ranges.sort();
+        let mut highlighted = String::with_capacity(window.len() + ranges.len() * 4);
+        let mut cursor = 0;
+        for (from, to) in ranges {
+            if from < cursor || !window.is_char_boundary(from) || !window.is_char_boundary(to) {
+                continue;
+            }
+            highlighted.push_str(&window[cursor..from]);
+            highlighted.push_str("**");
+            highlighted.push_str(&window[from..to]);
+            highlighted.push_str("**");
+            cursor = to;
+        }
+        highlighted.push_str(&window[cursor..]);
+
+        format!(
+            "{}{}{}",
+            if start > 0 { "…" } else { "" },
+            highlighted,
+            if end < content.len() { "…" } else { "" }
+        )
+    }
+
+    /
+    pub fn extract_details(text: &str) -> Vec<ExtractedDetail> {
+        let mut details: Vec<ExtractedDetail> = Vec::new();
+        let mut date_spans = Vec::new();
+        let mut push = |detail_type: &'static str, start: usize, end: usize, importance_score: f32| {
+            let content = text[start..end].trim().to_string();
+            if details.iter().any(|d| d.detail_type == detail_type && d.content == content) {
+                return;
+            }
+            details.push(ExtractedDetail {
+                detail_type,
+                content,
+                context: Self::surrounding_text(text, start, end, 80),
+                importance_score,
+            });
+        };
+
+        for m in DATE_REGEX.find_iter(text) {
+            date_spans.push(m.range());
+            push("date", m.start(), m.end(), 0.8);
+        }
+
+        for m in NUMBER_REGEX.find_iter(text) {
+            if date_spans.iter().any(|r| m.start() < r.end && r.start < m.end()) {
+                continue;
+            }
+            push("number", m.start(), m.end(), 0.6);
+        }
+
+        for m in NAME_REGEX.find_iter(text) {
+            if date_spans.iter().any(|r| m.start() < r.end && r.start < m.end()) {
+                continue;
+            }
+            let multi_word = m.as_str().contains(char::is_whitespace);
+            let sentence_start = text[..m.start()].trim_end().is_empty()
+                || text[..m.start()].trim_end().ends_with(['.', '!', '?', ':', '\n']);
+            if !multi_word && (sentence_start || !Self::is_significant_word(m.as_str(), 3)) {
+                continue;
+            }
+            push("name", m.start(), m.end(), 0.7);
+        }
+
+        details
+    }
+
+    fn surrounding_text(text: &str, start: usize, end: usize, radius: usize) -> String {
+        let mut from = start.saturating_sub(radius);
+        while !text.is_char_boundary(from) {
+            from -= 1;
+        }
+        let mut to = (end + radius).min(text.len());
+        while !text.is_char_boundary(to) {
+            to += 1;
+        }
+        Self::normalize_whitespace(&text[from..to]).into_owned()
+    }
+
+    /
+    pub fn strip_markup(text: &str) -> String {
+        let mut output = String::with_capacity(text.len());
+        let mut last = 0;
+        for caps in CODE_REGEX.captures_iter(text) {
+            let whole = caps.get(0).expect("capture group 0 always matches");
+            output.push_str(&Self::strip_prose_markup(&text[last..whole.start()]));
+            let code = caps.get(1).or_else(|| caps.get(2)).or_else(|| caps.get(3));
+            output.push(' ');
+            output.push_str(code.map_or("", |m| m.as_str()));
+            output.push(' ');
+            last = whole.end();
+        }
+        output.push_str(&Self::strip_prose_markup(&text[last..]));
+
+        output.lines()
+            .map(|line| HORIZONTAL_SPACE_REGEX.replace_all(line, " ").trim().to_string())
+            .filter(|line| !line.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn strip_prose_markup(text: &str) -> String {
+        let text = HTML_COMMENT_REGEX.replace_all(text, " ");
+        let text = HTML_TAG_REGEX.replace_all(&text, " ");
+        let text = text
+            .replace("&nbsp;", " ")
+            .replace("&lt;", "<")
+            .replace("&gt;", ">")
+            .replace("&quot;", "\"")
+            .replace("&#39;", "'")
+            .replace("&amp;", "&");
+        let text = MD_LINK_REGEX.replace_all(&text, "$1");
+        let text = MD_RULE_REGEX.replace_all(&text, "");
+        let text = MD_LINE_PREFIX_REGEX.replace_all(&text, "");
+        let text = MD_EMPHASIS_REGEX.replace_all(&text, "$1$2$3$4$5");
+        text.replace('`', "")
+    }
+
     /
     pub fn is_significant_word(word: &str, min_len: usize) -> bool {
         if word.len() < min_len {
@@ -94,3 +314,86 @@ impl TextUtils {
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::LangTag;
+    #[test]
+    fn test_extract_details() {
+        let details = TextUtils::extract_details(
+            "I met Alice Johnson on 2024-03-15 and we agreed on a budget of 12,500 dollars."
+        );
+        let find = |t: &str| details.iter().filter(|d| d.detail_type == t).map(|d| d.content.as_str()).collect::<Vec<_>>();
+
+        assert_eq!(find("name"), vec!["Alice Johnson"]);
+        assert_eq!(find("date"), vec!["2024-03-15"]);
+        assert_eq!(find("number"), vec!["12,500"]);
+        assert!(details[0].context.contains("met Alice Johnson"));
+    }
+
+    #[test]
+    fn test_truncate_at_sentence() {
+        let text = "The build failed on Monday. We traced it to a stale cache entry and rebuilt everything.";
+        assert_eq!(TextUtils::truncate_at_sentence(text, 200), text);
+        assert_eq!(TextUtils::truncate_at_sentence(text, 45), "The build failed on Monday.");
+        // No sentence break keeps at least half the budget, so fall back to a word break.
+        assert_eq!(TextUtils::truncate_at_sentence(text, 70), "The build failed on Monday. We traced it to a stale cache entry and");
+        assert_eq!(TextUtils::truncate_at_sentence("Version 3.14 is out now", 10), "Version");
+        assert_eq!(TextUtils::truncate_at_sentence("Supercalifragilistic", 5), "Super");
+        assert_eq!(TextUtils::truncate_at_sentence("今日は晴れです。明日は雨でしょう。", 10), "今日は晴れです。");
+    }
+
+    #[test]
+    fn test_highlight_snippet() {
+        let content = format!("{} the Invoice total was wrong {}", "padding ".repeat(30), "tail ".repeat(30));
+        let snippet = TextUtils::highlight_snippet(&content, &["invoice".to_string()], 20);
+        assert!(snippet.starts_with('…') && snippet.ends_with('…'));
+        assert!(snippet.contains("**Invoice**"));
+        assert!(snippet.ends_with("was…"));
+        assert_eq!(TextUtils::highlight_snippet("no match here", &["zzz".to_string()], 80), "no match here");
+    }
+
+    #[test]
+    fn test_detect_language() {
+        let detect = |text: &str| TextUtils::detect_language(text);
+        assert_eq!(detect("What is the best way to do this with the new API?").code, "en");
+        assert_eq!(detect("¿Cuál es la mejor manera de hacer esto con la nueva API? También tengo una pregunta.").code, "es");
+        assert_eq!(detect("Je ne sais pas comment configurer le serveur avec les nouvelles options.").code, "fr");
+        assert_eq!(detect("Ich weiß nicht, wie ich den Server mit der neuen Option konfigurieren soll.").code, "de");
+        assert_eq!(detect("Как настроить сервер с новыми параметрами?").code, "ru");
+        assert_eq!(detect("新しいサーバーの設定方法を教えてください").code, "ja");
+        assert_eq!(detect("如何配置新的服务器").code, "zh");
+
+        let english = detect("This is what the team wanted to have for the release.");
+        assert!(english.confidence > 0.8, "{:?}", english);
+        assert!(detect("Server ok").confidence < 0.5);
+        assert_eq!(detect("12345 -- !!"), LangTag::UNDETERMINED);
+    }
+
+    #[test]
+    fn test_strip_markup_mixed_markdown_and_code() {
+        let input = "## Setup guide\n\
+            <div class=\"note\">Use **bold** and _emphasis_ with [the docs](https://example.com).</div>\n\
+            - install `cargo` first\n\
+            ```rust\n\
+            let total = a * b; // **not markdown**\n\
+            ```\n\
+            > quoted &amp; ~~struck~~ text";
+        let stripped = TextUtils::strip_markup(input);
+
+        assert!(stripped.contains("Setup guide"));
+        assert!(stripped.contains("Use bold and emphasis with the docs."));
+        assert!(stripped.contains("install cargo first"));
+        assert!(stripped.contains("let total = a * b; // **not markdown**"));
+        assert!(stripped.contains("quoted & struck text"));
+        for marker in ["##", "<div", "</div>", "```", "](", "~~", "`"] {
+            assert!(!stripped.contains(marker), "{:?} left in {:?}", marker, stripped);
+        }
+    }
+
+    #[test]
+    fn test_strip_markup_keeps_snake_case_and_plain_text() {
+        assert_eq!(TextUtils::strip_markup("call snake_case_name now"), "call snake_case_name now");
+        assert_eq!(TextUtils::strip_markup("~~~\nraw <b>html</b>\n~~~"), "raw <b>html</b>");
+    }
+}
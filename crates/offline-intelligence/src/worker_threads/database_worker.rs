@@ -1,18 +1,102 @@
 ﻿//!
 //! Handles database operations in a dedicated thread with connection pooling.
 use std::sync::Arc;
+use tokio::sync::{mpsc, oneshot};
 use tracing::{info, debug};
 use crate::{
     shared_state::SharedState,
     memory::Message,
-    memory_db::{StoredMessage, Transaction, DatabaseStats},
+    memory_db::{MemoryDatabase, Session, SessionExport, SessionMetadata, StoredMessage, Transaction, DatabaseStats, score_message_importance},
 };
+/
+pub enum DatabaseCommand {
+    StoreMessages {
+        session_id: String,
+        messages: Vec<Message>,
+        reply: oneshot::Sender<anyhow::Result<Vec<StoredMessage>>>,
+    },
+    CreateSession {
+        session_id: Option<String>,
+        metadata: SessionMetadata,
+        reply: oneshot::Sender<anyhow::Result<(Session, bool)>>,
+    },
+    UpdateTitle {
+        session_id: String,
+        title: String,
+        reply: oneshot::Sender<anyhow::Result<()>>,
+    },
+    UpdatePinned {
+        session_id: String,
+        pinned: bool,
+        reply: oneshot::Sender<anyhow::Result<()>>,
+    },
+    UpdateFolder {
+        session_id: String,
+        folder: Option<String>,
+        reply: oneshot::Sender<anyhow::Result<()>>,
+    },
+    SetMetadata {
+        session_id: String,
+        key: String,
+        value: String,
+        reply: oneshot::Sender<anyhow::Result<SessionMetadata>>,
+    },
+    EditMessage {
+        message_id: i64,
+        content: String,
+        tokens: i32,
+        reply: oneshot::Sender<anyhow::Result<bool>>,
+    },
+    ClearMessages {
+        session_id: String,
+        reply: oneshot::Sender<anyhow::Result<usize>>,
+    },
+    ImportSession {
+        export: Box<SessionExport>,
+        remap_id: bool,
+        reply: oneshot::Sender<anyhow::Result<String>>,
+    },
+    ForkSession {
+        session_id: String,
+        up_to_message_id: i64,
+        reply: oneshot::Sender<anyhow::Result<Option<String>>>,
+    },
+    DeleteConversation {
+        session_id: String,
+        reply: oneshot::Sender<anyhow::Result<usize>>,
+    },
+    CleanupOldData {
+        older_than_days: i32,
+        reply: oneshot::Sender<anyhow::Result<usize>>,
+    },
+}
+/
+/
+/
 pub struct DatabaseWorker {
     shared_state: Arc<SharedState>,
+    writes: mpsc::UnboundedSender<DatabaseCommand>,
 }
 impl DatabaseWorker {
     pub fn new(shared_state: Arc<SharedState>) -> Self {
-        Self { shared_state }
+        let (writes, commands) = mpsc::unbounded_channel();
+        let database = shared_state.database_pool.clone();
+        std::thread::Builder::new()
+            .name("database-writer".to_string())
+            .spawn(move || run_writer(database, commands))
+            .expect("failed to spawn database writer thread");
+        Self { shared_state, writes }
+    }
+
+    async fn write<T>(
+        &self,
+        command: impl FnOnce(oneshot::Sender<anyhow::Result<T>>) -> DatabaseCommand,
+    ) -> anyhow::Result<T> {
+        let (reply, response) = oneshot::channel();
+        self.writes.send(command(reply))
+            .map_err(|_| anyhow::anyhow!("Database writer thread has stopped"))?;
+        response.await
+            .map_err(|_| anyhow::anyhow!("Database writer dropped the request"))?
     }
 
     /
@@ -22,10 +106,12 @@ impl DatabaseWorker {
         messages: Vec<Message>,
     ) -> anyhow::Result<()> {
         debug!("Database worker storing {} messages for session: {}", messages.len(), session_id);
-
-
-
-        info!("Stored {} messages for session {}", messages.len(), session_id);
+        let stored = self.write(|reply| DatabaseCommand::StoreMessages {
+            session_id: session_id.clone(),
+            messages,
+            reply,
+        }).await?;
+        info!("Stored {} messages for session {}", stored.len(), session_id);
         Ok(())
     }
 
@@ -35,13 +121,30 @@ impl DatabaseWorker {
         session_id: &str,
     ) -> anyhow::Result<Vec<StoredMessage>> {
         debug!("Database worker retrieving conversation: {}", session_id);
-
-
-        let messages = Vec::new();
+        let database = self.shared_state.database_pool.clone();
+        let sid = session_id.to_string();
+        let messages = tokio::task::spawn_blocking(move || {
+            database.conversations.get_session_messages(&sid, None, None)
+        }).await??;
         info!("Retrieved conversation {} with {} messages", session_id, messages.len());
         Ok(messages)
     }
 
+    /
+    /
+    pub async fn create_session(
+        &self,
+        session_id: Option<&str>,
+        metadata: SessionMetadata,
+    ) -> anyhow::Result<(Session, bool)> {
+        debug!("Database worker creating session {:?}", session_id);
+        self.write(|reply| DatabaseCommand::CreateSession {
+            session_id: session_id.map(str::to_string),
+            metadata,
+            reply,
+        }).await
+    }
+
     /
     pub async fn update_conversation_title(
         &self,
@@ -49,22 +152,107 @@ impl DatabaseWorker {
         title: &str,
     ) -> anyhow::Result<()> {
         debug!("Database worker updating title for session: {}", session_id);
-
+        self.write(|reply| DatabaseCommand::UpdateTitle {
+            session_id: session_id.to_string(),
+            title: title.to_string(),
+            reply,
+        }).await?;
         info!("Updated conversation title for session {}", session_id);
         Ok(())
     }
 
+    /
+    pub async fn update_conversation_pinned(&self, session_id: &str, pinned: bool) -> anyhow::Result<()> {
+        debug!("Database worker updating pinned status for session: {}", session_id);
+        self.write(|reply| DatabaseCommand::UpdatePinned {
+            session_id: session_id.to_string(),
+            pinned,
+            reply,
+        }).await
+    }
+
+    /
+    pub async fn update_conversation_folder(&self, session_id: &str, folder: Option<&str>) -> anyhow::Result<()> {
+        debug!("Database worker moving session {} to folder {:?}", session_id, folder);
+        self.write(|reply| DatabaseCommand::UpdateFolder {
+            session_id: session_id.to_string(),
+            folder: folder.map(str::to_string),
+            reply,
+        }).await
+    }
+
+    /
+    pub async fn set_conversation_metadata(
+        &self,
+        session_id: &str,
+        key: &str,
+        value: &str,
+    ) -> anyhow::Result<SessionMetadata> {
+        debug!("Database worker setting metadata '{}' for session: {}", key, session_id);
+        self.write(|reply| DatabaseCommand::SetMetadata {
+            session_id: session_id.to_string(),
+            key: key.to_string(),
+            value: value.to_string(),
+            reply,
+        }).await
+    }
+
+    /
+    pub async fn edit_message(&self, message_id: i64, content: &str, tokens: i32) -> anyhow::Result<bool> {
+        debug!("Database worker editing message {}", message_id);
+        self.write(|reply| DatabaseCommand::EditMessage {
+            message_id,
+            content: content.to_string(),
+            tokens,
+            reply,
+        }).await
+    }
+
+    /
+    pub async fn clear_conversation(&self, session_id: &str) -> anyhow::Result<usize> {
+        debug!("Database worker clearing messages for session: {}", session_id);
+        self.write(|reply| DatabaseCommand::ClearMessages {
+            session_id: session_id.to_string(),
+            reply,
+        }).await
+    }
+
+    /
+    pub async fn import_conversation(&self, export: SessionExport, remap_id: bool) -> anyhow::Result<String> {
+        debug!("Database worker importing session {} ({} messages)", export.session.id, export.messages.len());
+        self.write(|reply| DatabaseCommand::ImportSession {
+            export: Box::new(export),
+            remap_id,
+            reply,
+        }).await
+    }
+
+    /
+    /
+    pub async fn fork_conversation(&self, session_id: &str, up_to_message_id: i64) -> anyhow::Result<Option<String>> {
+        debug!("Database worker forking session {} at message {}", session_id, up_to_message_id);
+        self.write(|reply| DatabaseCommand::ForkSession {
+            session_id: session_id.to_string(),
+            up_to_message_id,
+            reply,
+        }).await
+    }
+
     /
     pub async fn delete_conversation(
         &self,
         session_id: &str,
-    ) -> anyhow::Result<()> {
+    ) -> anyhow::Result<usize> {
         debug!("Database worker deleting conversation: {}", session_id);
-
+        let deleted = self.write(|reply| DatabaseCommand::DeleteConversation {
+            session_id: session_id.to_string(),
+            reply,
+        }).await?;
         info!("Deleted conversation {}", session_id);
-        Ok(())
+        Ok(deleted)
     }
 
+    /
     /
     pub async fn begin_transaction(&self) -> anyhow::Result<Transaction<'_>> {
         debug!("Database worker beginning transaction");
@@ -77,18 +265,165 @@ impl DatabaseWorker {
     /
     pub async fn get_stats(&self) -> anyhow::Result<DatabaseStats> {
         debug!("Database worker getting statistics");
-
-        let stats = self.shared_state.database_pool.get_stats()?;
+        let database = self.shared_state.database_pool.clone();
+        let stats = tokio::task::spawn_blocking(move || database.get_stats()).await??;
         Ok(stats)
     }
 
     /
     pub async fn cleanup_old_data(&self, older_than_days: i32) -> anyhow::Result<usize> {
         debug!("Database worker cleaning up data older than {} days", older_than_days);
-
-        let deleted_count = self.shared_state.database_pool.cleanup_old_data(older_than_days)?;
+        let deleted_count = self.write(|reply| DatabaseCommand::CleanupOldData {
+            older_than_days,
+            reply,
+        }).await?;
         info!("Cleaned up {} old records", deleted_count);
         Ok(deleted_count)
     }
 }
+// Runs until every DatabaseWorker handle is dropped. Commands are applied one
+// at a time, so writes issued through the worker never contend for the
+// SQLite write lock with each other.
+fn run_writer(database: Arc<MemoryDatabase>, mut commands: mpsc::UnboundedReceiver<DatabaseCommand>) {
+    while let Some(command) = commands.blocking_recv() {
+        match command {
+            DatabaseCommand::StoreMessages { session_id, messages, reply } => {
+                let _ = reply.send(append_messages(&database, &session_id, &messages));
+            }
+            DatabaseCommand::CreateSession { session_id, metadata, reply } => {
+                let _ = reply.send(match session_id {
+                    Some(session_id) => database.conversations.get_or_create_session(&session_id, Some(metadata)),
+                    None => database.conversations.create_session(Some(metadata)).map(|session| (session, true)),
+                });
+            }
+            DatabaseCommand::UpdateTitle { session_id, title, reply } => {
+                let _ = reply.send(database.conversations.update_session_title(&session_id, &title));
+            }
+            DatabaseCommand::UpdatePinned { session_id, pinned, reply } => {
+                let _ = reply.send(database.conversations.update_session_pinned(&session_id, pinned));
+            }
+            DatabaseCommand::UpdateFolder { session_id, folder, reply } => {
+                let _ = reply.send(database.conversations.update_session_folder(&session_id, folder.as_deref()));
+            }
+            DatabaseCommand::SetMetadata { session_id, key, value, reply } => {
+                let _ = reply.send(database.conversations.set_session_metadata(&session_id, &key, &value));
+            }
+            DatabaseCommand::EditMessage { message_id, content, tokens, reply } => {
+                let _ = reply.send(database.conversations.update_message_content(message_id, &content, tokens));
+            }
+            DatabaseCommand::ClearMessages { session_id, reply } => {
+                let _ = reply.send(database.clear_session_messages(&session_id));
+            }
+            DatabaseCommand::ImportSession { export, remap_id, reply } => {
+                let _ = reply.send(database.conversations.import_session(&export, remap_id));
+            }
+            DatabaseCommand::ForkSession { session_id, up_to_message_id, reply } => {
+                let _ = reply.send(database.conversations.fork_session(&session_id, up_to_message_id));
+            }
+            DatabaseCommand::DeleteConversation { session_id, reply } => {
+                let _ = reply.send(database.conversations.delete_session(&session_id));
+            }
+            DatabaseCommand::CleanupOldData { older_than_days, reply } => {
+                let _ = reply.send(database.cleanup_old_data(older_than_days));
+            }
+        }
+    }
+    debug!("Database writer thread stopped");
+}
+fn append_messages(
+    database: &MemoryDatabase,
+    session_id: &str,
+    messages: &[Message],
+) -> anyhow::Result<Vec<StoredMessage>> {
+    let batch: Vec<(String, String, i32, f32)> = messages
+        .iter()
+        .map(|m| (
+            m.role.to_string(),
+            m.content.clone(),
+            (m.content.len() / 4) as i32,
+            score_message_importance(&m.role, &m.content),
+        ))
+        .collect();
+    database.conversations.append_messages(session_id, &batch)
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::tests::create_test_config;
+    use crate::memory::Role;
 
+    #[tokio::test]
+    async fn test_writes_are_applied_in_order() {
+        let database = Arc::new(MemoryDatabase::new_in_memory().unwrap());
+        let shared_state = Arc::new(SharedState::new(create_test_config(), database.clone()).unwrap());
+        let worker = Arc::new(DatabaseWorker::new(shared_state));
+
+        let mut handles = Vec::new();
+        for i in 0..8 {
+            let worker = worker.clone();
+            handles.push(tokio::spawn(async move {
+                worker.store_messages("db-worker".to_string(), vec![Message {
+                    role: Role::User,
+                    content: format!("message {}", i),
+                    parts: None,
+                }]).await
+            }));
+        }
+        for handle in handles {
+            handle.await.unwrap().unwrap();
+        }
+        worker.update_conversation_title("db-worker", "Renamed").await.unwrap();
+        worker.update_conversation_pinned("db-worker", true).await.unwrap();
+        worker.update_conversation_folder("db-worker", Some("work")).await.unwrap();
+        worker.set_conversation_metadata("db-worker", "ticket", "OPS-1").await.unwrap();
+
+        let stored = worker.get_conversation("db-worker").await.unwrap();
+        let mut indices: Vec<i32> = stored.iter().map(|m| m.message_index).collect();
+        indices.sort();
+        assert_eq!(indices, (0..8).collect::<Vec<_>>());
+        let session = database.conversations.get_session("db-worker").unwrap().unwrap();
+        assert_eq!(session.metadata.title.as_deref(), Some("Renamed"));
+        assert!(session.metadata.pinned);
+        assert_eq!(session.metadata.folder.as_deref(), Some("work"));
+        assert_eq!(session.metadata.user_defined.get("ticket").map(String::as_str), Some("OPS-1"));
+
+        assert_eq!(worker.clear_conversation("db-worker").await.unwrap(), 8);
+        assert!(worker.get_conversation("db-worker").await.unwrap().is_empty());
+
+        worker.delete_conversation("db-worker").await.unwrap();
+        assert!(worker.get_conversation("db-worker").await.unwrap().is_empty());
+    }
+    #[tokio::test]
+    async fn test_appends_from_outside_the_worker_do_not_reuse_indices() {
+        let dir = tempfile::tempdir().unwrap();
+        let database = Arc::new(MemoryDatabase::new(&dir.path().join("append.db")).unwrap());
+        let shared_state = Arc::new(SharedState::new(create_test_config(), database.clone()).unwrap());
+        let worker = Arc::new(DatabaseWorker::new(shared_state));
+
+        let mut handles = Vec::new();
+        for i in 0..8 {
+            let worker = worker.clone();
+            let database = database.clone();
+            handles.push(tokio::spawn(async move {
+                let message = Message { role: Role::User, content: format!("message {}", i), parts: None };
+                if i % 2 == 0 {
+                    worker.store_messages("shared".to_string(), vec![message]).await
+                } else {
+                    // A second writer, such as another process, bypassing the worker's queue.
+                    tokio::task::spawn_blocking(move || {
+                        append_messages(&database, "shared", &[message]).map(|_| ())
+                    }).await.unwrap()
+                }
+            }));
+        }
+        for handle in handles {
+            handle.await.unwrap().unwrap();
+        }
+        let mut indices: Vec<i32> = worker.get_conversation("shared").await.unwrap()
+            .iter()
+            .map(|m| m.message_index)
+            .collect();
+        indices.sort();
+        assert_eq!(indices, (0..8).collect::<Vec<_>>());
+    }
+}
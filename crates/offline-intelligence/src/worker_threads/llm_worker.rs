@@ -4,7 +4,97 @@
 use futures_util::StreamExt;
 use tracing::{info, debug, warn};
 use serde::{Deserialize, Serialize};
-use crate::memory::Message;
+use crate::memory::{Message, MessageContent, Role};
+use crate::utils::TextUtils;
+/
+pub const DEFAULT_MODEL_NAME: &str = "local-llm";
+/
+/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LlmError {
+    /
+    ConnectFailed(String),
+    /
+    Timeout(String),
+    /
+    BackendStatus(u16, String),
+    /
+    Parse(String),
+}
+impl LlmError {
+    /
+    pub fn status_code(&self) -> axum::http::StatusCode {
+        match self {
+            LlmError::ConnectFailed(_) => axum::http::StatusCode::SERVICE_UNAVAILABLE,
+            LlmError::Timeout(_) => axum::http::StatusCode::GATEWAY_TIMEOUT,
+            LlmError::BackendStatus(_, _) | LlmError::Parse(_) => axum::http::StatusCode::BAD_GATEWAY,
+        }
+    }
+}
+impl std::fmt::Display for LlmError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LlmError::ConnectFailed(msg) => write!(f, "LLM backend unreachable: {}", msg),
+            LlmError::Timeout(msg) => write!(f, "LLM backend timed out: {}", msg),
+            LlmError::BackendStatus(status, body) => write!(f, "LLM backend returned {}: {}", status, body),
+            LlmError::Parse(msg) => write!(f, "Failed to parse LLM backend response: {}", msg),
+        }
+    }
+}
+impl std::error::Error for LlmError {}
+impl From<reqwest::Error> for LlmError {
+    fn from(e: reqwest::Error) -> Self {
+        if e.is_timeout() {
+            LlmError::Timeout(e.to_string())
+        } else if e.is_decode() {
+            LlmError::Parse(e.to_string())
+        } else if let Some(status) = e.status() {
+            LlmError::BackendStatus(status.as_u16(), e.to_string())
+        } else {
+            LlmError::ConnectFailed(e.to_string())
+        }
+    }
+}
+/
+async fn ensure_success(response: reqwest::Response) -> Result<reqwest::Response, LlmError> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let body = response.text().await.unwrap_or_default();
+    Err(LlmError::BackendStatus(status.as_u16(), body))
+}
+/
+/
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BackendApi {
+    /
+    #[default]
+    OpenAiChat,
+    /
+    /
+    LlamaCppCompletion,
+}
+impl std::str::FromStr for BackendApi {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "openai" | "openai_chat" => Ok(BackendApi::OpenAiChat),
+            "llamacpp" | "llama_cpp" | "completion" => Ok(BackendApi::LlamaCppCompletion),
+            other => Err(anyhow::anyhow!(
+                "Unknown BACKEND_API '{}' (expected 'openai' or 'llamacpp')", other
+            )),
+        }
+    }
+}
+impl std::fmt::Display for BackendApi {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BackendApi::OpenAiChat => write!(f, "openai"),
+            BackendApi::LlamaCppCompletion => write!(f, "llamacpp"),
+        }
+    }
+}
 /
 #[derive(Debug, Serialize)]
 struct ChatCompletionRequest {
@@ -13,6 +103,84 @@ struct ChatCompletionRequest {
     max_tokens: u32,
     temperature: f32,
     stream: bool,
+    cache_prompt: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id_slot: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    n: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream_options: Option<StreamOptions>,
+}
+/
+#[derive(Debug, Clone, Copy, Serialize)]
+struct StreamOptions {
+    include_usage: bool,
+}
+/
+#[derive(Debug, Serialize)]
+struct NativeCompletionRequest {
+    prompt: String,
+    n_predict: u32,
+    temperature: f32,
+    stream: bool,
+    cache_prompt: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id_slot: Option<u32>,
+}
+impl NativeCompletionRequest {
+    /
+    fn new(request: &ChatCompletionRequest, prompt: String) -> Self {
+        Self {
+            prompt,
+            n_predict: request.max_tokens,
+            temperature: request.temperature,
+            stream: request.stream,
+            cache_prompt: request.cache_prompt,
+            id_slot: request.id_slot,
+        }
+    }
+}
+/
+#[derive(Debug, Serialize)]
+struct ApplyTemplateRequest<'a> {
+    messages: &'a [ChatMessage],
+}
+#[derive(Debug, Deserialize)]
+struct ApplyTemplateResponse {
+    prompt: String,
+}
+/
+#[derive(Debug, Deserialize)]
+struct NativeCompletionResponse {
+    #[serde(default)]
+    content: String,
+    #[serde(default)]
+    stop: bool,
+    #[serde(default)]
+    stop_type: Option<String>,
+    #[serde(default)]
+    tokens_predicted: Option<u32>,
+}
+fn native_to_chat_chunk(data: &str) -> String {
+    let Ok(native) = serde_json::from_str::<NativeCompletionResponse>(data) else {
+        return data.to_string();
+    };
+    let finish_reason = match (native.stop, native.stop_type.as_deref()) {
+        (false, _) => None,
+        (true, Some("limit")) => Some("length"),
+        (true, _) => Some("stop"),
+    };
+    let mut chunk = serde_json::json!({
+        "choices": [{
+            "index": 0,
+            "delta": { "content": native.content },
+            "finish_reason": finish_reason,
+        }]
+    });
+    if let (true, Some(tokens)) = (native.stop, native.tokens_predicted) {
+        chunk["usage"] = serde_json::json!({ "completion_tokens": tokens });
+    }
+    chunk.to_string()
 }
 /
 #[derive(Debug, Serialize)]
@@ -29,10 +197,19 @@ struct EmbeddingResponse {
 struct EmbeddingData {
     embedding: Vec<f32>,
 }
+/
+#[derive(Debug, Serialize)]
+struct TokenizeRequest {
+    content: String,
+}
+#[derive(Debug, Deserialize)]
+struct TokenizeResponse {
+    tokens: Vec<serde_json::Value>,
+}
 #[derive(Debug, Serialize, Deserialize, Clone)]
 struct ChatMessage {
     role: String,
-    content: String,
+    content: MessageContent,
 }
 /
 #[derive(Debug, Deserialize)]
@@ -41,12 +218,37 @@ struct ChatCompletionResponse {
 }
 #[derive(Debug, Deserialize)]
 struct ChatChoice {
+    #[serde(default)]
+    index: u32,
     message: Option<ChatMessage>,
 }
 /
 #[derive(Debug, Deserialize)]
 struct StreamChunk {
     choices: Vec<StreamChoice>,
+    #[serde(default)]
+    usage: Option<StreamUsage>,
+}
+#[derive(Debug, Deserialize)]
+struct StreamUsage {
+    completion_tokens: Option<u32>,
+}
+/
+#[derive(Debug, Clone)]
+pub enum StreamEvent {
+    /
+    Frame(String),
+    /
+    Finished(StreamSummary),
+}
+/
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StreamSummary {
+    pub index: u32,
+    pub finish_reason: Option<String>,
+    /
+    /
+    pub completion_tokens: Option<u32>,
 }
 #[derive(Debug, Deserialize)]
 struct StreamChoice {
@@ -59,114 +261,247 @@ struct ChatDelta {
 }
 pub struct LLMWorker {
     backend_url: String,
+    embedding_backend_url: String,
+    embedding_model: String,
     http_client: reqwest::Client,
+    slot_count: u32,
+    backend_api: BackendApi,
 }
 impl LLMWorker {
     /
     pub fn new(shared_state: std::sync::Arc<crate::shared_state::SharedState>) -> Self {
-        let backend_url = shared_state.config.backend_url.clone();
+        let config = shared_state.config.load();
         Self {
-            backend_url,
+            backend_url: config.backend_url.clone(),
+            embedding_backend_url: config.embedding_backend_url.clone(),
+            embedding_model: config.embedding_model.clone(),
             http_client: reqwest::Client::builder()
                 .timeout(std::time::Duration::from_secs(600))
                 .build()
                 .unwrap_or_default(),
+            slot_count: config.llama_slots.max(1),
+            backend_api: config.backend_api,
         }
     }
     /
     pub fn new_with_backend(backend_url: String) -> Self {
         info!("LLM worker initialized with backend: {}", backend_url);
         Self {
+            embedding_backend_url: backend_url.clone(),
+            embedding_model: DEFAULT_MODEL_NAME.to_string(),
             backend_url,
             http_client: reqwest::Client::builder()
                 .timeout(std::time::Duration::from_secs(600))
                 .build()
                 .unwrap_or_default(),
+            slot_count: 1,
+            backend_api: BackendApi::default(),
         }
     }
     /
+    pub fn with_slot_count(mut self, slot_count: u32) -> Self {
+        self.slot_count = slot_count.max(1);
+        self
+    }
+    /
+    pub fn with_embedding_backend(mut self, backend_url: String, model: String) -> Self {
+        self.embedding_backend_url = backend_url;
+        self.embedding_model = model;
+        self
+    }
+    /
+    pub fn with_backend_api(mut self, backend_api: BackendApi) -> Self {
+        self.backend_api = backend_api;
+        self
+    }
+    /
+    pub fn embedding_model(&self) -> &str {
+        &self.embedding_model
+    }
+    /
+    /
+    pub fn slot_for_session(&self, session_id: &str) -> u32 {
+        use std::hash::{Hash, Hasher};
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        session_id.hash(&mut hasher);
+        (hasher.finish() % self.slot_count as u64) as u32
+    }
+    /
     fn completions_url(&self) -> String {
-        format!("{}/v1/chat/completions", self.backend_url)
+        match self.backend_api {
+            BackendApi::OpenAiChat => format!("{}/v1/chat/completions", self.backend_url),
+            BackendApi::LlamaCppCompletion => format!("{}/completion", self.backend_url),
+        }
+    }
+    /
+    async fn send_completion(&self, request: &ChatCompletionRequest) -> Result<reqwest::Response, LlmError> {
+        match self.backend_api {
+            BackendApi::OpenAiChat => {
+                let builder = self.http_client.post(&self.completions_url()).json(request);
+                ensure_success(builder.send().await?).await
+            }
+            BackendApi::LlamaCppCompletion => {
+                let prompt = self.apply_template(&request.messages).await?;
+                self.send_native(request, prompt).await
+            }
+        }
+    }
+    /
+    /
+    async fn apply_template(&self, messages: &[ChatMessage]) -> Result<String, LlmError> {
+        let response = self.http_client
+            .post(format!("{}/apply-template", self.backend_url))
+            .json(&ApplyTemplateRequest { messages })
+            .send()
+            .await?;
+        let rendered: ApplyTemplateResponse = ensure_success(response).await?.json().await?;
+        Ok(rendered.prompt)
+    }
+    /
+    async fn send_native(&self, request: &ChatCompletionRequest, prompt: String) -> Result<reqwest::Response, LlmError> {
+        let builder = self.http_client
+            .post(&self.completions_url())
+            .json(&NativeCompletionRequest::new(request, prompt));
+        ensure_success(builder.send().await?).await
+    }
+    /
+    /
+    async fn complete(&self, request: ChatCompletionRequest) -> Result<Vec<String>, LlmError> {
+        match self.backend_api {
+            BackendApi::OpenAiChat => {
+                let response = self.send_completion(&request).await?;
+                let mut completion: ChatCompletionResponse = response.json().await?;
+                completion.choices.sort_by_key(|c| c.index);
+                Ok(completion.choices
+                    .into_iter()
+                    .map(|c| c.message.map(|m| m.content.text()).unwrap_or_default())
+                    .collect())
+            }
+            BackendApi::LlamaCppCompletion => {
+                let n = request.n.unwrap_or(1).max(1);
+                let prompt = self.apply_template(&request.messages).await?;
+                let candidates = (0..n).map(|index| {
+                    let candidate = ChatCompletionRequest {
+                        // The prompt is rendered once above; candidates only carry sampling settings.
+                        messages: Vec::new(),
+                        model: request.model.clone(),
+                        id_slot: if index == 0 { request.id_slot } else { None },
+                        n: None,
+                        ..request
+                    };
+                    let prompt = prompt.clone();
+                    async move {
+                        let response = self.send_native(&candidate, prompt).await?;
+                        let completion: NativeCompletionResponse = response.json().await?;
+                        Ok::<_, LlmError>(completion.content)
+                    }
+                });
+                futures_util::future::try_join_all(candidates).await
+            }
+        }
     }
     /
     fn embeddings_url(&self) -> String {
-        format!("{}/v1/embeddings", self.backend_url)
+        format!("{}/v1/embeddings", self.embedding_backend_url)
+    }
+    /
+    fn tokenize_url(&self) -> String {
+        format!("{}/tokenize", self.backend_url)
     }
     /
     fn to_chat_messages(messages: &[Message]) -> Vec<ChatMessage> {
         messages.iter().map(|m| ChatMessage {
-            role: m.role.clone(),
-            content: m.content.clone(),
+            role: m.role.to_string(),
+            content: m.wire_content(),
         }).collect()
     }
     /
     pub async fn generate_response(
         &self,
-        _session_id: String,
+        session_id: String,
         context: Vec<Message>,
-    ) -> anyhow::Result<String> {
-        debug!("LLM worker generating response (non-streaming)");
-        let request = ChatCompletionRequest {
-            model: "local-llm".to_string(),
-            messages: Self::to_chat_messages(&context),
-            max_tokens: 2000,
-            temperature: 0.7,
-            stream: false,
-        };
-        let response = self.http_client
-            .post(&self.completions_url())
-            .json(&request)
-            .send()
-            .await
-            .map_err(|e| anyhow::anyhow!("LLM backend request failed: {}", e))?;
-        if !response.status().is_success() {
-            let status = response.status();
-            let body = response.text().await.unwrap_or_default();
-            return Err(anyhow::anyhow!("LLM backend returned {}: {}", status, body));
-        }
-        let completion: ChatCompletionResponse = response.json().await
-            .map_err(|e| anyhow::anyhow!("Failed to parse LLM response: {}", e))?;
-        let content = completion.choices
-            .first()
-            .and_then(|c| c.message.as_ref())
-            .map(|m| m.content.clone())
-            .unwrap_or_default();
-        Ok(content)
+        n: u32,
+    ) -> Result<Vec<String>, LlmError> {
+        self.generate_choices(&session_id, context, 2000, 0.7, n).await
     }
     /
     /
-    pub async fn stream_response(
+    pub async fn generate_choices(
         &self,
+        session_id: &str,
         messages: Vec<Message>,
         max_tokens: u32,
         temperature: f32,
-    ) -> anyhow::Result<impl futures_util::Stream<Item = Result<String, anyhow::Error>>> {
-        debug!("LLM worker starting streaming response");
+        n: u32,
+    ) -> Result<Vec<String>, LlmError> {
+        debug!("LLM worker generating {} response(s) (non-streaming)", n);
         let request = ChatCompletionRequest {
-            model: "local-llm".to_string(),
+            model: DEFAULT_MODEL_NAME.to_string(),
             messages: Self::to_chat_messages(&messages),
             max_tokens,
             temperature,
-            stream: true,
+            stream: false,
+            cache_prompt: true,
+            id_slot: Some(self.slot_for_session(session_id)),
+            n: (n > 1).then_some(n),
+            stream_options: None,
         };
-        let response = self.http_client
-            .post(&self.completions_url())
-            .json(&request)
-            .send()
-            .await
-            .map_err(|e| anyhow::anyhow!("LLM backend request failed: {}", e))?;
-        if !response.status().is_success() {
-            let status = response.status();
-            let body = response.text().await.unwrap_or_default();
-            return Err(anyhow::anyhow!("LLM backend returned {}: {}", status, body));
+        let choices = self.complete(request).await?;
+        if choices.is_empty() {
+            return Ok(vec![String::new()]);
+        }
+        Ok(choices)
+    }
+    /
+    /
+    /
+    /
+    pub async fn stream_response(
+        &self,
+        session_id: &str,
+        messages: Vec<Message>,
+        max_tokens: u32,
+        temperature: f32,
+        n: u32,
+    ) -> Result<impl futures_util::Stream<Item = Result<StreamEvent, LlmError>>, LlmError> {
+        debug!("LLM worker starting streaming response ({} choice(s))", n);
+        let chat_messages = Self::to_chat_messages(&messages);
+        let mut candidates = Vec::new();
+        for index in 0..n.max(1) {
+            let request = ChatCompletionRequest {
+                model: DEFAULT_MODEL_NAME.to_string(),
+                messages: chat_messages.clone(),
+                max_tokens,
+                temperature,
+                stream: true,
+                cache_prompt: true,
+                // Extra candidates let the backend pick any idle slot rather
+                // than queueing behind the session's own.
+                id_slot: (index == 0).then(|| self.slot_for_session(session_id)),
+                n: None,
+                stream_options: Some(StreamOptions { include_usage: true }),
+            };
+            candidates.push(Box::pin(self.open_stream(request, index).await?));
         }
+        let done = futures_util::stream::once(async { Ok(StreamEvent::Frame("data: [DONE]\n\n".to_string())) });
+        Ok(futures_util::stream::select_all(candidates).chain(done))
+    }
+    /
+    /
+    async fn open_stream(
+        &self,
+        request: ChatCompletionRequest,
+        index: u32,
+    ) -> Result<impl futures_util::Stream<Item = Result<StreamEvent, LlmError>>, LlmError> {
+        let response = self.send_completion(&request).await?;
+        let backend_api = self.backend_api;
         let byte_stream = response.bytes_stream();
         let sse_stream = async_stream::try_stream! {
             let mut buffer = String::new();
+            let mut summary = StreamSummary { index, ..Default::default() };
             futures_util::pin_mut!(byte_stream);
-            while let Some(chunk_result) = byte_stream.next().await {
-                let chunk = chunk_result
-                    .map_err(|e| anyhow::anyhow!("Stream read error: {}", e))?;
+            'read: while let Some(chunk_result) = byte_stream.next().await {
+                let chunk = chunk_result.map_err(LlmError::from)?;
                 buffer.push_str(&String::from_utf8_lossy(&chunk));
                 while let Some(newline_pos) = buffer.find('\n') {
                     let line = buffer[..newline_pos].trim().to_string();
@@ -175,29 +510,38 @@ impl LLMWorker {
                         continue;
                     }
                     if line.starts_with("data: ") {
-                        let data = &line[6..];
+                        let data = match backend_api {
+                            BackendApi::OpenAiChat => line[6..].to_string(),
+                            BackendApi::LlamaCppCompletion => native_to_chat_chunk(&line[6..]),
+                        };
+                        let data = data.as_str();
                         if data == "[DONE]" {
-                            yield "data: [DONE]\n\n".to_string();
-                            return;
+                            break 'read;
                         }
                         match serde_json::from_str::<StreamChunk>(data) {
                             Ok(chunk) => {
-                                let finished = chunk.choices.iter()
-                                    .any(|c| c.finish_reason.is_some());
-                                yield format!("data: {}\n\n", data);
-                                if finished {
-                                    yield "data: [DONE]\n\n".to_string();
-                                    return;
+                                if let Some(tokens) = chunk.usage.and_then(|u| u.completion_tokens) {
+                                    summary.completion_tokens = Some(tokens);
                                 }
+                                // The usage-only chunk requested via include_usage follows the
+                                // finish chunk and carries no choices; it is recorded, not forwarded.
+                                if chunk.choices.is_empty() {
+                                    continue;
+                                }
+                                if let Some(finish_reason) = chunk.choices.into_iter().find_map(|c| c.finish_reason) {
+                                    summary.finish_reason = Some(finish_reason);
+                                }
+                                yield StreamEvent::Frame(format!("data: {}\n\n", with_choice_index(data, index)));
                             }
                             Err(_) => {
-                                yield format!("data: {}\n\n", data);
+                                yield StreamEvent::Frame(format!("data: {}\n\n", data));
                             }
                         }
                     }
                 }
             }
-            yield "data: [DONE]\n\n".to_string();
+            // Backends that omit usage leave completion_tokens unknown rather than guessed.
+            yield StreamEvent::Finished(summary);
         };
         Ok(sse_stream)
     }
@@ -205,12 +549,12 @@ impl LLMWorker {
     pub async fn batch_process(
         &self,
         prompts: Vec<(String, Vec<Message>)>,
-    ) -> anyhow::Result<Vec<String>> {
+    ) -> Result<Vec<String>, LlmError> {
         debug!("LLM worker batch processing {} prompts", prompts.len());
         let mut responses = Vec::new();
         for (session_id, messages) in prompts {
-            match self.generate_response(session_id.clone(), messages).await {
-                Ok(response) => responses.push(response),
+            match self.generate_response(session_id.clone(), messages, 1).await {
+                Ok(mut choices) => responses.push(choices.swap_remove(0)),
                 Err(e) => {
                     warn!("Batch item {} failed: {}", session_id, e);
                     responses.push(format!("Error: {}", e));
@@ -221,7 +565,26 @@ impl LLMWorker {
         Ok(responses)
     }
     /
-    pub async fn initialize_model(&self, model_path: &str) -> anyhow::Result<()> {
+    /
+    pub async fn warmup(&self) -> Result<(), LlmError> {
+        debug!("LLM worker warming up backend at {}", self.backend_url);
+        let request = ChatCompletionRequest {
+            model: DEFAULT_MODEL_NAME.to_string(),
+            messages: vec![ChatMessage { role: Role::User.to_string(), content: "Hi".into() }],
+            max_tokens: 1,
+            temperature: 0.0,
+            stream: false,
+            cache_prompt: false,
+            id_slot: None,
+            n: None,
+            stream_options: None,
+        };
+        self.complete(request).await?;
+        info!("LLM backend warmed up");
+        Ok(())
+    }
+    /
+    pub async fn initialize_model(&self, model_path: &str) -> Result<(), LlmError> {
         debug!("LLM worker model init (HTTP proxy mode): {}", model_path);
         Ok(())
     }
@@ -231,28 +594,22 @@ impl LLMWorker {
     pub async fn generate_embeddings(
         &self,
         texts: Vec<String>,
-    ) -> anyhow::Result<Vec<Vec<f32>>> {
+    ) -> Result<Vec<Vec<f32>>, LlmError> {
         if texts.is_empty() {
             return Ok(Vec::new());
         }
         debug!("Generating embeddings for {} text(s) via llama-server", texts.len());
         let request = EmbeddingRequest {
-            model: "local-llm".to_string(),
+            model: self.embedding_model.clone(),
             input: texts,
         };
         let response = self.http_client
             .post(&self.embeddings_url())
             .json(&request)
             .send()
-            .await
-            .map_err(|e| anyhow::anyhow!("Embedding request failed: {}", e))?;
-        if !response.status().is_success() {
-            let status = response.status();
-            let body = response.text().await.unwrap_or_default();
-            return Err(anyhow::anyhow!("Embedding endpoint returned {}: {}", status, body));
-        }
-        let embedding_response: EmbeddingResponse = response.json().await
-            .map_err(|e| anyhow::anyhow!("Failed to parse embedding response: {}", e))?;
+            .await?;
+        let response = ensure_success(response).await?;
+        let embedding_response: EmbeddingResponse = response.json().await?;
         let embeddings: Vec<Vec<f32>> = embedding_response.data
             .into_iter()
             .map(|d| d.embedding)
@@ -263,45 +620,311 @@ impl LLMWorker {
         Ok(embeddings)
     }
     /
+    pub async fn count_tokens(&self, messages: &[Message]) -> usize {
+        let content = messages.iter()
+            .map(|m| format!("{}: {}", m.role, m.content))
+            .collect::<Vec<_>>()
+            .join("\n");
+        match self.tokenize(content).await {
+            Ok(count) => count,
+            Err(e) => {
+                debug!("Tokenize endpoint unavailable, using local estimate: {}", e);
+                Self::estimate_tokens(messages)
+            }
+        }
+    }
+    /
+    pub fn estimate_tokens(messages: &[Message]) -> usize {
+        messages.iter()
+            .map(|m| TextUtils::estimate_tokens(&m.content) + 4)
+            .sum()
+    }
+    async fn tokenize(&self, content: String) -> Result<usize, LlmError> {
+        let response = self.http_client
+            .post(&self.tokenize_url())
+            .json(&TokenizeRequest { content })
+            .send()
+            .await?;
+        let response = ensure_success(response).await?;
+        let tokenized: TokenizeResponse = response.json().await?;
+        Ok(tokenized.tokens.len())
+    }
+    /
     pub async fn generate_title(
         &self,
         prompt: &str,
         max_tokens: u32,
-    ) -> anyhow::Result<String> {
+    ) -> Result<String, LlmError> {
         debug!("LLM worker generating title for prompt ({} chars)", prompt.len());
         let messages = vec![Message {
-            role: "user".to_string(),
+            role: Role::User,
             content: prompt.to_string(),
+            parts: None,
         }];
         let request = ChatCompletionRequest {
-            model: "local-llm".to_string(),
+            model: DEFAULT_MODEL_NAME.to_string(),
             messages: Self::to_chat_messages(&messages),
             max_tokens: max_tokens.min(20),
             temperature: 0.3,
             stream: false,
+            cache_prompt: true,
+            id_slot: None,
+            n: None,
+            stream_options: None,
         };
-        let response = self.http_client
-            .post(&self.completions_url())
-            .json(&request)
-            .send()
-            .await
-            .map_err(|e| anyhow::anyhow!("Title generation request failed: {}", e))?;
-        if !response.status().is_success() {
-            let status = response.status();
-            let body = response.text().await.unwrap_or_default();
-            return Err(anyhow::anyhow!("Title generation failed ({}): {}", status, body));
-        }
-        let completion: ChatCompletionResponse = response.json().await
-            .map_err(|e| anyhow::anyhow!("Failed to parse title response: {}", e))?;
-        let title = completion.choices
-            .first()
-            .and_then(|c| c.message.as_ref())
-            .map(|m| m.content.trim().to_string())
+        let title = self.complete(request).await?
+            .into_iter()
+            .next()
+            .map(|content| content.trim().to_string())
             .unwrap_or_else(|| "New Chat".to_string());
         let title = title.trim_matches('"').trim_matches('\'').to_string();
         info!("Generated title: '{}'", title);
         Ok(title)
     }
+    /
+    pub async fn generate_summary(
+        &self,
+        messages: &[Message],
+        max_tokens: u32,
+    ) -> Result<String, LlmError> {
+        debug!("LLM worker summarizing {} messages", messages.len());
+        let transcript = messages.iter()
+            .map(|m| format!("{}: {}", m.role, m.content))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let prompt = vec![
+            Message {
+                role: Role::System,
+                content: "Summarize the conversation below in a few sentences. Keep names, numbers and decisions.".to_string(),
+                parts: None,
+            },
+            Message { role: Role::User, content: transcript, parts: None },
+        ];
+        let request = ChatCompletionRequest {
+            model: DEFAULT_MODEL_NAME.to_string(),
+            messages: Self::to_chat_messages(&prompt),
+            max_tokens,
+            temperature: 0.2,
+            stream: false,
+            cache_prompt: false,
+            id_slot: None,
+            n: None,
+            stream_options: None,
+        };
+        let summary = self.complete(request).await?
+            .into_iter()
+            .next()
+            .map(|content| content.trim().to_string())
+            .unwrap_or_default();
+        if summary.is_empty() {
+            return Err(LlmError::Parse("backend returned an empty summary".to_string()));
+        }
+        Ok(summary)
+    }
+}
+/
+fn with_choice_index(data: &str, index: u32) -> String {
+    if index == 0 {
+        return data.to_string();
+    }
+    let Ok(mut chunk) = serde_json::from_str::<serde_json::Value>(data) else {
+        return data.to_string();
+    };
+    if let Some(choices) = chunk.get_mut("choices").and_then(|c| c.as_array_mut()) {
+        for choice in choices {
+            choice["index"] = serde_json::json!(index);
+        }
+    }
+    chunk.to_string()
 }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[tokio::test]
+    async fn test_embeddings_use_dedicated_backend_and_model() {
+        use axum::{routing::post, Json, Router};
+        let app = Router::new().route("/v1/embeddings", post(|Json(body): Json<serde_json::Value>| async move {
+            let model = body["model"].as_str().unwrap_or_default().len() as f32;
+            Json(serde_json::json!({ "data": [{ "embedding": [model] }] }))
+        }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let embedding_backend = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
 
+        // The chat backend is unreachable, so success proves the embedding URL was used.
+        let worker = LLMWorker::new_with_backend("http://127.0.0.1:1".to_string())
+            .with_embedding_backend(embedding_backend, "nomic-embed".to_string());
+        let embeddings = worker.generate_embeddings(vec!["hello".to_string()]).await.unwrap();
+        assert_eq!(embeddings, vec![vec!["nomic-embed".len() as f32]]);
+    }
+    #[test]
+    fn test_candidate_chunks_carry_their_index() {
+        let data = r#"{"choices":[{"index":0,"delta":{"content":"hi"},"finish_reason":null}]}"#;
+        assert_eq!(with_choice_index(data, 0), data);
+        let rewritten: serde_json::Value = serde_json::from_str(&with_choice_index(data, 2)).unwrap();
+        assert_eq!(rewritten["choices"][0]["index"], 2);
+        assert_eq!(rewritten["choices"][0]["delta"]["content"], "hi");
+        assert_eq!(with_choice_index("not json", 3), "not json");
+    }
+    #[test]
+    fn test_chat_messages_forward_image_parts() {
+        let message: Message = serde_json::from_str(r#"{"role":"user","content":[
+            {"type":"text","text":"Describe this"},
+            {"type":"image_url","image_url":{"url":"https://example.com/cat.png"}}
+        ]}"#).unwrap();
+        let chat = LLMWorker::to_chat_messages(&[message]);
+        let wire = serde_json::to_value(&chat).unwrap();
+        assert_eq!(wire[0]["content"][0]["text"], "Describe this");
+        assert_eq!(wire[0]["content"][1]["image_url"]["url"], "https://example.com/cat.png");
+    }
+    #[tokio::test]
+    async fn test_native_completion_renders_the_model_template() {
+        use axum::{routing::post, Json, Router};
+        use std::sync::{Arc, Mutex};
+        let prompts = Arc::new(Mutex::new(Vec::new()));
+        let seen = prompts.clone();
+        let app = Router::new()
+            .route("/apply-template", post(|Json(body): Json<serde_json::Value>| async move {
+                let turns: Vec<String> = body["messages"].as_array().unwrap().iter()
+                    .map(|m| format!("[{}] {}", m["role"].as_str().unwrap(), m["content"].as_str().unwrap()))
+                    .collect();
+                Json(serde_json::json!({ "prompt": format!("{} [/INST]", turns.join(" ")) }))
+            }))
+            .route("/completion", post(move |Json(body): Json<serde_json::Value>| {
+                let seen = seen.clone();
+                async move {
+                    seen.lock().unwrap().push(body.clone());
+                    Json(serde_json::json!({ "content": "Hello.", "stop": true }))
+                }
+            }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let backend = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let worker = LLMWorker::new_with_backend(backend).with_backend_api(BackendApi::LlamaCppCompletion);
+        let messages = vec![
+            Message { role: Role::System, content: "Be brief.".to_string(), parts: None },
+            Message { role: Role::User, content: "Hi".to_string(), parts: None },
+        ];
+        let choices = worker.generate_choices("native", messages, 64, 0.2, 2).await.unwrap();
+        assert_eq!(choices, vec!["Hello.".to_string(), "Hello.".to_string()]);
 
+        let prompts = prompts.lock().unwrap();
+        assert_eq!(prompts.len(), 2);
+        for body in prompts.iter() {
+            assert_eq!(body["prompt"], "[system] Be brief. [user] Hi [/INST]");
+            assert_eq!(body["n_predict"], 64);
+            assert!(body.get("stop").is_none());
+        }
+    }
+    #[test]
+    fn test_native_chunks_translate_to_chat_chunks() {
+        let chunk: StreamChunk = serde_json::from_str(&native_to_chat_chunk(r#"{"content":"Hel","stop":false}"#)).unwrap();
+        assert_eq!(chunk.choices[0].delta.as_ref().unwrap().content.as_deref(), Some("Hel"));
+        assert!(chunk.choices[0].finish_reason.is_none());
+        let last: StreamChunk = serde_json::from_str(&native_to_chat_chunk(r#"{"content":"","stop":true}"#)).unwrap();
+        assert_eq!(last.choices[0].finish_reason.as_deref(), Some("stop"));
+        let truncated: StreamChunk = serde_json::from_str(&native_to_chat_chunk(
+            r#"{"content":"","stop":true,"stop_type":"limit","tokens_predicted":64}"#,
+        )).unwrap();
+        assert_eq!(truncated.choices[0].finish_reason.as_deref(), Some("length"));
+        assert_eq!(truncated.usage.and_then(|u| u.completion_tokens), Some(64));
+        assert_eq!("llamacpp".parse::<BackendApi>().unwrap(), BackendApi::LlamaCppCompletion);
+        assert!("grpc".parse::<BackendApi>().is_err());
+    }
+    #[test]
+    fn test_llm_error_status_codes() {
+        use axum::http::StatusCode;
+        assert_eq!(LlmError::ConnectFailed("refused".into()).status_code(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(LlmError::Timeout("slow".into()).status_code(), StatusCode::GATEWAY_TIMEOUT);
+        assert_eq!(LlmError::BackendStatus(500, "boom".into()).status_code(), StatusCode::BAD_GATEWAY);
+        assert_eq!(LlmError::Parse("bad json".into()).status_code(), StatusCode::BAD_GATEWAY);
+    }
+    #[tokio::test]
+    async fn test_warmup_sends_single_token_completion() {
+        use axum::{routing::post, Json, Router};
+        let app = Router::new().route("/v1/chat/completions", post(|Json(body): Json<serde_json::Value>| async move {
+            assert_eq!(body["max_tokens"], 1);
+            Json(serde_json::json!({ "choices": [{ "index": 0, "message": { "role": "assistant", "content": "." } }] }))
+        }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let backend = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        assert!(LLMWorker::new_with_backend(backend).warmup().await.is_ok());
+        assert!(LLMWorker::new_with_backend("http://127.0.0.1:1".to_string()).warmup().await.is_err());
+    }
+    #[tokio::test]
+    async fn test_stream_ends_with_finish_reason_and_usage_summary() {
+        use axum::{routing::post, Router};
+        let app = Router::new().route("/v1/chat/completions", post(|| async {
+            concat!(
+                "data: {\"choices\":[{\"index\":0,\"delta\":{\"content\":\"Hel\"},\"finish_reason\":null}]}\n\n",
+                "data: {\"choices\":[{\"index\":0,\"delta\":{\"content\":\"lo\"},\"finish_reason\":\"length\"}],",
+                "\"usage\":{\"completion_tokens\":2}}\n\n",
+            )
+        }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let backend = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let worker = LLMWorker::new_with_backend(backend);
+        let messages = vec![Message { role: Role::User, content: "Hi".to_string(), parts: None }];
+        let events: Vec<StreamEvent> = worker.stream_response("s", messages, 2, 0.0, 1).await.unwrap()
+            .map(|event| event.unwrap())
+            .collect()
+            .await;
+
+        let frames = events.iter().filter(|e| matches!(e, StreamEvent::Frame(_))).count();
+        assert_eq!(frames, 3);
+        let summaries: Vec<_> = events.iter()
+            .filter_map(|e| match e { StreamEvent::Finished(s) => Some(s.clone()), _ => None })
+            .collect();
+        assert_eq!(summaries, vec![StreamSummary {
+            index: 0,
+            finish_reason: Some("length".to_string()),
+            completion_tokens: Some(2),
+        }]);
+    }
+    #[tokio::test]
+    async fn test_usage_chunk_after_finish_is_recorded() {
+        use axum::{routing::post, Json, Router};
+        let app = Router::new().route("/v1/chat/completions", post(|Json(body): Json<serde_json::Value>| async move {
+            assert_eq!(body["stream_options"]["include_usage"], true);
+            concat!(
+                "data: {\"choices\":[{\"index\":0,\"delta\":{\"content\":\"Hi\"},\"finish_reason\":null}]}\n\n",
+                "data: {\"choices\":[{\"index\":0,\"delta\":{},\"finish_reason\":\"stop\"}]}\n\n",
+                "data: {\"choices\":[],\"usage\":{\"prompt_tokens\":5,\"completion_tokens\":7}}\n\n",
+                "data: [DONE]\n\n",
+            )
+        }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let backend = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let worker = LLMWorker::new_with_backend(backend);
+        let messages = vec![Message { role: Role::User, content: "Hi".to_string(), parts: None }];
+        let events: Vec<StreamEvent> = worker.stream_response("s", messages, 16, 0.0, 1).await.unwrap()
+            .map(|event| event.unwrap())
+            .collect()
+            .await;
+
+        let frames: Vec<_> = events.iter()
+            .filter_map(|e| match e { StreamEvent::Frame(f) => Some(f.as_str()), _ => None })
+            .collect();
+        assert_eq!(frames.len(), 3);
+        assert!(frames.iter().all(|f| !f.contains("\"choices\":[]")));
+        let summary = events.iter().find_map(|e| match e { StreamEvent::Finished(s) => Some(s.clone()), _ => None });
+        assert_eq!(summary, Some(StreamSummary {
+            index: 0,
+            finish_reason: Some("stop".to_string()),
+            completion_tokens: Some(7),
+        }));
+    }
+    #[tokio::test]
+    async fn test_unreachable_backend_is_connect_failure() {
+        let worker = LLMWorker::new_with_backend("http://127.0.0.1:1".to_string());
+        let err = worker.generate_embeddings(vec!["hello".to_string()]).await.unwrap_err();
+        assert!(matches!(err, LlmError::ConnectFailed(_)), "unexpected error: {:?}", err);
+    }
+}
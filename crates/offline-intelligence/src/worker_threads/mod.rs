@@ -1,9 +1,5 @@
-﻿pub mod context_worker;
-pub mod cache_worker;
-pub mod database_worker;
+﻿pub mod database_worker;
 pub mod llm_worker;
-pub use context_worker::ContextWorker;
-pub use cache_worker::CacheWorker;
 pub use database_worker::DatabaseWorker;
-pub use llm_worker::LLMWorker;
+pub use llm_worker::{BackendApi, LLMWorker, LlmError, StreamEvent, StreamSummary, DEFAULT_MODEL_NAME};
 
@@ -2,6 +2,11 @@
 use pyo3::types::{PyDict, PyList};
 use serde_json;
 use std::collections::HashMap;
+use std::sync::Arc;
+use offline_intelligence::context_engine::{ContextOrchestrator, OrchestratorConfig};
+use offline_intelligence::memory_db::MemoryDatabase;
+use offline_intelligence::Role;
+use offline_intelligence::worker_threads::LLMWorker;
 use tokio::runtime::Runtime;
 /
 #[pyclass]
@@ -59,56 +64,224 @@ impl Config {
 #[pyclass]
 pub struct OfflineIntelligence {
     rt: Runtime,
+    orchestrator: ContextOrchestrator,
+    llm_worker: Arc<LLMWorker>,
+}
+fn runtime_error(context: &str, e: impl std::fmt::Display) -> PyErr {
+    PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(format!("{}: {}", context, e))
+}
+fn not_found(session_id: &str) -> PyErr {
+    PyErr::new::<pyo3::exceptions::PyKeyError, _>(format!("Conversation not found: {}", session_id))
 }
 #[pymethods]
 impl OfflineIntelligence {
     #[new]
-    fn new() -> PyResult<Self> {
+    #[pyo3(signature = (db_path=None))]
+    fn new(db_path: Option<String>) -> PyResult<Self> {
         let rt = Runtime::new()
-            .map_err(|e| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(
-                format!("Failed to create async runtime: {}", e)
-            ))?;
+            .map_err(|e| runtime_error("Failed to create async runtime", e))?;
 
-        Ok(OfflineIntelligence { rt })
+        let db_path = db_path.unwrap_or_else(|| "./data/conversations.db".to_string());
+        let database = MemoryDatabase::new(std::path::Path::new(&db_path))
+            .map_err(|e| runtime_error("Failed to open memory database", e))?;
+        let backend_url = offline_intelligence::Config::from_env()
+            .map(|cfg| cfg.backend_url)
+            .map_err(|e| runtime_error("Failed to load config", e))?;
+        let llm_worker = Arc::new(LLMWorker::new_with_backend(backend_url));
+        let mut orchestrator = rt
+            .block_on(ContextOrchestrator::new(Arc::new(database), OrchestratorConfig::default()))
+            .map_err(|e| runtime_error("Failed to create context orchestrator", e))?;
+        rt.block_on(orchestrator.set_llm_worker(llm_worker.clone()));
+
+        Ok(OfflineIntelligence { rt, orchestrator, llm_worker })
     }
 
     /
-    fn optimize_context(&self, session_id: &str, messages: Vec<Message>, user_query: Option<&str>) -> PyResult<PyObject> {
+    /
+    #[pyo3(signature = (session_id, messages, user_query=None))]
+    fn optimize_context(&self, py: Python<'_>, session_id: &str, messages: Vec<Message>, user_query: Option<&str>) -> PyResult<PyObject> {
         let python_messages: Vec<offline_intelligence::Message> = messages
             .into_iter()
             .map(|m| offline_intelligence::Message {
-                role: m.role,
+                role: m.role.into(),
                 content: m.content,
+                parts: None,
             })
             .collect();
 
+        let optimized = py
+            .allow_threads(|| {
+                self.rt.block_on(self.orchestrator.process_conversation(session_id, &python_messages, user_query))
+            })
+            .map_err(|e| runtime_error("Context optimization failed", e))?;
+
+        let optimized_messages = PyList::empty(py);
+        for message in &optimized {
+            optimized_messages.append(Message {
+                role: message.role.to_string(),
+                content: message.content.clone(),
+            }.into_py(py))?;
+        }
+        let dict = PyDict::new(py);
+        dict.set_item("optimized_messages", optimized_messages)?;
+        dict.set_item("original_count", python_messages.len())?;
+        dict.set_item("optimized_count", optimized.len())?;
+        // Same definition as the HTTP /memory/optimize response: the share of messages removed.
+        let original_len = python_messages.len();
+        dict.set_item(
+            "compression_ratio",
+            if original_len > 0 {
+                (original_len as f32 - optimized.len() as f32) / original_len as f32
+            } else {
+                0.0
+            },
+        )?;
+        Ok(dict.into())
+    }
+
+    /
+    #[pyo3(signature = (query, session_id=None, limit=None, user_id=None))]
+    fn search(&self, py: Python<'_>, query: &str, session_id: Option<&str>, limit: Option<i32>, user_id: Option<String>) -> PyResult<PyObject> {
+        let keywords: Vec<String> = query
+            .split_whitespace()
+            .filter(|word| word.len() > 2)
+            .map(|s| s.to_lowercase())
+            .collect();
+        let limit = limit.unwrap_or(10).clamp(1, 100) as usize;
+        let filter = offline_intelligence::memory_db::MessageSearchFilter { user_id, ..Default::default() };
+
+        let found = py
+            .allow_threads(|| {
+                self.rt.block_on(self.orchestrator.search_messages(session_id, &keywords, &filter, limit))
+            })
+            .map_err(|e| runtime_error("Search failed", e))?;
+
+        let results = PyList::empty(py);
+        for message in &found {
+            let result = PyDict::new(py);
+            result.set_item("session_id", &message.session_id)?;
+            result.set_item("message_id", message.id)?;
+            result.set_item("role", &message.role)?;
+            result.set_item("content", &message.content)?;
+            results.append(result)?;
+        }
+        let dict = PyDict::new(py);
+        dict.set_item("results", results)?;
+        dict.set_item("total", found.len())?;
+        dict.set_item("search_type", "keyword")?;
+        Ok(dict.into())
+    }
+
+    /
+    fn generate_title(&self, py: Python<'_>, messages: Vec<Message>) -> PyResult<String> {
+        let prompt = messages.iter()
+            .filter(|m| Role::from(m.role.as_str()) == Role::User)
+            .map(|m| m.content.as_str())
+            .collect::<Vec<_>>()
+            .join("\n");
+        let title_instruction = format!(
+            "User prompt: {}\n\n\
+             Create a short, meaningful chat title using 1-5 words maximum that captures the essence of this prompt.",
+            prompt
+        );
+
+        py.allow_threads(|| self.rt.block_on(self.llm_worker.generate_title(&title_instruction, 20)))
+            .map_err(|e| runtime_error("Title generation failed", e))
+    }
 
+    /
+    fn list_conversations(&self, py: Python<'_>) -> PyResult<PyObject> {
+        let conversations = &self.orchestrator.database().conversations;
+        let sessions = py
+            .allow_threads(|| conversations.get_all_sessions())
+            .map_err(|e| runtime_error("Failed to list conversations", e))?;
 
-        Python::with_gil(|py| {
-            let dict = PyDict::new(py);
-            dict.set_item("optimized_messages", PyList::empty(py))?;
-            dict.set_item("original_count", python_messages.len())?;
-            dict.set_item("optimized_count", 0)?;
-            dict.set_item("compression_ratio", 0.0)?;
-            Ok(dict.into())
-        })
+        let results = PyList::empty(py);
+        for session in &sessions {
+            // Untitled sessions are hidden, as in GET /conversations.
+            let Some(ref title) = session.metadata.title else {
+                continue;
+            };
+            let conversation = PyDict::new(py);
+            conversation.set_item("id", &session.id)?;
+            conversation.set_item("title", title)?;
+            conversation.set_item("created_at", session.created_at.to_rfc3339())?;
+            conversation.set_item("last_accessed", session.last_accessed.to_rfc3339())?;
+            conversation.set_item("message_count", conversations.get_session_message_count(&session.id).unwrap_or(0))?;
+            conversation.set_item("pinned", session.metadata.pinned)?;
+            if let Some(ref folder) = session.metadata.folder {
+                conversation.set_item("folder", folder)?;
+            }
+            conversation.set_item("tags", &session.metadata.tags)?;
+            conversation.set_item("metadata", &session.metadata.user_defined)?;
+            results.append(conversation)?;
+        }
+        let dict = PyDict::new(py);
+        dict.set_item("conversations", results)?;
+        Ok(dict.into())
     }
 
     /
-    fn search(&self, query: &str, session_id: Option<&str>, limit: Option<i32>) -> PyResult<PyObject> {
-        Python::with_gil(|py| {
-            let dict = PyDict::new(py);
-            dict.set_item("results", PyList::empty(py))?;
-            dict.set_item("total", 0)?;
-            dict.set_item("search_type", "keyword")?;
-            Ok(dict.into())
-        })
+    fn get_conversation(&self, py: Python<'_>, session_id: &str) -> PyResult<PyObject> {
+        let conversations = &self.orchestrator.database().conversations;
+        let session = py
+            .allow_threads(|| conversations.get_session(session_id))
+            .map_err(|e| runtime_error("Failed to fetch conversation", e))?
+            .ok_or_else(|| not_found(session_id))?;
+        let stored = py
+            .allow_threads(|| conversations.get_session_messages(session_id, None, None))
+            .map_err(|e| runtime_error("Failed to fetch messages", e))?;
+
+        let messages = PyList::empty(py);
+        for message in &stored {
+            let entry = PyDict::new(py);
+            entry.set_item("role", &message.role)?;
+            entry.set_item("content", &message.content)?;
+            messages.append(entry)?;
+        }
+        let dict = PyDict::new(py);
+        dict.set_item("id", &session.id)?;
+        dict.set_item("title", session.metadata.title.as_deref().unwrap_or("New Chat"))?;
+        dict.set_item("messages", messages)?;
+        Ok(dict.into())
     }
 
     /
-    fn generate_title(&self, messages: Vec<Message>) -> PyResult<String> {
+    fn update_title(&self, py: Python<'_>, session_id: &str, title: &str) -> PyResult<PyObject> {
+        if title.is_empty() {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>("Title cannot be empty"));
+        }
+        let conversations = &self.orchestrator.database().conversations;
+        py.allow_threads(|| conversations.update_session_title(session_id, title))
+            .map_err(|e| {
+                if e.to_string().contains("not found") {
+                    not_found(session_id)
+                } else {
+                    runtime_error("Failed to update conversation title", e)
+                }
+            })?;
+
+        let dict = PyDict::new(py);
+        dict.set_item("success", true)?;
+        dict.set_item("id", session_id)?;
+        dict.set_item("title", title)?;
+        Ok(dict.into())
+    }
+
+    /
+    fn delete_conversation(&self, py: Python<'_>, session_id: &str) -> PyResult<PyObject> {
+        let conversations = &self.orchestrator.database().conversations;
+        let deleted = py
+            .allow_threads(|| conversations.delete_session(session_id))
+            .map_err(|e| runtime_error("Failed to delete conversation", e))?;
+        if deleted == 0 {
+            return Err(not_found(session_id));
+        }
 
-        Ok("Generated Title".to_string())
+        let dict = PyDict::new(py);
+        dict.set_item("success", true)?;
+        dict.set_item("id", session_id)?;
+        Ok(dict.into())
     }
 }
 /
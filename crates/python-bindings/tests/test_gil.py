@@ -0,0 +1,39 @@
+"""The blocking runtime calls must release the GIL so other Python threads keep running."""
+import threading
+import time
+
+from offline_intelligence_py import Message, OfflineIntelligence
+
+
+def test_optimize_context_releases_gil(tmp_path):
+    oi = OfflineIntelligence(str(tmp_path / "gil.db"))
+    # A long conversation with a past reference forces retrieval, which keeps
+    # the call busy long enough for the background thread to be scheduled.
+    query = "What did we discuss earlier about lifetimes?"
+    messages = [
+        Message("user" if i % 2 == 0 else "assistant", f"message {i} about the rust borrow checker " * 50)
+        for i in range(400)
+    ]
+    messages.append(Message("user", query))
+
+    ticks = []
+    stop = threading.Event()
+
+    def spin():
+        while not stop.is_set():
+            ticks.append(time.perf_counter())
+
+    worker = threading.Thread(target=spin)
+    worker.start()
+    try:
+        started = time.perf_counter()
+        oi.optimize_context("gil-test", messages, query)
+        finished = time.perf_counter()
+    finally:
+        stop.set()
+        worker.join()
+
+    # If the GIL were held across the call, the spinning thread could not record
+    # anything strictly inside the call's window.
+    during = [t for t in ticks if started < t < finished]
+    assert len(during) > 0, f"no ticks during a {finished - started:.3f}s call"